@@ -16,6 +16,7 @@ use std::{convert::TryInto, io};
 use actix_web::{error::BlockingError, web, Error};
 use actix_web::{App, HttpResponse, HttpServer};
 use cashcontracts::{Address, AddressType};
+use slpdexdb_base::{Network, Settings};
 use diesel::{
     prelude::*,
     r2d2::{self, ConnectionManager},
@@ -83,10 +84,10 @@ struct PandaFrontEnd {
     pub mouth: MouthTrait,
 }
 
-impl From<DbPandaFull> for PandaFrontEnd {
-    fn from(db_panda: DbPandaFull) -> PandaFrontEnd {
+impl PandaFrontEnd {
+    fn new(db_panda: DbPandaFull, network: &Network) -> PandaFrontEnd {
         let address = Address::from_bytes_prefix(
-            "simpleledger",
+            network.slp_addr_prefix,
             AddressType::P2PKH,
             (&db_panda.hash[..]).try_into().unwrap(),
         );
@@ -110,6 +111,7 @@ impl From<DbPandaFull> for PandaFrontEnd {
 fn breeders(
     hb: web::Data<Handlebars>,
     pool: web::Data<Pool>,
+    network: web::Data<Network>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     web::block(move || {
         // Get connection
@@ -124,7 +126,7 @@ fn breeders(
             .filter_map(|x| x)
             .map(|pubkey_hash| {
                 Address::from_bytes_prefix(
-                    "simpleledger",
+                    network.slp_addr_prefix,
                     AddressType::P2PKH,
                     (&pubkey_hash[..]).try_into().unwrap(),
                 )
@@ -173,6 +175,7 @@ fn breed(
 fn pandas_by_address(
     hb: web::Data<Handlebars>,
     pool: web::Data<Pool>,
+    network: web::Data<Network>,
     address: web::Path<String>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     web::block(move || {
@@ -194,7 +197,7 @@ fn pandas_by_address(
         // Grab attributes
         let attributes: Vec<PandaFrontEnd> = db_pandas
             .into_iter()
-            .map(|db_panda| PandaFrontEnd::from(db_panda))
+            .map(|db_panda| PandaFrontEnd::new(db_panda, &network))
             .collect();
 
         // Convert to JSON
@@ -224,6 +227,7 @@ struct SelectionQuery {
 fn selection(
     hb: web::Data<Handlebars>,
     pool: web::Data<Pool>,
+    network: web::Data<Network>,
     query: web::Query<SelectionQuery>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     web::block(move || {
@@ -249,7 +253,7 @@ fn selection(
         let attributes: Vec<PandaFrontEnd> = db_pandas
             .into_iter()
             .filter(|db_panda| db_panda.hash != raw_token_id)
-            .map(|db_panda| PandaFrontEnd::from(db_panda))
+            .map(|db_panda| PandaFrontEnd::new(db_panda, &network))
             .collect();
 
         // Convert to JSON
@@ -273,6 +277,7 @@ fn selection(
 fn panda_by_token_id(
     hb: web::Data<Handlebars>,
     pool: web::Data<Pool>,
+    network: web::Data<Network>,
     token_id: web::Path<String>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     web::block(move || {
@@ -289,7 +294,7 @@ fn panda_by_token_id(
             get_full_panda_by_token_id(&raw_token_id, &conn).map_err(GetByTokenError::Diesel)?;
 
         // Convert to frontend panda
-        let frontend_panda = PandaFrontEnd::from(db_panda);
+        let frontend_panda = PandaFrontEnd::new(db_panda, &network);
 
         // Convert to JSON
         let mut data = serde_json::to_value(&frontend_panda).map_err(GetByTokenError::Serde)?;
@@ -352,17 +357,26 @@ fn main() -> io::Result<()> {
     hb_helpers::configure_handlebars(&mut handlebars);
     let handlebars_ref = web::Data::new(handlebars);
 
+    let (settings, _) = Settings::load(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    });
+
     // Init SQL connection
-    let connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL");
-    let manager = ConnectionManager::<PgConnection>::new(connection_str);
+    let manager = ConnectionManager::<PgConnection>::new(settings.database.url.clone());
     let pool = r2d2::Pool::builder()
+        .max_size(settings.database.pool_size)
         .build(manager)
         .expect("failed to create pool");
 
+    let network = settings.slpdex.network.clone();
+    let static_dir = settings.frontend.static_dir.clone();
+
     HttpServer::new(move || {
         App::new()
             .register_data(handlebars_ref.clone())
             .data(pool.clone())
+            .data(network.clone())
             .service(web::resource("/selection").route(web::get().to_async(pandas_by_address)))
             .service(web::resource("/breeders").route(web::get().to_async(breeders)))
             .service(web::resource("/selection").route(web::get().to_async(selection)))
@@ -387,8 +401,8 @@ fn main() -> io::Result<()> {
             .service(pandascope)
             .service(halloffame)
             .service(mating)
-	    .default_service( Files::new("", "./static/"))
+	    .default_service( Files::new("", &static_dir))
     })
-    .bind("127.0.0.1:8080")?
+    .bind(settings.frontend.bind)?
     .run()
 }
@@ -2,6 +2,8 @@ use hex_literal::hex;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{io, io::{Write, Read}};
 use cashcontracts::{double_sha256, tx_hash_to_hex};
+use rug::Integer;
+use crate::difficulty::bits_to_target;
 
 #[derive(Clone, Debug)]
 pub struct BlockHeader {
@@ -52,6 +54,39 @@ impl BlockHeader {
         self.write_to_stream(&mut ser).unwrap();
         double_sha256(&ser)
     }
+
+    pub fn target(&self) -> Integer {
+        bits_to_target(self.bits)
+    }
+
+    pub fn work(&self) -> Integer {
+        let target = self.target();
+        if target == 0 {
+            return Integer::new();
+        }
+        (Integer::from(1) << 256) / (target + 1)
+    }
+}
+
+pub fn merkle_root(tx_hashes: &[[u8; 32]]) -> [u8; 32] {
+    if tx_hashes.len() == 0 {
+        return [0; 32];
+    }
+    let mut level = tx_hashes.to_vec();
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(level[level.len() - 1]);
+        }
+        level = level.chunks(2)
+            .map(|pair| {
+                let mut concat = [0; 64];
+                concat[..32].copy_from_slice(&pair[0]);
+                concat[32..].copy_from_slice(&pair[1]);
+                double_sha256(&concat)
+            })
+            .collect();
+    }
+    level[0]
 }
 
 impl std::fmt::Display for BlockHeader {
@@ -66,3 +101,34 @@ impl std::fmt::Display for BlockHeader {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashcontracts::tx_hex_to_hash;
+
+    #[test]
+    fn work_genesis() {
+        assert_eq!(GENESIS.target(), Integer::from(0xffff) << 208);
+        assert_eq!(GENESIS.work(), Integer::from(0x1_0001_0001u64));
+    }
+
+    #[test]
+    fn merkle_root_genesis() {
+        assert_eq!(merkle_root(&[GENESIS.merkle_root]), GENESIS.merkle_root);
+    }
+
+    #[test]
+    fn merkle_root_block_100000() {
+        let tx_hashes = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ].iter().map(|tx_hex| tx_hex_to_hash(tx_hex).unwrap()).collect::<Vec<_>>();
+        let expected = tx_hex_to_hash(
+            "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766"
+        ).unwrap();
+        assert_eq!(merkle_root(&tx_hashes), expected);
+    }
+}
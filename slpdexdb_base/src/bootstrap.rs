@@ -0,0 +1,156 @@
+use std::fmt;
+use std::io::Cursor;
+use rug::Integer;
+use cashcontracts::tx_hash_to_hex;
+use crate::block::BlockHeader;
+use crate::difficulty::{DifficultyParams, check_header_batch, difficulty_history};
+use crate::errors::{Result, Error, ErrorKind, HeaderError};
+
+/// Recent headers a fresh database starts from instead of genesis.
+/// Text format: `height <h>`, `chain_work <hex>`, then one header hex per line, oldest first.
+#[derive(Clone, Debug)]
+pub struct BootstrapCheckpoint {
+    pub height: i32,  // height of the last header
+    pub chain_work: Integer,  // cumulative work up to and including the last header
+    pub headers: Vec<BlockHeader>,
+}
+
+fn _invalid(message: String) -> Error {
+    ErrorKind::InvalidHeader(HeaderError::InvalidBootstrap(message)).into()
+}
+
+impl BootstrapCheckpoint {
+    /// Returns `None` if the text contains no checkpoint, e.g. only comments.
+    pub fn parse(text: &str) -> Result<Option<Self>> {
+        let mut height = None;
+        let mut chain_work = None;
+        let mut headers = Vec::new();
+        let lines = text.lines()
+            .map(str::trim)
+            .filter(|line| line.len() > 0 && !line.starts_with('#'));
+        for line in lines {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("height"), Some(value)) => height = Some(value.parse::<i32>()?),
+                (Some("chain_work"), Some(value)) => chain_work = Some(
+                    Integer::from_str_radix(value, 16)
+                        .map_err(|err| _invalid(format!("invalid chain_work {}: {}", value, err)))?
+                ),
+                (Some(header_hex), None) => {
+                    let header_bytes = hex::decode(header_hex)?;
+                    if header_bytes.len() != 80 {
+                        return Err(_invalid(format!("header must be 80 bytes, got {}", header_bytes.len())));
+                    }
+                    headers.push(BlockHeader::from_stream(&mut Cursor::new(header_bytes))?);
+                },
+                _ => return Err(_invalid(format!("unexpected line {}", line))),
+            }
+        }
+        match (height, chain_work) {
+            (None, None) if headers.len() == 0 => Ok(None),
+            (Some(height), Some(chain_work)) => Ok(Some(BootstrapCheckpoint { height, chain_work, headers })),
+            _ => Err(_invalid("height and chain_work are required".to_string())),
+        }
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.last().expect("Empty bootstrap checkpoint")
+    }
+
+    pub fn start_height(&self) -> i32 {
+        self.height - self.headers.len() as i32 + 1
+    }
+
+    /// Headers with their heights and cumulative work, oldest first.
+    pub fn blocks(&self) -> Vec<(i32, &BlockHeader, Integer)> {
+        let mut chain_work = self.chain_work.clone();
+        let mut blocks = Vec::with_capacity(self.headers.len());
+        for (i, header) in self.headers.iter().enumerate().rev() {
+            blocks.push((self.start_height() + i as i32, header, chain_work.clone()));
+            chain_work -= header.work();
+        }
+        blocks.reverse();
+        blocks
+    }
+
+    pub fn validate(&self, params: &DifficultyParams) -> Result<()> {
+        let n_required = difficulty_history(self.height, params);
+        if self.headers.len() < n_required {
+            return Err(_invalid(format!("{} headers needed at height {}, got {}",
+                                        n_required, self.height, self.headers.len())));
+        }
+        check_header_batch(&self.headers, params)?;
+        let work = self.headers.iter().map(BlockHeader::work).fold(Integer::new(), |sum, work| sum + work);
+        if self.chain_work < work {
+            return Err(_invalid(format!("chain_work too low for {}", tx_hash_to_hex(&self.tip().hash()))));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BootstrapCheckpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "height {}", self.height)?;
+        writeln!(f, "chain_work {:x}", self.chain_work)?;
+        for header in self.headers.iter() {
+            let mut header_bytes = Vec::with_capacity(80);
+            header.write_to_stream(&mut header_bytes).map_err(|_| fmt::Error)?;
+            writeln!(f, "{}", hex::encode(header_bytes))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+
+    fn mine_chain(n: usize) -> Vec<BlockHeader> {
+        let params = Network::regtest().difficulty;
+        let mut headers: Vec<BlockHeader> = Vec::new();
+        for i in 0..n as u32 {
+            let mut header = BlockHeader {
+                version: 1,
+                prev_block: headers.last().map(BlockHeader::hash).unwrap_or([0; 32]),
+                merkle_root: [0; 32],
+                timestamp: 1_600_000_000 + i * 600,
+                bits: params.pow_limit_bits,
+                nonce: 0,
+            };
+            while !header.has_valid_pow(&params) {
+                header.nonce += 1;
+            }
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn roundtrip_and_validate() {
+        let params = Network::regtest().difficulty;
+        assert!(BootstrapCheckpoint::parse("# no checkpoint yet\n").unwrap().is_none());
+        let headers = mine_chain(11);
+        let checkpoint = BootstrapCheckpoint {
+            height: 110,
+            chain_work: Integer::from(1000),
+            headers: headers.clone(),
+        };
+        let parsed = BootstrapCheckpoint::parse(&checkpoint.to_string()).unwrap().unwrap();
+        assert_eq!(parsed.height, 110);
+        assert_eq!(parsed.start_height(), 100);
+        assert_eq!(parsed.chain_work, 1000);
+        assert_eq!(parsed.tip().hash(), headers[10].hash());
+        assert!(parsed.validate(&params).is_ok());
+        let blocks = parsed.blocks();
+        assert_eq!(blocks[10].0, 110);
+        assert_eq!(blocks[10].2, 1000);
+        assert_eq!(blocks[9].2, Integer::from(1000) - headers[10].work());
+
+        let too_short = BootstrapCheckpoint { headers: headers[1..].to_vec(), ..checkpoint.clone() };
+        assert!(too_short.validate(&params).is_err());
+        let no_work = BootstrapCheckpoint { chain_work: Integer::from(1), ..checkpoint };
+        assert!(no_work.validate(&params).is_err());
+        assert!(BootstrapCheckpoint::parse("height 5\n").is_err());
+    }
+}
@@ -68,6 +68,18 @@ pub fn i128_to_pg_numeric(mut val: i128) -> PgNumeric {
     }
 }
 
+pub fn pg_numeric_to_integer(numeric: &PgNumeric) -> Result<Integer> {
+    let rational = pg_numeric_to_rational(numeric)?;
+    if *rational.denom() != 1 {
+        return Err(ErrorKind::NumericError(NumericError::NotInteger(numeric.clone())).into());
+    }
+    Ok(rational.into_numer_denom().0)
+}
+
+pub fn integer_to_pg_numeric(integer: Integer) -> PgNumeric {
+    rational_to_pg_numeric(Rational::from(integer), 0)
+}
+
 pub fn rational_to_pg_numeric(rational: Rational, scale: u16) -> PgNumeric {
     let zero = Rational::from(0);
     let ten_thousand = Integer::from(10_000);
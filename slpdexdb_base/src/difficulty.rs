@@ -0,0 +1,503 @@
+use crate::block::BlockHeader;
+use crate::errors::{Result, ErrorKind, HeaderError};
+use cashcontracts::tx_hash_to_hex;
+use rug::Integer;
+use rug::integer::Order;
+
+#[derive(Clone, Debug)]
+pub struct DifficultyParams {
+    pub pow_limit_bits: u32,
+    pub allow_min_difficulty_blocks: bool,  // testnets: blocks 20 minutes late may use pow_limit_bits
+    pub no_retargeting: bool,  // regtest
+    pub uahf_height: i32,
+    pub daa_height: i32,
+    pub asert_anchor_height: i32,
+    pub asert_anchor_bits: u32,
+    pub asert_anchor_prev_timestamp: i64,
+    pub asert_half_life: i64,
+}
+
+pub const MAINNET_DIFFICULTY: DifficultyParams = DifficultyParams {
+    pow_limit_bits: 0x1d00ffff,
+    allow_min_difficulty_blocks: false,
+    no_retargeting: false,
+    uahf_height: 478558,
+    daa_height: 504031,
+    asert_anchor_height: 661647,
+    asert_anchor_bits: 0x1804dafe,
+    asert_anchor_prev_timestamp: 1605447844,
+    asert_half_life: 2 * 24 * 3600,
+};
+
+// enough history for a legacy retarget, which needs the block 2016 blocks back
+pub const DIFFICULTY_WINDOW: usize = 2016;
+
+const TARGET_SPACING: i64 = 600;
+const RETARGET_INTERVAL: i32 = 2016;
+const RETARGET_TIMESPAN: i64 = 14 * 24 * 3600;
+const MEDIAN_TIME_SPAN: i32 = 11;
+
+// number of consecutive headers ending at `height` needed to validate the block after it
+pub fn difficulty_history(height: i32, params: &DifficultyParams) -> usize {
+    let n_headers = if params.no_retargeting || height >= params.asert_anchor_height {
+        MEDIAN_TIME_SPAN as usize
+    } else if height >= params.daa_height {
+        147  // 144 blocks plus the two before the first suitable block
+    } else {
+        DIFFICULTY_WINDOW
+    };
+    n_headers.min(height.max(0) as usize + 1)
+}
+
+pub fn bits_to_target(bits: u32) -> Integer {
+    let exponent = bits >> 24;
+    let mantissa = bits & 0x007f_ffff;
+    if bits & 0x0080_0000 != 0 {
+        return Integer::new();  // negative targets are invalid
+    }
+    if exponent <= 3 {
+        Integer::from(mantissa >> (8 * (3 - exponent)))
+    } else {
+        Integer::from(mantissa) << (8 * (exponent - 3))
+    }
+}
+
+pub fn target_to_bits(target: &Integer) -> u32 {
+    let mut size = (target.significant_bits() + 7) / 8;
+    let mut compact = if size <= 3 {
+        target.to_u32().unwrap() << (8 * (3 - size))
+    } else {
+        Integer::from(target >> (8 * (size - 3))).to_u32().unwrap()
+    };
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | (size << 24)
+}
+
+impl BlockHeader {
+    pub fn has_valid_pow(&self, params: &DifficultyParams) -> bool {
+        let target = self.target();
+        target != 0 &&
+            target <= bits_to_target(params.pow_limit_bits) &&
+            Integer::from_digits(&self.hash()[..], Order::Lsf) <= target
+    }
+}
+
+pub fn check_header_batch(headers: &[BlockHeader], params: &DifficultyParams) -> Result<()> {
+    for (i, header) in headers.iter().enumerate() {
+        if !header.has_valid_pow(params) {
+            let hash_hex = tx_hash_to_hex(&header.hash());
+            return Err(ErrorKind::InvalidHeader(HeaderError::InvalidProofOfWork(hash_hex)).into());
+        }
+        if i > 0 && header.prev_block != headers[i - 1].hash() {
+            let hash_hex = tx_hash_to_hex(&header.hash());
+            return Err(ErrorKind::InvalidHeader(HeaderError::NotConnected(hash_hex)).into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct HeaderChain {
+    start_height: i32,
+    headers: Vec<BlockHeader>,
+}
+
+impl HeaderChain {
+    pub fn new(start_height: i32, headers: Vec<BlockHeader>) -> Self {
+        HeaderChain { start_height, headers }
+    }
+
+    pub fn tip_height(&self) -> i32 {
+        self.start_height + self.headers.len() as i32 - 1
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+
+    pub fn get(&self, height: i32) -> Option<&BlockHeader> {
+        if height < self.start_height {
+            return None;
+        }
+        self.headers.get((height - self.start_height) as usize)
+    }
+
+    pub fn push(&mut self, header: BlockHeader) {
+        self.headers.push(header);
+        if self.headers.len() > DIFFICULTY_WINDOW {
+            let n_remove = self.headers.len() - DIFFICULTY_WINDOW;
+            self.headers.drain(..n_remove);
+            self.start_height += n_remove as i32;
+        }
+    }
+
+    pub fn median_time_past(&self, height: i32) -> Option<u32> {
+        let first_height = (height - MEDIAN_TIME_SPAN + 1).max(0);
+        let mut timestamps = (first_height..=height)
+            .map(|height| self.get(height).map(|header| header.timestamp))
+            .collect::<Option<Vec<_>>>()?;
+        timestamps.sort();
+        Some(timestamps[timestamps.len() / 2])
+    }
+
+    pub fn expected_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let prev_height = self.tip_height();
+        if params.no_retargeting {
+            Some(self.tip()?.bits)
+        } else if prev_height >= params.asert_anchor_height {
+            self._asert_bits(params)
+        } else if prev_height >= params.daa_height {
+            self._cash_work_bits(params)
+        } else if params.allow_min_difficulty_blocks && (prev_height + 1) % RETARGET_INTERVAL != 0 {
+            self._last_regular_bits(params)
+        } else if prev_height >= params.uahf_height {
+            self._eda_bits(params)
+        } else {
+            self._legacy_bits(params)
+        }
+    }
+
+    pub fn validate_next(&self, header: &BlockHeader, params: &DifficultyParams) -> Result<()> {
+        let hash_hex = tx_hash_to_hex(&header.hash());
+        let tip = self.tip()
+            .ok_or_else(|| ErrorKind::MissingHeaderHistory(hash_hex.clone()))?;
+        if header.prev_block != tip.hash() {
+            return Err(ErrorKind::InvalidHeader(HeaderError::NotConnected(hash_hex)).into());
+        }
+        if !header.has_valid_pow(params) {
+            return Err(ErrorKind::InvalidHeader(HeaderError::InvalidProofOfWork(hash_hex)).into());
+        }
+        let median_time_past = self.median_time_past(self.tip_height())
+            .ok_or_else(|| ErrorKind::MissingHeaderHistory(hash_hex.clone()))?;
+        if header.timestamp <= median_time_past {
+            return Err(ErrorKind::InvalidHeader(HeaderError::TimestampTooEarly(hash_hex)).into());
+        }
+        let is_min_difficulty = params.allow_min_difficulty_blocks && !params.no_retargeting &&
+            header.timestamp as i64 > tip.timestamp as i64 + 2 * TARGET_SPACING;
+        let expected_bits = if is_min_difficulty {
+            params.pow_limit_bits
+        } else {
+            self.expected_bits(params)
+                .ok_or_else(|| ErrorKind::MissingHeaderHistory(hash_hex.clone()))?
+        };
+        if header.bits != expected_bits {
+            return Err(ErrorKind::InvalidHeader(
+                HeaderError::UnexpectedBits(hash_hex, expected_bits, header.bits)
+            ).into());
+        }
+        Ok(())
+    }
+
+    fn _legacy_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let height = self.tip_height() + 1;
+        let prev = self.tip()?;
+        if height % RETARGET_INTERVAL != 0 {
+            return Some(prev.bits);
+        }
+        let first = self.get(height - RETARGET_INTERVAL)?;
+        let timespan = (prev.timestamp as i64 - first.timestamp as i64)
+            .max(RETARGET_TIMESPAN / 4)
+            .min(RETARGET_TIMESPAN * 4);
+        let target = bits_to_target(prev.bits) * timespan / RETARGET_TIMESPAN;
+        Some(target_to_bits(&target.min(bits_to_target(params.pow_limit_bits))))
+    }
+
+    // bits of the last block that wasn't mined under the min difficulty rule
+    fn _last_regular_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let mut height = self.tip_height();
+        let mut header = self.tip()?;
+        while height % RETARGET_INTERVAL != 0 && header.bits == params.pow_limit_bits {
+            height -= 1;
+            header = self.get(height)?;
+        }
+        Some(header.bits)
+    }
+
+    fn _eda_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let height = self.tip_height() + 1;
+        if height % RETARGET_INTERVAL == 0 {
+            return self._legacy_bits(params);
+        }
+        let prev = self.tip()?;
+        if prev.bits == params.pow_limit_bits {
+            return Some(prev.bits);
+        }
+        let mtp_prev = self.median_time_past(height - 1)? as i64;
+        let mtp_6_blocks = self.median_time_past(height - 7)? as i64;
+        if mtp_prev - mtp_6_blocks < 12 * 3600 {
+            return Some(prev.bits);
+        }
+        let target = bits_to_target(prev.bits);
+        let target = Integer::from(&target >> 2) + target;
+        Some(target_to_bits(&target.min(bits_to_target(params.pow_limit_bits))))
+    }
+
+    fn _suitable_height(&self, height: i32) -> Option<i32> {
+        let mut blocks = [height - 2, height - 1, height];
+        let timestamp = |height: i32| self.get(height).map(|header| header.timestamp);
+        for &(a, b) in [(0, 2), (0, 1), (1, 2)].iter() {
+            if timestamp(blocks[a])? > timestamp(blocks[b])? {
+                blocks.swap(a, b);
+            }
+        }
+        Some(blocks[1])
+    }
+
+    fn _cash_work_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let prev_height = self.tip_height();
+        let last = self._suitable_height(prev_height)?;
+        let first = self._suitable_height(prev_height - 144)?;
+        let mut work = Integer::new();
+        for height in first + 1..=last {
+            work += self.get(height)?.work();
+        }
+        let timespan = (self.get(last)?.timestamp as i64 - self.get(first)?.timestamp as i64)
+            .max(72 * TARGET_SPACING)
+            .min(288 * TARGET_SPACING);
+        let work = work * TARGET_SPACING / timespan;
+        let target = ((Integer::from(1) << 256) - &work) / work;
+        Some(target_to_bits(&target.min(bits_to_target(params.pow_limit_bits))))
+    }
+
+    fn _asert_bits(&self, params: &DifficultyParams) -> Option<u32> {
+        let prev = self.tip()?;
+        let pow_limit = bits_to_target(params.pow_limit_bits);
+        let time_diff = prev.timestamp as i64 - params.asert_anchor_prev_timestamp;
+        let height_diff = (self.tip_height() - params.asert_anchor_height) as i64;
+        let exponent = (time_diff - TARGET_SPACING * (height_diff + 1)) * 65536 / params.asert_half_life;
+        let shifts = (exponent >> 16) - 16;
+        let frac = exponent as u16 as u128;
+        let factor = 65536 + ((195_766_423_245_049 * frac +
+                               971_821_376 * frac * frac +
+                               5127 * frac * frac * frac +
+                               (1 << 47)) >> 48);
+        let target = bits_to_target(params.asert_anchor_bits) * factor as u64;
+        let target = if shifts <= 0 {
+            target >> (-shifts) as u32
+        } else {
+            target << shifts as u32
+        };
+        let target = if target == 0 { Integer::from(1) } else { target };
+        Some(target_to_bits(&target.min(pow_limit)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::GENESIS;
+    use cashcontracts::tx_hex_to_hash;
+    use hex_literal::hex;
+    use std::io::Cursor;
+
+    const REGTEST_DIFFICULTY: DifficultyParams = DifficultyParams {
+        pow_limit_bits: 0x207fffff,
+        allow_min_difficulty_blocks: false,
+        no_retargeting: false,
+        uahf_height: 3000,
+        daa_height: 4000,
+        asert_anchor_height: 5000,
+        asert_anchor_bits: 0x1f0fffff,
+        asert_anchor_prev_timestamp: 1_600_000_000,
+        asert_half_life: 2 * 24 * 3600,
+    };
+
+    fn block_1() -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block: GENESIS.hash(),
+            merkle_root: hex!("982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e"),
+            timestamp: 1231469665,
+            bits: 0x1d00ffff,
+            nonce: 2573394689,
+        }
+    }
+
+    fn mine(prev_block: [u8; 32], timestamp: u32, bits: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 1, prev_block, merkle_root: [0; 32], timestamp, bits, nonce: 0,
+        };
+        while !header.has_valid_pow(&REGTEST_DIFFICULTY) {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn synthetic_chain(start_height: i32, n: usize, bits: u32, spacing: u32) -> HeaderChain {
+        let headers = (0..n as u32)
+            .map(|i| BlockHeader {
+                version: 1,
+                prev_block: [0; 32],
+                merkle_root: [0; 32],
+                timestamp: 1_600_000_000 + i * spacing,
+                bits,
+                nonce: 0,
+            })
+            .collect();
+        HeaderChain::new(start_height, headers)
+    }
+
+    #[test]
+    fn compact_roundtrip() {
+        for &bits in [0x1d00ffff, 0x1804dafe, 0x207fffff, 0x1b0404cb, 0x03123456].iter() {
+            assert_eq!(target_to_bits(&bits_to_target(bits)), bits);
+        }
+        assert_eq!(target_to_bits(&Integer::from(0x80)), 0x02008000);
+    }
+
+    #[test]
+    fn mainnet_pow() {
+        assert!(GENESIS.has_valid_pow(&MAINNET_DIFFICULTY));
+        assert_eq!(
+            block_1().hash(),
+            tx_hex_to_hash("00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048").unwrap(),
+        );
+        assert!(block_1().has_valid_pow(&MAINNET_DIFFICULTY));
+        let mut fake = block_1();
+        fake.nonce += 1;
+        assert!(!fake.has_valid_pow(&MAINNET_DIFFICULTY));
+        assert!(check_header_batch(&[GENESIS, block_1()], &MAINNET_DIFFICULTY).is_ok());
+        assert!(check_header_batch(&[GENESIS, fake], &MAINNET_DIFFICULTY).is_err());
+        let chain = HeaderChain::new(0, vec![GENESIS]);
+        assert!(chain.validate_next(&block_1(), &MAINNET_DIFFICULTY).is_ok());
+    }
+
+    // `height <h>` of the first header, then one header hex per line
+    fn real_headers(text: &str) -> (i32, Vec<BlockHeader>) {
+        let mut lines = text.lines()
+            .map(str::trim)
+            .filter(|line| line.len() > 0 && !line.starts_with('#'));
+        let start_height = match lines.next().map(|line| line.split_whitespace().collect::<Vec<_>>()) {
+            Some(ref parts) if parts.len() == 2 && parts[0] == "height" => parts[1].parse().unwrap(),
+            _ => panic!("header fixture is empty, see the comment at its top"),
+        };
+        let headers = lines
+            .map(|line| BlockHeader::from_stream(&mut Cursor::new(hex::decode(line).unwrap())).unwrap())
+            .collect();
+        (start_height, headers)
+    }
+
+    fn validate_real_headers(text: &str, first_validated: i32, last_validated: i32) {
+        let (start_height, headers) = real_headers(text);
+        let n_history = (first_validated - start_height) as usize;
+        assert_eq!(start_height + headers.len() as i32 - 1, last_validated);
+        let mut chain = HeaderChain::new(start_height, headers[..n_history].to_vec());
+        for header in headers[n_history..].iter() {
+            assert_eq!(chain.expected_bits(&MAINNET_DIFFICULTY), Some(header.bits));
+            chain.validate_next(header, &MAINNET_DIFFICULTY).unwrap();
+            let mut changed_bits = header.clone();
+            changed_bits.bits -= 1;
+            assert!(chain.validate_next(&changed_bits, &MAINNET_DIFFICULTY).is_err());
+            chain.push(header.clone());
+        }
+    }
+
+    #[test]
+    fn mainnet_daa_activation() {
+        validate_real_headers(include_str!("../fixtures/mainnet_daa_headers.txt"), 504010, 504200);
+    }
+
+    #[test]
+    fn mainnet_asert_activation() {
+        validate_real_headers(include_str!("../fixtures/mainnet_asert_headers.txt"), 661600, 661700);
+    }
+
+    #[test]
+    fn legacy_retarget() {
+        let chain = synthetic_chain(1000, 1015, 0x1f0fffff, 300);
+        assert_eq!(chain.tip_height(), 2014);
+        assert_eq!(chain.expected_bits(&REGTEST_DIFFICULTY), Some(0x1f0fffff));
+        let mut chain = synthetic_chain(0, 2016, 0x1f0fffff, 60);
+        assert_eq!(chain.expected_bits(&REGTEST_DIFFICULTY), Some(0x1f03ffff));
+        chain.push(mine(chain.tip().unwrap().hash(), 1_600_700_000, 0x1f0fffff));
+        assert_eq!(chain.tip_height(), 2016);
+        assert_eq!(chain.expected_bits(&REGTEST_DIFFICULTY), Some(0x1f0fffff));
+    }
+
+    #[test]
+    fn eda_lowers_difficulty() {
+        let on_time = synthetic_chain(3000, 20, 0x1f0fffff, 600);
+        assert_eq!(on_time.expected_bits(&REGTEST_DIFFICULTY), Some(0x1f0fffff));
+        let slow = synthetic_chain(3000, 20, 0x1f0fffff, 3 * 3600);
+        let expected = bits_to_target(0x1f0fffff) * 5 / 4;
+        assert_eq!(slow.expected_bits(&REGTEST_DIFFICULTY), Some(target_to_bits(&expected)));
+    }
+
+    #[test]
+    fn cash_work_steady_state() {
+        let chain = synthetic_chain(4000, 200, 0x1f0fffff, 600);
+        let bits = chain.expected_bits(&REGTEST_DIFFICULTY).unwrap();
+        let target = bits_to_target(bits);
+        let expected = bits_to_target(0x1f0fffff);
+        assert!((target - &expected).abs() < expected / 1000);
+        let fast = synthetic_chain(4000, 200, 0x1f0fffff, 300);
+        let fast_target = bits_to_target(fast.expected_bits(&REGTEST_DIFFICULTY).unwrap());
+        assert!(fast_target < bits_to_target(0x1f0fffff));
+    }
+
+    #[test]
+    fn asert_half_life() {
+        let anchor = REGTEST_DIFFICULTY.asert_anchor_prev_timestamp as u32;
+        let on_schedule = HeaderChain::new(5000, vec![BlockHeader {
+            version: 1, prev_block: [0; 32], merkle_root: [0; 32],
+            timestamp: anchor + 600, bits: 0x1f0fffff, nonce: 0,
+        }]);
+        assert_eq!(on_schedule.expected_bits(&REGTEST_DIFFICULTY), Some(0x1f0fffff));
+        let late = HeaderChain::new(5000, vec![BlockHeader {
+            version: 1, prev_block: [0; 32], merkle_root: [0; 32],
+            timestamp: anchor + 600 + 2 * 24 * 3600, bits: 0x1f0fffff, nonce: 0,
+        }]);
+        assert_eq!(late.expected_bits(&REGTEST_DIFFICULTY), Some(target_to_bits(&(bits_to_target(0x1f0fffff) * 2))));
+    }
+
+    #[test]
+    fn reject_wrong_bits() {
+        let mut chain = synthetic_chain(0, 20, 0x207fffff, 600);
+        let tip = chain.tip().unwrap().clone();
+        let good = mine(tip.hash(), tip.timestamp + 600, 0x207fffff);
+        assert!(chain.validate_next(&good, &REGTEST_DIFFICULTY).is_ok());
+        let bad_bits = mine(tip.hash(), tip.timestamp + 600, 0x2000ffff);
+        assert!(chain.validate_next(&bad_bits, &REGTEST_DIFFICULTY).is_err());
+        let too_early = mine(tip.hash(), 1_600_000_000, 0x207fffff);
+        assert!(chain.validate_next(&too_early, &REGTEST_DIFFICULTY).is_err());
+        chain.push(good);
+        assert_eq!(chain.tip_height(), 20);
+    }
+
+    #[test]
+    fn min_difficulty_blocks() {
+        let params = DifficultyParams { allow_min_difficulty_blocks: true, ..REGTEST_DIFFICULTY };
+        let mut chain = synthetic_chain(100, 20, 0x1f0fffff, 600);
+        let tip = chain.tip().unwrap().clone();
+        let late = mine(tip.hash(), tip.timestamp + 1201, 0x207fffff);
+        assert!(chain.validate_next(&late, &params).is_ok());
+        assert!(chain.validate_next(&late, &REGTEST_DIFFICULTY).is_err());
+        chain.push(late.clone());
+        // the next on-time block goes back to the last regular difficulty
+        assert_eq!(chain.expected_bits(&params), Some(0x1f0fffff));
+        let on_time = mine(late.hash(), late.timestamp + 600, 0x207fffff);
+        assert!(chain.validate_next(&on_time, &params).is_err());
+    }
+
+    #[test]
+    fn missing_history() {
+        // the retarget at 2016 needs block 0
+        let chain = synthetic_chain(1001, 1015, 0x1f0fffff, 600);
+        let tip = chain.tip().unwrap().clone();
+        let next = mine(tip.hash(), tip.timestamp + 600, 0x1f0fffff);
+        match chain.validate_next(&next, &REGTEST_DIFFICULTY).unwrap_err().kind() {
+            ErrorKind::MissingHeaderHistory(_) => {},
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn no_retargeting() {
+        let params = DifficultyParams { no_retargeting: true, ..REGTEST_DIFFICULTY };
+        let chain = synthetic_chain(0, 2016, 0x1f0fffff, 60);
+        assert_eq!(chain.expected_bits(&params), Some(0x1f0fffff));
+    }
+}
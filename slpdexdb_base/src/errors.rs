@@ -9,6 +9,11 @@ pub enum SLPError {
     InvalidTokenTypeLength(String),
     InvalidTokenHashLength(String),
     NoMatch,
+    InvalidPush(String),
+    UnexpectedOpCode(u8),
+    UnsupportedTokenType(u16),
+    WrongNumberOfPushops(usize),
+    InvalidField(&'static str, String),
 }
 
 #[derive(Debug)]
@@ -57,6 +62,16 @@ pub enum PNDError {
 }
 
 
+#[derive(Debug)]
+pub enum HeaderError {
+    InvalidProofOfWork(String),
+    NotConnected(String),
+    TimestampTooEarly(String),
+    UnexpectedBits(String, u32, u32),  // hash, expected bits, actual bits
+    CheckpointMismatch(String, i32),  // hash, height
+    InvalidBootstrap(String),
+}
+
 #[derive(Debug)]
 pub enum PandaError {
     NoParentUtxosLeft,
@@ -70,10 +85,12 @@ error_chain! {
         VarError(std::env::VarError);
         Query(diesel::result::Error);
         DbConnection(diesel::ConnectionError);
+        DbPool(diesel::r2d2::PoolError);
         Request(reqwest::Error);
         ParseInt(std::num::ParseIntError);
         FromHex(hex::FromHexError);
         Mailbox(actix::MailboxError);
+        Json(serde_json::Error);
     }
 
     errors {
@@ -106,5 +123,31 @@ error_chain! {
             description("Panda Error")
             display("Panda Error: {:?}", panda_error)
         }
+
+        InvalidHeader(header_error: HeaderError) {
+            description("Invalid block header")
+            display("Invalid block header: {:?}", header_error)
+        }
+
+        // not enough stored headers to validate a header, which says nothing about the header itself
+        MissingHeaderHistory(hash: String) {
+            description("Missing header history")
+            display("Missing header history to validate block header {}", hash)
+        }
+
+        ChainSource(message: String) {
+            description("Chain source error")
+            display("Chain source error: {}", message)
+        }
+
+        InvalidRequest(message: String) {
+            description("Invalid request")
+            display("Invalid request: {}", message)
+        }
+
+        Config(message: String) {
+            description("Invalid configuration")
+            display("Invalid configuration: {}", message)
+        }
     }
 }
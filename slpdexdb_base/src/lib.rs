@@ -8,11 +8,20 @@ pub mod convert_numeric;
 mod errors;
 mod slp_amount;
 mod block;
+mod difficulty;
+mod network;
+mod settings;
+mod bootstrap;
 
 pub use config::*;
-pub use errors::{Error, ErrorKind, TradeOfferError, NumericError, SLPError, TokenError, Result, PNDError, PandaError};
+pub use errors::{Error, ErrorKind, TradeOfferError, NumericError, SLPError, TokenError, Result, PNDError, PandaError,
+                 HeaderError};
 pub use slp_amount::*;
 pub use block::*;
+pub use difficulty::*;
+pub use network::*;
+pub use settings::*;
+pub use bootstrap::*;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,272 @@
+use hex_literal::hex;
+use cashcontracts::{Address, tx_hex_to_hash};
+use crate::block::{BlockHeader, GENESIS};
+use crate::bootstrap::BootstrapCheckpoint;
+use crate::difficulty::{DifficultyParams, MAINNET_DIFFICULTY};
+use crate::errors::{Result, ErrorKind};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum NetworkKind {
+    Mainnet,
+    Testnet,
+    Testnet4,
+    Regtest,
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub height: i32,
+    pub hash: [u8; 32],
+}
+
+#[derive(Clone, Debug)]
+pub struct Network {
+    pub kind: NetworkKind,
+    pub magic: [u8; 4],
+    pub genesis: BlockHeader,
+    pub default_port: u16,
+    pub dns_seeds: Vec<&'static str>,
+    pub cash_addr_prefix: &'static str,
+    pub slp_addr_prefix: &'static str,
+    pub difficulty: DifficultyParams,
+    pub checkpoints: Vec<Checkpoint>,
+    pub bootstrap: Option<BootstrapCheckpoint>,
+    // defaults for SLPDEXConfig
+    pub fee_address: Address,
+    pub panda_token_hash: [u8; 32],
+}
+
+fn bootstrap(text: &str) -> Option<BootstrapCheckpoint> {
+    BootstrapCheckpoint::parse(text).expect("Invalid embedded bootstrap checkpoint")
+}
+
+fn checkpoint(height: i32, hash_hex: &str) -> Checkpoint {
+    Checkpoint { height, hash: tx_hex_to_hash(hash_hex).unwrap() }
+}
+
+const MAINNET_FEE_ADDRESS: &str = "bitcoincash:qr4tqy4xye3y7cxtwxskr0l445lf55tnnchv8474jd";
+
+impl Network {
+    pub fn mainnet() -> Self {
+        Network {
+            kind: NetworkKind::Mainnet,
+            magic: hex!("e3e1f3e8"),
+            genesis: GENESIS,
+            default_port: 8333,
+            dns_seeds: vec![
+                "seed.bitcoinabc.org",
+                "seed-abc.bitcoinforks.org",
+                "btccash-seeder.bitcoinunlimited.info",
+                "seed.bchd.cash",
+            ],
+            cash_addr_prefix: "bitcoincash",
+            slp_addr_prefix: "simpleledger",
+            difficulty: MAINNET_DIFFICULTY,
+            checkpoints: vec![
+                checkpoint( 11111, "0000000069e244f73d78e8fd29ba2fd2ed618bd6fa2ee92559f542fdb26e7c1d"),
+                checkpoint( 33333, "000000002dd5588a74784eaa7ab0507a18ad16a236e7b1ce69f00d7ddfb5d0a6"),
+                checkpoint( 74000, "0000000000573993a3c9e41ce34471c079dcf5f52a0e824a81e7f953b8661a20"),
+                checkpoint(105000, "00000000000291ce28027faea320c8d2b054b2e0fe44a773f3eefb151d6bdc97"),
+                checkpoint(134444, "00000000000005b12ffd4cd315cd34ffd4a594f430ac814c91184a0d42d2b0fe"),
+                checkpoint(168000, "000000000000099e61ea72015e79632f216fe6cb33d7899acb35b75c8303b763"),
+                checkpoint(193000, "000000000000059f452a5f7340de6682a977387c17010ff6e6c3bd83ca8b1317"),
+                checkpoint(210000, "000000000000048b95347e83192f69cf0366076336c639f9b7228e9ba171342e"),
+                checkpoint(216116, "00000000000001b4f4b433e81ee46494af945cf96014816a4e2370f11b23df4e"),
+                checkpoint(225430, "00000000000001c108384350f74090433e7fcf79a606b8e797f065b130575932"),
+                checkpoint(250000, "000000000000003887df1f29024b06fc2200b55f8af8f35453d7be294df2d214"),
+                checkpoint(279000, "0000000000000001ae8c72a0b0c301f67e3afca10e819efa9041e458e9bd7e40"),
+                checkpoint(295000, "00000000000000004d9b4ef50f0f9d686fd69db2e03af35a100370c64632a983"),
+                checkpoint(478558, "0000000000000000011865af4122fe3b144e2cbeea86142e8ff2fb4107352d43"),
+                checkpoint(556767, "0000000000000000004626ff6e3b936941d341c5932ece4357eeccac44e6d56c"),
+            ],
+            bootstrap: bootstrap(include_str!("../checkpoints/mainnet.txt")),
+            fee_address: Address::from_cash_addr(MAINNET_FEE_ADDRESS.to_string()).unwrap(),
+            panda_token_hash: tx_hex_to_hash(
+                "af5fb817275c12a403df832cf61af135d0cd7a63f9c0fedb10ff3b2b50799533"
+            ).unwrap(),
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Network {
+            kind: NetworkKind::Testnet,
+            magic: hex!("f4e5f3f4"),
+            genesis: BlockHeader { timestamp: 1296688602, nonce: 414098458, ..GENESIS },
+            default_port: 18333,
+            dns_seeds: vec![
+                "testnet-seed.bitcoinabc.org",
+                "testnet-seed-abc.bitcoinforks.org",
+                "testnet-seed.bchd.cash",
+            ],
+            cash_addr_prefix: "bchtest",
+            slp_addr_prefix: "slptest",
+            difficulty: DifficultyParams {
+                pow_limit_bits: 0x1d00ffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: false,
+                uahf_height: 1155875,
+                daa_height: 1188697,
+                asert_anchor_height: 1421481,
+                asert_anchor_bits: 0x1d00ffff,
+                asert_anchor_prev_timestamp: 1605445400,
+                asert_half_life: 3600,
+            },
+            checkpoints: vec![
+                checkpoint(546, "000000002a936ca763904c3c35fce2f3556c559c0214345d31b1bcebf76acb70"),
+            ],
+            bootstrap: bootstrap(include_str!("../checkpoints/testnet.txt")),
+            fee_address: Self::_test_fee_address("bchtest"),
+            panda_token_hash: [0; 32],
+        }
+    }
+
+    pub fn testnet4() -> Self {
+        Network {
+            kind: NetworkKind::Testnet4,
+            magic: hex!("e2b7daaf"),
+            genesis: BlockHeader { timestamp: 1597811185, nonce: 114152193, ..GENESIS },
+            default_port: 28333,
+            dns_seeds: vec![
+                "testnet4-seed-bch.bitcoinforks.org",
+                "testnet4-seed-bch.toom.im",
+                "seed.tbch4.loping.net",
+            ],
+            cash_addr_prefix: "bchtest",
+            slp_addr_prefix: "slptest",
+            difficulty: DifficultyParams {
+                pow_limit_bits: 0x1d00ffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: false,
+                uahf_height: 6,
+                daa_height: 3000,
+                asert_anchor_height: 16844,
+                asert_anchor_bits: 0x1d00ffff,
+                asert_anchor_prev_timestamp: 1605451779,
+                asert_half_life: 3600,
+            },
+            checkpoints: vec![],
+            bootstrap: bootstrap(include_str!("../checkpoints/testnet4.txt")),
+            fee_address: Self::_test_fee_address("bchtest"),
+            panda_token_hash: [0; 32],
+        }
+    }
+
+    pub fn regtest() -> Self {
+        Network {
+            kind: NetworkKind::Regtest,
+            magic: hex!("dab5bffa"),
+            genesis: BlockHeader { timestamp: 1296688602, bits: 0x207fffff, nonce: 2, ..GENESIS },
+            default_port: 18444,
+            dns_seeds: vec![],
+            cash_addr_prefix: "bchreg",
+            slp_addr_prefix: "slpreg",
+            difficulty: DifficultyParams {
+                pow_limit_bits: 0x207fffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: true,
+                uahf_height: 0,
+                daa_height: 0,
+                asert_anchor_height: 0,
+                asert_anchor_bits: 0x207fffff,
+                asert_anchor_prev_timestamp: 0,
+                asert_half_life: 3600,
+            },
+            checkpoints: vec![],
+            bootstrap: None,
+            fee_address: Self::_test_fee_address("bchreg"),
+            panda_token_hash: [0; 32],
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "mainnet" => Ok(Network::mainnet()),
+            "testnet" | "testnet3" => Ok(Network::testnet()),
+            "testnet4" => Ok(Network::testnet4()),
+            "regtest" => Ok(Network::regtest()),
+            _ => Err(ErrorKind::InvalidRequest(format!("unknown network {}", name)).into()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            NetworkKind::Mainnet => "mainnet",
+            NetworkKind::Testnet => "testnet",
+            NetworkKind::Testnet4 => "testnet4",
+            NetworkKind::Regtest => "regtest",
+        }
+    }
+
+    // no fee address is deployed off mainnet, reuse its hash so fees remain spendable by the same key
+    fn _test_fee_address(prefix: &str) -> Address {
+        Address::from_cash_addr(MAINNET_FEE_ADDRESS.to_string()).unwrap()
+            .with_prefix(prefix.to_string())
+    }
+
+    pub fn cash_addr(&self, address: &Address) -> String {
+        address.with_prefix(self.cash_addr_prefix.to_string()).cash_addr().to_string()
+    }
+
+    pub fn slp_addr(&self, address: &Address) -> String {
+        address.with_prefix(self.slp_addr_prefix.to_string()).cash_addr().to_string()
+    }
+
+    pub fn checkpoint(&self, height: i32) -> Option<&Checkpoint> {
+        self.checkpoints.iter().find(|checkpoint| checkpoint.height == height)
+    }
+
+    // header and height a fresh database starts from
+    pub fn initial_tip(&self) -> (BlockHeader, i32) {
+        match &self.bootstrap {
+            Some(bootstrap) => (bootstrap.tip().clone(), bootstrap.height),
+            None => (self.genesis.clone(), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genesis_hashes() {
+        let expected = [
+            (Network::mainnet(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"),
+            (Network::testnet(), "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943"),
+            (Network::testnet4(), "000000001dd410c49a788668ce26751718cc797474d3152a5fc073dd44fd9f7b"),
+            (Network::regtest(), "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"),
+        ];
+        for (network, genesis_hex) in expected.iter() {
+            assert_eq!(network.genesis.hash(), tx_hex_to_hash(genesis_hex).unwrap());
+            assert!(network.genesis.has_valid_pow(&network.difficulty));
+            assert_eq!(Network::from_name(network.name()).unwrap().kind, network.kind);
+            if let Some(bootstrap) = &network.bootstrap {
+                bootstrap.validate(&network.difficulty).unwrap();
+                for (height, header, _) in bootstrap.blocks() {
+                    if let Some(checkpoint) = network.checkpoint(height) {
+                        assert_eq!(checkpoint.hash, header.hash());
+                    }
+                }
+            }
+        }
+        assert!(Network::from_name("simnet").is_err());
+    }
+
+    #[test]
+    fn embeds_bootstrap_checkpoints() {
+        for network in [Network::mainnet(), Network::testnet(), Network::testnet4()].iter() {
+            let bootstrap = network.bootstrap.as_ref()
+                .unwrap_or_else(|| panic!("no bootstrap checkpoint embedded for {}", network.name()));
+            bootstrap.validate(&network.difficulty).unwrap();
+        }
+    }
+
+    #[test]
+    fn formats_addresses() {
+        let network = Network::testnet();
+        let address = Network::mainnet().fee_address;
+        assert!(network.cash_addr(&address).starts_with("bchtest:"));
+        assert!(network.slp_addr(&address).starts_with("slptest:"));
+        assert_eq!(Network::mainnet().cash_addr(&address), MAINNET_FEE_ADDRESS);
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use cashcontracts::{Address, tx_hex_to_hash};
+use crate::config::SLPDEXConfig;
+use crate::network::{Network, NetworkKind};
+use crate::errors::{Error, Result, ErrorKind};
+
+pub const CONFIG_FILE_ENV: &str = "SLPDEX_CONFIG";
+
+// connections the endpoint needs besides its read workers: the resync and header/peer writers
+// plus two spare, e.g. for running repair-utxos next to it
+const MIN_WRITER_CONNECTIONS: usize = 4;
+
+// setting key (as in the config file and --flags) and the env var overriding it
+const SETTINGS: &[(&str, &str)] = &[
+    ("network", "NETWORK"),
+    ("database.url", "DATABASE_URL"),
+    ("database.pool_size", "DB_POOL_SIZE"),
+    ("slpdex.fee_address", "SLPDEX_FEE_ADDRESS"),
+    ("slpdex.fee_divisor", "SLPDEX_FEE_DIVISOR"),
+    ("slpdex.dust_limit", "SLPDEX_DUST_LIMIT"),
+    ("slpdex.exch_lokad", "SLPDEX_EXCH_LOKAD"),
+    ("slpdex.exch_version", "SLPDEX_EXCH_VERSION"),
+    ("slpdex.panda_token_id", "SLPDEX_PANDA_TOKEN_ID"),
+    ("slpdex.panda_fee", "SLPDEX_PANDA_FEE"),
+    ("endpoint.bind", "ENDPOINT_BIND"),
+    ("endpoint.port", "PORT"),
+    ("endpoint.secret", "SECRET"),
+    ("endpoint.read_workers", "DB_READ_WORKERS"),
+    ("endpoint.seed_peers", "SEED_PEERS"),
+    ("endpoint.target_outbound", "TARGET_OUTBOUND"),
+    ("endpoint.ban_threshold", "BAN_THRESHOLD"),
+    ("endpoint.ban_duration_secs", "BAN_DURATION_SECS"),
+    ("chain_source.kind", "CHAIN_SOURCE"),
+    ("chain_source.bitdb_url", "BITDB_URL"),
+    ("chain_source.slpdb_url", "SLPDB_URL"),
+    ("chain_source.fixture", "CHAIN_FIXTURE"),
+    ("chain_source.rpc_url", "BITCOIND_RPC_URL"),
+    ("chain_source.rpc_user", "BITCOIND_RPC_USER"),
+    ("chain_source.rpc_password", "BITCOIND_RPC_PASSWORD"),
+    ("frontend.bind", "FRONTEND_BIND"),
+    ("frontend.port", "FRONTEND_PORT"),
+    ("frontend.static_dir", "FRONTEND_STATIC_DIR"),
+];
+
+#[derive(Clone, Debug)]
+pub struct DatabaseSettings {
+    pub url: String,
+    pub pool_size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub enum ChainSourceSettings {
+    Slpdb { bitdb_url: Option<String>, slpdb_url: Option<String> },
+    Fixture { path: String },
+    Rpc { url: String, user: Option<String>, password: Option<String> },
+}
+
+#[derive(Clone)]
+pub struct EndpointSettings {
+    pub bind: SocketAddr,
+    pub secret: Option<Vec<u8>>,
+    pub read_workers: usize,
+    pub seed_peers: Vec<SocketAddr>,
+    pub target_outbound: usize,
+    pub ban_threshold: i32,
+    pub ban_duration_secs: i64,
+    pub chain_source: ChainSourceSettings,
+}
+
+#[derive(Clone, Debug)]
+pub struct FrontendSettings {
+    pub bind: SocketAddr,
+    pub static_dir: String,
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub slpdex: SLPDEXConfig,
+    pub database: DatabaseSettings,
+    pub endpoint: EndpointSettings,
+    pub frontend: FrontendSettings,
+}
+
+impl EndpointSettings {
+    pub fn secret(&self) -> Result<&[u8]> {
+        self.secret.as_ref().map(Vec::as_slice).ok_or_else(|| _missing("endpoint.secret"))
+    }
+}
+
+impl Settings {
+    /// Loads the config file (--config or SLPDEX_CONFIG), then env overrides, then --key=value flags.
+    /// Returns the settings and the positional arguments.
+    pub fn load(args: impl IntoIterator<Item=String>) -> Result<(Settings, Vec<String>)> {
+        Self::from_sources(
+            args,
+            |var| std::env::var(var).ok(),
+            |path| std::fs::read_to_string(path)
+                .map_err(|err| _config_error(format!("cannot read config file {}: {}", path, err))),
+        )
+    }
+
+    fn from_sources(args: impl IntoIterator<Item=String>,
+                    env: impl Fn(&str) -> Option<String>,
+                    read_file: impl Fn(&str) -> Result<String>) -> Result<(Settings, Vec<String>)> {
+        let mut config_path = env(CONFIG_FILE_ENV);
+        let mut flags = Vec::new();
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+            let flag = &arg[2..];
+            let (key, value) = match flag.find('=') {
+                Some(idx) => (flag[..idx].to_string(), flag[idx + 1..].to_string()),
+                None => match args.next() {
+                    Some(value) => (flag.to_string(), value),
+                    None => return Err(_config_error(format!("missing value for flag {}", arg))),
+                },
+            };
+            let key = key.replace('-', "_");
+            if key == "config" {
+                config_path = Some(value);
+            } else {
+                flags.push((key, value));
+            }
+        }
+        let mut values = HashMap::new();
+        if let Some(path) = config_path {
+            let text = read_file(&path)?;
+            let table = text.parse::<toml::Value>()
+                .map_err(|err| _config_error(format!("invalid config file {}: {}", path, err)))?;
+            let mut entries = Vec::new();
+            _flatten_toml("", &table, &mut entries);
+            for (key, value) in entries {
+                let source = format!("config file {}", path);
+                values.insert(_known_key(&key, &source)?, (value, source));
+            }
+        }
+        for &(key, var) in SETTINGS {
+            if let Some(value) = env(var) {
+                values.insert(key, (value, format!("env {}", var)));
+            }
+        }
+        for (key, value) in flags {
+            let source = format!("flag --{}", key);
+            values.insert(_known_key(&key, &source)?, (value, source));
+        }
+        Ok((SettingValues { values }.build()?, positional))
+    }
+}
+
+fn _config_error(message: String) -> Error {
+    ErrorKind::Config(message).into()
+}
+
+fn _env_var(key: &str) -> &'static str {
+    SETTINGS.iter().find(|(known, _)| *known == key).map(|(_, var)| *var).unwrap_or("")
+}
+
+fn _missing(key: &str) -> Error {
+    _config_error(format!("missing required setting {} (set it in the config file, with env {} or with --{})",
+                          key, _env_var(key), key))
+}
+
+fn _known_key(key: &str, source: &str) -> Result<&'static str> {
+    SETTINGS.iter()
+        .find(|(known, _)| *known == key)
+        .map(|(known, _)| *known)
+        .ok_or_else(|| _config_error(format!("unknown setting {} in {}", key, source)))
+}
+
+fn _flatten_toml(prefix: &str, value: &toml::Value, entries: &mut Vec<(String, String)>) {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(string) => string.clone(),
+        value => value.to_string(),
+    };
+    match value {
+        toml::Value::Table(table) => for (key, value) in table {
+            let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            _flatten_toml(&key, value, entries);
+        },
+        toml::Value::Array(items) => {
+            let items = items.iter().map(scalar).collect::<Vec<_>>();
+            entries.push((prefix.to_string(), items.join(",")));
+        },
+        value => entries.push((prefix.to_string(), scalar(value))),
+    }
+}
+
+struct SettingValues {
+    values: HashMap<&'static str, (String, String)>,  // key -> (value, source)
+}
+
+impl SettingValues {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|(value, _)| value.as_str())
+    }
+
+    fn require(&self, key: &str) -> Result<&str> {
+        self.get(key).ok_or_else(|| _missing(key))
+    }
+
+    fn invalid(&self, key: &str, message: impl Display) -> Error {
+        let (value, source) = match self.values.get(key) {
+            Some((value, source)) => (value.as_str(), source.as_str()),
+            None => ("", "defaults"),
+        };
+        if key.ends_with("secret") || key.ends_with("password") {
+            _config_error(format!("invalid value for {} (from {}): {}", key, source, message))
+        } else {
+            _config_error(format!("invalid value {:?} for {} (from {}): {}", value, key, source, message))
+        }
+    }
+
+    fn parse<T: FromStr>(&self, key: &str, default: T) -> Result<T> where T::Err: Display {
+        match self.get(key) {
+            Some(value) => value.trim().parse().map_err(|err| self.invalid(key, err)),
+            None => Ok(default),
+        }
+    }
+
+    fn positive<T: FromStr + PartialOrd + Default>(&self, key: &str, default: T) -> Result<T>
+            where T::Err: Display {
+        let value = self.parse(key, default)?;
+        if value <= T::default() {
+            return Err(self.invalid(key, "must be positive"));
+        }
+        Ok(value)
+    }
+
+    fn socket_addr(&self, bind_key: &str, port_key: &str, default_port: u16) -> Result<SocketAddr> {
+        let ip = self.parse(bind_key, IpAddr::from([127, 0, 0, 1]))?;
+        let port = self.parse(port_key, default_port)?;
+        Ok(SocketAddr::new(ip, port))
+    }
+
+    fn build(&self) -> Result<Settings> {
+        let network = Network::from_name(self.get("network").unwrap_or("mainnet").trim())
+            .map_err(|_| self.invalid("network", "expected mainnet, testnet, testnet4 or regtest"))?;
+        let slpdex = self.build_slpdex(network)?;
+        let network = &slpdex.network;
+        let database = DatabaseSettings {
+            url: self.require("database.url")?.to_string(),
+            pool_size: self.positive("database.pool_size", 16)?,
+        };
+        let secret = match self.get("endpoint.secret") {
+            Some(secret) => {
+                let secret = hex::decode(secret.trim()).map_err(|err| self.invalid("endpoint.secret", err))?;
+                if secret.len() != 32 {
+                    return Err(self.invalid("endpoint.secret", "expected a 32 byte hex private key"));
+                }
+                Some(secret)
+            },
+            None => None,
+        };
+        let seed_peers = match self.get("endpoint.seed_peers") {
+            Some(peers) => peers.split(',')
+                .map(str::trim)
+                .filter(|peer| peer.len() > 0)
+                .map(|peer| {
+                    SocketAddr::from_str(peer)
+                        .or_else(|_| IpAddr::from_str(peer).map(|ip| SocketAddr::new(ip, network.default_port)))
+                        .map_err(|err| self.invalid("endpoint.seed_peers", err))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => match network.kind {
+                NetworkKind::Mainnet => vec![SocketAddr::from(([137, 74, 30, 99], 8333))],
+                NetworkKind::Regtest => vec![SocketAddr::from(([127, 0, 0, 1], network.default_port))],
+                _ => vec![],
+            },
+        };
+        let ban_duration_secs = self.parse("endpoint.ban_duration_secs", 24 * 3600)?;
+        if ban_duration_secs < 0 {
+            return Err(self.invalid("endpoint.ban_duration_secs", "must not be negative"));
+        }
+        let endpoint = EndpointSettings {
+            bind: self.socket_addr("endpoint.bind", "endpoint.port", 7501)?,
+            secret,
+            read_workers: self.positive("endpoint.read_workers", 4)?,
+            seed_peers,
+            target_outbound: self.parse("endpoint.target_outbound", 8)?,
+            ban_threshold: self.positive("endpoint.ban_threshold", 100)?,
+            ban_duration_secs,
+            chain_source: self.build_chain_source(network)?,
+        };
+        if (database.pool_size as usize) < MIN_WRITER_CONNECTIONS + endpoint.read_workers {
+            return Err(self.invalid("database.pool_size", format!(
+                "must be at least {} + endpoint.read_workers ({})",
+                MIN_WRITER_CONNECTIONS, MIN_WRITER_CONNECTIONS + endpoint.read_workers,
+            )));
+        }
+        let frontend = FrontendSettings {
+            bind: self.socket_addr("frontend.bind", "frontend.port", 8080)?,
+            static_dir: self.get("frontend.static_dir").unwrap_or("./static/").to_string(),
+        };
+        Ok(Settings { slpdex, database, endpoint, frontend })
+    }
+
+    fn build_slpdex(&self, network: Network) -> Result<SLPDEXConfig> {
+        let mut config = SLPDEXConfig::for_network(network);
+        if let Some(fee_address) = self.get("slpdex.fee_address") {
+            let fee_address = fee_address.trim();
+            let prefix = config.network.cash_addr_prefix;
+            let fee_address = if fee_address.contains(':') {
+                fee_address.to_string()
+            } else {
+                format!("{}:{}", prefix, fee_address)
+            };
+            if !fee_address.starts_with(&format!("{}:", prefix)) {
+                return Err(self.invalid("slpdex.fee_address", format!("expected a {} address", prefix)));
+            }
+            config.fee_address = Address::from_cash_addr(fee_address)
+                .map_err(|err| self.invalid("slpdex.fee_address", format!("{:?}", err)))?;
+        }
+        config.fee_divisor = self.positive("slpdex.fee_divisor", config.fee_divisor)?;
+        config.dust_limit = self.parse("slpdex.dust_limit", config.dust_limit)?;
+        if let Some(lokad) = self.get("slpdex.exch_lokad") {
+            if lokad.len() != 4 {
+                return Err(self.invalid("slpdex.exch_lokad", "must be 4 bytes long"));
+            }
+            config.exch_lokad = lokad.to_string();
+            config.exch_lokad_b64 = base64::encode(lokad);
+        }
+        config.exch_version = self.parse("slpdex.exch_version", config.exch_version)?;
+        if config.exch_version < 1 || config.exch_version > 16 {
+            // pushed as OP_1..OP_16
+            return Err(self.invalid("slpdex.exch_version", "must be between 1 and 16"));
+        }
+        if let Some(token_id) = self.get("slpdex.panda_token_id") {
+            config.panda_token_hash = tx_hex_to_hash(token_id.trim())
+                .ok_or_else(|| self.invalid("slpdex.panda_token_id", "expected a 32 byte hex token id"))?;
+        }
+        config.panda_fee = self.parse("slpdex.panda_fee", config.panda_fee)?;
+        if config.panda_fee < config.dust_limit {
+            return Err(self.invalid("slpdex.panda_fee", "must be at least slpdex.dust_limit"));
+        }
+        Ok(config)
+    }
+
+    fn build_chain_source(&self, network: &Network) -> Result<ChainSourceSettings> {
+        let optional = |key: &str| self.get(key).map(str::to_string);
+        match self.get("chain_source.kind").unwrap_or("slpdb").trim() {
+            "slpdb" => Ok(ChainSourceSettings::Slpdb {
+                bitdb_url: optional("chain_source.bitdb_url"),
+                slpdb_url: optional("chain_source.slpdb_url"),
+            }),
+            "fixture" => {
+                let path = self.require("chain_source.fixture")?.to_string();
+                if !std::path::Path::new(&path).is_file() {
+                    return Err(self.invalid("chain_source.fixture", "no such file"));
+                }
+                Ok(ChainSourceSettings::Fixture { path })
+            },
+            "rpc" => Ok(ChainSourceSettings::Rpc {
+                // bitcoind's rpc port is one below its p2p port on every network
+                url: optional("chain_source.rpc_url")
+                    .unwrap_or_else(|| format!("http://127.0.0.1:{}", network.default_port - 1)),
+                user: optional("chain_source.rpc_user"),
+                password: optional("chain_source.rpc_password"),
+            }),
+            _ => Err(self.invalid("chain_source.kind", "expected slpdb, fixture or rpc")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(file: &str, env: &[(&str, &str)], args: &[&str]) -> Result<(Settings, Vec<String>)> {
+        let env = env.iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        let file = file.to_string();
+        Settings::from_sources(
+            args.iter().map(|arg| arg.to_string()),
+            |var| env.get(var).cloned(),
+            |_| Ok(file.clone()),
+        )
+    }
+
+    #[test]
+    fn layers_file_env_and_flags() {
+        let file = r#"
+            network = "testnet"
+
+            [database]
+            url = "postgres://file"
+            pool_size = 8
+
+            [endpoint]
+            port = 1
+            seed_peers = ["10.0.0.1", "10.0.0.2:1234"]
+
+            [slpdex]
+            fee_divisor = 1000
+        "#;
+        let env = [
+            ("SLPDEX_CONFIG", "staging.toml"),
+            ("DATABASE_URL", "postgres://env"),
+            ("PORT", "2"),
+            ("BAN_THRESHOLD", "50"),
+        ];
+        let (settings, positional) = load(file, &env, &["repair-utxos", "--endpoint.port", "3"]).unwrap();
+        assert_eq!(positional, vec!["repair-utxos".to_string()]);
+        assert_eq!(settings.slpdex.network.kind, NetworkKind::Testnet);
+        assert_eq!(settings.database.url, "postgres://env");
+        assert_eq!(settings.database.pool_size, 8);
+        assert_eq!(settings.endpoint.bind, SocketAddr::from(([127, 0, 0, 1], 3)));
+        assert_eq!(settings.endpoint.seed_peers, vec![
+            SocketAddr::from(([10, 0, 0, 1], 18333)),
+            SocketAddr::from(([10, 0, 0, 2], 1234)),
+        ]);
+        assert_eq!(settings.endpoint.ban_threshold, 50);
+        assert_eq!(settings.slpdex.fee_divisor, 1000);
+        assert!(settings.endpoint.secret().is_err());
+        assert!(settings.slpdex.fee_address.cash_addr().starts_with("bchtest:"));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let env = [("DATABASE_URL", "postgres://env")];
+        assert!(load("", &env, &[]).is_ok());
+        assert!(load("", &[], &[]).unwrap_err().to_string().contains("database.url"));
+        assert!(load("", &env, &["--endpoint.prot=1"]).unwrap_err().to_string().contains("endpoint.prot"));
+        assert!(load("", &env, &["--port"]).is_err());
+        let err = load("", &env, &["--endpoint.port=http"]).unwrap_err().to_string();
+        assert!(err.contains("endpoint.port") && err.contains("flag --endpoint.port"));
+        let err = load("", &env, &["--endpoint.secret=abcd"]).unwrap_err().to_string();
+        assert!(err.contains("endpoint.secret") && !err.contains("abcd"));
+        assert!(load("", &env, &["--slpdex.exch_version=17"]).is_err());
+        assert!(load("", &env, &["--network=simnet"]).is_err());
+        assert!(load("", &env, &["--endpoint.ban_threshold=0"]).is_err());
+        let err = load("", &env, &["--database.pool_size=7"]).unwrap_err().to_string();
+        assert!(err.contains("database.pool_size"));
+        assert!(load("", &env, &["--database.pool_size=7", "--endpoint.read_workers=3"]).is_ok());
+        let err = load("", &env, &["--chain_source.kind=fixture", "--chain_source.fixture=missing.json"])
+            .unwrap_err().to_string();
+        assert!(err.contains("chain_source.fixture") && err.contains("missing.json"));
+        let fixture = concat!("--chain_source.fixture=", env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        assert!(load("", &env, &["--chain_source.kind=fixture", fixture]).is_ok());
+        let file = "[database]\nurl = \"postgres://file\"\nuser = \"dex\"\n";
+        let err = load(file, &[("SLPDEX_CONFIG", "prod.toml")], &[]).unwrap_err().to_string();
+        assert!(err.contains("database.user") && err.contains("prod.toml"));
+    }
+}
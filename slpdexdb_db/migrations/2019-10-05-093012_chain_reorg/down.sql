@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE panda_birth;
+
+DROP INDEX tx_height_idx;
+DROP INDEX blocks_main_chain_height_idx;
+DROP INDEX blocks_prev_block_idx;
+
+ALTER TABLE blocks
+    DROP COLUMN "chain_work",
+    DROP COLUMN "is_main_chain";
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+
+ALTER TABLE blocks
+    ADD COLUMN "chain_work" NUMERIC(80),
+    ADD COLUMN "is_main_chain" BOOL NOT NULL DEFAULT true;
+
+UPDATE blocks SET "chain_work" = cumulative."chain_work"
+FROM (
+    SELECT
+        "hash",
+        SUM(
+            DIV(POWER(2::NUMERIC, 256),
+                ("bits" & 8388607) * POWER(2::NUMERIC, 8 * (("bits" >> 24) - 3)) + 1)
+        ) OVER (ORDER BY "height") AS "chain_work"
+    FROM blocks
+) AS cumulative
+WHERE blocks."hash" = cumulative."hash";
+
+ALTER TABLE blocks
+    ALTER COLUMN "chain_work" SET NOT NULL;
+
+CREATE INDEX blocks_prev_block_idx ON blocks ("prev_block");
+CREATE INDEX blocks_main_chain_height_idx ON blocks ("height") WHERE "is_main_chain";
+CREATE INDEX tx_height_idx ON tx ("height");
+
+CREATE TABLE panda_birth (
+    "pnd1_tx"    BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    "panda"      BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    "block_hash" BYTEA NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE peer (
+    "ip"           TEXT NOT NULL,
+    "port"         INT NOT NULL,
+    "last_seen"    BIGINT NOT NULL,
+    "last_attempt" BIGINT,
+    "n_failures"   INT NOT NULL DEFAULT 0,
+    PRIMARY KEY ("ip", "port")
+);
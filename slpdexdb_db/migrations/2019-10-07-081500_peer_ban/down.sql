@@ -0,0 +1,2 @@
+ALTER TABLE peer
+    DROP COLUMN "banned_until";
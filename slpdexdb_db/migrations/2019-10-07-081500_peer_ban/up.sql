@@ -0,0 +1,2 @@
+ALTER TABLE peer
+    ADD COLUMN "banned_until" BIGINT;
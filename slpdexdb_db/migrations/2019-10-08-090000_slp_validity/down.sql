@@ -0,0 +1 @@
+DROP TABLE slp_validity;
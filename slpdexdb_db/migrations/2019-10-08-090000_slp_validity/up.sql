@@ -0,0 +1,5 @@
+CREATE TABLE slp_validity (
+    "tx_hash"    BYTEA PRIMARY KEY,
+    "is_valid"   BOOL NOT NULL,
+    "slp_script" BYTEA NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE token_burn;
+DROP TABLE token_supply;
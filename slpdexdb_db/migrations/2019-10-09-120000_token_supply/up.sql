@@ -0,0 +1,19 @@
+CREATE TABLE token_supply (
+    "token"          INT PRIMARY KEY REFERENCES token ("id") ON DELETE CASCADE,
+    "mint_baton_tx"  BYTEA,
+    "mint_baton_idx" INT,
+    "minted_supply"  NUMERIC(52, 26) NOT NULL,
+    "burned_supply"  NUMERIC(52, 26) NOT NULL
+);
+
+CREATE INDEX token_supply_mint_baton ON token_supply ("mint_baton_tx", "mint_baton_idx");
+
+CREATE TABLE token_burn (
+    "tx"     BIGINT NOT NULL REFERENCES tx ("id") ON DELETE CASCADE,
+    "token"  INT NOT NULL REFERENCES token ("id") ON DELETE CASCADE,
+    "amount" NUMERIC(52, 26) NOT NULL,
+    PRIMARY KEY ("tx", "token")
+);
+
+INSERT INTO token_supply ("token", "minted_supply", "burned_supply")
+SELECT "id", 0, 0 FROM token;
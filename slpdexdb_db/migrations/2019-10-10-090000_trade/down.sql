@@ -0,0 +1,2 @@
+DROP TABLE trade;
+DROP TABLE trade_offer_spend;
@@ -0,0 +1,18 @@
+CREATE TABLE trade_offer_spend (
+    "trade_offer" INT PRIMARY KEY REFERENCES trade_offer ("id") ON DELETE CASCADE,
+    "tx"          BIGINT NOT NULL REFERENCES tx ("id") ON DELETE CASCADE,
+    "is_filled"   BOOL NOT NULL
+);
+
+CREATE TABLE trade (
+    "trade_offer"       INT PRIMARY KEY REFERENCES trade_offer ("id") ON DELETE CASCADE,
+    "tx"                BIGINT NOT NULL REFERENCES tx ("id") ON DELETE CASCADE,
+    "token"             INT NOT NULL REFERENCES token ("id") ON DELETE CASCADE,
+    "price_per_token"   NUMERIC(52, 26) NOT NULL,
+    "amount_token_base" NUMERIC(26) NOT NULL,
+    "value_satoshis"    BIGINT NOT NULL,
+    "buyer_address"     BYTEA,
+    "timestamp"         BIGINT NOT NULL
+);
+
+CREATE INDEX trade_token_timestamp ON trade ("token", "timestamp");
@@ -0,0 +1,8 @@
+CREATE TABLE token_stats (
+    "token"               INT PRIMARY KEY REFERENCES token ("id") ON DELETE CASCADE,
+    "holder_count"        INT NOT NULL,
+    "tx_count"            INT NOT NULL,
+    "utxo_count"          INT NOT NULL,
+    "last_active_height"  INT,
+    "last_updated_height" INT NOT NULL
+);
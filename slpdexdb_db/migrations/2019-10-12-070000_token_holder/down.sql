@@ -0,0 +1,3 @@
+DROP TABLE token_holder_count;
+DROP TABLE token_holder;
+DROP INDEX tx_output_address;
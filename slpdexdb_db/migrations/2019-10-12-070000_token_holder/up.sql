@@ -0,0 +1,29 @@
+CREATE TABLE token_holder (
+    "token"        INT NOT NULL REFERENCES token ("id") ON DELETE CASCADE,
+    "address"      BYTEA NOT NULL,
+    "balance_base" NUMERIC(26) NOT NULL,
+    "utxo_count"   INT NOT NULL,
+    PRIMARY KEY ("token", "address")
+);
+
+CREATE INDEX token_holder_balance ON token_holder ("token", "balance_base" DESC);
+CREATE INDEX token_holder_address ON token_holder ("address");
+CREATE INDEX tx_output_address ON tx_output ("address");
+
+CREATE TABLE token_holder_count (
+    "token"        INT NOT NULL REFERENCES token ("id") ON DELETE CASCADE,
+    "height"       INT NOT NULL,
+    "holder_count" INT NOT NULL,
+    PRIMARY KEY ("token", "height")
+);
+
+INSERT INTO token_holder ("token", "address", "balance_base", "utxo_count")
+SELECT slp_tx.token, tx_output.address, SUM(tx_output.value_token_base), COUNT(*)
+FROM tx_output
+INNER JOIN tx ON tx.id = tx_output.tx
+INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+WHERE tx_output.address IS NOT NULL
+AND tx_output.value_token_base > 0
+AND tx_input.tx IS NULL
+GROUP BY slp_tx.token, tx_output.address;
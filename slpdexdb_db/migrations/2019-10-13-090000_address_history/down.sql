@@ -0,0 +1,2 @@
+DROP INDEX tx_timestamp_hash;
+DROP INDEX tx_input_address;
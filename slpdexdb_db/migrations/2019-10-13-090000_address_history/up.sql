@@ -0,0 +1,2 @@
+CREATE INDEX tx_input_address ON tx_input ("address");
+CREATE INDEX tx_timestamp_hash ON tx ("timestamp" DESC, "hash" DESC);
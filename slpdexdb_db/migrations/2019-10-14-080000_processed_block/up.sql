@@ -0,0 +1,7 @@
+CREATE TABLE processed_block (
+    "hash"         BYTEA PRIMARY KEY,
+    "height"       INT NOT NULL,
+    "processed_at" BIGINT NOT NULL
+);
+
+CREATE INDEX processed_block_height ON processed_block ("height");
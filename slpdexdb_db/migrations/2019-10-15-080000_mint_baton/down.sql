@@ -0,0 +1,2 @@
+ALTER TABLE token_supply DROP COLUMN "is_synced";
+DROP TABLE mint_baton;
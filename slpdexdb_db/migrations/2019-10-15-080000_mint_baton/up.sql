@@ -0,0 +1,21 @@
+CREATE TABLE mint_baton (
+    "id"      SERIAL PRIMARY KEY,
+    "token"   INT NOT NULL REFERENCES token ("id") ON DELETE CASCADE,
+    "tx_hash" BYTEA NOT NULL,
+    "idx"     INT NOT NULL,
+    UNIQUE ("tx_hash", "idx")
+);
+
+CREATE INDEX mint_baton_token ON mint_baton ("token");
+
+INSERT INTO mint_baton ("token", "tx_hash", "idx")
+SELECT "token", "mint_baton_tx", "mint_baton_idx"
+FROM token_supply
+WHERE "mint_baton_tx" IS NOT NULL AND "mint_baton_idx" IS NOT NULL;
+
+-- tokens which existed before token_supply got all-zero supplies and no baton;
+-- these are refreshed from the chain source on the next resync
+ALTER TABLE token_supply ADD COLUMN "is_synced" BOOLEAN NOT NULL DEFAULT TRUE;
+
+UPDATE token_supply SET "is_synced" = FALSE
+WHERE "mint_baton_tx" IS NULL AND "minted_supply" = 0 AND "burned_supply" = 0;
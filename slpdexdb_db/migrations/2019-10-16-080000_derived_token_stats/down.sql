@@ -0,0 +1 @@
+DROP INDEX slp_tx_token;
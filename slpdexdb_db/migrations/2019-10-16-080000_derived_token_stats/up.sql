@@ -0,0 +1,5 @@
+CREATE INDEX slp_tx_token ON slp_tx ("token");
+
+-- stats used to be copied from slpdb, they're recomputed from our own tables on the next resync
+DELETE FROM token_stats;
+DELETE FROM update_history WHERE subject_type = 5;
@@ -0,0 +1 @@
+-- nothing to undo, seeded rows are indistinguishable from processed blocks
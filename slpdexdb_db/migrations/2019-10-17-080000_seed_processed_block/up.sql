@@ -0,0 +1,8 @@
+-- the backfill only looks above the lowest processed block, start it at the current tip
+INSERT INTO processed_block ("hash", "height", "processed_at")
+SELECT "hash", "height", EXTRACT(EPOCH FROM NOW())::BIGINT
+FROM blocks
+WHERE is_main_chain
+ORDER BY "height" DESC
+LIMIT 1
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,752 @@
+use crate::endpoint::Endpoint;
+use crate::tx_source::{TxSource, TxFilter, SortKey, Confirmedness, tx_result};
+use crate::token_source::{TokenSource, token_result};
+use crate::tx_history::{TxHistory, TokenType};
+use crate::slp_validator::{SlpTxProvider, SlpValidator, SlpValidity, SlpMessage, SlpMessageKind};
+use slpdexdb_base::{SLPDEXConfig, Network, SLPAmount, Result, ErrorKind};
+use cashcontracts::{Address, Tx, tx_hash_to_hex, tx_hex_to_hash};
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{self, Read};
+use std::path::Path;
+use std::collections::HashSet;
+
+pub trait ChainSource {
+    fn request_txs(&self, filters: &[TxFilter], config: &SLPDEXConfig, confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxEntry>>;
+
+    fn request_slp_tx_validity(&self, filters: &[TxFilter], config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>>;
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>>;
+
+    // the subset of the given outputs which are spent by a (possibly unconfirmed) tx
+    fn request_spent_outputs(&self, outputs: &[([u8; 32], i32)]) -> Result<Vec<([u8; 32], i32)>>;
+
+    // raw txs for validating SLP ancestors which aren't in the db yet
+    fn slp_tx_provider(&self) -> Option<&dyn SlpTxProvider> {
+        None
+    }
+
+    // whether request_tokens reports minted, burned and circulating supply
+    fn knows_token_supplies(&self) -> bool {
+        true
+    }
+}
+
+fn _spent_outputs<'a>(inputs: impl Iterator<Item=&'a tx_result::TxInput>,
+                      outputs: &[([u8; 32], i32)]) -> Vec<([u8; 32], i32)> {
+    inputs
+        .filter_map(|input| Some((tx_hex_to_hash(&input.e.h)?, input.e.i)))
+        .filter(|outpoint| outputs.contains(outpoint))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn _is_sorted(filters: &[TxFilter]) -> bool {
+    filters.iter().any(|filter| filter == &TxFilter::SortBy(SortKey::TxHash))
+}
+
+pub struct SlpdbChainSource {
+    tx_source: TxSource,
+    token_source: TokenSource,
+}
+
+impl SlpdbChainSource {
+    pub fn new(endpoint: Endpoint) -> Self {
+        SlpdbChainSource {
+            tx_source: TxSource::with_endpoint(endpoint.clone()),
+            token_source: TokenSource::with_endpoint(endpoint),
+        }
+    }
+}
+
+impl ChainSource for SlpdbChainSource {
+    fn request_txs(&self, filters: &[TxFilter], config: &SLPDEXConfig, confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxEntry>> {
+        Ok(self.tx_source.request_txs(filters, config, confirmedness)?)
+    }
+
+    fn request_slp_tx_validity(&self, filters: &[TxFilter], config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>> {
+        Ok(self.tx_source.request_slp_tx_validity(filters, config, confirmedness)?)
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        Ok(self.token_source.request_tokens(filters)?)
+    }
+
+    fn request_spent_outputs(&self, outputs: &[([u8; 32], i32)]) -> Result<Vec<([u8; 32], i32)>> {
+        if outputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let txs = self.tx_source.request_spending_txs(outputs, Confirmedness::Both)?;
+        Ok(_spent_outputs(txs.iter().flat_map(|tx| tx.inputs.iter()), outputs))
+    }
+}
+
+// recorded slpdb/bitdb results; "c" and "u" hold confirmed and unconfirmed txs, "t" tokens
+#[derive(Deserialize, Default)]
+struct Fixture {
+    #[serde(default)]
+    c: Vec<tx_result::TxEntry>,
+    #[serde(default)]
+    u: Vec<tx_result::TxEntry>,
+    #[serde(default)]
+    t: Vec<token_result::TokenEntry>,
+}
+
+pub struct FixtureChainSource {
+    fixture: Fixture,
+}
+
+impl FixtureChainSource {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(FixtureChainSource { fixture: serde_json::from_str(json)? })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    fn _address_bytes(address: &str, network: &Network) -> Option<Vec<u8>> {
+        let address = if address.contains(":") {
+            address.to_string()
+        } else {
+            format!("{}:{}", network.cash_addr_prefix, address)
+        };
+        Address::from_cash_addr(address).ok().map(|address| address.bytes().to_vec())
+    }
+
+    fn _matches_tx(entry: &tx_result::TxEntry, filters: &[TxFilter], config: &SLPDEXConfig) -> bool {
+        use tx_result::StackItem;
+        let is_exch = filters.iter().any(|filter| filter == &TxFilter::Exch);
+        let slp_only = filters.iter().any(|filter| match filter {
+            TxFilter::TokenId(_) | TxFilter::Exch => true,
+            _ => false,
+        });
+        if slp_only && !entry.slp.as_ref().map(|slp| slp.valid).unwrap_or(false) {
+            return false;
+        }
+        let addresses = filters.iter()
+            .filter_map(|filter| match filter {
+                TxFilter::Address(address) => Some(address.bytes().to_vec()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if addresses.len() > 0 {
+            let entry_addresses = if is_exch {
+                entry.inputs.iter()
+                    .filter_map(|input| base64::decode(input.b4.get_str()?).ok())
+                    .collect::<Vec<_>>()
+            } else {
+                entry.inputs.iter().filter_map(|input| input.e.a.as_ref())
+                    .chain(entry.outputs.iter().filter_map(|output| output.e.a.as_ref()))
+                    .filter_map(|address| Self::_address_bytes(address, &config.network))
+                    .collect::<Vec<_>>()
+            };
+            if !entry_addresses.iter().any(|address| addresses.contains(address)) {
+                return false;
+            }
+        }
+        let output_addresses = filters.iter()
+            .filter_map(|filter| match filter {
+                TxFilter::OutputAddress(address) => Some(address.bytes().to_vec()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if output_addresses.len() > 0 {
+            let is_paid = entry.outputs.iter()
+                .filter_map(|output| Self::_address_bytes(output.e.a.as_ref()?, &config.network))
+                .any(|address| output_addresses.contains(&address));
+            if !is_paid {
+                return false;
+            }
+        }
+        let tx_hashes = filters.iter()
+            .filter_map(|filter| match filter {
+                TxFilter::TxHash(tx_hash) => Some(tx_hash_to_hex(tx_hash)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if tx_hashes.len() > 0 && !tx_hashes.contains(&entry.tx.h) {
+            return false;
+        }
+        filters.iter().all(|filter| match filter {
+            TxFilter::TokenId(token_hash) => entry.slp.as_ref()
+                .map(|slp| slp.detail.token_id == tx_hash_to_hex(token_hash))
+                .unwrap_or(false),
+            TxFilter::MinBlockHeight(height) => entry.blk.as_ref()
+                .map(|blk| blk.i >= *height)
+                .unwrap_or(true),
+            TxFilter::MinTxHash(tx_hash) => entry.tx.h > tx_hash_to_hex(tx_hash),
+            TxFilter::Exch => entry.inputs.iter().any(|input| {
+                input.b0 == StackItem::Str(config.exch_lokad_b64.clone()) &&
+                    input.b1 == (StackItem::Op {op: 0x50 + config.exch_version})
+            }),
+            _ => true,
+        })
+    }
+
+    fn _matches_token(entry: &token_result::TokenEntry, filters: &[TxFilter]) -> bool {
+        filters.iter().all(|filter| match filter {
+            TxFilter::TokenId(token_hash) =>
+                entry.token_details.token_id_hex == tx_hash_to_hex(token_hash),
+            TxFilter::MinTxHash(token_hash) =>
+                entry.token_details.token_id_hex > tx_hash_to_hex(token_hash),
+            TxFilter::MinBlockHeight(height) => entry.token_stats.block_created
+                .map(|block_created| block_created >= *height)
+                .unwrap_or(false),
+            _ => true,
+        })
+    }
+}
+
+impl ChainSource for FixtureChainSource {
+    fn request_txs(&self, filters: &[TxFilter], config: &SLPDEXConfig, confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxEntry>> {
+        let (confirmed, unconfirmed) = match confirmedness {
+            Confirmedness::Confirmed   => (true, false),
+            Confirmedness::Unconfirmed => (false, true),
+            Confirmedness::Both        => (true, true),
+        };
+        let mut entries = self.fixture.c.iter().filter(|_| confirmed)
+            .chain(self.fixture.u.iter().filter(|_| unconfirmed))
+            .filter(|entry| Self::_matches_tx(entry, filters, config))
+            .cloned()
+            .collect::<Vec<_>>();
+        if _is_sorted(filters) {
+            entries.sort_by(|a, b| a.tx.h.cmp(&b.tx.h));
+        }
+        Ok(entries)
+    }
+
+    fn request_slp_tx_validity(&self, filters: &[TxFilter], config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>> {
+        Ok(self.request_txs(filters, config, confirmedness)?
+            .into_iter()
+            .filter_map(|entry| Some(tx_result::TxSLPValidity { tx: entry.tx, slp: entry.slp? }))
+            .collect())
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        let mut entries = self.fixture.t.iter()
+            .filter(|entry| Self::_matches_token(entry, filters))
+            .cloned()
+            .collect::<Vec<_>>();
+        if _is_sorted(filters) {
+            entries.sort_by(|a, b| a.token_details.token_id_hex.cmp(&b.token_details.token_id_hex));
+        }
+        Ok(entries)
+    }
+
+    fn request_spent_outputs(&self, outputs: &[([u8; 32], i32)]) -> Result<Vec<([u8; 32], i32)>> {
+        let inputs = self.fixture.c.iter()
+            .chain(self.fixture.u.iter())
+            .flat_map(|entry| entry.inputs.iter());
+        Ok(_spent_outputs(inputs, outputs))
+    }
+}
+
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcTx {
+    hex: String,
+    blockhash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RpcBlockHeader {
+    height: i32,
+    time: u64,
+}
+
+// bitcoind-style JSON-RPC; only lookups by tx hash or token id are supported
+pub struct RpcChainSource {
+    url: String,
+    user: Option<String>,
+    password: Option<String>,
+    client: reqwest::Client,
+}
+
+impl RpcChainSource {
+    pub fn new(url: String, user: Option<String>, password: Option<String>) -> Self {
+        RpcChainSource { url, user, password, client: reqwest::Client::new() }
+    }
+
+    fn _call(&self, method: &str, params: Value) -> Result<Option<Value>> {
+        let mut request = self.client
+            .post(&self.url)
+            .json(&json!({"jsonrpc": "1.0", "id": "slpdexdb", "method": method, "params": params}));
+        if let Some(user) = &self.user {
+            request = request.basic_auth(user, self.password.clone());
+        }
+        let response: RpcResponse = request.send()?.json()?;
+        match response.error {
+            Some(ref error) if error.code == RPC_INVALID_ADDRESS_OR_KEY => Ok(None),
+            Some(error) => Err(ErrorKind::ChainSource(
+                format!("{} failed: {} ({})", method, error.message, error.code)
+            ).into()),
+            None => Ok(response.result),
+        }
+    }
+
+    fn _tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        let tx_hex = match self._call("getrawtransaction", json!([tx_hash_to_hex(tx_hash), false]))? {
+            Some(Value::String(tx_hex)) => tx_hex,
+            _ => return Ok(None),
+        };
+        Ok(Some(Tx::read_from_stream(&mut io::Cursor::new(hex::decode(&tx_hex)?))?))
+    }
+
+    fn _tx_with_block(&self, tx_hash: &[u8; 32]) -> Result<Option<(Tx, Option<tx_result::Blk>)>> {
+        let rpc_tx: RpcTx = match self._call("getrawtransaction", json!([tx_hash_to_hex(tx_hash), true]))? {
+            Some(result) => serde_json::from_value(result)?,
+            None => return Ok(None),
+        };
+        let tx = Tx::read_from_stream(&mut io::Cursor::new(hex::decode(&rpc_tx.hex)?))?;
+        let blk = match rpc_tx.blockhash {
+            Some(block_hash) => self._call("getblockheader", json!([block_hash]))?
+                .map(serde_json::from_value::<RpcBlockHeader>)
+                .transpose()?
+                .map(|header| tx_result::Blk { t: header.time, i: header.height }),
+            None => None,
+        };
+        Ok(Some((tx, blk)))
+    }
+
+    fn _slp_message(tx: &Tx) -> Option<SlpMessage> {
+        let script = tx.outputs().get(0)?.script().to_vec();
+        SlpMessage::parse(&script).ok()?
+    }
+
+    fn _stack_items(script: &[u8]) -> Vec<tx_result::StackItem> {
+        use tx_result::StackItem;
+        let mut items = Vec::new();
+        let mut cursor = io::Cursor::new(script);
+        while let Ok(opcode) = cursor.read_u8() {
+            let len = match opcode {
+                0x01..=0x4b => Some(opcode as usize),
+                0x4c => cursor.read_u8().ok().map(|len| len as usize),
+                0x4d => cursor.read_u16::<LittleEndian>().ok().map(|len| len as usize),
+                0x4e => cursor.read_u32::<LittleEndian>().ok().map(|len| len as usize),
+                _ => {
+                    items.push(StackItem::Op {op: opcode as i32});
+                    continue;
+                },
+            };
+            let remaining = script.len() - cursor.position() as usize;
+            let len = match len {
+                Some(len) if len <= remaining => len,
+                _ => break,
+            };
+            let mut data = vec![0; len];
+            cursor.read_exact(&mut data).unwrap();
+            items.push(StackItem::Str(base64::encode(&data)));
+        }
+        items
+    }
+
+    fn _tx_slp(&self, tx: &Tx, output_addresses: &[Option<String>]) -> Result<Option<tx_result::TxSLP>> {
+        let message = match Self::_slp_message(tx) {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let token_hash = message.token_hash(&tx.hash());
+        let decimals = match &message.kind {
+            SlpMessageKind::Genesis {decimals, ..} => *decimals,
+            _ => match self._tx(&token_hash)?.as_ref().and_then(Self::_slp_message) {
+                Some(SlpMessage {kind: SlpMessageKind::Genesis {decimals, ..}, ..}) => decimals,
+                _ => return Ok(None),
+            },
+        };
+        let mut validator = SlpValidator::new(None, Some(self as &dyn SlpTxProvider));
+        validator.add_txs(&[tx.clone()]);
+        let valid = validator.validate(&tx.hash())? == SlpValidity::Valid;
+        let outputs = (1..tx.outputs().len())
+            .map(|vout| tx_result::TxSLPOutput {
+                address: output_addresses[vout].clone().unwrap_or_default(),
+                amount: SLPAmount::new(message.amount(vout as u32) as i128, decimals as u32).to_string(),
+            })
+            .collect();
+        Ok(Some(tx_result::TxSLP {
+            valid,
+            detail: tx_result::TxSLPDetail {
+                decimals: decimals as i32,
+                token_id: tx_hash_to_hex(&token_hash),
+                transaction_type: String::from_utf8_lossy(message.slp_type().to_bytes()).to_string(),
+                version_type: message.token_type as i32,
+                outputs,
+            },
+        }))
+    }
+
+    fn _tx_entry(&self, tx: &Tx, blk: Option<tx_result::Blk>) -> Result<tx_result::TxEntry> {
+        let inputs = tx.inputs().iter()
+            .map(|input| {
+                let mut stack = Self::_stack_items(&input.script.to_vec()).into_iter();
+                let mut next = || stack.next().unwrap_or_default();
+                tx_result::TxInput {
+                    e: tx_result::TxInputEdge {
+                        a: TxHistory::_process_input_script(&input.script).address()
+                            .map(|address| address.cash_addr().to_string()),
+                        h: tx_hash_to_hex(&input.outpoint.tx_hash),
+                        i: input.outpoint.vout as i32,
+                    },
+                    b0: next(), b1: next(), b2: next(), b3: next(),
+                    b4: next(), b5: next(), b6: next(), b7: next(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let outputs = tx.outputs().iter()
+            .map(|output| {
+                tx_result::TxOutput {
+                    e: tx_result::TxOutputEdge {
+                        v: output.value,
+                        a: TxHistory::_process_output_script(&output.script).address()
+                            .map(|address| address.cash_addr().to_string()),
+                    },
+                    b0: Self::_stack_items(&output.script.to_vec()).into_iter().next().unwrap_or_default(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let output_addresses = outputs.iter().map(|output| output.e.a.clone()).collect::<Vec<_>>();
+        Ok(tx_result::TxEntry {
+            blk,
+            tx: tx_result::Tx { h: tx_hash_to_hex(&tx.hash()) },
+            inputs,
+            outputs,
+            slp: self._tx_slp(tx, &output_addresses)?,
+        })
+    }
+
+    fn _tx_hashes(filters: &[TxFilter]) -> Result<Vec<[u8; 32]>> {
+        let mut tx_hashes = Vec::new();
+        for filter in filters {
+            match filter {
+                TxFilter::TxHash(tx_hash) => tx_hashes.push(*tx_hash),
+                TxFilter::SortBy(_) => {},
+                filter => return Err(ErrorKind::ChainSource(
+                    format!("{:?} not supported by RPC source", filter)
+                ).into()),
+            }
+        }
+        Ok(tx_hashes)
+    }
+
+    fn _token_entry(&self, token_hash: &[u8; 32]) -> Result<Option<token_result::TokenEntry>> {
+        let (tx, blk) = match self._tx_with_block(token_hash)? {
+            Some(tx_with_block) => tx_with_block,
+            None => return Ok(None),
+        };
+        let message = match Self::_slp_message(&tx) {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let (ticker, name, document_uri, document_hash, decimals, mint_baton_vout, initial_quantity) =
+            match message.kind {
+                SlpMessageKind::Genesis {ticker, name, document_uri, document_hash, decimals,
+                                         mint_baton_vout, initial_quantity} =>
+                    (ticker, name, document_uri, document_hash, decimals, mint_baton_vout, initial_quantity),
+                _ => return Ok(None),
+            };
+        let mint_baton_utxo = match mint_baton_vout {
+            Some(vout) => self._call("gettxout", json!([tx_hash_to_hex(token_hash), vout]))?
+                .filter(|txout| !txout.is_null())
+                .map(|_| format!("{}:{}", tx_hash_to_hex(token_hash), vout)),
+            None => None,
+        };
+        let nft_parent_id = match message.token_type {
+            TokenType::NFT1Child => match tx.inputs().get(0) {
+                Some(input) => self._tx(&input.outpoint.tx_hash)?
+                    .as_ref()
+                    .and_then(|group_tx| Some((group_tx.hash(), Self::_slp_message(group_tx)?)))
+                    .filter(|(_, group)| group.token_type == TokenType::NFT1Parent)
+                    .map(|(group_tx_hash, group)| tx_hash_to_hex(&group.token_hash(&group_tx_hash))),
+                None => None,
+            },
+            _ => None,
+        };
+        let initial_supply = SLPAmount::new(initial_quantity as i128, decimals as u32).to_string();
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+        Ok(Some(token_result::TokenEntry {
+            schema_version: 0,
+            last_updated_block: blk.as_ref().map(|blk| blk.i).unwrap_or(0),
+            mint_baton_utxo: mint_baton_utxo.clone().unwrap_or_default(),
+            token_stats: token_result::TokenStats {
+                block_created: blk.as_ref().map(|blk| blk.i),
+                block_last_active_send: None,
+                block_last_active_mint: None,
+                qty_valid_txns_since_genesis: None,
+                qty_valid_token_utxos: None,
+                qty_valid_token_addresses: None,
+                qty_token_minted: "0".to_string(),
+                qty_token_burned: "0".to_string(),
+                qty_token_circulating_supply: initial_supply.clone(),
+                qty_satoshis_locked_up: None,
+                minting_baton_status: match (mint_baton_vout, &mint_baton_utxo) {
+                    (None, _) => "NEVER_CREATED",
+                    (Some(_), Some(_)) => "ALIVE",
+                    (Some(_), None) => "UNKNOWN",
+                }.to_string(),
+            },
+            token_details: token_result::TokenDetails {
+                decimals: decimals as i32,
+                token_id_hex: tx_hash_to_hex(token_hash),
+                timestamp: None,
+                timestamp_unix: blk.as_ref().map(|blk| blk.t as i64),
+                transaction_type: "GENESIS".to_string(),
+                version_type: message.token_type as i16,
+                document_uri: text(&document_uri[..]),
+                document_sha256_hex: if document_hash.is_empty() {
+                    None
+                } else {
+                    Some(hex::encode(&document_hash))
+                },
+                symbol: text(&ticker[..]),
+                name: text(&name[..]),
+                baton_vout: mint_baton_vout.map(|vout| vout as i32),
+                contains_baton: mint_baton_vout.is_some(),
+                genesis_or_mint_quantity: initial_supply,
+            },
+            nft_parent_id,
+        }))
+    }
+}
+
+impl SlpTxProvider for RpcChainSource {
+    fn slp_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        self._tx(tx_hash)
+    }
+}
+
+impl ChainSource for RpcChainSource {
+    fn request_txs(&self, filters: &[TxFilter], _config: &SLPDEXConfig, confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxEntry>> {
+        let mut entries = Vec::new();
+        for tx_hash in Self::_tx_hashes(filters)? {
+            let (tx, blk) = match self._tx_with_block(&tx_hash)? {
+                Some(tx_with_block) => tx_with_block,
+                None => continue,
+            };
+            match (confirmedness, &blk) {
+                (Confirmedness::Confirmed, None) | (Confirmedness::Unconfirmed, Some(_)) => continue,
+                _ => {},
+            }
+            entries.push(self._tx_entry(&tx, blk)?);
+        }
+        if _is_sorted(filters) {
+            entries.sort_by(|a, b| a.tx.h.cmp(&b.tx.h));
+        }
+        Ok(entries)
+    }
+
+    fn request_slp_tx_validity(&self, filters: &[TxFilter], config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>> {
+        Ok(self.request_txs(filters, config, confirmedness)?
+            .into_iter()
+            .filter_map(|entry| Some(tx_result::TxSLPValidity { tx: entry.tx, slp: entry.slp? }))
+            .collect())
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        let mut entries = Vec::new();
+        for token_hash in Self::_tx_hashes(filters)? {
+            entries.extend(self._token_entry(&token_hash)?);
+        }
+        Ok(entries)
+    }
+
+    // gettxout (including the mempool) only knows unspent outputs, so an output it doesn't know
+    // is only spent if its tx exists
+    fn request_spent_outputs(&self, outputs: &[([u8; 32], i32)]) -> Result<Vec<([u8; 32], i32)>> {
+        let mut spent = Vec::new();
+        for &(tx_hash, vout) in outputs {
+            let txout = self._call("gettxout", json!([tx_hash_to_hex(&tx_hash), vout, true]))?
+                .filter(|txout| !txout.is_null());
+            if txout.is_some() {
+                continue;
+            }
+            let output_exists = self._tx(&tx_hash)?
+                .map(|tx| vout >= 0 && (vout as usize) < tx.outputs().len())
+                .unwrap_or(false);
+            if output_exists {
+                spent.push((tx_hash, vout));
+            }
+        }
+        Ok(spent)
+    }
+
+    fn slp_tx_provider(&self) -> Option<&dyn SlpTxProvider> {
+        Some(self)
+    }
+
+    // a node only knows the GENESIS, not what was minted and burned since
+    fn knows_token_supplies(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    // NFT1 child GENESIS of a CryptoPanda
+    const PANDA_GENESIS_TX: &str = "0100000002f7cf2ac976eb7ff1435cebe7f634f15d0e91e8afa227741106f72a4f2a963d92010000006a473044022014f382515b206c87313fa43b7a744a73adc62c6bf38983d6aa3f7c2b4e49821202200bc1c6d73e4462ac800daa39702098075e497fe4804991a48906e10c87b4354e4121031162a9a8f307b8e1efcafe3ce76b35ad293fad97ec885bcb8dbc6756d18ca941ffffffff1ca7052b7bf8e941aeeaaa3c10a783af0a31d1d4b5ba758f3694a2275b40faaf020000006a47304402206e38e36193f527d0679b49d56a84728fb473d981b229dd6d26fb4636c1b7d13e02203996d8c705d93f3f5b57c7d289d41ea17cb30c88931649271f07b295b13388ef4121031162a9a8f307b8e1efcafe3ce76b35ad293fad97ec885bcb8dbc6756d18ca941ffffffff030000000000000000896a04534c500001410747454e45534953065450414e4441044164616d4c5c68747470733a2f2f70616e642e61732e636173682f67656e6f6d652f303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030304c0001004c0008000000000000000122020000000000001976a9141431a2d4241cf1aa9df855cfd329304935a0383488acfa220000000000001976a9142cb677ece4990b3f587e90130f99660bfe4554f488ac00000000";
+
+    #[test]
+    fn fixture_filters() {
+        let config = SLPDEXConfig::default();
+        let token_hash = [7; 32];
+        let source = FixtureChainSource::from_file(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain_source.json")
+        ).unwrap();
+        let hashes = |entries: Vec<tx_result::TxEntry>| {
+            entries.into_iter().map(|entry| entry.tx.h[..2].to_string()).collect::<Vec<_>>()
+        };
+        let txs = source.request_txs(&[TxFilter::SortBy(SortKey::TxHash)], &config,
+                                     Confirmedness::Both).unwrap();
+        assert_eq!(hashes(txs), vec!["aa", "bb", "cc"]);
+        let txs = source.request_txs(&[TxFilter::Address(config.fee_address.clone())], &config,
+                                     Confirmedness::Both).unwrap();
+        assert_eq!(hashes(txs), vec!["bb"]);
+        let txs = source.request_txs(&[TxFilter::TokenId(token_hash)], &config,
+                                     Confirmedness::Confirmed).unwrap();
+        assert!(txs.is_empty());
+        let txs = source.request_txs(&[TxFilter::TokenId(token_hash)], &config,
+                                     Confirmedness::Unconfirmed).unwrap();
+        assert_eq!(hashes(txs), vec!["cc"]);
+        let txs = source.request_txs(&[TxFilter::MinBlockHeight(595000)], &config,
+                                     Confirmedness::Confirmed).unwrap();
+        assert_eq!(hashes(txs), vec!["bb"]);
+        let validity = source.request_slp_tx_validity(&[], &config, Confirmedness::Both).unwrap();
+        assert_eq!(validity.len(), 1);
+        assert!(validity[0].slp.valid);
+        let txs = source.request_txs(&[TxFilter::OutputAddress(config.fee_address.clone())], &config,
+                                     Confirmedness::Both).unwrap();
+        assert_eq!(hashes(txs), vec!["bb"]);
+        let spent = source.request_spent_outputs(&[([0; 32], 0), ([0; 32], 1)]).unwrap();
+        assert_eq!(spent, vec![([0; 32], 0)]);
+    }
+
+    fn mock_rpc_server(responses: HashMap<String, Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            'connections: for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                let body_start = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    if n == 0 {
+                        continue 'connections;  // closed before sending a full request
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(pos) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                };
+                let headers = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+                let content_length = headers.lines()
+                    .find(|line| line.starts_with("content-length:"))
+                    .and_then(|line| line["content-length:".len()..].trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                while request.len() < body_start + content_length {
+                    let n = stream.read(&mut buf).unwrap();
+                    if n == 0 {
+                        continue 'connections;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request: Value = serde_json::from_slice(&request[body_start..]).unwrap();
+                let key = format!("{} {}", request["method"].as_str().unwrap(), request["params"]);
+                let body = match responses.get(&key) {
+                    Some(result) => json!({"result": result, "error": null, "id": request["id"]}),
+                    None => json!({
+                        "result": null,
+                        "error": {"code": -5, "message": "No such mempool or blockchain transaction"},
+                        "id": request["id"],
+                    }),
+                }.to_string();
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                                Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                       body.len(), body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn rpc_source_decodes_txs() {
+        let tx = Tx::read_from_stream(&mut io::Cursor::new(hex::decode(PANDA_GENESIS_TX).unwrap())).unwrap();
+        let tx_hash = tx_hash_to_hex(&tx.hash());
+        let block_hash = "11".repeat(32);
+        let mut responses = HashMap::new();
+        responses.insert(format!("getrawtransaction [\"{}\",true]", tx_hash),
+                         json!({"hex": PANDA_GENESIS_TX, "blockhash": block_hash}));
+        responses.insert(format!("getrawtransaction [\"{}\",false]", tx_hash),
+                         json!(PANDA_GENESIS_TX));
+        responses.insert(format!("getblockheader [\"{}\"]", block_hash),
+                         json!({"height": 600000, "time": 1570000000}));
+        responses.insert(format!("gettxout [\"{}\",1,true]", tx_hash), json!({"value": 0.00000546}));
+        let source = RpcChainSource::new(mock_rpc_server(responses), None, None);
+        let config = SLPDEXConfig::default();
+
+        let entries = source.request_txs(&[TxFilter::TxHash(tx.hash())], &config,
+                                         Confirmedness::Both).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.tx.h, tx_hash);
+        assert_eq!(entry.blk.as_ref().map(|blk| (blk.i, blk.t)), Some((600000, 1570000000)));
+        assert_eq!(entry.inputs.len(), 2);
+        assert!(entry.inputs[0].e.a.is_some());
+        assert_eq!(entry.outputs.len(), 3);
+        assert_eq!(entry.outputs[0].b0, tx_result::StackItem::Op {op: 0x6a});
+        assert_eq!(entry.outputs[1].e.v, 546);
+        let slp = entry.slp.as_ref().unwrap();
+        assert_eq!(slp.detail.transaction_type, "GENESIS");
+        assert_eq!(slp.detail.version_type, TokenType::NFT1Child as i32);
+        assert_eq!(slp.detail.outputs[0].amount, "1");
+        assert!(!slp.valid);  // the NFT1 group input isn't known to the mock node
+
+        let unconfirmed = source.request_txs(&[TxFilter::TxHash(tx.hash())], &config,
+                                             Confirmedness::Unconfirmed).unwrap();
+        assert!(unconfirmed.is_empty());
+        let missing = source.request_txs(&[TxFilter::TxHash([3; 32])], &config,
+                                         Confirmedness::Both).unwrap();
+        assert!(missing.is_empty());
+        assert!(source.request_txs(&[TxFilter::Exch], &config, Confirmedness::Both).is_err());
+        assert!(source.request_txs(&[TxFilter::TokenId(tx.hash())], &config, Confirmedness::Both).is_err());
+
+        // output 1 is unspent, output 5 and the outputs of unknown txs don't exist
+        let spent = source.request_spent_outputs(&[(tx.hash(), 0), (tx.hash(), 1), (tx.hash(), 5), ([3; 32], 0)])
+            .unwrap();
+        assert_eq!(spent, vec![(tx.hash(), 0)]);
+    }
+}
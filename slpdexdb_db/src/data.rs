@@ -1,5 +1,7 @@
 use cashcontracts::Address;
 use slpdexdb_base::SLPAmount;
+use rug::Rational;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Debug)]
 pub struct Utxo {
@@ -16,19 +18,6 @@ pub struct SpentUtxo {
     pub vout: i32,
 }
 
-#[derive(Clone, Debug)]
-pub enum NewUtxo {
-    Address {
-        tx_hash: [u8; 32],
-        vout: i32,
-        address: Address,
-    },
-    TradeOffer {
-        tx_hash: [u8; 32],
-        vout: i32,
-    },
-}
-
 #[derive(Clone, Debug)]
 pub enum TradeOfferFilter {
     TokenHash([u8; 32]),
@@ -44,6 +33,184 @@ pub struct TxDelta {
     pub timestamp: i64,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct TxHistoryFilter {
+    pub token_hash: Option<[u8; 32]>,
+    pub bch_only: bool,
+    pub confirmed: Option<bool>,
+}
+
+// txs are ordered by (timestamp, tx_hash) descending; a page starts strictly after its cursor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TxHistoryCursor {
+    pub timestamp: i64,
+    pub tx_hash: [u8; 32],
+}
+
+#[derive(Clone, Debug)]
+pub struct TxHistoryPage {
+    pub tx_deltas: Vec<TxDelta>,
+    pub next_cursor: Option<TxHistoryCursor>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChainReorg {
+    pub fork_height: i32,
+    pub disconnected_blocks: Vec<[u8; 32]>,
+    pub connected_blocks: Vec<[u8; 32]>,
+    pub unconfirmed_txs: Vec<UnconfirmedTx>,
+    pub address_txs: HashMap<Address, Vec<[u8; 32]>>,
+    pub token_txs: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UnconfirmedTx {
+    pub tx_hash: [u8; 32],
+    pub timestamp: i64,
+    pub inputs: Vec<SpentUtxo>,
+    pub n_outputs: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DoubleSpentUtxos {
+    pub tx_hashes: Vec<[u8; 32]>,
+    pub address_utxos: HashMap<Address, Vec<SpentUtxo>>,
+    pub trade_offer_utxos: HashMap<[u8; 32], Vec<SpentUtxo>>,
+    // tokens with removed or restored trade offer utxos
+    pub trade_offer_tokens: HashSet<[u8; 32]>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TxConfirmation {
+    pub tx_hash: [u8; 32],
+    pub height: i32,
+    pub timestamp: i64,
+    pub confirmations: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockConfirmations {
+    pub block_hash: [u8; 32],
+    pub height: i32,
+    pub confirmations: Vec<TxConfirmation>,
+    pub address_txs: HashMap<Address, Vec<[u8; 32]>>,
+    pub token_txs: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub tx_hash: [u8; 32],
+    pub price_per_token: Rational,
+    pub amount_token: SLPAmount,
+    pub value_satoshis: i64,
+    pub buyer_address: Option<Address>,
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenHolder {
+    pub address: Address,
+    pub balance: SLPAmount,
+    pub utxo_count: i32,
+}
+
+// position after the last holder of a page, holders are ordered by descending balance
+#[derive(Clone, Debug)]
+pub struct TokenHolderCursor {
+    pub balance: SLPAmount,
+    pub address: Address,
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenBalance {
+    pub token_hash: [u8; 32],
+    pub balance: SLPAmount,
+    pub utxo_count: i32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HolderCount {
+    pub height: i32,
+    pub holder_count: i32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum CandleResolution {
+    Minute,
+    Hour,
+    Day,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub timestamp: i64,
+    pub open: Rational,
+    pub high: Rational,
+    pub low: Rational,
+    pub close: Rational,
+    pub volume_token: SLPAmount,
+    pub volume_satoshis: i64,
+    pub num_trades: usize,
+}
+
+impl CandleResolution {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleResolution::Minute => 60,
+            CandleResolution::Hour   => 60 * 60,
+            CandleResolution::Day    => 24 * 60 * 60,
+        }
+    }
+}
+
+impl Candle {
+    // trades must be sorted by timestamp
+    pub fn aggregate(timestamp: i64, trades: &[Trade]) -> Option<Candle> {
+        let first = trades.first()?;
+        let mut candle = Candle {
+            timestamp,
+            open: first.price_per_token.clone(),
+            high: first.price_per_token.clone(),
+            low: first.price_per_token.clone(),
+            close: first.price_per_token.clone(),
+            volume_token: first.amount_token,
+            volume_satoshis: first.value_satoshis,
+            num_trades: 1,
+        };
+        for trade in &trades[1..] {
+            if trade.price_per_token > candle.high {
+                candle.high = trade.price_per_token.clone();
+            }
+            if trade.price_per_token < candle.low {
+                candle.low = trade.price_per_token.clone();
+            }
+            candle.close = trade.price_per_token.clone();
+            candle.volume_token += trade.amount_token;
+            candle.volume_satoshis += trade.value_satoshis;
+            candle.num_trades += 1;
+        }
+        Some(candle)
+    }
+
+    // trades must be sorted by timestamp; buckets without trades are left out
+    pub fn from_trades(trades: &[Trade], resolution: CandleResolution) -> Vec<Candle> {
+        let seconds = resolution.seconds();
+        let bucket = |trade: &Trade| trade.timestamp - trade.timestamp % seconds;
+        let mut candles = Vec::new();
+        let mut start = 0;
+        while start < trades.len() {
+            let timestamp = bucket(&trades[start]);
+            let end = trades[start..].iter()
+                .position(|trade| bucket(trade) != timestamp)
+                .map(|len| start + len)
+                .unwrap_or(trades.len());
+            candles.extend(Candle::aggregate(timestamp, &trades[start..end]));
+            start = end;
+        }
+        candles
+    }
+}
+
 pub fn tx_hash_from_slice(slice: &[u8]) -> [u8; 32] {
     let mut hash = [0; 32];
     hash.copy_from_slice(&slice);
@@ -63,3 +230,44 @@ pub fn address_hash_from_slice(slice: &[u8]) -> [u8; 20] {
     hash.copy_from_slice(&slice);
     hash
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: i64, price_per_token: i32, amount: i128) -> Trade {
+        Trade {
+            tx_hash: [0; 32],
+            price_per_token: Rational::from(price_per_token),
+            amount_token: SLPAmount::new(amount, 0),
+            value_satoshis: price_per_token as i64 * amount as i64,
+            buyer_address: None,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn builds_candles() {
+        let trades = vec![
+            trade(120, 5, 1),
+            trade(130, 7, 2),
+            trade(150, 4, 1),
+            trade(179, 6, 3),
+            trade(300, 9, 1),
+        ];
+        let candles = Candle::from_trades(&trades, CandleResolution::Minute);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].timestamp, 120);
+        assert_eq!((candles[0].open.clone(), candles[0].high.clone(), candles[0].low.clone(),
+                    candles[0].close.clone()),
+                   (Rational::from(5), Rational::from(7), Rational::from(4), Rational::from(6)));
+        assert_eq!(candles[0].volume_token, SLPAmount::new(7, 0));
+        assert_eq!(candles[0].volume_satoshis, 5 + 14 + 4 + 18);
+        assert_eq!(candles[0].num_trades, 4);
+        assert_eq!(candles[1].timestamp, 300);
+        let candles = Candle::from_trades(&trades, CandleResolution::Hour);
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].timestamp, 0);
+        assert_eq!(candles[0].close, Rational::from(9));
+    }
+}
@@ -1,80 +1,427 @@
 use diesel::pg::PgConnection;
 use diesel::data_types::PgNumeric;
 use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
 
-use cashcontracts::{Address, AddressType};
-use slpdexdb_base::{BlockHeader, GENESIS};
+use cashcontracts::{Address, AddressType, tx_hash_to_hex};
+use rug::{Integer, Rational};
+use slpdexdb_base::{BlockHeader, Network, HeaderChain, BootstrapCheckpoint, DIFFICULTY_WINDOW, Result, Error,
+                    ErrorKind, HeaderError, check_header_batch, difficulty_history};
 use slpdexdb_base::SLPAmount;
-use slpdexdb_base::convert_numeric::{rational_to_pg_numeric, pg_numeric_to_rational};
-use crate::tx_history::{TxHistory, TxType, TradeOffer, TokenType};
+use slpdexdb_base::convert_numeric::{rational_to_pg_numeric, pg_numeric_to_rational,
+                                     integer_to_pg_numeric, pg_numeric_to_integer,
+                                     pg_numeric_to_i128, i128_to_pg_numeric};
+use crate::tx_history::{TxHistory, HistoricTx, TxType, SLPTxType, OutputType, TradeOffer, TokenType};
 use crate::update_history::{UpdateHistory, UpdateSubject};
-use crate::token::Token;
+use crate::token::{Token, TokenSupply, TokenStats};
 use crate::{models, schema::*};
 use crate::convert::pg_safe_string;
-use crate::data::{Utxo, NewUtxo, SpentUtxo, TxDelta, tx_hash_from_slice, address_hash_from_slice,
-                  TradeOfferFilter};
+use crate::data::{Utxo, SpentUtxo, TxDelta, TxHistoryFilter, TxHistoryCursor, TxHistoryPage, Trade, Candle, CandleResolution,
+                  TokenHolder, TokenHolderCursor, TokenBalance, HolderCount,
+                  tx_hash_from_slice, address_hash_from_slice,
+                  TradeOfferFilter, ChainReorg, UnconfirmedTx, DoubleSpentUtxos,
+                  TxConfirmation, BlockConfirmations};
 
-use std::collections::{HashMap, HashSet, BTreeSet};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
 
 const PRICE_DIGITS: u16 = 26;
+const HEADERS_IMPORT_BATCH: usize = 2000;
+
+sql_function!(fn coalesce(x: diesel::sql_types::Nullable<diesel::sql_types::Integer>,
+                          y: diesel::sql_types::Nullable<diesel::sql_types::Integer>)
+                          -> diesel::sql_types::Nullable<diesel::sql_types::Integer>);
+
+fn _chain_work(block: &models::Block) -> Result<Integer> {
+    pg_numeric_to_integer(&block.chain_work)
+}
+
+// the 10 most recent heights, then exponentially spaced back to genesis
+pub fn block_locator_heights(tip_height: i32) -> Vec<i32> {
+    let mut heights = Vec::new();
+    let mut height = tip_height;
+    let mut step = 1;
+    while height > 0 {
+        heights.push(height);
+        if heights.len() >= 10 {
+            step *= 2;
+        }
+        height -= step;
+    }
+    heights.push(0);
+    heights
+}
+
+pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+pub type DbConnection = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
+
+// satoshis paid to the seller if the tx fills a trade offer. Accepting sends the tokens to the
+// buyer; the seller cancels by spending the offer back to their own receiving address, which
+// pays that address everything and must not count as a trade.
+fn _offer_fill(tx: &HistoricTx, receiving_address: &[u8], expected_satoshis: &Rational) -> Option<u64> {
+    let is_receiving_address = |output: &OutputType| {
+        output.address()
+            .map(|address| address.addr_type() == AddressType::P2PKH &&
+                           &address.bytes()[..] == receiving_address)
+            .unwrap_or(false)
+    };
+    let token_output = tx.outputs.iter().find(|output| output.value_token.base_amount() > 0)?;
+    if is_receiving_address(&token_output.output) {
+        return None;
+    }
+    let paid_satoshis = tx.outputs.iter()
+        .filter(|output| is_receiving_address(&output.output))
+        .map(|output| output.value_satoshis)
+        .sum::<u64>();
+    // the contract rounds the price down to whole satoshis
+    if Rational::from(paid_satoshis + 1) > *expected_satoshis {
+        Some(paid_satoshis)
+    } else {
+        None
+    }
+}
+
+pub fn create_pool(database_url: &str, max_size: u32) -> Result<DbPool> {
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    Ok(r2d2::Pool::builder()
+        .max_size(max_size)
+        .build(manager)?)
+}
 
 pub struct Db {
-    connection: PgConnection,
+    connection: DbConnection,
 }
 
 impl Db {
-    pub fn new(connection: PgConnection) -> Self {
+    pub fn new(connection: DbConnection) -> Self {
         Db { connection }
     }
 
+    pub fn from_pool(pool: &DbPool) -> Result<Self> {
+        Ok(Db::new(pool.get()?))
+    }
+
     pub fn connection(&self) -> &PgConnection {
         &self.connection
     }
 
-    pub fn add_headers(&self, headers: &[BlockHeader]) -> QueryResult<()> {
-        let mut remaining_visit = (0..headers.len()).into_iter().collect::<BTreeSet<_>>();
-        let mut heights = self.header_tips(10)?
-            .iter()
-            .map(|(header, height)| (header.hash(), *height))
-            .collect::<HashMap<_, _>>();
-        if heights.len() == 0 {
-            diesel::insert_into(blocks::table)
-                .values(&models::Block::from_block_header(&GENESIS, 0))
-                .execute(&self.connection)?;
-            heights.insert(GENESIS.hash(), 0);
+    // a fresh database starts at the network's bootstrap checkpoint, or genesis if it has none
+    pub fn init_headers(&self, network: &Network) -> Result<()> {
+        self.connection().transaction::<_, Error, _>(|| {
+            self._init_headers(network)?;
+            Ok(())
+        })
+    }
+
+    fn _init_headers(&self, network: &Network) -> Result<models::Block> {
+        if let Some(block) = self._main_tip_block()? {
+            return Ok(block);
         }
-        loop {
-            let tuple = remaining_visit
-                .iter().cloned()
-                .find_map(|i|
-                    if headers[i].prev_block != [0; 32] {
-                        heights.get(&headers[i].prev_block).map(|i| *i + 1)
-                    } else {
-                        Some(0)
-                    }.map(|height| (i, &headers[i], height))
+        let blocks = match &network.bootstrap {
+            Some(bootstrap) => {
+                bootstrap.validate(&network.difficulty)?;
+                bootstrap.blocks().into_iter()
+                    .map(|(height, header, chain_work)| {
+                        models::Block::from_block_header(header, height, integer_to_pg_numeric(chain_work), true)
+                    })
+                    .collect::<Vec<_>>()
+            },
+            None => vec![models::Block::from_block_header(
+                &network.genesis, 0, integer_to_pg_numeric(network.genesis.work()), true,
+            )],
+        };
+        diesel::insert_into(blocks::table)
+            .values(&blocks)
+            .execute(self.connection())?;
+        Ok(blocks[blocks.len() - 1].clone())
+    }
+
+    pub fn add_headers(&self, headers: &[BlockHeader], network: &Network) -> Result<Option<ChainReorg>> {
+        self.connection().transaction::<_, Error, _>(|| {
+            let main_tip = self._init_headers(network)?;
+            let mut chains = HashMap::new();
+            let mut best_block: Option<models::Block> = None;
+            for header in headers {
+                let hash = header.hash();
+                if self._block(&hash)?.is_some() {
+                    continue;
+                }
+                let parent = match self._block(&header.prev_block)? {
+                    Some(parent) => parent,
+                    None => {
+                        eprintln!("Skipping orphan header {}", tx_hash_to_hex(&hash));
+                        continue
+                    },
+                };
+                if let Some(checkpoint) = network.checkpoint(parent.height + 1) {
+                    if checkpoint.hash != hash {
+                        return Err(ErrorKind::InvalidHeader(
+                            HeaderError::CheckpointMismatch(tx_hash_to_hex(&hash), parent.height + 1)
+                        ).into());
+                    }
+                }
+                let mut chain = match chains.remove(&header.prev_block) {
+                    Some(chain) => chain,
+                    None => self._header_chain(&parent)?,
+                };
+                if let Err(err) = chain.validate_next(header, &network.difficulty) {
+                    // e.g. a fork below the bootstrap checkpoint, which we can't follow anyway
+                    if let ErrorKind::MissingHeaderHistory(_) = err.kind() {
+                        eprintln!("Skipping header {} without history to validate it", tx_hash_to_hex(&hash));
+                        chains.insert(header.prev_block, chain);
+                        continue
+                    }
+                    return Err(err);
+                }
+                chain.push(header.clone());
+                chains.insert(hash, chain);
+                let chain_work = _chain_work(&parent)? + header.work();
+                let block = models::Block::from_block_header(
+                    header, parent.height + 1, integer_to_pg_numeric(chain_work), false,
                 );
-            match tuple {
-                Some((i, header, height)) => {
-                    heights.insert(header.hash(), height);
-                    remaining_visit.remove(&i);
-                },
-                None => break,
+                diesel::insert_into(blocks::table)
+                    .values(&block)
+                    .execute(self.connection())?;
+                let is_better = match &best_block {
+                    Some(best_block) => _chain_work(&block)? > _chain_work(best_block)?,
+                    None => true,
+                };
+                if is_better {
+                    best_block = Some(block);
+                }
+            }
+            let best_block = match best_block {
+                Some(block) => block,
+                None => return Ok(None),
             };
+            if _chain_work(&best_block)? <= _chain_work(&main_tip)? {
+                return Ok(None);
+            }
+            Ok(self._switch_main_chain(&best_block)?)
+        })
+    }
+
+    /// Imports raw 80 byte headers, as stored by e.g. Electrum, where the first header is at `start_height`.
+    /// Headers we already have are skipped. Returns by how many blocks the main chain grew.
+    pub fn import_headers(&self, read: &mut impl Read, start_height: i32, network: &Network) -> Result<i32> {
+        let tip_height = self.connection().transaction::<_, Error, _>(|| {
+            Ok(self._init_headers(network)?.height)
+        })?;
+        let n_skip = (tip_height + 1 - start_height).max(0) as u64;
+        io::copy(&mut Read::take(&mut *read, n_skip * 80), &mut io::sink())?;
+        loop {
+            let mut headers = Vec::with_capacity(HEADERS_IMPORT_BATCH);
+            while headers.len() < HEADERS_IMPORT_BATCH {
+                match BlockHeader::from_stream(read) {
+                    Ok(header) => headers.push(header),
+                    Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            if headers.len() == 0 {
+                break;
+            }
+            check_header_batch(&headers, &network.difficulty)?;
+            self.add_headers(&headers, network)?;
+            if headers.len() < HEADERS_IMPORT_BATCH {
+                break;
+            }
         }
-        let db_blocks = headers.iter().map(|header| {
-            models::Block::from_block_header(header, heights[&header.hash()])
-        }).collect::<Vec<_>>();
-        diesel::insert_into(blocks::table)
-            .values(&db_blocks)
-            .execute(&self.connection)?;
-        Ok(())
+        let new_tip_height = self._main_tip_block()?.map(|tip| tip.height).unwrap_or(tip_height);
+        Ok(new_tip_height - tip_height)
+    }
+
+    /// Main chain headers ending at `height`, to be embedded as the network's bootstrap checkpoint.
+    pub fn bootstrap_checkpoint(&self, height: i32, network: &Network) -> Result<BootstrapCheckpoint> {
+        let n_headers = difficulty_history(height, &network.difficulty) as i32;
+        let blocks = blocks::table
+            .filter(blocks::is_main_chain.eq(true))
+            .filter(blocks::height.between(height - n_headers + 1, height))
+            .order(blocks::height.asc())
+            .load::<models::Block>(self.connection())?;
+        if blocks.len() as i32 != n_headers {
+            return Err(ErrorKind::MissingHeaderHistory(format!("height {}", height)).into());
+        }
+        Ok(BootstrapCheckpoint {
+            height,
+            chain_work: _chain_work(&blocks[blocks.len() - 1])?,
+            headers: blocks.iter().map(models::Block::to_block_header).collect(),
+        })
+    }
+
+    fn _header_chain(&self, tip: &models::Block) -> QueryResult<HeaderChain> {
+        let mut branch = Vec::new();
+        let mut block = tip.clone();
+        while !block.is_main_chain && branch.len() < DIFFICULTY_WINDOW {
+            let prev_block = tx_hash_from_slice(&block.prev_block);
+            branch.push(block.to_block_header());
+            block = match self._block(&prev_block)? {
+                Some(block) => block,
+                None => return Err(diesel::result::Error::NotFound),
+            };
+        }
+        let n_main = (DIFFICULTY_WINDOW - branch.len()) as i32;
+        let mut headers = if block.is_main_chain && n_main > 0 {
+            blocks::table
+                .filter(blocks::is_main_chain.eq(true))
+                .filter(blocks::height.between(block.height - n_main + 1, block.height))
+                .order(blocks::height.asc())
+                .load::<models::Block>(self.connection())?
+                .into_iter()
+                .map(|block| block.to_block_header())
+                .collect::<Vec<_>>()
+        } else {
+            vec![]
+        };
+        headers.extend(branch.into_iter().rev());
+        Ok(HeaderChain::new(tip.height - headers.len() as i32 + 1, headers))
+    }
+
+    fn _block(&self, block_hash: &[u8; 32]) -> QueryResult<Option<models::Block>> {
+        blocks::table
+            .filter(blocks::hash.eq(block_hash.to_vec()))
+            .first::<models::Block>(self.connection())
+            .optional()
+    }
+
+    fn _main_tip_block(&self) -> QueryResult<Option<models::Block>> {
+        blocks::table
+            .filter(blocks::is_main_chain.eq(true))
+            .order(blocks::height.desc())
+            .first::<models::Block>(self.connection())
+            .optional()
+    }
+
+    fn _switch_main_chain(&self, new_tip: &models::Block) -> QueryResult<Option<ChainReorg>> {
+        let mut connected_blocks = Vec::new();
+        let mut block = new_tip.clone();
+        while !block.is_main_chain {
+            connected_blocks.push(tx_hash_from_slice(&block.hash));
+            block = match self._block(&tx_hash_from_slice(&block.prev_block))? {
+                Some(parent) => parent,
+                None => return Err(diesel::result::Error::NotFound),
+            };
+        }
+        let fork_height = block.height;
+        let disconnected_blocks = blocks::table
+            .filter(blocks::is_main_chain.eq(true))
+            .filter(blocks::height.gt(fork_height))
+            .select(blocks::hash)
+            .load::<Vec<u8>>(self.connection())?
+            .into_iter()
+            .map(|hash| tx_hash_from_slice(&hash))
+            .collect::<Vec<_>>();
+        diesel::update(blocks::table)
+            .filter(blocks::is_main_chain.eq(true))
+            .filter(blocks::height.gt(fork_height))
+            .set(blocks::is_main_chain.eq(false))
+            .execute(self.connection())?;
+        diesel::update(blocks::table)
+            .filter(blocks::hash.eq_any(connected_blocks.iter().map(|hash| hash.to_vec())))
+            .set(blocks::is_main_chain.eq(true))
+            .execute(self.connection())?;
+        if disconnected_blocks.len() == 0 {
+            return Ok(None);
+        }
+        connected_blocks.reverse();
+        let mut reorg = self._rollback_txs(fork_height, &disconnected_blocks)?;
+        reorg.disconnected_blocks = disconnected_blocks;
+        reorg.connected_blocks = connected_blocks;
+        Ok(Some(reorg))
+    }
+
+    // txs of disconnected blocks become unconfirmed again, so their outputs, spends, supply, trades and
+    // holders stay until they're double spent; only what was derived from the blocks themselves is rolled back
+    fn _rollback_txs(&self, fork_height: i32, disconnected_blocks: &[[u8; 32]]) -> QueryResult<ChainReorg> {
+        use diesel::sql_types::{Array, Binary, Integer};
+        let txs: Vec<(i64, Vec<u8>, i64)> = tx::table
+            .filter(tx::height.gt(fork_height))
+            .select((tx::id, tx::hash, tx::timestamp))
+            .load(self.connection())?;
+        let tx_ids = txs.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+        let tx_hashes = txs.iter()
+            .map(|(id, hash, _)| (*id, tx_hash_from_slice(hash)))
+            .collect::<HashMap<_, _>>();
+        diesel::update(tx::table)
+            .filter(tx::id.eq_any(&tx_ids))
+            .set(tx::height.eq(None::<i32>))
+            .execute(self.connection())?;
+        diesel::update(token::table)
+            .filter(token::hash.eq_any(tx_hashes.values().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()))
+            .set(token::block_created_height.eq(0))
+            .execute(self.connection())?;
+        self._update_token_stats(&self._tx_tokens(&tx_ids)?)?;
+        // the PND1 stays pending and gives birth again once it confirms on the new chain
+        diesel::delete(panda::table)
+            .filter(panda::id.eq_any(
+                panda_birth::table
+                    .filter(panda_birth::pnd1_tx.eq_any(&tx_ids))
+                    .select(panda_birth::panda)
+            ))
+            .execute(self.connection())?;
+        diesel::sql_query("
+            DELETE FROM processed_block WHERE hash = ANY($1)
+        ")
+            .bind::<Array<Binary>, _>(disconnected_blocks.iter().map(|hash| hash.to_vec()).collect::<Vec<_>>())
+            .execute(self.connection())?;
+        diesel::sql_query("
+            DELETE FROM token_holder_count WHERE height > $1
+        ").bind::<Integer, _>(fork_height).execute(self.connection())?;
+        let (address_txs, token_txs) = self._affected_subjects(&tx_hashes)?;
+        Ok(ChainReorg {
+            fork_height,
+            disconnected_blocks: vec![],
+            connected_blocks: vec![],
+            unconfirmed_txs: self._unconfirmed_txs(txs)?,
+            address_txs,
+            token_txs,
+        })
+    }
+
+    fn _affected_subjects(&self, tx_hashes: &HashMap<i64, [u8; 32]>)
+            -> QueryResult<(HashMap<Address, Vec<[u8; 32]>>, HashMap<[u8; 32], Vec<[u8; 32]>>)> {
+        let tx_ids = tx_hashes.keys().cloned().collect::<Vec<_>>();
+        let output_addresses: Vec<(i64, Option<Vec<u8>>)> = tx_output::table
+            .filter(tx_output::tx.eq_any(&tx_ids))
+            .select((tx_output::tx, tx_output::address))
+            .load(self.connection())?;
+        let input_addresses: Vec<(i64, Option<Vec<u8>>)> = tx_input::table
+            .filter(tx_input::tx.eq_any(&tx_ids))
+            .select((tx_input::tx, tx_input::address))
+            .load(self.connection())?;
+        let mut address_txs = HashMap::new();
+        for (tx_id, address) in output_addresses.into_iter().chain(input_addresses) {
+            let address = match address {
+                Some(address) => address_hash_from_slice(&address),
+                None => continue,
+            };
+            let address = Address::from_bytes(AddressType::P2PKH, address);
+            let txs = address_txs.entry(address).or_insert_with(Vec::new);
+            let tx_hash = tx_hashes[&tx_id];
+            if !txs.contains(&tx_hash) {
+                txs.push(tx_hash);
+            }
+        }
+        let slp_txs: Vec<(i64, Vec<u8>)> = slp_tx::table
+            .inner_join(token::table)
+            .filter(slp_tx::tx.eq_any(&tx_ids))
+            .select((slp_tx::tx, token::hash))
+            .load(self.connection())?;
+        let mut token_txs = HashMap::new();
+        for (tx_id, token_hash) in slp_txs {
+            token_txs.entry(tx_hash_from_slice(&token_hash))
+                .or_insert_with(Vec::new)
+                .push(tx_hashes[&tx_id]);
+        }
+        Ok((address_txs, token_txs))
     }
 
     pub fn header_tips(&self, n_recent: i64) -> QueryResult<Vec<(BlockHeader, i32)>> {
         Ok(blocks::table
+            .filter(blocks::is_main_chain.eq(true))
             .order(blocks::height.desc())
             .limit(n_recent)
-            .load::<models::Block>(&self.connection)?
+            .load::<models::Block>(self.connection())?
             .into_iter()
             .map(|block: models::Block| (block.to_block_header(), block.height))
             .collect())
@@ -89,24 +436,104 @@ impl Db {
         }
     }
 
-    pub fn set_address_active(&self, address: &Address, is_active: bool) -> QueryResult<()> {
-        if is_active {
-            diesel::insert_into(active_address::table)
-                .values(models::ActiveAddress { address: address.bytes().to_vec() })
-                .on_conflict_do_nothing()
-                .execute(&self.connection)?;
-        } else {
-            diesel::delete(active_address::table)
-                .filter(
-                    active_address::address.eq(address.bytes().to_vec())
-                )
-                .execute(&self.connection)?;
-        }
+    pub fn block_locator(&self) -> QueryResult<Vec<[u8; 32]>> {
+        let tip_height = match self._main_tip_block()? {
+            Some(tip) => tip.height,
+            None => return Ok(vec![]),
+        };
+        Ok(blocks::table
+            .filter(blocks::is_main_chain.eq(true))
+            .filter(blocks::height.eq_any(block_locator_heights(tip_height)))
+            .order(blocks::height.desc())
+            .select(blocks::hash)
+            .load::<Vec<u8>>(self.connection())?
+            .into_iter()
+            .map(|hash| tx_hash_from_slice(&hash))
+            .collect())
+    }
+
+    pub fn is_block_processed(&self, block_hash: &[u8; 32]) -> QueryResult<bool> {
+        use diesel::sql_types::Binary;
+        let blocks: Vec<models::BlockHashHeight> = diesel::sql_query("
+            SELECT hash, height FROM processed_block WHERE hash = $1
+        ").bind::<Binary, _>(block_hash.to_vec()).load(self.connection())?;
+        Ok(blocks.len() > 0)
+    }
+
+    pub fn mark_block_processed(&self, block_hash: &[u8; 32], height: i32, timestamp: i64) -> QueryResult<()> {
+        use diesel::sql_types::{Binary, Integer, BigInt};
+        diesel::sql_query("
+            INSERT INTO processed_block (hash, height, processed_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+        ")
+            .bind::<Binary, _>(block_hash.to_vec())
+            .bind::<Integer, _>(height)
+            .bind::<BigInt, _>(timestamp)
+            .execute(self.connection())?;
         Ok(())
     }
 
+    // main chain blocks missed since the first processed block, e.g. during downtime, lowest first
+    pub fn unprocessed_blocks(&self, limit: i64) -> QueryResult<Vec<([u8; 32], i32)>> {
+        use diesel::sql_types::BigInt;
+        let blocks: Vec<models::BlockHashHeight> = diesel::sql_query("
+            SELECT blocks.hash, blocks.height
+            FROM blocks
+            LEFT JOIN processed_block ON processed_block.hash = blocks.hash
+            WHERE blocks.is_main_chain
+            AND processed_block.hash IS NULL
+            AND blocks.height > (SELECT MIN(height) FROM processed_block)
+            ORDER BY blocks.height
+            LIMIT $1
+        ").bind::<BigInt, _>(limit).load(self.connection())?;
+        Ok(blocks.into_iter()
+            .map(|block| (tx_hash_from_slice(&block.hash), block.height))
+            .collect())
+    }
+
+    pub fn header_height(&self, block_hash: &[u8; 32]) -> QueryResult<Option<i32>> {
+        blocks::table
+            .filter(blocks::hash.eq(block_hash.to_vec()))
+            .filter(blocks::is_main_chain.eq(true))
+            .select(blocks::height)
+            .first::<i32>(self.connection())
+            .optional()
+    }
+
+    // utxo_address only tracks active addresses, so (de)activating seeds or drops its utxos
+    pub fn set_address_active(&self, address: &Address, is_active: bool) -> QueryResult<()> {
+        self.connection().transaction(|| {
+            if is_active {
+                let n_inserted = diesel::insert_into(active_address::table)
+                    .values(models::ActiveAddress { address: address.bytes().to_vec() })
+                    .on_conflict_do_nothing()
+                    .execute(self.connection())?;
+                if n_inserted > 0 {
+                    self._insert_address_utxos(address)?;
+                }
+            } else {
+                diesel::delete(active_address::table)
+                    .filter(
+                        active_address::address.eq(address.bytes().to_vec())
+                    )
+                    .execute(self.connection())?;
+                diesel::delete(utxo_address::table)
+                    .filter(utxo_address::address.eq(address.bytes().to_vec()))
+                    .execute(self.connection())?;
+            }
+            Ok(())
+        })
+    }
+
     pub fn add_tx_history(&self, tx_history: &TxHistory) -> QueryResult<()> {
-        self.connection.transaction(|| {
+        use diesel::pg::upsert::excluded;
+        self.connection().transaction(|| {
+            if !tx_history.new_tokens.is_empty() {
+                let mut new_tokens = tx_history.new_tokens.iter().collect::<Vec<_>>();
+                new_tokens.sort_by_key(|(tx_idx, _)| **tx_idx);
+                self.add_tokens(&new_tokens.into_iter().map(|(_, token)| token.clone()).collect::<Vec<_>>())?;
+            }
             let token_hashes = tx_history.txs.iter()
                 .filter_map(|tx| {
                     match tx.tx_type {
@@ -126,16 +553,17 @@ impl Db {
             let tokens: Vec<(Vec<u8>, i32)> = token::table
                 .select((token::hash, token::id))
                 .filter(token::hash.eq_any(token_hashes))
-                .load(&self.connection)?;
+                .load(self.connection())?;
             let token_ids = tokens.into_iter().collect::<HashMap<_, _>>();
             let tx_ids = diesel::insert_into(tx::table)
                 .values(&new_txs)
                 .on_conflict(tx::hash)
-                .do_update().set((tx::height.eq(tx::height),
+                // a tx seen in the mempool again must not lose its known height
+                .do_update().set((tx::height.eq(coalesce(excluded(tx::height), tx::height)),
                                   tx::tx_type.eq(tx::tx_type),
                                   tx::timestamp.eq(tx::timestamp)))
                 .returning(tx::id)
-                .get_results::<i64>(&self.connection)?;
+                .get_results::<i64>(self.connection())?;
             let new_slp_txs = tx_history.txs
                 .iter()
                 .zip(tx_ids.iter().cloned())
@@ -150,10 +578,13 @@ impl Db {
                         TxType::Default => None,
                     }
                 }).collect::<Vec<_>>();
-            diesel::insert_into(slp_tx::table)
+            let inserted_slp_txs = diesel::insert_into(slp_tx::table)
                 .values(&new_slp_txs)
                 .on_conflict_do_nothing()
-                .execute(&self.connection)?;
+                .returning(slp_tx::tx)
+                .get_results::<i64>(self.connection())?
+                .into_iter()
+                .collect::<HashSet<_>>();
             let new_outputs = tx_history.txs.iter()
                 .zip(tx_ids.iter().cloned())
                 .flat_map(|(tx, id)| {
@@ -172,7 +603,7 @@ impl Db {
             diesel::insert_into(tx_output::table)
                 .values(&new_outputs)
                 .on_conflict_do_nothing()
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
             let new_inputs = tx_history.txs.iter()
                 .zip(tx_ids.iter().cloned())
                 .flat_map(|(tx, id)| {
@@ -191,7 +622,8 @@ impl Db {
             diesel::insert_into(tx_input::table)
                 .values(&new_inputs)
                 .on_conflict_do_nothing()
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
+            self._update_token_supplies(tx_history, &tx_ids, &inserted_slp_txs, &token_ids)?;
             let new_trade_offers = tx_history.trade_offers
                 .iter()
                 .map(|(tx_idx, trade_offer)| {
@@ -212,7 +644,14 @@ impl Db {
             diesel::insert_into(trade_offer::table)
                 .values(&new_trade_offers)
                 .on_conflict_do_nothing()
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
+            self._record_trades(tx_history, &tx_ids)?;
+            self._update_utxos(&tx_ids)?;
+            let token_holders = self._affected_token_holders(&tx_ids)?;
+            self._update_token_holders(&token_holders)?;
+            let mut stats_tokens = self._tx_tokens(&tx_ids)?;
+            stats_tokens.extend(token_holders.iter().map(|holder| holder.token));
+            self._update_token_stats(&stats_tokens)?;
             let new_pending_pnd1_txs = tx_history.pnd_txs
                 .iter()
                 .map(|(tx_idx, pnd_tx)| {
@@ -228,11 +667,687 @@ impl Db {
             diesel::insert_into(pending_pnd1_tx::table)
                 .values(&new_pending_pnd1_txs)
                 .on_conflict_do_nothing()
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
             Ok(())
         })
     }
 
+    // adds the unspent outputs of the given txs and removes the outputs they spend
+    fn _update_utxos(&self, tx_ids: &[i64]) -> QueryResult<()> {
+        use diesel::sql_types::{Array, BigInt};
+        diesel::sql_query("
+            INSERT INTO utxo_address (tx, idx, address)
+            SELECT tx_output.tx, tx_output.idx, tx_output.address
+            FROM tx_output
+            INNER JOIN tx ON tx.id = tx_output.tx
+            INNER JOIN active_address ON active_address.address = tx_output.address
+            LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+            WHERE tx_output.tx = ANY($1)
+            AND tx_input.tx IS NULL
+            ON CONFLICT DO NOTHING
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        diesel::sql_query("
+            INSERT INTO utxo_trade_offer (tx, idx)
+            SELECT tx_output.tx, tx_output.idx
+            FROM tx_output
+            INNER JOIN tx ON tx.id = tx_output.tx
+            INNER JOIN trade_offer ON trade_offer.tx = tx_output.tx AND trade_offer.output_idx = tx_output.idx
+            LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+            WHERE tx_output.tx = ANY($1)
+            AND tx_input.tx IS NULL
+            ON CONFLICT DO NOTHING
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        diesel::sql_query("
+            DELETE FROM utxo_address
+            USING tx_input, tx
+            WHERE tx_input.tx = ANY($1)
+            AND tx.hash = tx_input.output_tx
+            AND utxo_address.tx = tx.id
+            AND utxo_address.idx = tx_input.output_idx
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        diesel::sql_query("
+            DELETE FROM utxo_trade_offer
+            USING tx_input, tx
+            WHERE tx_input.tx = ANY($1)
+            AND tx.hash = tx_input.output_tx
+            AND utxo_trade_offer.tx = tx.id
+            AND utxo_trade_offer.idx = tx_input.output_idx
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        Ok(())
+    }
+
+    // (token, address) pairs whose balance changes by adding or removing the given txs
+    fn _affected_token_holders(&self, tx_ids: &[i64]) -> QueryResult<Vec<models::TokenHolderKey>> {
+        use diesel::sql_types::{Array, BigInt};
+        diesel::sql_query("
+            SELECT slp_tx.token, tx_output.address
+            FROM tx_output
+            INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+            WHERE tx_output.tx = ANY($1)
+            AND tx_output.address IS NOT NULL
+            AND tx_output.value_token_base > 0
+            UNION
+            SELECT slp_tx.token, tx_output.address
+            FROM tx_input
+            INNER JOIN tx ON tx.hash = tx_input.output_tx
+            INNER JOIN tx_output ON tx_output.tx = tx.id AND tx_output.idx = tx_input.output_idx
+            INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+            WHERE tx_input.tx = ANY($1)
+            AND tx_output.address IS NOT NULL
+            AND tx_output.value_token_base > 0
+        ").bind::<Array<BigInt>, _>(tx_ids).load(self.connection())
+    }
+
+    // recomputes the balances of the given holders from their unspent token outputs
+    fn _update_token_holders(&self, token_holders: &[models::TokenHolderKey]) -> QueryResult<()> {
+        use diesel::sql_types::{Array, Integer, Binary};
+        if token_holders.is_empty() {
+            return Ok(());
+        }
+        let tokens = token_holders.iter().map(|holder| holder.token).collect::<Vec<_>>();
+        let addresses = token_holders.iter().map(|holder| holder.address.clone()).collect::<Vec<_>>();
+        diesel::sql_query("
+            INSERT INTO token_holder (token, address, balance_base, utxo_count)
+            SELECT slp_tx.token, tx_output.address, SUM(tx_output.value_token_base), COUNT(*)
+            FROM tx_output
+            INNER JOIN tx ON tx.id = tx_output.tx
+            INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+            LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+            WHERE (slp_tx.token, tx_output.address) IN (SELECT * FROM UNNEST($1::INT[], $2::BYTEA[]))
+            AND tx_output.value_token_base > 0
+            AND tx_input.tx IS NULL
+            GROUP BY slp_tx.token, tx_output.address
+            ON CONFLICT (token, address) DO UPDATE
+            SET balance_base = EXCLUDED.balance_base, utxo_count = EXCLUDED.utxo_count
+        ")
+            .bind::<Array<Integer>, _>(&tokens)
+            .bind::<Array<Binary>, _>(&addresses)
+            .execute(self.connection())?;
+        diesel::sql_query("
+            DELETE FROM token_holder
+            WHERE (token, address) IN (SELECT * FROM UNNEST($1::INT[], $2::BYTEA[]))
+            AND NOT EXISTS (
+                SELECT 1
+                FROM tx_output
+                INNER JOIN tx ON tx.id = tx_output.tx
+                INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+                LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+                WHERE slp_tx.token = token_holder.token
+                AND tx_output.address = token_holder.address
+                AND tx_output.value_token_base > 0
+                AND tx_input.tx IS NULL
+            )
+        ")
+            .bind::<Array<Integer>, _>(&tokens)
+            .bind::<Array<Binary>, _>(&addresses)
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    fn _record_holder_counts(&self, token_ids: &[i32], height: i32) -> QueryResult<()> {
+        use diesel::sql_types::{Array, Integer};
+        diesel::sql_query("
+            INSERT INTO token_holder_count (token, height, holder_count)
+            SELECT tokens.token, $2, COUNT(token_holder.address)
+            FROM UNNEST($1::INT[]) AS tokens (token)
+            LEFT JOIN token_holder ON token_holder.token = tokens.token
+            GROUP BY tokens.token
+            ON CONFLICT (token, height) DO UPDATE SET holder_count = EXCLUDED.holder_count
+        ")
+            .bind::<Array<Integer>, _>(token_ids)
+            .bind::<Integer, _>(height)
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    fn _tx_tokens(&self, tx_ids: &[i64]) -> QueryResult<Vec<i32>> {
+        slp_tx::table
+            .filter(slp_tx::tx.eq_any(tx_ids))
+            .select(slp_tx::token)
+            .distinct()
+            .load(self.connection())
+    }
+
+    // recomputes the stats of the given tokens from their txs and holders
+    fn _update_token_stats(&self, token_ids: &[i32]) -> QueryResult<()> {
+        use diesel::sql_types::{Array, Integer};
+        if token_ids.is_empty() {
+            return Ok(());
+        }
+        diesel::sql_query("
+            INSERT INTO token_stats (token, holder_count, tx_count, utxo_count, last_active_height,
+                                     last_updated_height)
+            SELECT token.id,
+                   (SELECT COUNT(*) FROM token_holder WHERE token_holder.token = token.id),
+                   (SELECT COUNT(*) FROM slp_tx WHERE slp_tx.token = token.id),
+                   (SELECT COALESCE(SUM(token_holder.utxo_count), 0)
+                    FROM token_holder WHERE token_holder.token = token.id),
+                   (SELECT MAX(tx.height)
+                    FROM slp_tx INNER JOIN tx ON tx.id = slp_tx.tx
+                    WHERE slp_tx.token = token.id),
+                   COALESCE((SELECT MAX(height) FROM blocks WHERE is_main_chain), 0)
+            FROM token
+            WHERE token.id = ANY($1)
+            ON CONFLICT (token) DO UPDATE
+            SET holder_count = EXCLUDED.holder_count,
+                tx_count = EXCLUDED.tx_count,
+                utxo_count = EXCLUDED.utxo_count,
+                last_active_height = EXCLUDED.last_active_height,
+                last_updated_height = EXCLUDED.last_updated_height
+        ").bind::<Array<Integer>, _>(token_ids).execute(self.connection())?;
+        Ok(())
+    }
+
+    // stats are kept up to date with the txs; this recomputes those of tokens without stats and of
+    // tokens with txs at or after min_height or in the mempool, returning how many were updated
+    pub fn refresh_token_stats(&self, min_height: i32) -> QueryResult<usize> {
+        let mut token_ids: Vec<i32> = slp_tx::table
+            .inner_join(tx::table.on(tx::id.eq(slp_tx::tx)))
+            .filter(tx::height.is_null().or(tx::height.ge(min_height)))
+            .select(slp_tx::token)
+            .distinct()
+            .load(self.connection())?;
+        token_ids.extend(
+            token::table
+                .left_join(token_stats::table)
+                .filter(token_stats::token.is_null())
+                .select(token::id)
+                .load::<i32>(self.connection())?
+        );
+        self._update_token_stats(&token_ids)?;
+        Ok(token_ids.len())
+    }
+
+    // holders by descending balance, starting after the cursor
+    pub fn token_holders(&self, token_hash: &[u8; 32], cursor: Option<TokenHolderCursor>, limit: i64)
+            -> QueryResult<Vec<TokenHolder>> {
+        let mut query = token_holder::table
+            .inner_join(token::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .into_boxed();
+        if let Some(cursor) = cursor {
+            let balance_base: PgNumeric = cursor.balance.into();
+            query = query.filter(
+                token_holder::balance_base.lt(balance_base.clone())
+                    .or(token_holder::balance_base.eq(balance_base)
+                        .and(token_holder::address.gt(cursor.address.bytes().to_vec())))
+            );
+        }
+        let holders = query
+            .order((token_holder::balance_base.desc(), token_holder::address))
+            .limit(limit)
+            .select((token_holder::address, token_holder::balance_base, token_holder::utxo_count,
+                     token::decimals))
+            .load::<(Vec<u8>, PgNumeric, i32, i32)>(self.connection())?;
+        Ok(holders.into_iter()
+            .map(|(address, balance_base, utxo_count, decimals)| TokenHolder {
+                address: Address::from_bytes(AddressType::P2PKH, address_hash_from_slice(&address)),
+                balance: SLPAmount::from_numeric_decimals(&balance_base, decimals as u32),
+                utxo_count,
+            })
+            .collect())
+    }
+
+    pub fn token_holder_count(&self, token_hash: &[u8; 32]) -> QueryResult<i64> {
+        token_holder::table
+            .inner_join(token::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .count()
+            .get_result(self.connection())
+    }
+
+    pub fn address_token_balances(&self, address: &Address) -> QueryResult<Vec<TokenBalance>> {
+        let balances = token_holder::table
+            .inner_join(token::table)
+            .filter(token_holder::address.eq(address.bytes().to_vec()))
+            .order(token::hash)
+            .select((token::hash, token_holder::balance_base, token_holder::utxo_count, token::decimals))
+            .load::<(Vec<u8>, PgNumeric, i32, i32)>(self.connection())?;
+        Ok(balances.into_iter()
+            .map(|(token_hash, balance_base, utxo_count, decimals)| TokenBalance {
+                token_hash: tx_hash_from_slice(&token_hash),
+                balance: SLPAmount::from_numeric_decimals(&balance_base, decimals as u32),
+                utxo_count,
+            })
+            .collect())
+    }
+
+    pub fn token_holder_count_history(&self, token_hash: &[u8; 32], min_height: i32, max_height: i32)
+            -> QueryResult<Vec<HolderCount>> {
+        let counts = token_holder_count::table
+            .inner_join(token::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .filter(token_holder_count::height.ge(min_height))
+            .filter(token_holder_count::height.le(max_height))
+            .order(token_holder_count::height)
+            .select((token_holder_count::height, token_holder_count::holder_count))
+            .load::<(i32, i32)>(self.connection())?;
+        Ok(counts.into_iter()
+            .map(|(height, holder_count)| HolderCount { height, holder_count })
+            .collect())
+    }
+
+    fn _record_trades(&self, tx_history: &TxHistory, tx_ids: &[i64]) -> QueryResult<()> {
+        let spent_txs = tx_history.txs.iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.output_tx.to_vec()))
+            .collect::<HashSet<_>>();
+        let offers = trade_offer::table
+            .inner_join(tx::table)
+            .inner_join(slp_tx::table.on(tx::id.eq(slp_tx::tx)))
+            .inner_join(token::table.on(slp_tx::token.eq(token::id)))
+            .filter(tx::hash.eq_any(spent_txs.into_iter().collect::<Vec<_>>()))
+            .filter(trade_offer::output_idx.is_not_null())
+            .select((trade_offer::id, tx::hash, trade_offer::output_idx, trade_offer::price_per_token,
+                     trade_offer::sell_amount_token_base, trade_offer::receiving_address,
+                     token::id, token::decimals))
+            .load::<(i32, Vec<u8>, Option<i32>, PgNumeric, PgNumeric, Vec<u8>, i32, i32)>(self.connection())?
+            .into_iter()
+            .filter_map(|(trade_offer_id, tx_hash, output_idx, price_per_token, sell_amount_token_base,
+                          receiving_address, token_id, decimals)| {
+                Some(((tx_hash_from_slice(&tx_hash), output_idx?),
+                      (trade_offer_id, price_per_token, sell_amount_token_base, receiving_address,
+                       token_id, decimals)))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut spends = Vec::new();
+        let mut trades = Vec::new();
+        for (tx, tx_id) in tx_history.txs.iter().zip(tx_ids.iter().cloned()) {
+            for input in tx.inputs.iter() {
+                let (trade_offer_id, price_per_token, sell_amount_token_base, receiving_address,
+                     token_id, decimals) = match offers.get(&(input.output_tx, input.output_idx)) {
+                    Some(offer) => offer,
+                    None => continue,
+                };
+                let (price, amount) = match (pg_numeric_to_rational(price_per_token),
+                                             pg_numeric_to_integer(sell_amount_token_base)) {
+                    (Ok(price), Ok(amount)) => (price, amount),
+                    _ => continue,
+                };
+                let expected_satoshis = price * Rational::from(amount.clone()) /
+                    Rational::from(10u64.pow(*decimals as u32));
+                let paid_satoshis = if amount > 0 {
+                    _offer_fill(tx, receiving_address, &expected_satoshis)
+                } else {
+                    None
+                };
+                spends.push(models::TradeOfferSpend {
+                    trade_offer: *trade_offer_id,
+                    tx: tx_id,
+                    is_filled: paid_satoshis.is_some(),
+                });
+                let paid_satoshis = match paid_satoshis {
+                    Some(paid_satoshis) => paid_satoshis,
+                    None => continue,
+                };
+                trades.push(models::NewTrade {
+                    trade_offer: *trade_offer_id,
+                    tx: tx_id,
+                    token: *token_id,
+                    price_per_token: price_per_token.clone(),
+                    amount_token_base: sell_amount_token_base.clone(),
+                    value_satoshis: paid_satoshis as i64,
+                    buyer_address: tx.outputs.iter()
+                        .find(|output| output.value_token.base_amount() > 0)
+                        .and_then(|output| output.output.address())
+                        .map(|address| address.bytes().to_vec()),
+                    timestamp: tx.timestamp,
+                });
+            }
+        }
+        diesel::insert_into(trade_offer_spend::table)
+            .values(&spends)
+            .on_conflict_do_nothing()
+            .execute(self.connection())?;
+        diesel::insert_into(trade::table)
+            .values(&trades)
+            .on_conflict_do_nothing()
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    pub fn token_trades(&self, token_hash: &[u8; 32], min_timestamp: i64, max_timestamp: i64)
+            -> QueryResult<Vec<Trade>> {
+        Ok(trade::table
+            .inner_join(tx::table)
+            .inner_join(token::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .filter(trade::timestamp.ge(min_timestamp))
+            .filter(trade::timestamp.lt(max_timestamp))
+            .order((trade::timestamp.asc(), trade::tx.asc()))
+            .select((tx::hash, trade::price_per_token, trade::amount_token_base, trade::value_satoshis,
+                     trade::buyer_address, trade::timestamp, token::decimals))
+            .load::<(Vec<u8>, PgNumeric, PgNumeric, i64, Option<Vec<u8>>, i64, i32)>(self.connection())?
+            .into_iter()
+            .filter_map(|(tx_hash, price_per_token, amount_token_base, value_satoshis, buyer_address,
+                          timestamp, decimals)| {
+                Some(Trade {
+                    tx_hash: tx_hash_from_slice(&tx_hash),
+                    price_per_token: pg_numeric_to_rational(&price_per_token).ok()?,
+                    amount_token: SLPAmount::from_numeric_decimals(&amount_token_base, decimals as u32),
+                    value_satoshis,
+                    buyer_address: buyer_address.map(|address| {
+                        Address::from_bytes(AddressType::P2PKH, address_hash_from_slice(&address))
+                    }),
+                    timestamp,
+                })
+            })
+            .collect())
+    }
+
+    pub fn token_candles(&self,
+                         token_hash: &[u8; 32],
+                         resolution: CandleResolution,
+                         min_timestamp: i64,
+                         max_timestamp: i64) -> QueryResult<Vec<Candle>> {
+        let trades = self.token_trades(token_hash, min_timestamp, max_timestamp)?;
+        Ok(Candle::from_trades(&trades, resolution))
+    }
+
+    pub fn token_volume_24h(&self, token_hash: &[u8; 32], now: i64) -> QueryResult<Option<Candle>> {
+        let since = now - CandleResolution::Day.seconds();
+        let trades = self.token_trades(token_hash, since, now + 1)?;
+        Ok(Candle::aggregate(since, &trades))
+    }
+
+    fn _update_token_supplies(&self,
+                              tx_history: &TxHistory,
+                              tx_ids: &[i64],
+                              inserted_slp_txs: &HashSet<i64>,
+                              token_ids: &HashMap<Vec<u8>, i32>) -> QueryResult<()> {
+        let spent_outputs = tx_history.txs.iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| (input.output_tx, input.output_idx)))
+            .collect::<HashSet<_>>();
+        let input_tokens = tx_output::table
+            .inner_join(tx::table.inner_join(slp_tx::table))
+            .filter(tx::hash.eq_any(
+                spent_outputs.iter().map(|(tx_hash, _)| tx_hash.to_vec()).collect::<Vec<_>>()
+            ))
+            .select((tx::hash, tx_output::idx, slp_tx::token, tx_output::value_token_base))
+            .load::<(Vec<u8>, i32, i32, PgNumeric)>(self.connection())?
+            .into_iter()
+            .map(|(tx_hash, idx, token_id, value_token_base)| {
+                ((tx_hash_from_slice(&tx_hash), idx),
+                 (token_id, pg_numeric_to_i128(&value_token_base).unwrap_or(0)))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut burns = Vec::new();
+        let mut minted = HashMap::new();
+        let mut new_batons = Vec::new();
+        for (tx_idx, (tx, tx_id)) in tx_history.txs.iter().zip(tx_ids.iter().cloned()).enumerate() {
+            let mut token_inputs = HashMap::new();
+            for input in tx.inputs.iter() {
+                if let Some((token_id, amount)) = input_tokens.get(&(input.output_tx, input.output_idx)) {
+                    *token_inputs.entry(*token_id).or_insert(0i128) += amount;
+                }
+            }
+            let slp_tx = match &tx.tx_type {
+                TxType::SLP {token_hash, slp_type, ..} =>
+                    token_ids.get(&token_hash[..]).map(|token_id| (*token_id, slp_type)),
+                TxType::Default => None,
+            };
+            match slp_tx {
+                Some((token_id, SLPTxType::Send)) => {
+                    let burned_outputs = tx.outputs.iter()
+                        .filter(|output| match output.output {
+                            OutputType::Burned => true,
+                            _ => false,
+                        })
+                        .map(|output| output.value_token.base_amount())
+                        .sum::<i128>();
+                    let total_outputs = tx.outputs.iter()
+                        .map(|output| output.value_token.base_amount())
+                        .sum::<i128>();
+                    let input_amount = token_inputs.remove(&token_id).unwrap_or(0);
+                    token_inputs.insert(
+                        token_id,
+                        (input_amount - total_outputs).max(0) + burned_outputs,
+                    );
+                },
+                Some((token_id, slp_type @ SLPTxType::Genesis)) |
+                Some((token_id, slp_type @ SLPTxType::Mint)) if inserted_slp_txs.contains(&tx_id) => {
+                    if let SLPTxType::Mint = slp_type {
+                        let amount = tx.outputs.get(1)
+                            .map(|output| output.value_token.base_amount())
+                            .unwrap_or(0);
+                        *minted.entry(token_id).or_insert(0i128) += amount;
+                    }
+                    if let Some(baton_vout) = tx_history.mint_batons.get(&tx_idx) {
+                        new_batons.push((token_id, baton_vout.map(|vout| (tx.hash, vout))));
+                    }
+                },
+                _ => {},
+            }
+            burns.extend(
+                token_inputs.into_iter()
+                    .filter(|(_, amount)| *amount > 0)
+                    .map(|(token_id, amount)| models::TokenBurn {
+                        tx: tx_id,
+                        token: token_id,
+                        amount: i128_to_pg_numeric(amount),
+                    })
+            );
+        }
+        let inserted_burns = diesel::insert_into(token_burn::table)
+            .values(&burns)
+            .on_conflict_do_nothing()
+            .returning((token_burn::token, token_burn::amount))
+            .get_results::<(i32, PgNumeric)>(self.connection())?;
+        for (token_id, amount) in inserted_burns {
+            diesel::update(token_supply::table.filter(token_supply::token.eq(token_id)))
+                .set(token_supply::burned_supply.eq(token_supply::burned_supply + amount.clone()))
+                .execute(self.connection())?;
+            diesel::update(token::table.filter(token::id.eq(token_id)))
+                .set(token::current_supply.eq(token::current_supply - amount))
+                .execute(self.connection())?;
+        }
+        for (token_id, amount) in minted {
+            let amount = i128_to_pg_numeric(amount);
+            diesel::update(token_supply::table.filter(token_supply::token.eq(token_id)))
+                .set(token_supply::minted_supply.eq(token_supply::minted_supply + amount.clone()))
+                .execute(self.connection())?;
+            diesel::update(token::table.filter(token::id.eq(token_id)))
+                .set(token::current_supply.eq(token::current_supply + amount))
+                .execute(self.connection())?;
+        }
+        let mut baton_tokens = self._mint_baton_tokens(
+            &spent_outputs.iter().map(|(tx_hash, _)| tx_hash.to_vec()).collect::<Vec<_>>()
+        )?;
+        for (token_id, baton) in new_batons {
+            if let Some((tx_hash, vout)) = baton {
+                self._add_mint_baton(token_id, &tx_hash, vout)?;
+            }
+            baton_tokens.push(token_id);
+        }
+        self._update_mint_batons(&baton_tokens)
+    }
+
+    // undoes the supply changes of txs about to be deleted; returns the tokens whose baton has to be updated
+    fn _revert_token_supplies(&self, tx_ids: &[i64], tx_hashes: &[Vec<u8>]) -> QueryResult<Vec<i32>> {
+        use diesel::sql_types::{Array, BigInt, Binary};
+        diesel::sql_query("
+            WITH burned AS (
+                SELECT token, SUM(amount) AS amount FROM token_burn WHERE tx = ANY($1) GROUP BY token
+            ), supply AS (
+                UPDATE token_supply SET burned_supply = token_supply.burned_supply - burned.amount
+                FROM burned WHERE token_supply.token = burned.token
+            )
+            UPDATE token SET current_supply = token.current_supply + burned.amount
+            FROM burned WHERE token.id = burned.token
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        diesel::sql_query("
+            WITH minted AS (
+                SELECT slp_tx.token, SUM(tx_output.value_token_base) AS amount
+                FROM slp_tx
+                INNER JOIN tx_output ON tx_output.tx = slp_tx.tx AND tx_output.idx = 1
+                WHERE slp_tx.tx = ANY($1) AND slp_tx.slp_type = 'MINT'
+                GROUP BY slp_tx.token
+            ), supply AS (
+                UPDATE token_supply SET minted_supply = token_supply.minted_supply - minted.amount
+                FROM minted WHERE token_supply.token = minted.token
+            )
+            UPDATE token SET current_supply = token.current_supply - minted.amount
+            FROM minted WHERE token.id = minted.token
+        ").bind::<Array<BigInt>, _>(tx_ids).execute(self.connection())?;
+        let spent_tx_hashes: Vec<models::TxHash> = diesel::sql_query(
+            "SELECT DISTINCT output_tx AS hash FROM tx_input WHERE tx = ANY($1)"
+        ).bind::<Array<BigInt>, _>(tx_ids).load(self.connection())?;
+        let mut baton_tokens = self._mint_baton_tokens(
+            &spent_tx_hashes.into_iter().map(|tx| tx.hash).chain(tx_hashes.iter().cloned()).collect::<Vec<_>>()
+        )?;
+        baton_tokens.sort();
+        baton_tokens.dedup();
+        diesel::sql_query("DELETE FROM mint_baton WHERE tx_hash = ANY($1)")
+            .bind::<Array<Binary>, _>(tx_hashes)
+            .execute(self.connection())?;
+        Ok(baton_tokens)
+    }
+
+    fn _mint_baton_tokens(&self, tx_hashes: &[Vec<u8>]) -> QueryResult<Vec<i32>> {
+        use diesel::sql_types::{Array, Binary};
+        let tokens: Vec<models::TokenId> = diesel::sql_query(
+            "SELECT DISTINCT token FROM mint_baton WHERE tx_hash = ANY($1)"
+        ).bind::<Array<Binary>, _>(tx_hashes).load(self.connection())?;
+        Ok(tokens.into_iter().map(|token| token.token).collect())
+    }
+
+    fn _add_mint_baton(&self, token_id: i32, tx_hash: &[u8; 32], vout: i32) -> QueryResult<()> {
+        use diesel::sql_types::{Binary, Integer};
+        diesel::sql_query("
+            INSERT INTO mint_baton (token, tx_hash, idx) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+        ")
+            .bind::<Integer, _>(token_id)
+            .bind::<Binary, _>(tx_hash.to_vec())
+            .bind::<Integer, _>(vout)
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    // the current baton is the latest one not spent by any tx we know of
+    fn _update_mint_batons(&self, token_ids: &[i32]) -> QueryResult<()> {
+        use diesel::sql_types::{Array, Integer};
+        diesel::sql_query("
+            UPDATE token_supply SET mint_baton_tx = baton.tx_hash, mint_baton_idx = baton.idx
+            FROM token_supply supply
+            LEFT JOIN LATERAL (
+                SELECT mint_baton.tx_hash, mint_baton.idx
+                FROM mint_baton
+                WHERE mint_baton.token = supply.token
+                AND NOT EXISTS (
+                    SELECT 1 FROM tx_input
+                    WHERE tx_input.output_tx = mint_baton.tx_hash
+                    AND tx_input.output_idx = mint_baton.idx
+                )
+                ORDER BY mint_baton.id DESC
+                LIMIT 1
+            ) baton ON TRUE
+            WHERE supply.token = ANY($1) AND token_supply.token = supply.token
+        ").bind::<Array<Integer>, _>(token_ids).execute(self.connection())?;
+        Ok(())
+    }
+
+    // tokens whose supply was never synced from the chain source
+    pub fn unsynced_token_supplies(&self) -> QueryResult<Vec<[u8; 32]>> {
+        Ok(token::table
+            .inner_join(token_supply::table)
+            .filter(token_supply::is_synced.eq(false))
+            .select(token::hash)
+            .load::<Vec<u8>>(self.connection())?
+            .iter()
+            .map(|hash| tx_hash_from_slice(hash))
+            .collect())
+    }
+
+    pub fn confirm_block_txs(&self,
+                             block_hash: &[u8; 32],
+                             tx_hashes: &[[u8; 32]],
+                             height: i32,
+                             block_timestamp: i64,
+                             max_confirmations: i32) -> QueryResult<BlockConfirmations> {
+        self.connection().transaction(|| {
+            diesel::update(tx::table)
+                .filter(tx::hash.eq_any(
+                    tx_hashes.iter().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
+                ))
+                .set((tx::height.eq(Some(height)), tx::timestamp.eq(block_timestamp)))
+                .execute(self.connection())?;
+            diesel::update(token::table)
+                .filter(token::hash.eq_any(
+                    tx_hashes.iter().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
+                ))
+                .set(token::block_created_height.eq(height))
+                .execute(self.connection())?;
+            let token_ids: Vec<i32> = slp_tx::table
+                .inner_join(tx::table.on(tx::id.eq(slp_tx::tx)))
+                .filter(tx::hash.eq_any(
+                    tx_hashes.iter().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
+                ))
+                .select(slp_tx::token)
+                .distinct()
+                .load(self.connection())?;
+            self._record_holder_counts(&token_ids, height)?;
+            self._update_token_stats(&token_ids)?;
+            let txs: Vec<(i64, Vec<u8>, Option<i32>, i64)> = tx::table
+                .filter(tx::height.gt(height - max_confirmations))
+                .filter(tx::height.le(height))
+                .select((tx::id, tx::hash, tx::height, tx::timestamp))
+                .load(self.connection())?;
+            let confirmed_tx_hashes = txs.iter()
+                .map(|(id, hash, _, _)| (*id, tx_hash_from_slice(hash)))
+                .collect::<HashMap<_, _>>();
+            let (address_txs, token_txs) = self._affected_subjects(&confirmed_tx_hashes)?;
+            Ok(BlockConfirmations {
+                block_hash: *block_hash,
+                height,
+                confirmations: txs.into_iter()
+                    .filter_map(|(_, hash, tx_height, timestamp)| Some(TxConfirmation {
+                        tx_hash: tx_hash_from_slice(&hash),
+                        height: tx_height?,
+                        timestamp,
+                        confirmations: height - tx_height? + 1,
+                    }))
+                    .collect(),
+                address_txs,
+                token_txs,
+            })
+        })
+    }
+
+    pub fn peers(&self) -> QueryResult<Vec<models::Peer>> {
+        peer::table
+            .order((peer::n_failures.asc(), peer::last_seen.desc()))
+            .load::<models::Peer>(self.connection())
+    }
+
+    pub fn upsert_peers(&self, peers: &[models::Peer]) -> QueryResult<()> {
+        use diesel::pg::upsert::excluded;
+        diesel::insert_into(peer::table)
+            .values(peers)
+            .on_conflict((peer::ip, peer::port))
+            .do_update()
+            .set((
+                peer::last_seen.eq(excluded(peer::last_seen)),
+                peer::last_attempt.eq(excluded(peer::last_attempt)),
+                peer::n_failures.eq(excluded(peer::n_failures)),
+                peer::banned_until.eq(excluded(peer::banned_until)),
+            ))
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    // keeps the peers that come first in `peers`
+    pub fn prune_peers(&self, max_peers: i64) -> QueryResult<usize> {
+        use diesel::sql_types::BigInt;
+        diesel::sql_query("
+            DELETE FROM peer
+            WHERE (ip, port) NOT IN (
+                SELECT ip, port FROM peer
+                ORDER BY n_failures ASC, last_seen DESC
+                LIMIT $1
+            )
+        ").bind::<BigInt, _>(max_peers).execute(self.connection())
+    }
+
     pub fn last_update(&self, subject: UpdateSubject) -> QueryResult<Option<UpdateHistory>> {
         let query = update_history::table
             .filter(update_history::subject_type.eq(subject.subject_type as i32))
@@ -242,10 +1357,10 @@ impl Db {
         let update: Option<models::UpdateHistory> = match subject.hash.clone() {
             Some(subject_hash) => query
                 .filter(update_history::subject_hash.eq(subject_hash))
-                .first::<models::UpdateHistory>(&self.connection)
+                .first::<models::UpdateHistory>(self.connection())
                 .optional()?,
             None => query
-                .first::<models::UpdateHistory>(&self.connection)
+                .first::<models::UpdateHistory>(self.connection())
                 .optional()?,
         };
         Ok(update.map(|update| {
@@ -273,12 +1388,12 @@ impl Db {
                 completed: update_history.completed,
                 is_confirmed: update_history.subject.is_confirmed,
             })
-            .execute(&self.connection)?;
+            .execute(self.connection())?;
         Ok(())
     }
 
     pub fn add_tokens(&self, tokens: &[Token]) -> QueryResult<()> {
-        diesel::insert_into(token::table)
+        let token_ids = diesel::insert_into(token::table)
             .values(&tokens.iter()
                 .map(|token| {
                     models::NewToken {
@@ -305,14 +1420,124 @@ impl Db {
                 token::parent_token.eq(token::parent_token),
                 token::parent_token_hash.eq(token::parent_token_hash),
             ))
-            .execute(&self.connection)?;
+            .returning(token::id)
+            .get_results::<i32>(self.connection())?;
+        diesel::insert_into(token_supply::table)
+            .values(&token_ids.into_iter()
+                .map(|token_id| {
+                    models::TokenSupply {
+                        token: token_id,
+                        mint_baton_tx: None,
+                        mint_baton_idx: None,
+                        minted_supply: SLPAmount::new(0, 0).into(),
+                        burned_supply: SLPAmount::new(0, 0).into(),
+                        is_synced: true,
+                    }
+                })
+                .collect::<Vec<_>>()
+            )
+            .on_conflict_do_nothing()
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    pub fn add_token_supplies(&self, supplies: &[TokenSupply]) -> QueryResult<()> {
+        use diesel::sql_types::Integer;
+        let token_ids: HashMap<Vec<u8>, i32> = token::table
+            .select((token::hash, token::id))
+            .filter(token::hash.eq_any(
+                supplies.iter().map(|supply| supply.token_hash.to_vec()).collect::<Vec<_>>()
+            ))
+            .load(self.connection())?
+            .into_iter()
+            .collect();
+        for supply in supplies {
+            let token_id = match token_ids.get(&supply.token_hash[..]) {
+                Some(token_id) => *token_id,
+                None => continue,
+            };
+            let new_supply = models::TokenSupply {
+                token: token_id,
+                mint_baton_tx: supply.mint_baton.map(|(tx_hash, _)| tx_hash.to_vec()),
+                mint_baton_idx: supply.mint_baton.map(|(_, vout)| vout),
+                minted_supply: supply.minted_supply.into(),
+                burned_supply: supply.burned_supply.into(),
+                is_synced: true,
+            };
+            diesel::insert_into(token_supply::table)
+                .values(&new_supply)
+                .on_conflict(token_supply::token)
+                .do_update().set((
+                    token_supply::mint_baton_tx.eq(new_supply.mint_baton_tx.clone()),
+                    token_supply::mint_baton_idx.eq(new_supply.mint_baton_idx),
+                    token_supply::minted_supply.eq(new_supply.minted_supply.clone()),
+                    token_supply::burned_supply.eq(new_supply.burned_supply.clone()),
+                    token_supply::is_synced.eq(true),
+                ))
+                .execute(self.connection())?;
+            // the source knows the current baton, older ones we saw must not come back on a rollback
+            diesel::sql_query("DELETE FROM mint_baton WHERE token = $1")
+                .bind::<Integer, _>(token_id)
+                .execute(self.connection())?;
+            if let Some((tx_hash, vout)) = supply.mint_baton {
+                self._add_mint_baton(token_id, &tx_hash, vout)?;
+            }
+            diesel::update(token::table.filter(token::id.eq(token_id)))
+                .set(token::current_supply.eq::<PgNumeric>(supply.circulating_supply.into()))
+                .execute(self.connection())?;
+        }
         Ok(())
     }
 
+    pub fn token_stats(&self, token_hash: &[u8; 32]) -> QueryResult<Option<TokenStats>> {
+        let stats = token::table
+            .inner_join(token_stats::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .select(token_stats::all_columns)
+            .first::<models::TokenStats>(self.connection())
+            .optional()?;
+        Ok(stats.map(|stats| TokenStats {
+            token_hash: token_hash.clone(),
+            holder_count: stats.holder_count,
+            tx_count: stats.tx_count,
+            utxo_count: stats.utxo_count,
+            last_active_height: stats.last_active_height,
+            last_updated_height: stats.last_updated_height,
+        }))
+    }
+
+    pub fn token_supply(&self, token_hash: &[u8; 32]) -> QueryResult<Option<TokenSupply>> {
+        let supply = token::table
+            .inner_join(token_supply::table)
+            .filter(token::hash.eq(token_hash.to_vec()))
+            .select((token::decimals, token::initial_supply, token::current_supply,
+                     token_supply::all_columns))
+            .first::<(i32, PgNumeric, PgNumeric, models::TokenSupply)>(self.connection())
+            .optional()?;
+        Ok(supply.map(|(decimals, initial_supply, current_supply, supply)| {
+            let decimals = decimals as u32;
+            TokenSupply {
+                token_hash: token_hash.clone(),
+                mint_baton: match (supply.mint_baton_tx, supply.mint_baton_idx) {
+                    (Some(tx_hash), Some(vout)) => Some((tx_hash_from_slice(&tx_hash), vout)),
+                    _ => None,
+                },
+                initial_supply: SLPAmount::from_numeric_decimals(&initial_supply, decimals),
+                minted_supply: SLPAmount::from_numeric_decimals(&supply.minted_supply, decimals),
+                burned_supply: SLPAmount::from_numeric_decimals(&supply.burned_supply, decimals),
+                circulating_supply: SLPAmount::from_numeric_decimals(&current_supply, decimals),
+            }
+        }))
+    }
+
+    pub fn token_mint_baton(&self, token_hash: &[u8; 32]) -> QueryResult<Option<([u8; 32], i32)>> {
+        Ok(self.token_supply(token_hash)?.and_then(|supply| supply.mint_baton))
+    }
+
     pub fn token(&self, token_hash: &[u8; 32]) -> QueryResult<Option<Token>> {
         let token: Option<models::Token> = token::table
             .filter(token::hash.eq(token_hash.to_vec()))
-            .first::<models::Token>(&self.connection)
+            .first::<models::Token>(self.connection())
             .optional()?;
         Ok(token.and_then(|token| {
             Some(Token {
@@ -334,35 +1559,41 @@ impl Db {
         }))
     }
 
-    pub fn update_utxo_set(&self, address: &cashcontracts::Address) -> QueryResult<()> {
-        self.connection.transaction(|| {
+    fn _insert_address_utxos(&self, address: &Address) -> QueryResult<()> {
+        diesel::insert_into(utxo_address::table)
+            .values(
+                tx_output::table
+                    .left_join(tx::table)
+                    .left_outer_join(tx_input::table.on(
+                        tx::hash.eq(tx_input::output_tx)
+                            .and(tx_output::idx.eq(tx_input::output_idx))
+                    ))
+                    .filter(tx_input::tx.is_null())
+                    .filter(tx_output::address.eq(address.bytes().to_vec()))
+                    .select((tx_output::tx, tx_output::idx, tx_output::address))
+            )
+            .on_conflict_do_nothing()
+            .execute(self.connection())?;
+        Ok(())
+    }
+
+    // utxos are maintained by add_tx_history; the rebuilds are only for repairing the tables
+    pub fn rebuild_utxo_set(&self, address: &Address) -> QueryResult<()> {
+        self.connection().transaction(|| {
             diesel::delete(utxo_address::table)
                 .filter(
                     utxo_address::address.eq(address.bytes().to_vec())
                 )
-                .execute(&self.connection)?;
-            diesel::insert_into(utxo_address::table)
-                .values(
-                    tx_output::table
-                        .left_join(tx::table)
-                        .left_outer_join(tx_input::table.on(
-                            tx::hash.eq(tx_input::output_tx)
-                                .and(tx_output::idx.eq(tx_input::output_idx))
-                        ))
-                        .filter(tx_input::tx.is_null())
-                        .filter(tx_output::address.eq(address.bytes().to_vec()))
-                        .select((tx_output::tx, tx_output::idx, tx_output::address))
-                )
-                .execute(&self.connection)?;
-            Ok(())
+                .execute(self.connection())?;
+            self._insert_address_utxos(address)
         })
     }
 
-    pub fn update_utxo_set_exch(&self) -> QueryResult<()> {
+    pub fn rebuild_utxo_set_exch(&self) -> QueryResult<()> {
         use diesel::dsl::*;
-        self.connection.transaction(|| {
+        self.connection().transaction(|| {
             diesel::delete(utxo_trade_offer::table)
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
             diesel::insert_into(utxo_trade_offer::table)
                 .values(
                     tx_output::table
@@ -380,11 +1611,68 @@ impl Db {
                         .select((tx_output::tx, tx_output::idx))
                 )
                 .on_conflict_do_nothing()  // this shouldn't happen
-                .execute(&self.connection)?;
+                .execute(self.connection())?;
             Ok(())
         })
     }
 
+    // for outputs known to be spent without the spending tx being stored
+    pub fn remove_spent_utxos(&self, utxos: &[SpentUtxo]) -> QueryResult<()> {
+        let txs = self.txs(utxos.iter().map(|utxo| utxo.tx_hash))?;
+        self.connection().transaction(|| {
+            for utxo in utxos {
+                let tx = match txs.get(&utxo.tx_hash) {
+                    Some(tx) => tx,
+                    None => continue,
+                };
+                diesel::delete(utxo_address::table)
+                    .filter(utxo_address::tx.eq(tx.id).and(utxo_address::idx.eq(utxo.vout)))
+                    .execute(self.connection())?;
+                diesel::delete(utxo_trade_offer::table)
+                    .filter(utxo_trade_offer::tx.eq(tx.id).and(utxo_trade_offer::idx.eq(utxo.vout)))
+                    .execute(self.connection())?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn rebuild_token_holders(&self) -> QueryResult<()> {
+        self.connection().transaction(|| {
+            diesel::delete(token_holder::table)
+                .execute(self.connection())?;
+            diesel::sql_query("
+                INSERT INTO token_holder (token, address, balance_base, utxo_count)
+                SELECT slp_tx.token, tx_output.address, SUM(tx_output.value_token_base), COUNT(*)
+                FROM tx_output
+                INNER JOIN tx ON tx.id = tx_output.tx
+                INNER JOIN slp_tx ON slp_tx.tx = tx_output.tx
+                LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+                WHERE tx_output.address IS NOT NULL
+                AND tx_output.value_token_base > 0
+                AND tx_input.tx IS NULL
+                GROUP BY slp_tx.token, tx_output.address
+            ").execute(self.connection())?;
+            Ok(())
+        })
+    }
+
+    pub fn rebuild_utxo_sets(&self) -> QueryResult<()> {
+        self.connection().transaction(|| {
+            diesel::delete(utxo_address::table)
+                .execute(self.connection())?;
+            diesel::sql_query("
+                INSERT INTO utxo_address (tx, idx, address)
+                SELECT tx_output.tx, tx_output.idx, tx_output.address
+                FROM tx_output
+                INNER JOIN tx ON tx.id = tx_output.tx
+                INNER JOIN active_address ON active_address.address = tx_output.address
+                LEFT JOIN tx_input ON tx_input.output_tx = tx.hash AND tx_input.output_idx = tx_output.idx
+                WHERE tx_input.tx IS NULL
+            ").execute(self.connection())?;
+            self.rebuild_utxo_set_exch()
+        })
+    }
+
     pub fn utxos_address(&self, address: &Address) -> QueryResult<Vec<Utxo>> {
         let result = tx_output::table
             .inner_join(utxo_address::table.on(
@@ -400,7 +1688,7 @@ impl Db {
                      tx_output::value_token_base,
                      token::hash.nullable(),
                      token::decimals.nullable()))
-            .load::<(Vec<u8>, i32, i64, PgNumeric, Option<Vec<u8>>, Option<i32>)>(&self.connection)?;
+            .load::<(Vec<u8>, i32, i64, PgNumeric, Option<Vec<u8>>, Option<i32>)>(self.connection())?;
         Ok(result.into_iter()
             .map(|(tx_hash, vout, value_satoshis, value_token_base, token_hash, decimals)| {
                 let slp_amount = decimals.map(
@@ -419,8 +1707,40 @@ impl Db {
             .collect())
     }
 
-    pub fn address_tx_deltas(&self, address: &Address) -> QueryResult<Vec<TxDelta>> {
-        use diesel::sql_types::Binary;
+    pub fn address_tx_deltas(&self,
+                             address: &Address,
+                             filter: &TxHistoryFilter,
+                             cursor: Option<TxHistoryCursor>,
+                             limit: i64) -> QueryResult<TxHistoryPage> {
+        use diesel::sql_types::{Binary, BigInt, Bool, Nullable, Array};
+        let tx_rows = diesel::sql_query("
+            SELECT tx.id AS id, tx.hash AS hash, tx.timestamp AS timestamp
+            FROM tx
+                LEFT JOIN slp_tx ON (tx.id = slp_tx.tx)
+                LEFT JOIN token  ON (token.id = slp_tx.token)
+            WHERE tx.id IN (SELECT tx_input.tx  FROM tx_input  WHERE tx_input.address = $1
+                            UNION
+                            SELECT tx_output.tx FROM tx_output WHERE tx_output.address = $1)
+            AND ($2::BIGINT IS NULL OR (tx.timestamp, tx.hash) < ($2, $3))
+            AND ($4::BYTEA IS NULL OR token.hash = $4)
+            AND (NOT $5 OR slp_tx.tx IS NULL)
+            AND ($6::BOOLEAN IS NULL OR (tx.height IS NOT NULL) = $6)
+            ORDER BY tx.timestamp DESC, tx.hash DESC
+            LIMIT $7
+        ")
+            .bind::<Binary, _>(address.bytes().to_vec())
+            .bind::<Nullable<BigInt>, _>(cursor.map(|cursor| cursor.timestamp))
+            .bind::<Nullable<Binary>, _>(cursor.map(|cursor| cursor.tx_hash.to_vec()))
+            .bind::<Nullable<Binary>, _>(filter.token_hash.map(|token_hash| token_hash.to_vec()))
+            .bind::<Bool, _>(filter.bch_only)
+            .bind::<Nullable<Bool>, _>(filter.confirmed)
+            .bind::<BigInt, _>(limit)
+            .load::<models::TxHistoryId>(self.connection())?;
+        // the page ends at the last matching tx, even if it's dropped from the deltas below
+        let next_cursor = tx_rows.last()
+            .filter(|_| tx_rows.len() as i64 == limit)
+            .map(|tx| TxHistoryCursor { timestamp: tx.timestamp, tx_hash: tx_hash_from_slice(&tx.hash) });
+        let tx_ids = tx_rows.iter().map(|tx| tx.id).collect::<Vec<_>>();
         let input_query = diesel::sql_query("\
             SELECT
                 tx.id AS tx_id,
@@ -439,9 +1759,9 @@ impl Db {
                 LEFT JOIN tx_output AS tx_input_output ON (tx_input_tx.id = tx_input_output.tx AND
                                                            tx_input.output_idx = tx_input_output.idx)
             WHERE
-                tx_input.address = $1
+                tx_input.address = $1 AND tx.id = ANY($2)
             GROUP BY tx.id, tx.hash, token.hash, token.decimals
-        ").bind::<Binary, _>(address.bytes().to_vec());
+        ").bind::<Binary, _>(address.bytes().to_vec()).bind::<Array<BigInt>, _>(&tx_ids);
         let output_query = diesel::sql_query("\
             SELECT
                 tx.id AS tx_id,
@@ -457,26 +1777,27 @@ impl Db {
                 LEFT JOIN tx_output                    ON (tx.id = tx_output.tx AND
                                                            tx_output.address = $1)
             WHERE
-                tx_output.address = $1
+                tx_output.address = $1 AND tx.id = ANY($2)
             GROUP BY tx.id, tx.hash, token.hash, token.decimals
-        ").bind::<Binary, _>(address.bytes().to_vec());
+        ").bind::<Binary, _>(address.bytes().to_vec()).bind::<Array<BigInt>, _>(&tx_ids);
         let mut result_input = input_query
-            .load::<models::TxDeltaInput>(&self.connection)?
+            .load::<models::TxDeltaInput>(self.connection())?
             .into_iter()
             .map(|delta_input| (delta_input.tx_id, delta_input))
             .collect::<HashMap<_, _>>();
         let mut result_output = output_query
-            .load::<models::TxDeltaOutput>(&self.connection)?
+            .load::<models::TxDeltaOutput>(self.connection())?
             .into_iter()
             .map(|delta_output| (delta_output.tx_id, delta_output))
             .collect::<HashMap<_, _>>();
-        let tx_ids = result_input.keys().cloned()
-            .chain(result_output.keys().cloned())
-            .collect::<HashSet<_>>();
-        Ok(tx_ids.into_iter()
-            .map(|tx_id| {
-                let delta_input = result_input.remove(&tx_id);
-                let delta_output = result_output.remove(&tx_id);
+        let tx_deltas = tx_ids.iter()
+            .filter_map(|tx_id| {
+                let delta_input = result_input.remove(tx_id);
+                let delta_output = result_output.remove(tx_id);
+                if delta_input.is_none() && delta_output.is_none() {
+                    // removed by a double spend in between the queries
+                    return None;
+                }
                 let (decimals, token_hash) = delta_input.as_ref()
                     .and_then(
                         |delta_input| Some((delta_input.decimals?,
@@ -505,7 +1826,7 @@ impl Db {
                     .unwrap_or((0, zero));
                 let delta_satoshis = output_value_satoshis - input_value_satoshis;
                 let delta_token = output_value_token - input_value_token;
-                TxDelta {
+                Some(TxDelta {
                     tx_hash: tx_hash_from_slice(
                         &delta_output.as_ref()
                             .map(|delta_output| delta_output.tx_hash.clone())
@@ -518,9 +1839,10 @@ impl Db {
                     timestamp: delta_output.as_ref()
                         .map(|delta_output| delta_output.timestamp)
                         .unwrap_or_else(|| delta_input.as_ref().unwrap().timestamp),
-                }
+                })
             })
-            .collect())
+            .collect::<Vec<_>>();
+        Ok(TxHistoryPage { tx_deltas, next_cursor })
     }
 
     pub fn trade_offer_utxos(&self, filter: TradeOfferFilter) -> QueryResult<Vec<TradeOffer>> {
@@ -539,11 +1861,11 @@ impl Db {
             .select(s);
         let result = match filter {
             TradeOfferFilter::TokenHash(token_hash) => tables
-                .filter(tx::hash.eq(token_hash.to_vec()))
-                .load::<Q>(&self.connection)?,
+                .filter(token::hash.eq(token_hash.to_vec()))
+                .load::<Q>(self.connection())?,
             TradeOfferFilter::ReceivingAddress(address) => tables
                 .filter(trade_offer::receiving_address.eq(address.bytes().to_vec()))
-                .load::<Q>(&self.connection)?,
+                .load::<Q>(self.connection())?,
         };
         Ok(result
             .into_iter()
@@ -573,7 +1895,7 @@ impl Db {
             .filter(tx::hash.eq_any(
                 tx_hashes.map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
             ))
-            .load::<models::Tx>(&self.connection)?
+            .load::<models::Tx>(self.connection())?
             .into_iter()
             .map(|tx| (tx_hash_from_slice(&tx.hash), tx))
             .collect())
@@ -587,7 +1909,7 @@ impl Db {
             .filter(tx::hash.eq_any(
                 tx_hashes.map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
             ))
-            .load::<(models::Tx, models::SlpTx, models::Token)>(&self.connection)?
+            .load::<(models::Tx, models::SlpTx, models::Token)>(self.connection())?
             .into_iter()
             .map(|(tx, slp_tx, token)| (tx_hash_from_slice(&tx.hash), (tx, slp_tx, token)))
             .collect())
@@ -602,7 +1924,7 @@ impl Db {
             ))
             .select((tx::hash, tx_output::tx, tx_output::idx, tx_output::value_satoshis,
                      tx_output::value_token_base, tx_output::address, tx_output::output_type))
-            .load::<(Vec<u8>, i64, i32, i64, PgNumeric, Option<Vec<u8>>, i32)>(&self.connection)?
+            .load::<(Vec<u8>, i64, i32, i64, PgNumeric, Option<Vec<u8>>, i32)>(self.connection())?
             .into_iter()
             .map(|(hash, tx, idx, value_satoshis, value_token_base, address, output_type)|
                 ((tx_hash_from_slice(&hash), idx),
@@ -611,73 +1933,810 @@ impl Db {
             .collect())
     }
 
-    pub fn remove_utxos(&self, utxos: &[SpentUtxo]) -> QueryResult<()> {
-        let txs = self.txs(utxos.iter().map(|utxo| utxo.tx_hash))?;
-        for utxo in utxos {
-            let tx = &txs[&utxo.tx_hash];
-            diesel::delete(utxo_address::table)
-                .filter(utxo_address::tx.eq(tx.id).and(utxo_address::idx.eq(utxo.vout)))
-                .execute(&self.connection)?;
-            diesel::delete(utxo_trade_offer::table)
-                .filter(utxo_trade_offer::tx.eq(tx.id).and(utxo_trade_offer::idx.eq(utxo.vout)))
-                .execute(&self.connection)?;
+    pub fn unconfirmed_txs(&self) -> QueryResult<Vec<UnconfirmedTx>> {
+        let txs: Vec<(i64, Vec<u8>, i64)> = tx::table
+            .filter(tx::height.is_null())
+            .select((tx::id, tx::hash, tx::timestamp))
+            .load(self.connection())?;
+        self._unconfirmed_txs(txs)
+    }
+
+    fn _unconfirmed_txs(&self, txs: Vec<(i64, Vec<u8>, i64)>) -> QueryResult<Vec<UnconfirmedTx>> {
+        let tx_ids = txs.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+        let inputs: Vec<(i64, Vec<u8>, i32)> = tx_input::table
+            .filter(tx_input::tx.eq_any(&tx_ids))
+            .order((tx_input::tx, tx_input::idx))
+            .select((tx_input::tx, tx_input::output_tx, tx_input::output_idx))
+            .load(self.connection())?;
+        let outputs: Vec<(i64, i32)> = tx_output::table
+            .filter(tx_output::tx.eq_any(&tx_ids))
+            .select((tx_output::tx, tx_output::idx))
+            .load(self.connection())?;
+        let mut tx_inputs = HashMap::new();
+        for (tx_id, output_tx, output_idx) in inputs {
+            tx_inputs.entry(tx_id).or_insert_with(Vec::new).push(SpentUtxo {
+                tx_hash: tx_hash_from_slice(&output_tx),
+                vout: output_idx,
+            });
         }
-        Ok(())
+        let mut tx_n_outputs = HashMap::new();
+        for (tx_id, idx) in outputs {
+            let n_outputs = tx_n_outputs.entry(tx_id).or_insert(0);
+            *n_outputs = (*n_outputs).max(idx + 1);
+        }
+        Ok(txs.into_iter()
+            .map(|(tx_id, hash, timestamp)| UnconfirmedTx {
+                tx_hash: tx_hash_from_slice(&hash),
+                timestamp,
+                inputs: tx_inputs.remove(&tx_id).unwrap_or_default(),
+                n_outputs: tx_n_outputs.get(&tx_id).cloned().unwrap_or(0),
+            })
+            .collect())
     }
 
-    pub fn add_utxos(&self, utxos: &[NewUtxo]) -> QueryResult<()> {
-        let txs = self.txs(utxos.iter().map(|utxo| match utxo {
-            NewUtxo::Address {tx_hash, ..} => tx_hash.clone(),
-            NewUtxo::TradeOffer {tx_hash, ..} => tx_hash.clone(),
-        }))?;
-        diesel::insert_into(utxo_address::table)
-            .values(utxos.iter()
-                .filter_map(|utxo| match utxo {
-                    NewUtxo::Address {tx_hash, vout, address} => Some(models::UtxoAddress {
-                        tx: txs[tx_hash].id,
-                        idx: *vout,
-                        address: Some(address.bytes().to_vec()),
-                    }),
-                    NewUtxo::TradeOffer {..} => None,
-                })
-                .collect::<Vec<_>>()
-            )
-            .execute(&self.connection)?;
-        diesel::insert_into(utxo_trade_offer::table)
-            .values(utxos.iter()
-                .filter_map(|utxo| match utxo {
-                    NewUtxo::Address {..} => None,
-                    NewUtxo::TradeOffer {tx_hash, vout} => Some(models::Utxo {
-                        tx: txs[tx_hash].id,
-                        idx: *vout,
-                    }),
-                })
-                .collect::<Vec<_>>()
-            )
-            .execute(&self.connection)?;
+    // removes the unconfirmed txs and everything spending their outputs; trades, trade offer spends and
+    // pending PND1s go with the txs, spent outputs, token holders and supplies are restored
+    pub fn remove_double_spent_txs(&self, tx_hashes: &[[u8; 32]]) -> QueryResult<DoubleSpentUtxos> {
+        use diesel::sql_types::{Array, BigInt, Binary};
+        self.connection().transaction(|| {
+            let txs: Vec<models::TxIdHash> = diesel::sql_query("
+                WITH RECURSIVE removed (id, hash) AS (
+                    SELECT id, hash FROM tx WHERE hash = ANY($1) AND height IS NULL
+                    UNION
+                    SELECT tx.id, tx.hash
+                    FROM removed
+                    INNER JOIN tx_input ON tx_input.output_tx = removed.hash
+                    INNER JOIN tx ON tx.id = tx_input.tx
+                    WHERE tx.height IS NULL
+                )
+                SELECT id, hash FROM removed
+            ")
+                .bind::<Array<Binary>, _>(tx_hashes.iter().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>())
+                .load(self.connection())?;
+            let tx_ids = txs.iter().map(|tx| tx.id).collect::<Vec<_>>();
+            let removed_tx_hashes = txs.iter()
+                .map(|tx| (tx.id, tx_hash_from_slice(&tx.hash)))
+                .collect::<HashMap<_, _>>();
+            let address_utxos: Vec<(i64, i32, Option<Vec<u8>>)> = utxo_address::table
+                .filter(utxo_address::tx.eq_any(&tx_ids))
+                .select((utxo_address::tx, utxo_address::idx, utxo_address::address))
+                .load(self.connection())?;
+            let trade_offer_utxos: Vec<(i64, i32)> = utxo_trade_offer::table
+                .filter(utxo_trade_offer::tx.eq_any(&tx_ids))
+                .select((utxo_trade_offer::tx, utxo_trade_offer::idx))
+                .load(self.connection())?;
+            let tx_tokens: HashMap<i64, Vec<u8>> = slp_tx::table
+                .inner_join(token::table)
+                .filter(slp_tx::tx.eq_any(&tx_ids))
+                .select((slp_tx::tx, token::hash))
+                .load(self.connection())?
+                .into_iter()
+                .collect();
+            diesel::sql_query("
+                INSERT INTO utxo_address (tx, idx, address)
+                SELECT tx_output.tx, tx_output.idx, tx_output.address
+                FROM tx_input
+                INNER JOIN tx ON tx.hash = tx_input.output_tx
+                INNER JOIN tx_output ON tx_output.tx = tx.id AND tx_output.idx = tx_input.output_idx
+                INNER JOIN active_address ON active_address.address = tx_output.address
+                WHERE tx_input.tx = ANY($1)
+                AND NOT EXISTS (
+                    SELECT 1 FROM tx_input other_input
+                    WHERE other_input.output_tx = tx_input.output_tx
+                    AND other_input.output_idx = tx_input.output_idx
+                    AND other_input.tx <> ALL($1)
+                )
+                ON CONFLICT DO NOTHING
+            ").bind::<Array<BigInt>, _>(&tx_ids).execute(self.connection())?;
+            let restored_offer_tokens: Vec<models::TxHash> = diesel::sql_query("
+                WITH restored AS (
+                    INSERT INTO utxo_trade_offer (tx, idx)
+                    SELECT tx_output.tx, tx_output.idx
+                    FROM tx_input
+                    INNER JOIN tx ON tx.hash = tx_input.output_tx
+                    INNER JOIN tx_output ON tx_output.tx = tx.id AND tx_output.idx = tx_input.output_idx
+                    INNER JOIN trade_offer ON trade_offer.tx = tx.id AND trade_offer.output_idx = tx_output.idx
+                    WHERE tx_input.tx = ANY($1)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM tx_input other_input
+                        WHERE other_input.output_tx = tx_input.output_tx
+                        AND other_input.output_idx = tx_input.output_idx
+                        AND other_input.tx <> ALL($1)
+                    )
+                    ON CONFLICT DO NOTHING
+                    RETURNING tx
+                )
+                SELECT DISTINCT token.hash
+                FROM restored
+                INNER JOIN slp_tx ON slp_tx.tx = restored.tx
+                INNER JOIN token ON token.id = slp_tx.token
+            ").bind::<Array<BigInt>, _>(&tx_ids).load(self.connection())?;
+            let token_holders = self._affected_token_holders(&tx_ids)?;
+            let mut stats_tokens = self._tx_tokens(&tx_ids)?;
+            stats_tokens.extend(token_holders.iter().map(|holder| holder.token));
+            let baton_tokens = self._revert_token_supplies(
+                &tx_ids,
+                &txs.iter().map(|tx| tx.hash.clone()).collect::<Vec<_>>(),
+            )?;
+            diesel::delete(tx::table)
+                .filter(tx::id.eq_any(&tx_ids))
+                .execute(self.connection())?;
+            // a token goes with its GENESIS
+            diesel::sql_query("
+                DELETE FROM token
+                WHERE hash = ANY($1)
+                AND NOT EXISTS (SELECT 1 FROM slp_tx WHERE slp_tx.token = token.id)
+            ")
+                .bind::<Array<Binary>, _>(txs.iter().map(|tx| tx.hash.clone()).collect::<Vec<_>>())
+                .execute(self.connection())?;
+            self._update_token_holders(&token_holders)?;
+            self._update_mint_batons(&baton_tokens)?;
+            self._update_token_stats(&stats_tokens)?;
+            let mut double_spent = DoubleSpentUtxos {
+                tx_hashes: removed_tx_hashes.values().cloned().collect(),
+                trade_offer_tokens: restored_offer_tokens.iter()
+                    .map(|token| tx_hash_from_slice(&token.hash))
+                    .collect(),
+                ..Default::default()
+            };
+            for (tx_id, idx, address) in address_utxos {
+                let address = match address {
+                    Some(address) => address_hash_from_slice(&address),
+                    None => continue,
+                };
+                double_spent.address_utxos
+                    .entry(Address::from_bytes(AddressType::P2PKH, address))
+                    .or_insert_with(Vec::new)
+                    .push(SpentUtxo { tx_hash: removed_tx_hashes[&tx_id], vout: idx });
+            }
+            for (tx_id, idx) in trade_offer_utxos {
+                let token_hash = match tx_tokens.get(&tx_id) {
+                    Some(token_hash) => tx_hash_from_slice(token_hash),
+                    None => continue,
+                };
+                double_spent.trade_offer_tokens.insert(token_hash);
+                double_spent.trade_offer_utxos
+                    .entry(token_hash)
+                    .or_insert_with(Vec::new)
+                    .push(SpentUtxo { tx_hash: removed_tx_hashes[&tx_id], vout: idx });
+            }
+            Ok(double_spent)
+        })
+    }
+
+    pub fn slp_validity(&self, tx_hash: &[u8; 32]) -> QueryResult<Option<models::SlpValidity>> {
+        slp_validity::table
+            .filter(slp_validity::tx_hash.eq(tx_hash.to_vec()))
+            .first::<models::SlpValidity>(self.connection())
+            .optional()
+    }
+
+    pub fn add_slp_validities(&self, validities: &[models::SlpValidity]) -> QueryResult<()> {
+        diesel::insert_into(slp_validity::table)
+            .values(validities)
+            .on_conflict_do_nothing()
+            .execute(self.connection())?;
         Ok(())
     }
 
+    // PND1 txs without a panda yet; a reorg deletes the panda, which makes its PND1 pending again
     pub fn pending_pnd(&self) -> QueryResult<Vec<(models::PND1Tx, models::Tx)>> {
         pending_pnd1_tx::table
             .inner_join(tx::table)
-            .load(&self.connection)
+            .left_join(panda_birth::table.on(panda_birth::pnd1_tx.eq(pending_pnd1_tx::tx)))
+            .filter(panda_birth::panda.is_null())
+            .select((pending_pnd1_tx::all_columns, tx::all_columns))
+            .load(self.connection())
     }
 
     pub fn get_some_pandaop_utxo(&self) -> QueryResult<Option<models::PandaopUtxo>> {
         let utxo = pandaop_utxo::table
             .limit(1)
-            .get_result::<models::PandaopUtxo>(&self.connection)
+            .get_result::<models::PandaopUtxo>(self.connection())
             .optional()?;
         match utxo {
             Some(utxo) => {
                 diesel::delete(pandaop_utxo::table)
                     .filter(pandaop_utxo::tx_hash.eq(utxo.tx_hash.clone()))
                     .filter(pandaop_utxo::vout.eq(utxo.vout))
-                    .execute(&self.connection)?;
+                    .execute(self.connection())?;
                 Ok(Some(utxo))
             },
             None => Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    // #[ignore]d tests require a migrated database at DATABASE_URL; all changes are rolled back
+    use super::*;
+    use crate::tx_history::{HistoricTx, HistoricTxInput, HistoricTxOutput};
+
+    fn tx(hash: u8, inputs: &[(u8, i32)], outputs: Vec<OutputType>) -> HistoricTx {
+        HistoricTx {
+            hash: [hash; 32],
+            height: None,
+            timestamp: 0,
+            tx_type: TxType::Default,
+            inputs: inputs.iter()
+                .map(|&(output_tx, output_idx)| HistoricTxInput {
+                    output_tx: [output_tx; 32],
+                    output_idx,
+                    output: OutputType::Unknown,
+                })
+                .collect(),
+            outputs: outputs.into_iter()
+                .map(|output| HistoricTxOutput {
+                    value_satoshis: 1000,
+                    value_token: SLPAmount::new(0, 0),
+                    output,
+                })
+                .collect(),
+        }
+    }
+
+    fn slp_tx(hash: u8, inputs: &[(u8, i32)], outputs: Vec<(OutputType, i128)>, token_hash: u8,
+              slp_type: SLPTxType) -> HistoricTx {
+        let values = outputs.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+        let mut tx = tx(hash, inputs, outputs.into_iter().map(|(output, _)| output).collect());
+        tx.tx_type = TxType::SLP {
+            token_hash: [token_hash; 32],
+            token_type: TokenType::Standard,
+            slp_type,
+        };
+        for (output, value) in tx.outputs.iter_mut().zip(values) {
+            output.value_token = SLPAmount::new(value, 0);
+        }
+        tx
+    }
+
+    fn token(hash: u8) -> Token {
+        Token {
+            hash: [hash; 32],
+            parent_hash: None,
+            decimals: 0,
+            timestamp: 0,
+            version_type: TokenType::Standard,
+            document_uri: None,
+            symbol: None,
+            name: None,
+            document_hash: None,
+            initial_supply: SLPAmount::new(0, 0),
+            current_supply: SLPAmount::new(0, 0),
+            block_created_height: 0,
+        }
+    }
+
+    fn history(txs: Vec<HistoricTx>, trade_offers: HashMap<usize, TradeOffer>) -> TxHistory {
+        TxHistory {
+            txs,
+            trade_offers,
+            pnd_txs: HashMap::new(),
+            pandas_slp: HashSet::new(),
+            mint_batons: HashMap::new(),
+            new_tokens: HashMap::new(),
+        }
+    }
+
+    fn with_test_db(test: impl FnOnce(&Db) -> QueryResult<()>) {
+        let pool = create_pool(&std::env::var("DATABASE_URL").expect("DATABASE_URL"), 1).unwrap();
+        let db = Db::from_pool(&pool).unwrap();
+        db.connection().test_transaction(|| test(&db));
+    }
+
+    #[test]
+    fn locator_heights() {
+        assert_eq!(block_locator_heights(0), vec![0]);
+        assert_eq!(block_locator_heights(3), vec![3, 2, 1, 0]);
+        assert_eq!(block_locator_heights(20), vec![20, 19, 18, 17, 16, 15, 14, 13, 12, 11, 9, 5, 0]);
+        let heights = block_locator_heights(600_000);
+        assert!(heights.len() < 40);
+        assert_eq!(heights[..10], (599_991..=600_000).rev().collect::<Vec<_>>()[..]);
+        assert_eq!(heights.last(), Some(&0));
+    }
+
+    fn utxo_rows(db: &Db, address: &Address, tx_hashes: &[u8]) -> (Vec<(i64, i32)>, Vec<(i64, i32)>) {
+        let tx_ids: Vec<i64> = tx::table
+            .filter(tx::hash.eq_any(tx_hashes.iter().map(|&hash| vec![hash; 32]).collect::<Vec<_>>()))
+            .select(tx::id)
+            .load(db.connection()).unwrap();
+        let address_utxos = utxo_address::table
+            .filter(utxo_address::address.eq(address.bytes().to_vec()))
+            .select((utxo_address::tx, utxo_address::idx))
+            .order((utxo_address::tx, utxo_address::idx))
+            .load(db.connection()).unwrap();
+        let trade_offer_utxos = utxo_trade_offer::table
+            .filter(utxo_trade_offer::tx.eq_any(tx_ids))
+            .select((utxo_trade_offer::tx, utxo_trade_offer::idx))
+            .order((utxo_trade_offer::tx, utxo_trade_offer::idx))
+            .load(db.connection()).unwrap();
+        (address_utxos, trade_offer_utxos)
+    }
+
+    #[test]
+    #[ignore]
+    fn incremental_utxos_match_rebuild() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xa1; 20]);
+            let trade_offer = TradeOffer {
+                tx: [0xa2; 32],
+                output_idx: Some(1),
+                input_tx: [0xa1; 32],
+                input_idx: 0,
+                price_per_token: Rational::from(1000),
+                script_price: 1000,
+                is_inverted: false,
+                sell_amount_token: SLPAmount::new(0, 0),
+                receiving_address: address.clone(),
+            };
+            db.set_address_active(&address, true)?;
+            let check = |expected_address_utxos: usize, expected_trade_offer_utxos: usize| {
+                let incremental = utxo_rows(db, &address, &[0xa1, 0xa2, 0xa3]);
+                db.rebuild_utxo_sets().unwrap();
+                let rebuilt = utxo_rows(db, &address, &[0xa1, 0xa2, 0xa3]);
+                assert_eq!(incremental, rebuilt);
+                assert_eq!(incremental.0.len(), expected_address_utxos);
+                assert_eq!(incremental.1.len(), expected_trade_offer_utxos);
+            };
+            let mut trade_offers = HashMap::new();
+            trade_offers.insert(1, trade_offer);
+            db.add_tx_history(&history(vec![
+                tx(0xa1, &[(0xa0, 0)], vec![OutputType::Address(address.clone()),
+                                            OutputType::Address(address.clone())]),
+                tx(0xa2, &[(0xa1, 0)], vec![OutputType::Address(address.clone()),
+                                            OutputType::Unknown]),
+            ], trade_offers))?;
+            check(2, 1);
+            db.add_tx_history(&history(vec![
+                tx(0xa3, &[(0xa2, 1), (0xa1, 1)], vec![OutputType::Address(address.clone())]),
+            ], HashMap::new()))?;
+            check(2, 0);
+            Ok(())
+        });
+    }
+
+    fn holder_rows(db: &Db) -> Vec<(i32, Vec<u8>, PgNumeric, i32)> {
+        token_holder::table
+            .select((token_holder::token, token_holder::address, token_holder::balance_base,
+                     token_holder::utxo_count))
+            .order((token_holder::token, token_holder::address))
+            .load(db.connection()).unwrap()
+    }
+
+    #[test]
+    #[ignore]
+    fn incremental_holders_match_rebuild() {
+        with_test_db(|db| {
+            let addresses = (0xc1..=0xc3)
+                .map(|byte| Address::from_bytes(AddressType::P2PKH, [byte; 20]))
+                .collect::<Vec<_>>();
+            let check = || {
+                let incremental = holder_rows(db);
+                db.rebuild_token_holders().unwrap();
+                assert_eq!(incremental, holder_rows(db));
+            };
+            db.add_tokens(&[token(0xc0)])?;
+            db.add_tx_history(&history(vec![
+                slp_tx(0xc0, &[(0xbf, 0)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(addresses[0].clone()), 60),
+                                                (OutputType::Address(addresses[0].clone()), 40)],
+                       0xc0, SLPTxType::Genesis),
+                slp_tx(0xc1, &[(0xc0, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(addresses[1].clone()), 30),
+                                                (OutputType::Address(addresses[2].clone()), 30)],
+                       0xc0, SLPTxType::Send),
+            ], HashMap::new()))?;
+            check();
+            // a non-SLP tx burning tokens
+            db.add_tx_history(&history(vec![
+                tx(0xc2, &[(0xc1, 1)], vec![OutputType::Address(addresses[2].clone())]),
+            ], HashMap::new()))?;
+            check();
+            db.remove_double_spent_txs(&[[0xc2; 32]])?;
+            check();
+
+            // pages by descending balance and then address
+            let page = db.token_holders(&[0xc0; 32], None, 2)?;
+            assert_eq!(page.iter().map(|holder| holder.address.bytes().to_vec()).collect::<Vec<_>>(),
+                       vec![addresses[0].bytes().to_vec(), addresses[1].bytes().to_vec()]);
+            let last = page.last().unwrap();
+            let cursor = TokenHolderCursor { balance: last.balance, address: last.address.clone() };
+            let page = db.token_holders(&[0xc0; 32], Some(cursor), 2)?;
+            assert_eq!(page.iter().map(|holder| holder.address.bytes().to_vec()).collect::<Vec<_>>(),
+                       vec![addresses[2].bytes().to_vec()]);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn pages_address_history() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xb1; 20]);
+            db.add_tx_history(&history(
+                (0..3u8)
+                    .map(|i| HistoricTx {
+                        timestamp: 1000 + i as i64,
+                        ..tx(0xb1 + i, &[(0xb0, i as i32)], vec![OutputType::Address(address.clone())])
+                    })
+                    .collect(),
+                HashMap::new(),
+            ))?;
+            let filter = TxHistoryFilter::default();
+            let page = db.address_tx_deltas(&address, &filter, None, 2)?;
+            assert_eq!(page.tx_deltas.iter().map(|delta| delta.tx_hash).collect::<Vec<_>>(),
+                       vec![[0xb3; 32], [0xb2; 32]]);
+            assert_eq!(page.next_cursor, Some(TxHistoryCursor { timestamp: 1001, tx_hash: [0xb2; 32] }));
+            let page = db.address_tx_deltas(&address, &filter, page.next_cursor, 2)?;
+            assert_eq!(page.tx_deltas.iter().map(|delta| delta.tx_hash).collect::<Vec<_>>(),
+                       vec![[0xb1; 32]]);
+            assert_eq!(page.next_cursor, None);
+            let confirmed = TxHistoryFilter { confirmed: Some(true), ..TxHistoryFilter::default() };
+            assert!(db.address_tx_deltas(&address, &confirmed, None, 2)?.tx_deltas.is_empty());
+            let bch_only = TxHistoryFilter { bch_only: true, ..TxHistoryFilter::default() };
+            assert_eq!(db.address_tx_deltas(&address, &bch_only, None, 10)?.tx_deltas.len(), 3);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn keeps_known_tx_height() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xc1; 20]);
+            let unconfirmed = tx(0xc1, &[(0xc0, 0)], vec![OutputType::Address(address.clone())]);
+            let confirmed = HistoricTx { height: Some(120), ..unconfirmed.clone() };
+            let height = || db.txs(vec![[0xc1; 32]].into_iter()).unwrap()[&[0xc1; 32]].height;
+            db.add_tx_history(&history(vec![unconfirmed.clone()], HashMap::new()))?;
+            assert_eq!(height(), None);
+            db.add_tx_history(&history(vec![confirmed], HashMap::new()))?;
+            assert_eq!(height(), Some(120));
+            db.add_tx_history(&history(vec![unconfirmed], HashMap::new()))?;
+            assert_eq!(height(), Some(120));
+            Ok(())
+        });
+    }
+
+    fn mine_headers(prev_block: [u8; 32], start_timestamp: u32, n: usize, network: &Network) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = Vec::new();
+        for i in 0..n as u32 {
+            let mut header = BlockHeader {
+                version: 1,
+                prev_block: headers.last().map(BlockHeader::hash).unwrap_or(prev_block),
+                merkle_root: [0; 32],
+                timestamp: start_timestamp + i * 600,
+                bits: network.difficulty.pow_limit_bits,
+                nonce: 0,
+            };
+            while !header.has_valid_pow(&network.difficulty) {
+                header.nonce += 1;
+            }
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    #[ignore]
+    fn bootstraps_from_checkpoint() {
+        with_test_db(|db| {
+            let mut network = Network::regtest();
+            let headers = mine_headers([0xc0; 32], 1_600_000_000, 13, &network);
+            network.bootstrap = Some(BootstrapCheckpoint {
+                height: 110,
+                chain_work: Integer::from(1_000_000),
+                headers: headers[..11].to_vec(),
+            });
+            network.checkpoints = vec![slpdexdb_base::Checkpoint { height: 112, hash: [0xc1; 32] }];
+            diesel::delete(blocks::table).execute(db.connection())?;
+            db.init_headers(&network).unwrap();
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(110));
+            assert_eq!(db.block_locator()?.len(), 10);
+            let mut file = Vec::new();
+            for header in headers[..12].iter() {
+                header.write_to_stream(&mut file).unwrap();
+            }
+            assert_eq!(db.import_headers(&mut file.as_slice(), 100, &network).unwrap(), 1);
+            assert_eq!(db.header_height(&headers[11].hash())?, Some(111));
+            assert!(db.add_headers(&headers[12..], &network).is_err());
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(111));
+            let exported = db.bootstrap_checkpoint(111, &network).unwrap();
+            assert_eq!(exported.headers.len(), 11);
+            assert_eq!(exported.chain_work, Integer::from(1_000_000) + headers[11].work());
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn follows_main_chain_in_block_locator() {
+        with_test_db(|db| {
+            let mut network = Network::regtest();
+            let headers = mine_headers([0xc4; 32], 1_600_000_000, 41, &network);
+            let fork_headers = mine_headers(headers[35].hash(), 1_600_021_300, 7, &network);
+            network.bootstrap = Some(BootstrapCheckpoint {
+                height: 110,
+                chain_work: Integer::from(1_000_000),
+                headers: headers[..11].to_vec(),
+            });
+            // locators of a tip at 100 + len - 1, as far back as the bootstrap reaches
+            let expected = |headers: &[BlockHeader]| block_locator_heights(100 + headers.len() as i32 - 1)
+                .into_iter()
+                .filter(|&height| height >= 100)
+                .map(|height| headers[height as usize - 100].hash())
+                .collect::<Vec<_>>();
+            diesel::delete(blocks::table).execute(db.connection())?;
+            diesel::sql_query("DELETE FROM processed_block").execute(db.connection())?;
+            db.init_headers(&network).unwrap();
+            assert!(db.add_headers(&headers[11..], &network).unwrap().is_none());
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(140));
+            assert_eq!(db.block_locator()?, expected(&headers));
+            assert!(db.add_headers(&fork_headers, &network).unwrap().is_some());
+            let fork_chain = headers[..36].iter().chain(fork_headers.iter()).cloned().collect::<Vec<_>>();
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(142));
+            assert_eq!(db.block_locator()?, expected(&fork_chain));
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn skips_headers_below_bootstrap() {
+        with_test_db(|db| {
+            let mut network = Network::regtest();
+            let headers = mine_headers([0xc8; 32], 1_600_000_000, 12, &network);
+            let fork_headers = mine_headers(headers[0].hash(), 1_600_000_300, 2, &network);
+            network.bootstrap = Some(BootstrapCheckpoint {
+                height: 110,
+                chain_work: Integer::from(1_000_000),
+                headers: headers[..11].to_vec(),
+            });
+            diesel::delete(blocks::table).execute(db.connection())?;
+            db.init_headers(&network).unwrap();
+            // the fork at 101 lacks the timestamps before 100, the rest of the batch still gets added
+            let batch = fork_headers.iter().chain(headers[11..].iter()).cloned().collect::<Vec<_>>();
+            assert!(db.add_headers(&batch, &network).unwrap().is_none());
+            assert_eq!(db.header_height(&fork_headers[0].hash())?, None);
+            assert_eq!(db.header_height(&fork_headers[1].hash())?, None);
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(111));
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn lists_unprocessed_blocks() {
+        with_test_db(|db| {
+            let mut network = Network::regtest();
+            let headers = mine_headers([0xd0; 32], 1_600_000_000, 11, &network);
+            network.bootstrap = Some(BootstrapCheckpoint {
+                height: 110,
+                chain_work: Integer::from(1_000_000),
+                headers: headers.clone(),
+            });
+            diesel::delete(blocks::table).execute(db.connection())?;
+            diesel::sql_query("DELETE FROM processed_block").execute(db.connection())?;
+            db.init_headers(&network).unwrap();
+            assert!(db.unprocessed_blocks(100)?.is_empty());
+            db.mark_block_processed(&headers[1].hash(), 101, 0)?;
+            db.mark_block_processed(&headers[4].hash(), 104, 0)?;
+            db.mark_block_processed(&headers[4].hash(), 104, 0)?;
+            assert!(db.is_block_processed(&headers[4].hash())?);
+            assert!(!db.is_block_processed(&headers[5].hash())?);
+            let heights = db.unprocessed_blocks(100)?.into_iter().map(|(_, height)| height).collect::<Vec<_>>();
+            assert_eq!(heights, vec![102, 103, 105, 106, 107, 108, 109, 110]);
+            assert_eq!(db.unprocessed_blocks(1)?, vec![(headers[2].hash(), 102)]);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn rolls_back_deep_reorg() {
+        with_test_db(|db| {
+            let mut network = Network::regtest();
+            let headers = mine_headers([0xe0; 32], 1_600_000_000, 11, &network);
+            let tip_hash = headers[10].hash();
+            let main_headers = mine_headers(tip_hash, 1_600_006_600, 2, &network);
+            let fork_headers = mine_headers(tip_hash, 1_600_006_601, 3, &network);
+            network.bootstrap = Some(BootstrapCheckpoint {
+                height: 110,
+                chain_work: Integer::from(1_000_000),
+                headers,
+            });
+            let address = Address::from_bytes(AddressType::P2PKH, [0xe1; 20]);
+            let other_address = Address::from_bytes(AddressType::P2PKH, [0xe2; 20]);
+            diesel::delete(blocks::table).execute(db.connection())?;
+            diesel::sql_query("DELETE FROM processed_block").execute(db.connection())?;
+            db.init_headers(&network).unwrap();
+            db.set_address_active(&address, true)?;
+            db.add_tokens(&[token(0xe0)])?;
+            let txs = vec![
+                slp_tx(0xe1, &[(0xe0, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 100),
+                                                (OutputType::Address(other_address.clone()), 50)],
+                       0xe0, SLPTxType::Send),
+                slp_tx(0xe2, &[(0xe1, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(other_address.clone()), 100)],
+                       0xe0, SLPTxType::Send),
+            ];
+            assert!(db.add_headers(&main_headers, &network).unwrap().is_none());
+            for (i, (tx, header)) in txs.iter().zip(main_headers.iter()).enumerate() {
+                db.add_tx_history(&history(vec![tx.clone()], HashMap::new()))?;
+                db.confirm_block_txs(&header.hash(), &[tx.hash], 111 + i as i32, header.timestamp as i64, 6)?;
+                db.mark_block_processed(&header.hash(), 111 + i as i32, 0)?;
+            }
+            assert_eq!(db.token_holder_count_history(&[0xe0; 32], 0, 1000)?.len(), 2);
+            let reorg = db.add_headers(&fork_headers, &network).unwrap().expect("reorg");
+            assert_eq!(reorg.fork_height, 110);
+            assert_eq!(reorg.disconnected_blocks.iter().cloned().collect::<HashSet<_>>(),
+                       main_headers.iter().map(BlockHeader::hash).collect::<HashSet<_>>());
+            assert_eq!(reorg.connected_blocks, fork_headers.iter().map(BlockHeader::hash).collect::<Vec<_>>());
+            assert_eq!(reorg.unconfirmed_txs.iter().map(|tx| tx.tx_hash).collect::<HashSet<_>>(),
+                       vec![[0xe1; 32], [0xe2; 32]].into_iter().collect::<HashSet<_>>());
+            assert_eq!(db.header_tip()?.map(|(_, height)| height), Some(113));
+            assert!(db.txs(vec![[0xe1; 32], [0xe2; 32]].into_iter())?.values().all(|tx| tx.height.is_none()));
+            assert!(!db.is_block_processed(&main_headers[0].hash())?);
+            assert!(!db.is_block_processed(&main_headers[1].hash())?);
+            assert!(db.token_holder_count_history(&[0xe0; 32], 0, 1000)?.is_empty());
+            let utxos = utxo_rows(db, &address, &[0xe1, 0xe2]);
+            let holders = holder_rows(db);
+            db.rebuild_utxo_sets()?;
+            db.rebuild_token_holders()?;
+            assert_eq!(utxos, utxo_rows(db, &address, &[0xe1, 0xe2]));
+            assert_eq!(holders, holder_rows(db));
+            assert_eq!(db.address_token_balances(&other_address)?[0].utxo_count, 2);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn removes_double_spent_descendants() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xf1; 20]);
+            let other_address = Address::from_bytes(AddressType::P2PKH, [0xf2; 20]);
+            db.set_address_active(&address, true)?;
+            db.add_tokens(&[token(0xf0)])?;
+            db.add_tx_history(&history(vec![
+                slp_tx(0xf1, &[(0xf0, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 100)],
+                       0xf0, SLPTxType::Send),
+                slp_tx(0xf2, &[(0xf1, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(other_address.clone()), 60),
+                                                (OutputType::Address(address.clone()), 40)],
+                       0xf0, SLPTxType::Send),
+                slp_tx(0xf3, &[(0xf2, 2)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 40)],
+                       0xf0, SLPTxType::Send),
+            ], HashMap::new()))?;
+            let double_spent = db.remove_double_spent_txs(&[[0xf2; 32]])?;
+            assert_eq!(double_spent.tx_hashes.iter().cloned().collect::<HashSet<_>>(),
+                       vec![[0xf2; 32], [0xf3; 32]].into_iter().collect::<HashSet<_>>());
+            assert_eq!(double_spent.address_utxos[&address].iter()
+                           .map(|utxo| (utxo.tx_hash, utxo.vout))
+                           .collect::<Vec<_>>(),
+                       vec![([0xf3; 32], 1)]);
+            assert_eq!(db.txs(vec![[0xf1; 32], [0xf2; 32], [0xf3; 32]].into_iter())?.len(), 1);
+            let utxos = utxo_rows(db, &address, &[0xf1]);
+            let holders = holder_rows(db);
+            assert_eq!(utxos.0.len(), 1);
+            db.rebuild_utxo_sets()?;
+            db.rebuild_token_holders()?;
+            assert_eq!(utxos, utxo_rows(db, &address, &[0xf1]));
+            assert_eq!(holders, holder_rows(db));
+            assert!(db.address_token_balances(&other_address)?.is_empty());
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn tracks_and_reverts_token_supply() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xd1; 20]);
+            let add = |tx: HistoricTx, baton: Option<Option<i32>>| {
+                let mut tx_history = history(vec![tx], HashMap::new());
+                if let Some(baton) = baton {
+                    tx_history.mint_batons.insert(0, baton);
+                }
+                db.add_tx_history(&tx_history)
+            };
+            let check = |baton: Option<([u8; 32], i32)>, minted: i128, burned: i128, circulating: i128| {
+                let supply = db.token_supply(&[0xd0; 32]).unwrap().unwrap();
+                assert_eq!(supply.mint_baton, baton);
+                assert_eq!(supply.minted_supply, SLPAmount::new(minted, 0));
+                assert_eq!(supply.burned_supply, SLPAmount::new(burned, 0));
+                assert_eq!(supply.circulating_supply, SLPAmount::new(circulating, 0));
+            };
+            db.add_tokens(&[token(0xd0)])?;
+            add(slp_tx(0xd0, &[(0xcf, 0)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 100),
+                                                (OutputType::Address(address.clone()), 0)],
+                       0xd0, SLPTxType::Genesis), Some(Some(2)))?;
+            check(Some(([0xd0; 32], 2)), 0, 0, 0);
+            add(slp_tx(0xd1, &[(0xd0, 2)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 50),
+                                                (OutputType::Address(address.clone()), 0)],
+                       0xd0, SLPTxType::Mint), Some(Some(2)))?;
+            check(Some(([0xd1; 32], 2)), 50, 0, 50);
+            add(slp_tx(0xd2, &[(0xd0, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 70)],
+                       0xd0, SLPTxType::Send), None)?;
+            check(Some(([0xd1; 32], 2)), 50, 30, 20);
+            // a MINT destroying the baton
+            add(slp_tx(0xd3, &[(0xd1, 2)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(address.clone()), 5)],
+                       0xd0, SLPTxType::Mint), Some(None))?;
+            check(None, 55, 30, 25);
+            // double spending the first MINT drops the second one too and brings back the genesis baton
+            db.remove_double_spent_txs(&[[0xd1; 32]])?;
+            check(Some(([0xd0; 32], 2)), 0, 30, -30);
+            db.remove_double_spent_txs(&[[0xd2; 32]])?;
+            check(Some(([0xd0; 32], 2)), 0, 0, 0);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn derives_token_stats() {
+        with_test_db(|db| {
+            let address = Address::from_bytes(AddressType::P2PKH, [0xe1; 20]);
+            let other_address = Address::from_bytes(AddressType::P2PKH, [0xe2; 20]);
+            let check = |holder_count: i32, tx_count: i32, utxo_count: i32, last_active_height: Option<i32>| {
+                let stats = db.token_stats(&[0xe0; 32]).unwrap().unwrap();
+                assert_eq!((stats.holder_count, stats.tx_count, stats.utxo_count, stats.last_active_height),
+                           (holder_count, tx_count, utxo_count, last_active_height));
+            };
+            db.add_tokens(&[token(0xe0)])?;
+            let mut genesis = slp_tx(0xe0, &[(0xdf, 0)], vec![(OutputType::OpReturn, 0),
+                                                             (OutputType::Address(address.clone()), 60),
+                                                             (OutputType::Address(address.clone()), 40)],
+                                     0xe0, SLPTxType::Genesis);
+            genesis.height = Some(100);
+            db.add_tx_history(&history(vec![genesis], HashMap::new()))?;
+            check(1, 1, 2, Some(100));
+            db.add_tx_history(&history(vec![
+                slp_tx(0xe1, &[(0xe0, 1)], vec![(OutputType::OpReturn, 0),
+                                                (OutputType::Address(other_address.clone()), 60)],
+                       0xe0, SLPTxType::Send),
+            ], HashMap::new()))?;
+            check(2, 2, 2, Some(100));
+            db.remove_double_spent_txs(&[[0xe1; 32]])?;
+            check(1, 1, 2, Some(100));
+            assert!(db.refresh_token_stats(0)? >= 1);
+            check(1, 1, 2, Some(100));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn classifies_offer_spends() {
+        let seller = Address::from_bytes(AddressType::P2PKH, [0x51; 20]);
+        let buyer = Address::from_bytes(AddressType::P2PKH, [0x52; 20]);
+        let receiving_address = seller.bytes().to_vec();
+        let expected_satoshis = Rational::from(50);
+        let spend = |token_receiver: &Address, paid_satoshis: u64| {
+            let mut tx = slp_tx(0x50, &[(0x4f, 1)], vec![
+                (OutputType::OpReturn, 0),
+                (OutputType::Address(token_receiver.clone()), 5),
+                (OutputType::Address(seller.clone()), 0),
+            ], 0x4e, SLPTxType::Send);
+            tx.outputs[0].value_satoshis = 0;
+            tx.outputs[1].value_satoshis = 546;
+            tx.outputs[2].value_satoshis = paid_satoshis;
+            tx
+        };
+        assert_eq!(_offer_fill(&spend(&buyer, 50), &receiving_address, &expected_satoshis), Some(50));
+        // rounded down by the contract
+        assert_eq!(_offer_fill(&spend(&buyer, 49), &receiving_address, &Rational::from((995, 20))), Some(49));
+        assert_eq!(_offer_fill(&spend(&buyer, 48), &receiving_address, &expected_satoshis), None);
+        // the seller cancelling pays their receiving address more than the price
+        assert_eq!(_offer_fill(&spend(&seller, 1000), &receiving_address, &expected_satoshis), None);
+        let mut burned = spend(&buyer, 50);
+        burned.outputs[1].value_token = SLPAmount::new(0, 0);
+        assert_eq!(_offer_fill(&burned, &receiving_address, &expected_satoshis), None);
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Debug)]
 pub struct Endpoint {
     pub bitdb_endpoint_url: String,
     pub slpdb_endpoint_url: String,
@@ -10,4 +11,8 @@ impl Endpoint {
             slpdb_endpoint_url: "https://slpdb.fountainhead.cash/q/".to_string(),
         }
     }
+
+    pub fn with_urls(bitdb_endpoint_url: String, slpdb_endpoint_url: String) -> Self {
+        Endpoint { bitdb_endpoint_url, slpdb_endpoint_url }
+    }
 }
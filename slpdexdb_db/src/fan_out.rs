@@ -1,5 +1,5 @@
 use cashcontracts::{UnsignedTx, UnsignedInput, Tx, Address, TxOutpoint, TxOutput, P2PKHOutput,
-                    SLPSend, Output, double_sha256, AddressType};
+                    SLPSend, Output, double_sha256, hash160, AddressType};
 use slpdexdb_base::SLPAmount;
 
 pub fn slp_fan_out_tx(secret: &[u8],
@@ -14,7 +14,7 @@ pub fn slp_fan_out_tx(secret: &[u8],
     let curve = secp256k1::Secp256k1::new();
     let secret_key = secp256k1::SecretKey::from_slice(secret).unwrap();
     let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-    let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
+    let address = Address::from_bytes(AddressType::P2PKH, hash160(&pub_key));
 
     let mut tx_build = UnsignedTx::new_simple();
     for (outpoint, amount) in inputs {
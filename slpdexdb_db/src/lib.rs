@@ -14,6 +14,9 @@ mod tx_history;
 mod update_history;
 mod convert;
 mod data;
+mod slp_validator;
+mod chain_source;
+mod order_book;
 pub mod panda_tools;
 pub mod panda;
 pub mod fan_out;
@@ -26,6 +29,9 @@ pub use tx_source::*;
 pub use tx_history::*;
 pub use update_history::*;
 pub use data::*;
+pub use slp_validator::*;
+pub use chain_source::*;
+pub use order_book::*;
 
 //use slpdexdb_base::Result;
 
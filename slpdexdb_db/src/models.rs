@@ -4,7 +4,7 @@ use diesel::data_types::PgNumeric;
 use diesel::sql_types::{Binary, BigInt, Numeric, Integer, Nullable};
 use panda_base::traits::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[derive(Queryable)]
 #[derive(Insertable)]
 #[table_name="blocks"]
@@ -17,6 +17,8 @@ pub struct Block {
     pub timestamp: i64,
     pub bits: i64,
     pub nonce: i64,
+    pub chain_work: PgNumeric,
+    pub is_main_chain: bool,
 }
 
 #[derive(Debug)]
@@ -120,6 +122,15 @@ pub struct PND1Tx {
     pub owner_address: Vec<u8>, // BYTEA NOT NULL
 }
 
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="panda_birth"]
+pub struct PandaBirth {
+    pub pnd1_tx:    i64, // BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    pub panda:      i64, // BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    pub block_hash: Vec<u8>, // BYTEA NOT NULL
+}
+
 #[derive(Queryable)]
 #[derive(Insertable)]
 #[table_name="pandaop_utxo"]
@@ -189,6 +200,80 @@ pub struct ActiveAddress {
     pub address: Vec<u8>,
 }
 
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="peer"]
+pub struct Peer {
+    pub ip: String,                 // TEXT NOT NULL,
+    pub port: i32,                  // INT NOT NULL,
+    pub last_seen: i64,             // BIGINT NOT NULL,
+    pub last_attempt: Option<i64>,  // BIGINT,
+    pub n_failures: i32,            // INT NOT NULL DEFAULT 0,
+    pub banned_until: Option<i64>,  // BIGINT
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="slp_validity"]
+pub struct SlpValidity {
+    pub tx_hash: Vec<u8>,     // BYTEA PRIMARY KEY,
+    pub is_valid: bool,       // BOOL NOT NULL,
+    pub slp_script: Vec<u8>,  // BYTEA NOT NULL
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="token_supply"]
+pub struct TokenSupply {
+    pub token:          i32,             // INT PRIMARY KEY REFERENCES token (id) ON DELETE CASCADE,
+    pub mint_baton_tx:  Option<Vec<u8>>, // BYTEA,
+    pub mint_baton_idx: Option<i32>,     // INT,
+    pub minted_supply:  PgNumeric,       // NUMERIC(52, 26) NOT NULL,
+    pub burned_supply:  PgNumeric,       // NUMERIC(52, 26) NOT NULL,
+    pub is_synced:      bool,            // BOOLEAN NOT NULL DEFAULT TRUE
+}
+
+#[derive(Insertable)]
+#[table_name="token_burn"]
+pub struct TokenBurn {
+    pub tx:     i64,       // BIGINT NOT NULL REFERENCES tx (id) ON DELETE CASCADE,
+    pub token:  i32,       // INT NOT NULL REFERENCES token (id) ON DELETE CASCADE,
+    pub amount: PgNumeric, // NUMERIC(52, 26) NOT NULL
+}
+
+#[derive(Insertable)]
+#[table_name="trade_offer_spend"]
+pub struct TradeOfferSpend {
+    pub trade_offer: i32,  // INT PRIMARY KEY REFERENCES trade_offer (id) ON DELETE CASCADE,
+    pub tx:          i64,  // BIGINT NOT NULL REFERENCES tx (id) ON DELETE CASCADE,
+    pub is_filled:   bool, // BOOL NOT NULL
+}
+
+#[derive(Insertable)]
+#[table_name="trade"]
+pub struct NewTrade {
+    pub trade_offer:       i32,             // INT PRIMARY KEY REFERENCES trade_offer (id) ON DELETE CASCADE,
+    pub tx:                i64,             // BIGINT NOT NULL REFERENCES tx (id) ON DELETE CASCADE,
+    pub token:             i32,             // INT NOT NULL REFERENCES token (id) ON DELETE CASCADE,
+    pub price_per_token:   PgNumeric,       // NUMERIC(52, 26) NOT NULL,
+    pub amount_token_base: PgNumeric,       // NUMERIC(26) NOT NULL,
+    pub value_satoshis:    i64,             // BIGINT NOT NULL,
+    pub buyer_address:     Option<Vec<u8>>, // BYTEA,
+    pub timestamp:         i64,             // BIGINT NOT NULL
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="token_stats"]
+pub struct TokenStats {
+    pub token:               i32,         // INT PRIMARY KEY REFERENCES token (id) ON DELETE CASCADE,
+    pub holder_count:        i32,         // INT NOT NULL,
+    pub tx_count:            i32,         // INT NOT NULL,
+    pub utxo_count:          i32,         // INT NOT NULL,
+    pub last_active_height:  Option<i32>, // INT,
+    pub last_updated_height: i32,         // INT NOT NULL
+}
+
 #[derive(Queryable)]
 #[derive(Insertable)]
 #[table_name="utxo_address"]
@@ -206,6 +291,68 @@ pub struct Utxo {
     pub idx: i32,
 }
 
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="token_holder"]
+pub struct TokenHolder {
+    pub token:        i32,       // INT NOT NULL REFERENCES token (id) ON DELETE CASCADE,
+    pub address:      Vec<u8>,   // BYTEA NOT NULL,
+    pub balance_base: PgNumeric, // NUMERIC(26) NOT NULL,
+    pub utxo_count:   i32,       // INT NOT NULL
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct TokenHolderKey {
+    #[sql_type="Integer"]
+    pub token: i32,
+    #[sql_type="Binary"]
+    pub address: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct BlockHashHeight {
+    #[sql_type="Binary"]
+    pub hash: Vec<u8>,
+    #[sql_type="Integer"]
+    pub height: i32,
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct TxIdHash {
+    #[sql_type="BigInt"]
+    pub id: i64,
+    #[sql_type="Binary"]
+    pub hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct TxHash {
+    #[sql_type="Binary"]
+    pub hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct TokenId {
+    #[sql_type="Integer"]
+    pub token: i32,
+}
+
+#[derive(Debug)]
+#[derive(QueryableByName)]
+pub struct TxHistoryId {
+    #[sql_type="BigInt"]
+    pub id: i64,
+    #[sql_type="Binary"]
+    pub hash: Vec<u8>,
+    #[sql_type="BigInt"]
+    pub timestamp: i64,
+}
+
 #[derive(Debug)]
 #[derive(QueryableByName)]
 pub struct TxDeltaInput {
@@ -245,7 +392,10 @@ pub struct TxDeltaOutput {
 }
 
 impl Block {
-    pub fn from_block_header(header: &BlockHeader, height: i32) -> Block {
+    pub fn from_block_header(header: &BlockHeader,
+                             height: i32,
+                             chain_work: PgNumeric,
+                             is_main_chain: bool) -> Block {
         Block {
             hash: header.hash().to_vec(),
             height,
@@ -255,6 +405,8 @@ impl Block {
             timestamp: header.timestamp as i64,
             bits: header.bits as i64,
             nonce: header.nonce as i64,
+            chain_work,
+            is_main_chain,
         }
     }
 
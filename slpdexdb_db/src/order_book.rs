@@ -0,0 +1,233 @@
+use crate::tx_history::TradeOffer;
+use crate::data::SpentUtxo;
+use slpdexdb_base::SLPAmount;
+use rug::Rational;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceLevel {
+    pub price_per_token: Rational,
+    pub amount_token: SLPAmount,
+    pub cumulative_amount_token: SLPAmount,
+    pub num_offers: usize,
+}
+
+// new state of a price level; an amount of zero means the level is gone
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceLevelDiff {
+    pub price_per_token: Rational,
+    pub amount_token: SLPAmount,
+    pub num_offers: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderBookSnapshot {
+    pub token_hash: [u8; 32],
+    pub sequence: u64,
+    pub asks: Vec<PriceLevel>,
+    pub best_ask: Option<Rational>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderBookDiff {
+    pub token_hash: [u8; 32],
+    pub sequence: u64,
+    pub asks: Vec<PriceLevelDiff>,
+}
+
+struct _Level {
+    amount_token: SLPAmount,
+    num_offers: usize,
+}
+
+// EXCH offers only ever sell tokens, so the book consists of asks only.
+pub struct OrderBook {
+    token_hash: [u8; 32],
+    sequence: u64,
+    offers: HashMap<([u8; 32], i32), TradeOffer>,
+    levels: BTreeMap<Rational, _Level>,
+}
+
+impl OrderBook {
+    pub fn new(token_hash: [u8; 32]) -> Self {
+        OrderBook {
+            token_hash,
+            sequence: 0,
+            offers: HashMap::new(),
+            levels: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_trade_offers(token_hash: [u8; 32],
+                             trade_offers: impl IntoIterator<Item=TradeOffer>) -> Self {
+        let mut order_book = OrderBook::new(token_hash);
+        let mut touched = BTreeMap::new();
+        for trade_offer in trade_offers {
+            order_book._add(trade_offer, &mut touched);
+        }
+        order_book
+    }
+
+    pub fn token_hash(&self) -> &[u8; 32] {
+        &self.token_hash
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn best_ask(&self) -> Option<Rational> {
+        self.levels.keys().next().cloned()
+    }
+
+    pub fn snapshot(&self) -> OrderBookSnapshot {
+        let mut cumulative: Option<SLPAmount> = None;
+        let asks = self.levels.iter()
+            .map(|(price_per_token, level)| {
+                let cumulative_amount_token = match cumulative {
+                    Some(cumulative) => cumulative + level.amount_token,
+                    None => level.amount_token,
+                };
+                cumulative = Some(cumulative_amount_token);
+                PriceLevel {
+                    price_per_token: price_per_token.clone(),
+                    amount_token: level.amount_token,
+                    cumulative_amount_token,
+                    num_offers: level.num_offers,
+                }
+            })
+            .collect();
+        OrderBookSnapshot {
+            token_hash: self.token_hash,
+            sequence: self.sequence,
+            asks,
+            best_ask: self.best_ask(),
+        }
+    }
+
+    pub fn apply(&mut self, add_offers: &[TradeOffer], remove_utxos: &[SpentUtxo]) -> Option<OrderBookDiff> {
+        let mut touched = BTreeMap::new();
+        for utxo in remove_utxos {
+            self._remove(utxo, &mut touched);
+        }
+        for trade_offer in add_offers {
+            self._add(trade_offer.clone(), &mut touched);
+        }
+        if touched.len() == 0 {
+            return None;
+        }
+        self.sequence += 1;
+        let asks = touched.into_iter()
+            .map(|(price_per_token, decimals)| match self.levels.get(&price_per_token) {
+                Some(level) => PriceLevelDiff {
+                    price_per_token,
+                    amount_token: level.amount_token,
+                    num_offers: level.num_offers,
+                },
+                None => PriceLevelDiff {
+                    price_per_token,
+                    amount_token: SLPAmount::new(0, decimals),
+                    num_offers: 0,
+                },
+            })
+            .collect();
+        Some(OrderBookDiff { token_hash: self.token_hash, sequence: self.sequence, asks })
+    }
+
+    fn _add(&mut self, trade_offer: TradeOffer, touched: &mut BTreeMap<Rational, u32>) {
+        let output_idx = match trade_offer.output_idx {
+            Some(output_idx) => output_idx,
+            None => return,
+        };
+        let key = (trade_offer.tx, output_idx);
+        if self.offers.contains_key(&key) {
+            return;
+        }
+        let decimals = trade_offer.sell_amount_token.decimals();
+        let level = self.levels
+            .entry(trade_offer.price_per_token.clone())
+            .or_insert_with(|| _Level { amount_token: SLPAmount::new(0, decimals), num_offers: 0 });
+        level.amount_token += trade_offer.sell_amount_token;
+        level.num_offers += 1;
+        touched.insert(trade_offer.price_per_token.clone(), decimals);
+        self.offers.insert(key, trade_offer);
+    }
+
+    fn _remove(&mut self, utxo: &SpentUtxo, touched: &mut BTreeMap<Rational, u32>) {
+        let trade_offer = match self.offers.remove(&(utxo.tx_hash, utxo.vout)) {
+            Some(trade_offer) => trade_offer,
+            None => return,
+        };
+        let is_empty = match self.levels.get_mut(&trade_offer.price_per_token) {
+            Some(level) => {
+                level.amount_token -= trade_offer.sell_amount_token;
+                level.num_offers -= 1;
+                level.num_offers == 0
+            },
+            None => false,
+        };
+        if is_empty {
+            self.levels.remove(&trade_offer.price_per_token);
+        }
+        touched.insert(trade_offer.price_per_token, trade_offer.sell_amount_token.decimals());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashcontracts::{Address, AddressType};
+
+    fn trade_offer(tx: u8, price_per_token: (u32, u32), amount: i128) -> TradeOffer {
+        TradeOffer {
+            tx: [tx; 32],
+            output_idx: Some(1),
+            input_tx: [0; 32],
+            input_idx: 0,
+            price_per_token: Rational::from(price_per_token),
+            script_price: 0,
+            is_inverted: false,
+            sell_amount_token: SLPAmount::new(amount, 2),
+            receiving_address: Address::from_bytes(AddressType::P2PKH, [0; 20]),
+        }
+    }
+
+    #[test]
+    fn aggregates_price_levels() {
+        let order_book = OrderBook::from_trade_offers([9; 32], vec![
+            trade_offer(1, (3, 2), 100),
+            trade_offer(2, (1, 1), 250),
+            trade_offer(3, (3, 2), 50),
+            trade_offer(3, (3, 2), 50),  // same utxo twice is only counted once
+        ]);
+        let snapshot = order_book.snapshot();
+        assert_eq!(snapshot.best_ask, Some(Rational::from(1)));
+        assert_eq!(snapshot.asks.len(), 2);
+        assert_eq!(snapshot.asks[0].amount_token, SLPAmount::new(250, 2));
+        assert_eq!(snapshot.asks[0].cumulative_amount_token, SLPAmount::new(250, 2));
+        assert_eq!(snapshot.asks[1].price_per_token, Rational::from((3, 2)));
+        assert_eq!(snapshot.asks[1].amount_token, SLPAmount::new(150, 2));
+        assert_eq!(snapshot.asks[1].num_offers, 2);
+        assert_eq!(snapshot.asks[1].cumulative_amount_token, SLPAmount::new(400, 2));
+    }
+
+    #[test]
+    fn applies_deltas() {
+        let mut order_book = OrderBook::from_trade_offers([9; 32], vec![
+            trade_offer(1, (3, 2), 100),
+            trade_offer(2, (1, 1), 250),
+        ]);
+        assert!(order_book.apply(&[], &[SpentUtxo { tx_hash: [7; 32], vout: 1 }]).is_none());
+        let diff = order_book.apply(
+            &[trade_offer(4, (3, 2), 30)],
+            &[SpentUtxo { tx_hash: [2; 32], vout: 1 }],
+        ).unwrap();
+        assert_eq!(diff.sequence, 1);
+        assert_eq!(diff.asks, vec![
+            PriceLevelDiff { price_per_token: Rational::from(1), amount_token: SLPAmount::new(0, 2), num_offers: 0 },
+            PriceLevelDiff { price_per_token: Rational::from((3, 2)), amount_token: SLPAmount::new(130, 2), num_offers: 2 },
+        ]);
+        assert_eq!(order_book.best_ask(), Some(Rational::from((3, 2))));
+        assert_eq!(order_book.snapshot().sequence, 1);
+    }
+}
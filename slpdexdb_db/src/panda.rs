@@ -1,5 +1,5 @@
 use cashcontracts::{UnsignedTx, UnsignedInput, Tx, TxOutpoint, TxOutput, Output, double_sha256,
-                    single_sha256,
+                    single_sha256, hash160,
                     Address, AddressType, P2PKHOutput, SLPGenesis, SLPSend, OpReturnOutput,
                     tx_hash_to_hex};
 use slpdexdb_base::{SLPDEXConfig, SLPAmount};
@@ -61,7 +61,7 @@ impl PandaTx {
         let mut tx_build = UnsignedTx::new_simple();
         let secret_key = self.secret_key;
         let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-        let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
+        let address = Address::from_bytes(AddressType::P2PKH, hash160(&pub_key));
         tx_build.add_input(UnsignedInput {
             outpoint: self.nft1_outpoint.clone(),
             output: Box::new(P2PKHOutput {
@@ -122,7 +122,7 @@ impl PND1Tx {
         let mut tx_build = UnsignedTx::new_simple();
         let secret_key = self.secret_key;
         let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-        let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
+        let address = Address::from_bytes(AddressType::P2PKH, hash160(&pub_key));
 
         let message = format!("PANDA S3X:{}+{}",
                               tx_hash_to_hex(&self.father_token),
@@ -248,6 +248,24 @@ pub fn switch_owners(token_hash: [u8; 32], to_tx_hash: [u8; 32], to_output_idx:
         .execute(conn)?;
     Ok(())
 }
+pub fn record_panda_birth(
+    pnd1_tx_id: &i64,
+    panda_id: &i64,
+    block_hash: &[u8; 32],
+    conn: &PgConnection
+) -> Result<(), DieselError> {
+    use self::schema::panda_birth::dsl as birth_dsl;
+    diesel::insert_into(birth_dsl::panda_birth)
+        .values(&PandaBirth {
+            pnd1_tx: *pnd1_tx_id,
+            panda: *panda_id,
+            block_hash: block_hash.to_vec(),
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,694 @@
+use crate::db::Db;
+use crate::models;
+use crate::tx_history::{TokenType, SLPTxType};
+use crate::data::tx_hash_from_le_slice;
+use slpdexdb_base::{Result, SLPError};
+use cashcontracts::{Tx, tx_hash_to_hex};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
+
+const OP_RETURN: u8 = 0x6a;
+const LOKAD_ID: &[u8] = b"SLP\0";
+const MAX_SEND_AMOUNTS: usize = 19;
+const MAX_DECIMALS: u8 = 9;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SlpMessageKind {
+    Genesis {
+        ticker: Vec<u8>,
+        name: Vec<u8>,
+        document_uri: Vec<u8>,
+        document_hash: Vec<u8>,
+        decimals: u8,
+        mint_baton_vout: Option<u8>,
+        initial_quantity: u64,
+    },
+    Mint {
+        token_hash: [u8; 32],
+        mint_baton_vout: Option<u8>,
+        quantity: u64,
+    },
+    Send {
+        token_hash: [u8; 32],
+        amounts: Vec<u64>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlpMessage {
+    pub token_type: TokenType,
+    pub kind: SlpMessageKind,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SlpValidity {
+    Valid,
+    Invalid,
+    Unknown,  // an ancestor needed to decide validity couldn't be fetched
+}
+
+pub trait SlpTxProvider {
+    fn slp_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>>;
+}
+
+impl SlpTxProvider for HashMap<[u8; 32], Tx> {
+    fn slp_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        Ok(self.get(tx_hash).cloned())
+    }
+}
+
+fn _hex(bytes: &[u8]) -> String {
+    hex::encode(bytes)
+}
+
+impl SlpMessage {
+    pub fn parse(script: &[u8]) -> std::result::Result<Option<Self>, SLPError> {
+        if script.first() != Some(&OP_RETURN) {
+            return Ok(None);
+        }
+        let mut cursor = io::Cursor::new(&script[1..]);
+        match Self::_read_push(&mut cursor) {
+            Ok(Some(ref lokad_id)) if lokad_id.as_slice() == LOKAD_ID => {},
+            _ => return Ok(None),
+        }
+        let mut pushes = vec![LOKAD_ID.to_vec()];
+        while let Some(push) = Self::_read_push(&mut cursor)? {
+            pushes.push(push);
+        }
+        if pushes.len() < 3 {
+            return Err(SLPError::TooFewPushops(pushes.len()));
+        }
+        let token_type = match pushes[1].len() {
+            1 | 2 => pushes[1].iter().fold(0u16, |acc, &byte| (acc << 8) | byte as u16),
+            _ => return Err(SLPError::InvalidTokenTypeLength(_hex(&pushes[1]))),
+        };
+        let token_type: TokenType = num::traits::FromPrimitive::from_u16(token_type)
+            .ok_or(SLPError::UnsupportedTokenType(token_type))?;
+        let kind = match pushes[2].as_slice() {
+            b"GENESIS" => {
+                if pushes.len() != 10 {
+                    return Err(SLPError::WrongNumberOfPushops(pushes.len()));
+                }
+                let document_hash = &pushes[6];
+                if document_hash.len() != 0 && document_hash.len() != 32 {
+                    return Err(SLPError::InvalidField("document_hash", _hex(document_hash)));
+                }
+                let decimals = match pushes[7].as_slice() {
+                    &[decimals] if decimals <= MAX_DECIMALS => decimals,
+                    decimals => return Err(SLPError::InvalidField("decimals", _hex(decimals))),
+                };
+                let mint_baton_vout = Self::_mint_baton_vout(&pushes[8])?;
+                let initial_quantity = Self::_amount(&pushes[9])?;
+                if token_type == TokenType::NFT1Child &&
+                        (decimals != 0 || mint_baton_vout.is_some() || initial_quantity != 1) {
+                    return Err(SLPError::InvalidField("nft1_child_genesis", _hex(&pushes[9])));
+                }
+                SlpMessageKind::Genesis {
+                    ticker: pushes[3].clone(),
+                    name: pushes[4].clone(),
+                    document_uri: pushes[5].clone(),
+                    document_hash: document_hash.clone(),
+                    decimals,
+                    mint_baton_vout,
+                    initial_quantity,
+                }
+            },
+            b"MINT" => {
+                if token_type == TokenType::NFT1Child {
+                    return Err(SLPError::InvalidSLPType("MINT of NFT1 child".to_string()));
+                }
+                if pushes.len() != 6 {
+                    return Err(SLPError::WrongNumberOfPushops(pushes.len()));
+                }
+                SlpMessageKind::Mint {
+                    token_hash: Self::_token_hash(&pushes[3])?,
+                    mint_baton_vout: Self::_mint_baton_vout(&pushes[4])?,
+                    quantity: Self::_amount(&pushes[5])?,
+                }
+            },
+            b"SEND" => {
+                if pushes.len() < 5 {
+                    return Err(SLPError::TooFewPushops(pushes.len()));
+                }
+                if pushes.len() - 4 > MAX_SEND_AMOUNTS {
+                    return Err(SLPError::TooManyAmounts(pushes.len() - 4));
+                }
+                SlpMessageKind::Send {
+                    token_hash: Self::_token_hash(&pushes[3])?,
+                    amounts: pushes[4..].iter()
+                        .map(|amount| Self::_amount(amount))
+                        .collect::<std::result::Result<Vec<_>, _>>()?,
+                }
+            },
+            tx_type => return Err(SLPError::InvalidSLPType(
+                format!("{} ({})", String::from_utf8_lossy(tx_type), _hex(tx_type))
+            )),
+        };
+        Ok(Some(SlpMessage { token_type, kind }))
+    }
+
+    pub fn token_hash(&self, tx_hash: &[u8; 32]) -> [u8; 32] {
+        match &self.kind {
+            SlpMessageKind::Genesis {..} => tx_hash.clone(),
+            SlpMessageKind::Mint {token_hash, ..} => token_hash.clone(),
+            SlpMessageKind::Send {token_hash, ..} => token_hash.clone(),
+        }
+    }
+
+    pub fn slp_type(&self) -> SLPTxType {
+        match &self.kind {
+            SlpMessageKind::Genesis {..} => SLPTxType::Genesis,
+            SlpMessageKind::Mint {..} => SLPTxType::Mint,
+            SlpMessageKind::Send {..} => SLPTxType::Send,
+        }
+    }
+
+    pub fn mint_baton_vout(&self) -> Option<u32> {
+        match &self.kind {
+            SlpMessageKind::Genesis {mint_baton_vout, ..} => mint_baton_vout.map(|vout| vout as u32),
+            SlpMessageKind::Mint {mint_baton_vout, ..} => mint_baton_vout.map(|vout| vout as u32),
+            SlpMessageKind::Send {..} => None,
+        }
+    }
+
+    pub fn amount(&self, vout: u32) -> u64 {
+        match &self.kind {
+            SlpMessageKind::Genesis {initial_quantity, ..} if vout == 1 => *initial_quantity,
+            SlpMessageKind::Mint {quantity, ..} if vout == 1 => *quantity,
+            SlpMessageKind::Send {amounts, ..} if vout > 0 =>
+                amounts.get(vout as usize - 1).cloned().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn _read_push(cursor: &mut io::Cursor<&[u8]>) -> std::result::Result<Option<Vec<u8>>, SLPError> {
+        let truncated = |_| SLPError::InvalidPush("truncated push length".to_string());
+        let opcode = match cursor.read_u8() {
+            Ok(opcode) => opcode,
+            Err(_) => return Ok(None),
+        };
+        let len = match opcode {
+            0x01..=0x4b => opcode as usize,
+            0x4c => cursor.read_u8().map_err(truncated)? as usize,
+            0x4d => cursor.read_u16::<LittleEndian>().map_err(truncated)? as usize,
+            0x4e => cursor.read_u32::<LittleEndian>().map_err(truncated)? as usize,
+            _ => return Err(SLPError::UnexpectedOpCode(opcode)),
+        };
+        let remaining = cursor.get_ref().len() - cursor.position() as usize;
+        if len > remaining {
+            return Err(SLPError::InvalidPush(format!("push of {} bytes, {} left", len, remaining)));
+        }
+        let mut data = vec![0; len];
+        cursor.read_exact(&mut data).map_err(truncated)?;
+        Ok(Some(data))
+    }
+
+    fn _token_hash(token_id: &[u8]) -> std::result::Result<[u8; 32], SLPError> {
+        if token_id.len() != 32 {
+            return Err(SLPError::InvalidTokenHashLength(_hex(token_id)));
+        }
+        Ok(tx_hash_from_le_slice(token_id))
+    }
+
+    fn _mint_baton_vout(vout: &[u8]) -> std::result::Result<Option<u8>, SLPError> {
+        match vout {
+            &[] => Ok(None),
+            &[vout] if vout >= 2 => Ok(Some(vout)),
+            vout => Err(SLPError::InvalidField("mint_baton_vout", _hex(vout))),
+        }
+    }
+
+    fn _amount(amount: &[u8]) -> std::result::Result<u64, SLPError> {
+        if amount.len() != 8 {
+            return Err(SLPError::InvalidField("amount", _hex(amount)));
+        }
+        Ok(io::Cursor::new(amount).read_u64::<BigEndian>().unwrap())
+    }
+}
+
+struct ValidatedTx {
+    validity: SlpValidity,
+    message: Option<SlpMessage>,
+    slp_script: Vec<u8>,
+}
+
+enum InputToken {
+    Token {
+        token_hash: [u8; 32],
+        token_type: TokenType,
+        amount: u64,
+        is_mint_baton: bool,
+    },
+    NoToken,
+    Unknown,
+}
+
+pub struct SlpValidator<'a> {
+    db: Option<&'a Db>,
+    provider: Option<&'a dyn SlpTxProvider>,
+    txs: HashMap<[u8; 32], Tx>,
+    validated: HashMap<[u8; 32], ValidatedTx>,
+    new_validities: Vec<[u8; 32]>,
+}
+
+impl<'a> SlpValidator<'a> {
+    pub fn new(db: Option<&'a Db>, provider: Option<&'a dyn SlpTxProvider>) -> Self {
+        SlpValidator {
+            db,
+            provider,
+            txs: HashMap::new(),
+            validated: HashMap::new(),
+            new_validities: Vec::new(),
+        }
+    }
+
+    pub fn add_txs(&mut self, txs: &[Tx]) {
+        for tx in txs.iter() {
+            self.txs.insert(tx.hash(), tx.clone());
+        }
+    }
+
+    pub fn message(&self, tx_hash: &[u8; 32]) -> Option<&SlpMessage> {
+        self.validated.get(tx_hash)?.message.as_ref()
+    }
+
+    pub fn validate(&mut self, tx_hash: &[u8; 32]) -> Result<SlpValidity> {
+        let mut stack = vec![tx_hash.clone()];
+        let mut visiting = HashSet::new();
+        while let Some(hash) = stack.last().cloned() {
+            if self._lookup(&hash)? {
+                stack.pop();
+                continue;
+            }
+            let tx = match self._fetch(&hash)? {
+                Some(tx) => tx,
+                None => {
+                    self._insert(hash, SlpValidity::Unknown, None, vec![], false);
+                    stack.pop();
+                    continue;
+                },
+            };
+            let slp_script = tx.outputs().get(0)
+                .map(|output| output.script().to_vec())
+                .unwrap_or_default();
+            let message = match SlpMessage::parse(&slp_script) {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    self._insert(hash, SlpValidity::Invalid, None, slp_script, false);
+                    stack.pop();
+                    continue;
+                },
+                Err(err) => {
+                    eprintln!("Invalid SLP message in {}: {:?}", tx_hash_to_hex(&hash), err);
+                    self._insert(hash, SlpValidity::Invalid, None, slp_script, true);
+                    stack.pop();
+                    continue;
+                },
+            };
+            visiting.insert(hash);
+            let mut missing = Vec::new();
+            for parent in Self::_parents(&tx, &message) {
+                if self._lookup(&parent)? {
+                    continue;
+                }
+                if visiting.contains(&parent) {
+                    self._insert(parent, SlpValidity::Unknown, None, vec![], false);
+                    continue;
+                }
+                missing.push(parent);
+            }
+            if missing.len() > 0 {
+                stack.extend(missing);
+                continue;
+            }
+            let validity = self._check(&tx, &message);
+            self._insert(hash, validity, Some(message), slp_script, true);
+            visiting.remove(&hash);
+            stack.pop();
+        }
+        Ok(self.validated[tx_hash].validity)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        let validities = self.new_validities.drain(..)
+            .map(|tx_hash| {
+                let validated = &self.validated[&tx_hash];
+                models::SlpValidity {
+                    tx_hash: tx_hash.to_vec(),
+                    is_valid: validated.validity == SlpValidity::Valid,
+                    slp_script: validated.slp_script.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        if let Some(db) = self.db {
+            db.add_slp_validities(&validities)?;
+        }
+        Ok(())
+    }
+
+    fn _insert(&mut self,
+               tx_hash: [u8; 32],
+               validity: SlpValidity,
+               message: Option<SlpMessage>,
+               slp_script: Vec<u8>,
+               is_slp: bool) {
+        if is_slp && validity != SlpValidity::Unknown {
+            self.new_validities.push(tx_hash);
+        }
+        self.validated.insert(tx_hash, ValidatedTx { validity, message, slp_script });
+    }
+
+    fn _lookup(&mut self, tx_hash: &[u8; 32]) -> Result<bool> {
+        if self.validated.contains_key(tx_hash) {
+            return Ok(true);
+        }
+        let db = match self.db {
+            Some(db) => db,
+            None => return Ok(false),
+        };
+        match db.slp_validity(tx_hash)? {
+            Some(entry) => {
+                let message = if entry.is_valid {
+                    SlpMessage::parse(&entry.slp_script).ok().and_then(|message| message)
+                } else {
+                    None
+                };
+                self.validated.insert(tx_hash.clone(), ValidatedTx {
+                    validity: if entry.is_valid { SlpValidity::Valid } else { SlpValidity::Invalid },
+                    message,
+                    slp_script: entry.slp_script,
+                });
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn _fetch(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        if let Some(tx) = self.txs.get(tx_hash) {
+            return Ok(Some(tx.clone()));
+        }
+        match self.provider {
+            Some(provider) => provider.slp_tx(tx_hash),
+            None => Ok(None),
+        }
+    }
+
+    fn _parents(tx: &Tx, message: &SlpMessage) -> Vec<[u8; 32]> {
+        let n_inputs = match &message.kind {
+            SlpMessageKind::Genesis {..} if message.token_type == TokenType::NFT1Child => 1,
+            SlpMessageKind::Genesis {..} => 0,
+            _ => tx.inputs().len(),
+        };
+        let mut parents = tx.inputs().iter()
+            .take(n_inputs)
+            .filter(|input| input.outpoint.vout > 0)
+            .map(|input| input.outpoint.tx_hash.clone())
+            .collect::<Vec<_>>();
+        parents.sort();
+        parents.dedup();
+        parents
+    }
+
+    fn _input_token(&self, tx_hash: &[u8; 32], vout: u32) -> InputToken {
+        if vout == 0 {
+            return InputToken::NoToken;
+        }
+        match self.validated.get(tx_hash) {
+            None => InputToken::Unknown,
+            Some(ValidatedTx {validity: SlpValidity::Unknown, ..}) => InputToken::Unknown,
+            Some(ValidatedTx {validity: SlpValidity::Valid, message: Some(message), ..}) =>
+                InputToken::Token {
+                    token_hash: message.token_hash(tx_hash),
+                    token_type: message.token_type,
+                    amount: message.amount(vout),
+                    is_mint_baton: message.mint_baton_vout() == Some(vout),
+                },
+            Some(_) => InputToken::NoToken,
+        }
+    }
+
+    fn _check(&self, tx: &Tx, message: &SlpMessage) -> SlpValidity {
+        match &message.kind {
+            SlpMessageKind::Genesis {..} if message.token_type != TokenType::NFT1Child =>
+                SlpValidity::Valid,
+            SlpMessageKind::Genesis {..} => {
+                // an NFT1 child GENESIS must burn a group token in its first input
+                let input = match tx.inputs().get(0) {
+                    Some(input) => input,
+                    None => return SlpValidity::Invalid,
+                };
+                match self._input_token(&input.outpoint.tx_hash, input.outpoint.vout) {
+                    InputToken::Token {token_type: TokenType::NFT1Parent, amount, ..} if amount > 0 =>
+                        SlpValidity::Valid,
+                    InputToken::Unknown => SlpValidity::Unknown,
+                    _ => SlpValidity::Invalid,
+                }
+            },
+            SlpMessageKind::Mint {token_hash, ..} => {
+                let mut has_unknown = false;
+                for input in tx.inputs() {
+                    match self._input_token(&input.outpoint.tx_hash, input.outpoint.vout) {
+                        InputToken::Token {token_hash: input_token_hash, token_type, is_mint_baton: true, ..}
+                                if &input_token_hash == token_hash && token_type == message.token_type =>
+                            return SlpValidity::Valid,
+                        InputToken::Unknown => has_unknown = true,
+                        _ => {},
+                    }
+                }
+                if has_unknown { SlpValidity::Unknown } else { SlpValidity::Invalid }
+            },
+            SlpMessageKind::Send {token_hash, amounts} => {
+                let output_sum = amounts.iter().map(|&amount| amount as u128).sum::<u128>();
+                let mut input_sum = 0u128;
+                let mut has_unknown = false;
+                for input in tx.inputs() {
+                    match self._input_token(&input.outpoint.tx_hash, input.outpoint.vout) {
+                        InputToken::Token {token_hash: input_token_hash, token_type, amount, ..}
+                                if &input_token_hash == token_hash && token_type == message.token_type =>
+                            input_sum += amount as u128,
+                        InputToken::Unknown => has_unknown = true,
+                        _ => {},
+                    }
+                }
+                if input_sum >= output_sum {
+                    SlpValidity::Valid
+                } else if has_unknown {
+                    SlpValidity::Unknown
+                } else {
+                    SlpValidity::Invalid
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use cashcontracts::serialize::write_var_int;
+
+    fn push(data: &[u8]) -> Vec<u8> {
+        let mut script = Vec::new();
+        match data.len() {
+            0 => script.extend_from_slice(&[0x4c, 0x00]),
+            len if len <= 0x4b => script.push(len as u8),
+            len => script.extend_from_slice(&[0x4c, len as u8]),
+        }
+        script.extend_from_slice(data);
+        script
+    }
+
+    fn script(pushes: &[&[u8]]) -> Vec<u8> {
+        let mut script = vec![OP_RETURN];
+        for data in pushes {
+            script.extend(push(data));
+        }
+        script
+    }
+
+    fn amount(amount: u64) -> Vec<u8> {
+        amount.to_be_bytes().to_vec()
+    }
+
+    fn token_id(tx_hash: &[u8; 32]) -> Vec<u8> {
+        tx_hash.iter().rev().cloned().collect()
+    }
+
+    pub(crate) fn genesis(token_type: &[u8], baton: &[u8], quantity: u64) -> Vec<u8> {
+        script(&[LOKAD_ID, token_type, b"GENESIS", b"TEST", b"Test Token", b"", b"", &[0], baton,
+                 &amount(quantity)])
+    }
+
+    pub(crate) fn send(token_type: &[u8], token_hash: &[u8; 32], amounts: &[u64]) -> Vec<u8> {
+        let amounts = amounts.iter().map(|&a| amount(a)).collect::<Vec<_>>();
+        let token_id = token_id(token_hash);
+        let mut pushes: Vec<&[u8]> = vec![LOKAD_ID, token_type, b"SEND", &token_id];
+        pushes.extend(amounts.iter().map(|a| a.as_slice()));
+        script(&pushes)
+    }
+
+    pub(crate) fn mint(token_type: &[u8], token_hash: &[u8; 32], baton: &[u8], quantity: u64) -> Vec<u8> {
+        script(&[LOKAD_ID, token_type, b"MINT", &token_id(token_hash), baton, &amount(quantity)])
+    }
+
+    pub(crate) fn tx(inputs: &[([u8; 32], u32)], slp_script: Vec<u8>, n_outputs: usize) -> Tx {
+        let mut bytes = Vec::new();
+        bytes.write_i32::<LittleEndian>(1).unwrap();
+        write_var_int(&mut bytes, inputs.len() as u64).unwrap();
+        for (tx_hash, vout) in inputs {
+            bytes.extend_from_slice(tx_hash);
+            bytes.write_u32::<LittleEndian>(*vout).unwrap();
+            write_var_int(&mut bytes, 0).unwrap();
+            bytes.write_u32::<LittleEndian>(0xffff_ffff).unwrap();
+        }
+        write_var_int(&mut bytes, n_outputs as u64 + 1).unwrap();
+        bytes.write_u64::<LittleEndian>(0).unwrap();
+        write_var_int(&mut bytes, slp_script.len() as u64).unwrap();
+        bytes.extend(slp_script);
+        for _ in 0..n_outputs {
+            let p2pkh = [&[0x76, 0xa9, 0x14][..], &[0; 20], &[0x88, 0xac]].concat();
+            bytes.write_u64::<LittleEndian>(546).unwrap();
+            write_var_int(&mut bytes, p2pkh.len() as u64).unwrap();
+            bytes.extend(p2pkh);
+        }
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        Tx::read_from_stream(&mut io::Cursor::new(bytes)).unwrap()
+    }
+
+    // Script cases follow the categories of the SLP unit-test vectors (script_tests.json)
+    #[test]
+    fn script_vectors() {
+        let token_hash = [7; 32];
+        let valid = vec![
+            ("genesis", genesis(&[0x01], &[0x02], 100)),
+            ("genesis without baton", genesis(&[0x01], &[], 100)),
+            ("genesis with 2 byte token type", genesis(&[0x00, 0x01], &[], 100)),
+            ("nft1 child genesis", genesis(&[0x41], &[], 1)),
+            ("mint", mint(&[0x01], &token_hash, &[0x02], 100)),
+            ("send with 1 amount", send(&[0x01], &token_hash, &[1])),
+            ("send with 19 amounts", send(&[0x01], &token_hash, &[1; 19])),
+            ("non-minimal lokad push", [&[OP_RETURN, 0x4c, 0x04][..], LOKAD_ID,
+                                         &send(&[0x01], &token_hash, &[1])[6..]].concat()),
+        ];
+        for (msg, script) in valid {
+            assert!(SlpMessage::parse(&script).unwrap().is_some(), "should be valid: {}", msg);
+        }
+        let not_slp = vec![
+            ("empty script", vec![]),
+            ("no OP_RETURN", script(&[LOKAD_ID])[1..].to_vec()),
+            ("wrong lokad", script(&[b"SLP\x01", &[0x01], b"SEND"])),
+            ("lokad without terminator", script(&[b"SLP", &[0x01], b"SEND"])),
+        ];
+        for (msg, script) in not_slp {
+            assert!(SlpMessage::parse(&script).unwrap().is_none(), "should not be SLP: {}", msg);
+        }
+        let invalid = vec![
+            ("OP_0 push", [&[OP_RETURN][..], &push(LOKAD_ID), &[0x00]].concat()),
+            ("OP_1 push", [&[OP_RETURN][..], &push(LOKAD_ID), &[0x51]].concat()),
+            ("truncated push", [&[OP_RETURN][..], &push(LOKAD_ID), &[0x05, 0x01]].concat()),
+            ("missing token type", script(&[LOKAD_ID])),
+            ("empty token type", script(&[LOKAD_ID, b"", b"SEND"])),
+            ("3 byte token type", script(&[LOKAD_ID, &[0, 0, 1], b"SEND"])),
+            ("unknown token type", genesis(&[0x02], &[], 100)),
+            ("unknown tx type", script(&[LOKAD_ID, &[0x01], b"BURN"])),
+            ("lowercase tx type", script(&[LOKAD_ID, &[0x01], b"send"])),
+            ("commit", script(&[LOKAD_ID, &[0x01], b"COMMIT"])),
+            ("genesis with extra push",
+             [genesis(&[0x01], &[], 100), push(b"extra")].concat()),
+            ("genesis decimals 10",
+             script(&[LOKAD_ID, &[0x01], b"GENESIS", b"", b"", b"", b"", &[10], b"", &amount(1)])),
+            ("genesis document hash 31 bytes",
+             script(&[LOKAD_ID, &[0x01], b"GENESIS", b"", b"", b"", &[0; 31], &[0], b"", &amount(1)])),
+            ("genesis baton vout 1", genesis(&[0x01], &[0x01], 100)),
+            ("genesis 7 byte quantity",
+             script(&[LOKAD_ID, &[0x01], b"GENESIS", b"", b"", b"", b"", &[0], b"", &[0; 7]])),
+            ("nft1 child genesis with baton", genesis(&[0x41], &[0x02], 1)),
+            ("nft1 child genesis quantity 2", genesis(&[0x41], &[], 2)),
+            ("nft1 child mint", mint(&[0x41], &token_hash, &[], 1)),
+            ("mint baton vout 0", mint(&[0x01], &token_hash, &[0x00], 1)),
+            ("mint with 31 byte token id",
+             script(&[LOKAD_ID, &[0x01], b"MINT", &[0; 31], b"", &amount(1)])),
+            ("send without amounts", script(&[LOKAD_ID, &[0x01], b"SEND", &token_id(&token_hash)])),
+            ("send with 20 amounts", send(&[0x01], &token_hash, &[1; 20])),
+            ("send with 9 byte amount",
+             script(&[LOKAD_ID, &[0x01], b"SEND", &token_id(&token_hash), &[0; 9]])),
+        ];
+        for (msg, script) in invalid {
+            assert!(SlpMessage::parse(&script).is_err(), "should be invalid: {}", msg);
+        }
+    }
+
+    #[test]
+    fn send_amounts() {
+        let token_hash = [7; 32];
+        let message = SlpMessage::parse(&send(&[0x01], &token_hash, &[5, 6])).unwrap().unwrap();
+        assert_eq!(message.token_type, TokenType::Standard);
+        assert_eq!(message.token_hash(&[1; 32]), token_hash);
+        assert_eq!((message.amount(0), message.amount(1), message.amount(2), message.amount(3)),
+                   (0, 5, 6, 0));
+    }
+
+    // DAG cases follow the SLP unit-test vectors (tx_input_tests.json)
+    #[test]
+    fn input_dag() {
+        let genesis_tx = tx(&[([0; 32], 0)], genesis(&[0x01], &[0x02], 100), 2);
+        let token = genesis_tx.hash();
+        let send_ok = tx(&[(token, 1)], send(&[0x01], &token, &[60, 40]), 2);
+        let send_too_much = tx(&[(send_ok.hash(), 1)], send(&[0x01], &token, &[61]), 1);
+        let send_combined = tx(&[(send_ok.hash(), 1), (send_ok.hash(), 2)],
+                               send(&[0x01], &token, &[100]), 1);
+        let send_from_invalid = tx(&[(send_too_much.hash(), 1)], send(&[0x01], &token, &[1]), 1);
+        let send_wrong_type = tx(&[(token, 1)], send(&[0x81], &token, &[1]), 1);
+        let mint_ok = tx(&[(token, 2)], mint(&[0x01], &token, &[0x02], 50), 2);
+        let mint_no_baton = tx(&[(token, 1)], mint(&[0x01], &token, &[0x02], 50), 2);
+        let mint_passed_baton = tx(&[(mint_ok.hash(), 2)], mint(&[0x01], &token, &[], 50), 1);
+        let burn = tx(&[(send_combined.hash(), 1)], vec![], 1);
+        let txs = vec![genesis_tx, send_ok, send_too_much, send_combined, send_from_invalid,
+                       send_wrong_type, mint_ok, mint_no_baton, mint_passed_baton, burn];
+        let mut validator = SlpValidator::new(None, None);
+        validator.add_txs(&txs);
+        let expected = [
+            SlpValidity::Valid, SlpValidity::Valid, SlpValidity::Invalid, SlpValidity::Valid,
+            SlpValidity::Invalid, SlpValidity::Invalid, SlpValidity::Valid, SlpValidity::Invalid,
+            SlpValidity::Valid, SlpValidity::Invalid,
+        ];
+        for (tx, expected) in txs.iter().zip(expected.iter()) {
+            assert_eq!(validator.validate(&tx.hash()).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn nft1_child_genesis_burns_group() {
+        let group = tx(&[([0; 32], 0)], genesis(&[0x81], &[], 2), 1);
+        let group_hash = group.hash();
+        let child = tx(&[(group_hash, 1)], genesis(&[0x41], &[], 1), 1);
+        let child_second_input = tx(&[([9; 32], 0), (group_hash, 1)], genesis(&[0x41], &[], 1), 1);
+        let type1 = tx(&[([0; 32], 1)], genesis(&[0x01], &[], 2), 1);
+        let child_type1 = tx(&[(type1.hash(), 1)], genesis(&[0x41], &[], 1), 1);
+        let child_unknown = tx(&[([5; 32], 1)], genesis(&[0x41], &[], 1), 1);
+        let mut validator = SlpValidator::new(None, None);
+        validator.add_txs(&[group, child.clone(), child_second_input.clone(), type1,
+                            child_type1.clone(), child_unknown.clone()]);
+        assert_eq!(validator.validate(&child.hash()).unwrap(), SlpValidity::Valid);
+        assert_eq!(validator.validate(&child_second_input.hash()).unwrap(), SlpValidity::Invalid);
+        assert_eq!(validator.validate(&child_type1.hash()).unwrap(), SlpValidity::Invalid);
+        assert_eq!(validator.validate(&child_unknown.hash()).unwrap(), SlpValidity::Unknown);
+    }
+
+    #[test]
+    #[ignore]
+    fn official_script_vectors() {
+        // set SLP_UNIT_TEST_DATA to a checkout of github.com/simpleledger/slp-unit-test-data
+        let dir = std::env::var("SLP_UNIT_TEST_DATA").expect("SLP_UNIT_TEST_DATA not set");
+        let vectors = std::fs::read_to_string(format!("{}/script_tests.json", dir)).unwrap();
+        for vector in json::parse(&vectors).unwrap().members() {
+            let script = hex::decode(vector["script"].as_str().unwrap()).unwrap();
+            let is_valid = vector["code"].is_null();
+            let parsed = SlpMessage::parse(&script).ok().and_then(|message| message);
+            assert_eq!(parsed.is_some(), is_valid, "{}", vector["msg"]);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use crate::token_source::token_result::TokenEntry;
 use slpdexdb_base::SLPAmount;
 use slpdexdb_base::{Result, ErrorKind, Error, TokenError};
 use crate::tx_history::TokenType;
+use crate::slp_validator::{SlpMessage, SlpMessageKind};
 use cashcontracts::tx_hex_to_hash;
 
 #[derive(Clone, Debug)]
@@ -20,11 +21,64 @@ pub struct Token {
     pub block_created_height: i32,
 }
 
+#[derive(Clone, Debug)]
+pub struct TokenSupply {
+    pub token_hash:         [u8; 32],
+    pub mint_baton:         Option<([u8; 32], i32)>,
+    pub initial_supply:     SLPAmount,
+    pub minted_supply:      SLPAmount,
+    pub burned_supply:      SLPAmount,
+    pub circulating_supply: SLPAmount,
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenStats {
+    pub token_hash:          [u8; 32],
+    pub holder_count:        i32,
+    pub tx_count:            i32,
+    pub utxo_count:          i32,
+    pub last_active_height:  Option<i32>,
+    pub last_updated_height: i32,
+}
+
 impl Token {
     pub fn str_or_empty(string: String) -> Option<String> {
         if string.is_empty() { None } else { Some(string) }
     }
 
+    pub fn from_genesis(token_hash: [u8; 32],
+                        genesis: &SlpMessage,
+                        parent_hash: Option<[u8; 32]>,
+                        timestamp: i64,
+                        block_created_height: i32) -> Option<Self> {
+        let text = |bytes: &[u8]| Self::str_or_empty(String::from_utf8_lossy(bytes).to_string());
+        match &genesis.kind {
+            SlpMessageKind::Genesis {ticker, name, document_uri, document_hash, decimals,
+                                     initial_quantity, ..} => {
+                let initial_supply = SLPAmount::new(*initial_quantity as i128, *decimals as u32);
+                Some(Token {
+                    hash: token_hash,
+                    parent_hash,
+                    decimals: *decimals as i32,
+                    timestamp,
+                    version_type: genesis.token_type,
+                    document_uri: text(&document_uri[..]),
+                    symbol: text(&ticker[..]),
+                    name: text(&name[..]),
+                    document_hash: if document_hash.is_empty() {
+                        None
+                    } else {
+                        Some(hex::encode(document_hash))
+                    },
+                    initial_supply,
+                    current_supply: initial_supply,
+                    block_created_height,
+                })
+            },
+            _ => None,
+        }
+    }
+
     pub fn from_entry(token_entry: TokenEntry) -> Result<Self> {
         let not_mined_yet_err = || -> Error {
             ErrorKind::TokenError(
@@ -76,3 +130,39 @@ impl Token {
         })
     }
 }
+
+impl TokenSupply {
+    pub fn from_entry(token_entry: &TokenEntry) -> Result<Self> {
+        let decimals = token_entry.token_details.decimals as u32;
+        let token_hash = tx_hex_to_hash(&token_entry.token_details.token_id_hex)
+            .ok_or_else(|| -> Error {
+                ErrorKind::TokenError(
+                    TokenError::InvalidTokenHex(token_entry.token_details.token_id_hex.clone())
+                ).into()
+            })?;
+        let mut baton_parts = token_entry.mint_baton_utxo.split(':');
+        let mint_baton = match (baton_parts.next().and_then(tx_hex_to_hash),
+                                baton_parts.next().and_then(|vout| vout.parse().ok())) {
+            (Some(tx_hash), Some(vout)) => Some((tx_hash, vout)),
+            _ => None,
+        };
+        let stats = &token_entry.token_stats;
+        let initial_supply = SLPAmount::from_str_decimals(
+            &token_entry.token_details.genesis_or_mint_quantity,
+            decimals,
+        )?;
+        let burned_supply = SLPAmount::from_str_decimals(&stats.qty_token_burned, decimals)?;
+        let circulating_supply = SLPAmount::from_str_decimals(
+            &stats.qty_token_circulating_supply,
+            decimals,
+        )?;
+        Ok(TokenSupply {
+            token_hash,
+            mint_baton,
+            initial_supply,
+            minted_supply: circulating_supply + burned_supply - initial_supply,
+            burned_supply,
+            circulating_supply,
+        })
+    }
+}
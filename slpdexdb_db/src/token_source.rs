@@ -1,7 +1,7 @@
 use crate::endpoint::Endpoint;
 use crate::tx_source::{TxFilter, SortKey};
 use cashcontracts::tx_hash_to_hex;
-use json::{JsonValue, object, object::Object};
+use json::{JsonValue, object};
 
 pub struct TokenSource {
     endpoint: Endpoint
@@ -10,7 +10,7 @@ pub struct TokenSource {
 pub mod token_result {
     use serde::Deserialize;
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Clone, Debug)]
     pub struct TokenDetails {
         pub decimals: i32,
         #[serde(rename = "tokenIdHex")]
@@ -35,22 +35,23 @@ pub mod token_result {
         pub genesis_or_mint_quantity: String,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Clone, Debug)]
     pub struct TokenStats {
         pub block_created: Option<i32>,
         pub block_last_active_send: Option<i32>,
         pub block_last_active_mint: Option<i32>,
-        pub qty_valid_txns_since_genesis: i32,
-        pub qty_valid_token_utxos: i32,
-        pub qty_valid_token_addresses: i32,
+        // counts are only known to an indexer, our own are derived from the db
+        pub qty_valid_txns_since_genesis: Option<i32>,
+        pub qty_valid_token_utxos: Option<i32>,
+        pub qty_valid_token_addresses: Option<i32>,
         pub qty_token_minted: String,
         pub qty_token_burned: String,
         pub qty_token_circulating_supply: String,
-        pub qty_satoshis_locked_up: i32,
+        pub qty_satoshis_locked_up: Option<i32>,
         pub minting_baton_status: String,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Clone, Debug)]
     pub struct TokenEntry {
         pub schema_version: i32,
         #[serde(rename = "lastUpdatedBlock")]
@@ -65,7 +66,7 @@ pub mod token_result {
         pub nft_parent_id: Option<String>,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Clone, Debug)]
     pub struct TokenResult {
         pub t: Vec<TokenEntry>,
     }
@@ -78,6 +79,10 @@ impl TokenSource {
         }
     }
 
+    pub fn with_endpoint(endpoint: Endpoint) -> Self {
+        TokenSource { endpoint }
+    }
+
     fn _conditions(filters: &[TxFilter]) -> Vec<(&'static str, JsonValue)> {
         filters.iter()
             .filter_map(|filter| match filter {
@@ -112,16 +117,21 @@ impl TokenSource {
 
     pub fn request_tokens(&self, filters: &[TxFilter])
             -> reqwest::Result<Vec<token_result::TokenEntry>> {
-        let mut condition_json = Object::new();
+        // conditions may share a key (e.g. TokenId and MinTxHash), so they're combined with $and
+        let mut conditions_json = Vec::new();
         for (key, json) in Self::_conditions(filters) {
-            condition_json.insert(key, json);
+            conditions_json.push(object!{key => json});
         }
         let sort = Self::_sort_by(filters);
         let query_json = json::stringify(object!{
             "v" => 3,
             "q" => object!{
                 "db" => "t",
-                "find" => JsonValue::Object(condition_json),
+                "find" => if conditions_json.is_empty() {
+                    object!{}
+                } else {
+                    object!{"$and" => JsonValue::Array(conditions_json)}
+                },
                 "sort" => sort,
             },
         });
@@ -1,12 +1,15 @@
-use crate::tx_source::{tx_result, TxSource, TxFilter, Confirmedness};
-use slpdexdb_base::{SLPDEXConfig, SLPAmount, Result, Error, ErrorKind, SLPError, TokenError, TradeOfferError, PNDError};
+use crate::tx_source::tx_result;
+use slpdexdb_base::{SLPDEXConfig, Network, SLPAmount, Result, Error, ErrorKind, SLPError, TokenError, TradeOfferError, PNDError};
 use crate::token::Token;
 use crate::db::Db;
+use crate::slp_validator::{SlpValidator, SlpValidity, SlpMessage, SlpMessageKind, SlpTxProvider};
 use crate::data::{tx_hash_from_slice, tx_hash_from_le_slice};
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use std::io;
 use std::collections::{HashSet, HashMap};
-use cashcontracts::{Output, P2PKHOutput, AddressType, Address, tx_hash_to_hex, tx_hex_to_hash, single_sha256, double_sha256};
+use std::convert::identity;
+use cashcontracts::{Output, P2PKHOutput, AddressType, Address, tx_hash_to_hex, tx_hex_to_hash, single_sha256, double_sha256,
+                    hash160};
 use rug::Rational;
 use crate::panda_tools::get_panda_by_owner_utxo;
 
@@ -16,6 +19,8 @@ pub struct TxHistory {
     pub trade_offers: HashMap<usize, TradeOffer>,
     pub pnd_txs: HashMap<usize, PND1Tx>,
     pub pandas_slp: HashSet<usize>,
+    pub mint_batons: HashMap<usize, Option<i32>>,  // tx idx -> new baton vout, None if destroyed
+    pub new_tokens: HashMap<usize, Token>,  // tx idx -> token of a GENESIS, added with the tx once validated
 }
 
 #[derive(Clone, Debug)]
@@ -161,13 +166,13 @@ impl TxType {
 }
 
 impl TxHistory {
-    fn _process_address(address: &Option<String>) -> OutputType {
+    fn _process_address(address: &Option<String>, network: &Network) -> OutputType {
         match address {
             Some(addr) => {
                 let address = if addr.contains(":") {
                     Address::from_cash_addr(addr.to_string())
                 } else {
-                    Address::from_cash_addr("bitcoincash:".to_string() + addr)
+                    Address::from_cash_addr(format!("{}:{}", network.cash_addr_prefix, addr))
                 };
                 address.map(OutputType::Address).unwrap_or(OutputType::Unknown)
             },
@@ -187,7 +192,7 @@ impl TxHistory {
                     HistoricTxInput {
                         output_tx: cashcontracts::tx_hex_to_hash(&input.e.h).unwrap(),
                         output_idx: input.e.i,
-                        output: Self::_process_address(&input.e.a)
+                        output: Self::_process_address(&input.e.a, &config.network)
                     }
                 })
                 .collect::<Vec<_>>();
@@ -211,7 +216,7 @@ impl TxHistory {
                         output: if output.b0 == (tx_result::StackItem::Op {op: 0x6a}) {
                             OutputType::OpReturn
                         } else {
-                            Self::_process_address(&output.e.a)
+                            Self::_process_address(&output.e.a, &config.network)
                         },
                     }
                 })
@@ -230,10 +235,10 @@ impl TxHistory {
                 inputs,
                 outputs,
             };
-            entry.slp.as_ref().and_then(|slp| {
+            entry.slp.as_ref().and_then(|_| {
                 trade_offers.insert(
                     historic_txs.len(),
-                    TradeOffer::from_entry(&historic_tx, entry, config, slp.detail.decimals as u32)?,
+                    TradeOffer::from_entry(&historic_tx, entry, config)?,
                 );
                 Some(())
             });
@@ -244,6 +249,8 @@ impl TxHistory {
             trade_offers,
             pnd_txs,
             pandas_slp: HashSet::new(),
+            mint_batons: HashMap::new(),
+            new_tokens: HashMap::new(),
         }
     }
 
@@ -256,7 +263,7 @@ impl TxHistory {
         match &ops[1] {
             Op::Push(pubkey) if pubkey.len() == 33 => {
                 OutputType::Address(
-                    Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, pubkey)
+                    Address::from_bytes(AddressType::P2PKH, hash160(pubkey))
                 )
             },
             _ => OutputType::Unknown
@@ -281,8 +288,11 @@ impl TxHistory {
         }
     }
 
-    pub fn _process_slp_output(tx: &cashcontracts::Tx, db: &Db)
-            -> Result<Option<(TxType, Vec<SLPAmount>, Token)>> {
+    pub fn _process_slp_output(tx: &cashcontracts::Tx,
+                               height: Option<i32>,
+                               timestamp: i64,
+                               new_tokens: &HashMap<[u8; 32], Token>,
+                               db: &Db) -> Result<Option<(TxType, Vec<SLPAmount>, Token)>> {
         use cashcontracts::{Op::*, OpCodeType::*, serialize};
         let script = tx.outputs()[0].script();
         let script_hex = || hex::encode(script.to_vec());
@@ -299,27 +309,26 @@ impl TxHistory {
             return Err(ErrorKind::InvalidSLPOutput(script_hex(),
                                                    SLPError::NotSLPSafe).into());
         }
-        match &ops {
-            &[Code(OpReturn), Push(_), Push(token_type), Push(tx_type), Push(token_ticker),
-              Push(token_name), Push(token_document_url), Push(token_document_hash), Push(decimals),
-              Push(mint_baton_vout), Push(initial_token_mint_quantity)] if tx_type == b"GENESIS" => {
-                let decimals = decimals[0];
-                return Ok(Some((
-                    TxType::SLP {
-                        slp_type: SLPTxType::Genesis,
-                        token_type: num::traits::FromPrimitive::from_u8(token_type[0]).unwrap(),
-                        token_hash: tx.hash(),
+        match (&ops[0], &ops[1], &ops[2], &ops[3], &ops[4]) {
+            (Code(OpReturn), Push(_), Push(_), Push(tx_type), Push(_)) if tx_type == b"GENESIS" => {
+                let message = SlpMessage::parse(&script.to_vec())
+                    .map_err(|err| ErrorKind::InvalidSLPOutput(script_hex(), err))?;
+                match message {
+                    Some(message) => {
+                        let token = Self::_genesis_token(tx, &message, height, timestamp, db)?;
+                        Ok(Some((
+                            TxType::SLP {
+                                slp_type: SLPTxType::Genesis,
+                                token_type: message.token_type,
+                                token_hash: tx.hash(),
+                            },
+                            vec![token.initial_supply],
+                            token,
+                        )))
                     },
-                    vec![SLPAmount::new(
-                        io::Cursor::new(&initial_token_mint_quantity).read_u64::<BigEndian>()? as i128,
-                        decimals as u32,
-                    )],
-                    Self::_fetch_token(&tx.hash(), db)?,
-                )))
+                    None => Ok(None),
+                }
             },
-            _ => {},
-        }
-        match (&ops[0], &ops[1], &ops[2], &ops[3], &ops[4]) {
             (Code(OpReturn), Push(_), Push(token_type), Push(tx_type), Push(token_id))
                     if tx_type == b"SEND" => {
                 if token_type.len() > 2 || token_type.len() == 0 {
@@ -336,7 +345,7 @@ impl TxHistory {
                 }
                 let mut token_hash = [0; 32];
                 token_hash.copy_from_slice(&token_id.iter().rev().cloned().collect::<Vec<_>>());
-                let token = Self::_fetch_token(&token_hash, db)?;
+                let token = Self::_token(&token_hash, new_tokens, db)?;
                 let decimals = token.decimals as u32;
                 let token_type = serialize::vec_to_int(token_type);
                 let amounts = ops[5..].iter()
@@ -377,6 +386,28 @@ impl TxHistory {
                     Ok(None)
                 }
             },
+            (Code(OpReturn), Push(_), Push(_), Push(tx_type), Push(_)) if tx_type == b"MINT" => {
+                let message = SlpMessage::parse(&script.to_vec())
+                    .map_err(|err| ErrorKind::InvalidSLPOutput(script_hex(), err))?;
+                match message {
+                    Some(SlpMessage {
+                        token_type,
+                        kind: SlpMessageKind::Mint { token_hash, quantity, .. },
+                    }) => {
+                        let token = Self::_token(&token_hash, new_tokens, db)?;
+                        Ok(Some((
+                            TxType::SLP {
+                                slp_type: SLPTxType::Mint,
+                                token_type,
+                                token_hash,
+                            },
+                            vec![SLPAmount::new(quantity as i128, token.decimals as u32)],
+                            token,
+                        )))
+                    },
+                    _ => Ok(None),
+                }
+            },
             _ => { Err(ErrorKind::InvalidSLPOutput(script_hex(), SLPError::NoMatch).into()) }
         }
     }
@@ -495,7 +526,7 @@ impl TxHistory {
                             PNDError::InvalidFatherUTXO(format!("{}:{}", hex::encode(mother_hash), mother_output_idx))
                         ).into()
                     })?;
-                let address = Address::from_serialized_pub_key("simpleledger", AddressType::P2PKH, &pubkey);
+                let address = Address::from_serialized_pub_key(config.network.slp_addr_prefix, AddressType::P2PKH, &pubkey);
                 if father_output.address != Some(address.bytes().to_vec()) {
                     return Err(ErrorKind::InvalidPND(
                         PNDError::FatherNotOwnedByAddress(hex::encode(father_hash), address.cash_addr().to_string())
@@ -552,10 +583,29 @@ impl TxHistory {
     }
 
     pub fn from_txs(txs: &[cashcontracts::Tx], now: i64, config: &SLPDEXConfig, db: &Db) -> Self {
+        Self::_from_txs(txs, None, now, config, db)
+    }
+
+    pub fn from_block_txs(txs: &[cashcontracts::Tx],
+                          height: Option<i32>,
+                          block_timestamp: i64,
+                          config: &SLPDEXConfig,
+                          db: &Db) -> Self {
+        Self::_from_txs(txs, height, block_timestamp, config, db)
+    }
+
+    fn _from_txs(txs: &[cashcontracts::Tx],
+                 height: Option<i32>,
+                 timestamp: i64,
+                 config: &SLPDEXConfig,
+                 db: &Db) -> Self {
         let mut historic_txs = Vec::new();
         let mut trade_offers = HashMap::new();
         let mut pnd_txs = HashMap::new();
         let mut pandas_slp = HashSet::new();
+        let mut mint_batons = HashMap::new();
+        let mut new_tokens = HashMap::new();
+        let mut batch_tokens = HashMap::new();
         for tx in txs.iter() {
             let inputs = tx.inputs().iter()
                 .map(|input| {
@@ -569,7 +619,7 @@ impl TxHistory {
             let (tx_type, slp_amounts, token) = tx.outputs()
                 .get(0)
                 .and_then(|output| {
-                    match Self::_process_slp_output(tx, db) {
+                    match Self::_process_slp_output(tx, height, timestamp, &batch_tokens, db) {
                         Ok(slp_output) => slp_output,
                         Err(err) => {
                             eprintln!("Invalid SLP output: {} in {}", err, tx_hash_to_hex(&tx.hash()));
@@ -608,8 +658,8 @@ impl TxHistory {
                 .collect::<Vec<_>>();
             let historic_tx = HistoricTx {
                 hash: tx.hash(),
-                height: None,
-                timestamp: now,
+                height,
+                timestamp,
                 tx_type,
                 inputs,
                 outputs,
@@ -633,6 +683,14 @@ impl TxHistory {
             if let Some(trade_offer) = trade_offer {
                 trade_offers.insert(historic_txs.len(), trade_offer);
             }
+            if let TxType::SLP { slp_type: SLPTxType::Genesis, .. } |
+                   TxType::SLP { slp_type: SLPTxType::Mint, .. } = &historic_tx.tx_type {
+                mint_batons.insert(historic_txs.len(), Self::_mint_baton_vout(tx));
+            }
+            if let (Some(token), TxType::SLP { slp_type: SLPTxType::Genesis, .. }) = (token, &historic_tx.tx_type) {
+                batch_tokens.insert(token.hash, token.clone());
+                new_tokens.insert(historic_txs.len(), token);
+            }
             historic_txs.push(historic_tx);
         }
         TxHistory {
@@ -640,157 +698,109 @@ impl TxHistory {
             trade_offers,
             pnd_txs,
             pandas_slp,
+            mint_batons,
+            new_tokens,
         }
     }
 
+    fn _mint_baton_vout(tx: &cashcontracts::Tx) -> Option<i32> {
+        let script = tx.outputs().get(0)?.script().to_vec();
+        SlpMessage::parse(&script).ok()
+            .and_then(identity)?
+            .mint_baton_vout()
+            .map(|vout| vout as i32)
+    }
+
     pub fn _fetch_token(token_hash: &[u8; 32], db: &Db) -> Result<Token> {
         match db.token(token_hash)? {
             Some(token) => Ok(token),
-            None => {
-                return Err(   // CryptoPandas only cares about known tokens
-                    ErrorKind::TokenError(
-                        TokenError::UnknownTokenId(tx_hash_to_hex(token_hash))
-                    ).into()
-                );
-                let mut token_entries = crate::token_source::TokenSource::new()
-                    .request_tokens(&[TxFilter::TokenId(token_hash.clone())])?;
-                println!("token entry: {:?}", token_entries);
-                if token_entries.len() == 0 {
-                    return Err(
-                        ErrorKind::TokenError(
-                            TokenError::UnknownTokenId(tx_hash_to_hex(token_hash))
-                        ).into()
-                    )
-                }
-                let token = Token::from_entry(token_entries.remove(0))?;
-                println!("new token: {:?}", token);
-                db.add_tokens(&[token.clone()])?;
-                Ok(token)
-            },
+            None => Err(   // tokens are indexed from their GENESIS tx
+                ErrorKind::TokenError(
+                    TokenError::UnknownTokenId(tx_hash_to_hex(token_hash))
+                ).into()
+            ),
         }
     }
 
-    pub fn validate_slp(&mut self, tx_source: &TxSource, db: &Db, config: &SLPDEXConfig)
-                        -> Result<()> {
-        let tx_to_check = self.txs.iter()
-            .flat_map(|tx| {
-                tx.inputs.iter()
-                    .map(|input| input.output_tx)
-                    .take(match tx.tx_type {
-                        TxType::SLP {..} => tx.inputs.len(),
-                        TxType::Default => 0,
-                    })
+    fn _token(token_hash: &[u8; 32], new_tokens: &HashMap<[u8; 32], Token>, db: &Db) -> Result<Token> {
+        match new_tokens.get(token_hash) {
+            Some(token) => Ok(token.clone()),
+            None => Self::_fetch_token(token_hash, db),
+        }
+    }
+
+    fn _genesis_token(tx: &cashcontracts::Tx,
+                      genesis: &SlpMessage,
+                      height: Option<i32>,
+                      timestamp: i64,
+                      db: &Db) -> Result<Token> {
+        if let Some(token) = db.token(&tx.hash())? {
+            return Ok(token);
+        }
+        let parent_hash = match genesis.token_type {
+            TokenType::NFT1Child => {
+                let group_tx = tx.inputs().get(0).map(|input| input.outpoint.tx_hash);
+                db.slp_txs(group_tx.into_iter())?
+                    .into_iter()
+                    .map(|(_, (_, _, token))| token)
+                    .find(|token| token.version_type == TokenType::NFT1Parent as i16)
+                    .map(|token| tx_hash_from_slice(&token.hash))
+            },
+            _ => None,
+        };
+        Token::from_genesis(tx.hash(), genesis, parent_hash, timestamp, height.unwrap_or(0))
+            .ok_or_else(|| -> Error {
+                ErrorKind::InvalidSLPOutput(
+                    hex::encode(tx.outputs()[0].script().to_vec()),
+                    SLPError::NoMatch,
+                ).into()
             })
-            .collect::<HashSet<_>>();
-        if tx_to_check.len() == 0 { return Ok(()); }
-        let tx_outputs = db.tx_outputs(tx_to_check.iter().cloned())?;
-        let slp_txs = db.slp_txs(tx_to_check.iter().cloned())?;
-        for i in 0..self.txs.len() {
-            let tx = &mut self.txs[i];
-            let (token_hash, token_type) = match tx.tx_type {
-                TxType::SLP {ref token_hash, token_type, ..} => (token_hash, token_type),
-                TxType::Default => continue,
-            };
-            println!("validating {}", cashcontracts::tx_hash_to_hex(&tx.hash));
-            println!("token found: ");
-            let output_sum = tx.outputs.iter()
-                .map(|output| output.value_token)
-                .sum::<SLPAmount>();
-            let input_sum = tx.inputs.iter()
-                .filter_map(|input| {
-                    let (tx, slp_tx, token) = slp_txs.get(&input.output_tx)?;
-                    Some((
-                        input,
-                        tx,
-                        slp_tx,
-                        tx_outputs.get(&(input.output_tx.clone(), input.output_idx))?,
-                        token,
-                    ))
-                })
-                .filter(|(input, tx, slp_tx, output, token)| {
-                    input.output_idx > 0 &&
-                        &token.hash == token_hash &&
-                        slp_tx.version == token_type as i32
-                })
-                .map(|(input, tx, slp_tx, output, token)|
-                    SLPAmount::from_numeric_decimals(&output.value_token_base, token.decimals as u32)
-                )
-                .sum::<SLPAmount>();
-            println!("input sum: {}", input_sum);
-            println!("output sum: {}", output_sum);
-            if input_sum < output_sum {
-                tx.tx_type = TxType::Default;
-                tx.outputs.iter_mut().for_each(|output| {
-                    output.value_token = SLPAmount::new(0, 0);
-                });
-                self.trade_offers.remove(&i);
-                self.pandas_slp.remove(&i);
+    }
+
+    // downgrades txs proven invalid; returns the txs whose ancestors couldn't be fetched yet
+    pub fn validate_slp(&mut self,
+                        txs: &[cashcontracts::Tx],
+                        db: &Db,
+                        provider: Option<&dyn SlpTxProvider>) -> Result<Vec<[u8; 32]>> {
+        let mut validator = SlpValidator::new(Some(db), provider);
+        validator.add_txs(txs);
+        let slp_txs = txs.iter().filter(|tx| {
+            let script = tx.outputs().get(0).map(|output| output.script().to_vec()).unwrap_or_default();
+            match SlpMessage::parse(&script) {
+                Ok(None) => false,
+                _ => true,
             }
+        });
+        for tx in slp_txs {
+            validator.validate(&tx.hash())?;
         }
-        Ok(())
+        validator.flush()?;
+        self._apply_slp_validity(&mut validator)
     }
 
-    pub fn validate_slp_remote(&mut self, tx_source: &TxSource, db: &Db, config: &SLPDEXConfig)
-            -> Result<()> {
-        let tx_to_check = self.txs.iter()
-            .flat_map(|tx| {
-                tx.inputs.iter()
-                    .map(|input| input.output_tx)
-                    .take(match tx.tx_type {
-                        TxType::SLP {..} => tx.inputs.len(),
-                        TxType::Default => 0,
-                    })
-            })
-            .collect::<HashSet<_>>();
-        if tx_to_check.len() == 0 { return Ok(()); }
-        let tx_to_check = tx_to_check.into_iter()
-            .map(TxFilter::TxHash)
-            .collect::<Vec<_>>();
-        let validity_map = tx_source
-            .request_slp_tx_validity(&tx_to_check, config, Confirmedness::Both)?
-            .into_iter()
-            .map(|validity| (cashcontracts::tx_hex_to_hash(&validity.tx.h).unwrap(), validity))
-            .collect::<HashMap<_, _>>();
+    fn _apply_slp_validity(&mut self, validator: &mut SlpValidator) -> Result<Vec<[u8; 32]>> {
+        let mut pending = Vec::new();
         for i in 0..self.txs.len() {
             let tx = &mut self.txs[i];
-            let (token_hash, token_type) = match &tx.tx_type {
-                TxType::SLP {token_hash, token_type, ..} => (token_hash, token_type),
-                TxType::Default => continue,
-            };
-            println!("validating {}", cashcontracts::tx_hash_to_hex(&tx.hash));
-            println!("token found: ");
-            let decimals = tx.outputs.iter()
-                .map(|output| output.value_token.decimals())
-                .next();
-            let output_sum = tx.outputs.iter()
-                .map(|output| output.value_token)
-                .sum::<SLPAmount>();
-            let input_sum = tx.inputs.iter()
-                .filter_map(|input| Some((input, validity_map.get(&input.output_tx)?)))
-                .filter(|(tx_input, validity)|
-                    validity.slp.valid &&
-                        tx_input.output_idx > 0 &&
-                        tx_hex_to_hash(&validity.slp.detail.token_id).as_ref() == Some(token_hash) &&
-                        validity.slp.detail.version_type == *token_type as i32
-                )
-                .filter_map(|(tx_input, validity)|
-                    validity.slp.detail.outputs.get((tx_input.output_idx - 1) as usize)
-                )
-                .filter_map(|slp_output: &tx_result::TxSLPOutput| {
-                    Some(SLPAmount::from_str_decimals(&slp_output.amount, decimals?).ok()?)
-                })
-                .sum::<SLPAmount>();
-            println!("input sum: {}", input_sum);
-            println!("output sum: {}", output_sum);
-            if input_sum < output_sum {
-                tx.tx_type = TxType::Default;
-                tx.outputs.iter_mut().for_each(|output| {
-                    output.value_token = SLPAmount::new(0, 0);
-                });
-                self.trade_offers.remove(&i);
+            if let TxType::Default = tx.tx_type {
+                continue;
+            }
+            match validator.validate(&tx.hash)? {
+                SlpValidity::Valid => {},
+                SlpValidity::Unknown => pending.push(tx.hash),
+                SlpValidity::Invalid => {
+                    tx.tx_type = TxType::Default;
+                    tx.outputs.iter_mut().for_each(|output| {
+                        output.value_token = SLPAmount::new(0, 0);
+                    });
+                    self.trade_offers.remove(&i);
+                    self.pandas_slp.remove(&i);
+                    self.mint_batons.remove(&i);
+                    self.new_tokens.remove(&i);
+                },
             }
         }
-        Ok(())
+        Ok(pending)
     }
 }
 
@@ -845,6 +855,7 @@ impl TradeOffer {
     }
 
     fn _contract_hash(output: &HistoricTxOutput,
+                      version: u8,
                       price: &_Price,
                       tx_type: &TxType,
                       config: &SLPDEXConfig,
@@ -854,31 +865,35 @@ impl TradeOffer {
             TxType::Default => return None,
         };
         let address = output.output.address()?;
-        if address.addr_type() == AddressType::P2SH {
-            /*let hash = cashcontracts::hash160(
-                &cashcontracts::AdvancedTradeOffer {
-                    value: output.value_satoshis,
-                    lokad_id: b"EXCH".to_vec(),
-                    version: 2,
-                    power: price.power,
-                    is_inverted: price.is_inverted,
-                    token_id: token_hash.clone(),
-                    token_type: token_type as u8,
-                    sell_amount_token: output.value_token.base_amount() as u64,
-                    price: price.script_price,
-                    dust_amount: config.dust_limit,
-                    address: receiving_address.clone(),
-                    fee_address: Some(config.fee_address.clone()),
-                    fee_divisor: Some(config.fee_divisor.clone()),
-                    spend_params: None,
-                }.script().to_vec()
-            );
-            if address.bytes() == &hash {
-                Some(output.value_token)
-            } else {
-                None
-            }*/
-            None
+        if address.addr_type() != AddressType::P2SH {
+            return None;
+        }
+        // version 1 offers predate the DEX fee
+        let (fee_address, fee_divisor) = match version {
+            1 => (None, None),
+            2 => (Some(config.fee_address.clone()), Some(config.fee_divisor)),
+            _ => return None,
+        };
+        let hash = cashcontracts::hash160(
+            &cashcontracts::AdvancedTradeOffer {
+                value: output.value_satoshis,
+                lokad_id: config.exch_lokad.as_bytes().to_vec(),
+                version,
+                power: price.power,
+                is_inverted: price.is_inverted,
+                token_id: token_hash.clone(),
+                token_type: token_type as u8,
+                sell_amount_token: output.value_token.base_amount() as u64,
+                price: price.script_price,
+                dust_amount: config.dust_limit,
+                address: receiving_address.clone(),
+                fee_address,
+                fee_divisor,
+                spend_params: None,
+            }.script().to_vec()
+        );
+        if address.bytes() == &hash {
+            Some(output.value_token)
         } else {
             None
         }
@@ -886,12 +901,15 @@ impl TradeOffer {
 
     pub fn from_entry(tx: &HistoricTx,
                       entry: &tx_result::TxEntry,
-                      config: &SLPDEXConfig,
-                      decimals: u32)
+                      config: &SLPDEXConfig)
             -> Option<Self> {
         entry.inputs.iter().find_map(|input| {
-            if input.b0 == tx_result::StackItem::Str(base64::encode("EXCH")) &&
-                    input.b1 == (tx_result::StackItem::Op {op: 0x52}) {
+            let version = match input.b1 {
+                tx_result::StackItem::Op {op: 0x51} => 1,
+                tx_result::StackItem::Op {op: 0x52} => 2,
+                _ => return None,
+            };
+            if input.b0 == tx_result::StackItem::Str(config.exch_lokad_b64.clone()) {
                 let price = entry.slp.as_ref()
                     .and_then(|slp| {
                         Self::_decode_price(
@@ -912,23 +930,24 @@ impl TradeOffer {
                         Some((
                             output_idx,
                             Self::_contract_hash(output,
+                                                 version,
                                                  &price,
                                                  &tx.tx_type,
                                                  config,
                                                  &receiving_address)?,
                         ))
                     });
+                // a P2SH output that isn't the contract can't be accepted, so it's no offer
+                let (output_idx, sell_amount_token) = contract_vals?;
                 Some(TradeOffer {
                     tx: cashcontracts::tx_hex_to_hash(&entry.tx.h).unwrap(),
-                    output_idx: contract_vals.map(|(idx, _)| idx),
+                    output_idx: Some(output_idx),
                     input_tx: cashcontracts::tx_hex_to_hash(&input.e.h).unwrap(),
                     input_idx: input.e.i,
                     price_per_token: price.price_per_token,
                     is_inverted: price.is_inverted,
                     script_price: price.script_price as i64,
-                    sell_amount_token: contract_vals
-                        .map(|(_, amount)| amount)
-                        .unwrap_or(SLPAmount::new(0, decimals)),
+                    sell_amount_token,
                     receiving_address,
                 })
             } else {
@@ -950,8 +969,13 @@ impl TradeOffer {
             let ops = input.script.ops();
             if ops.len() < 5 { return None; }
             match &input.script.ops()[..5] {
-                &[Push(ref exch), Code(Op2), Push(ref power), Push(ref price), Push(ref address)]
+                &[Push(ref exch), Code(ref version), Push(ref power), Push(ref price), Push(ref address)]
                         if exch.as_slice() == config.exch_lokad.as_bytes() => {
+                    let version = match version {
+                        cashcontracts::OpCodeType::Op1 => 1,
+                        cashcontracts::OpCodeType::Op2 => 2,
+                        _ => return None,
+                    };
                     let price = Self::_decode_price(token.decimals, power, price)
                         .map_err(|err| {
                             eprintln!("Trade offer error {}", err);
@@ -968,6 +992,7 @@ impl TradeOffer {
                             Some((
                                 output_idx,
                                 Self::_contract_hash(output,
+                                                     version,
                                                      &price,
                                                      &historic_tx.tx_type,
                                                      config,
@@ -975,17 +1000,16 @@ impl TradeOffer {
                             ))
                         });
                     println!("contract vals {:?}", contract_vals);
+                    let (output_idx, sell_amount_token) = contract_vals?;
                     Some(TradeOffer {
                         tx: historic_tx.hash.clone(),
-                        output_idx: contract_vals.map(|(idx, _)| idx),
+                        output_idx: Some(output_idx),
                         input_tx: input.outpoint.tx_hash.clone(),
                         input_idx: input.outpoint.vout as i32,
                         price_per_token: price.price_per_token,
                         is_inverted: price.is_inverted,
                         script_price: price.script_price as i64,
-                        sell_amount_token: contract_vals
-                            .map(|(_, amount)| amount)
-                            .unwrap_or(SLPAmount::new(0, token.decimals as u32)),
+                        sell_amount_token,
                         receiving_address,
                     })
                 }
@@ -1040,3 +1064,182 @@ impl std::fmt::Display for OutputType {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer_output(version: u8, script_price: u32, config: &SLPDEXConfig) -> (HistoricTxOutput, Address) {
+        let receiving_address = Address::from_bytes(AddressType::P2PKH, [3; 20]);
+        let (fee_address, fee_divisor) = if version == 2 {
+            (Some(config.fee_address.clone()), Some(config.fee_divisor))
+        } else {
+            (None, None)
+        };
+        let script = cashcontracts::AdvancedTradeOffer {
+            value: 546,
+            lokad_id: b"EXCH".to_vec(),
+            version,
+            power: 0,
+            is_inverted: false,
+            token_id: config.panda_token_hash.clone(),
+            token_type: 1,
+            sell_amount_token: 1,
+            price: script_price,
+            dust_amount: config.dust_limit,
+            address: receiving_address.clone(),
+            fee_address,
+            fee_divisor,
+            spend_params: None,
+        }.script().to_vec();
+        let output = HistoricTxOutput {
+            value_satoshis: 546,
+            value_token: SLPAmount::new(1, 0),
+            output: OutputType::Address(
+                Address::from_bytes(AddressType::P2SH, cashcontracts::hash160(&script)),
+            ),
+        };
+        (output, receiving_address)
+    }
+
+    // built with the same cashcontracts script as _contract_hash, so this checks which parameters
+    // go into the hash, not the script itself
+    #[test]
+    fn verifies_contract_hash() {
+        let config = SLPDEXConfig::default();
+        let tx_type = TxType::SLP {
+            token_hash: config.panda_token_hash.clone(),
+            token_type: TokenType::Standard,
+            slp_type: SLPTxType::Send,
+        };
+        let price = TradeOffer::_decode_price(0, &[0], &[0, 0, 0x03, 0xe8]).unwrap();
+        for &version in &[1, 2] {
+            let (output, receiving_address) = offer_output(version, 1000, &config);
+            assert_eq!(
+                TradeOffer::_contract_hash(&output, version, &price, &tx_type, &config, &receiving_address),
+                Some(SLPAmount::new(1, 0)),
+            );
+            // other version, other price or other receiver must not match
+            assert_eq!(
+                TradeOffer::_contract_hash(&output, 3 - version, &price, &tx_type, &config, &receiving_address),
+                None,
+            );
+            let (output, receiving_address) = offer_output(version, 999, &config);
+            assert_eq!(
+                TradeOffer::_contract_hash(&output, version, &price, &tx_type, &config, &receiving_address),
+                None,
+            );
+            let other_address = Address::from_bytes(AddressType::P2PKH, [4; 20]);
+            assert_eq!(
+                TradeOffer::_contract_hash(&output, version, &price, &tx_type, &config, &other_address),
+                None,
+            );
+        }
+    }
+
+    #[test]
+    fn downgrades_only_invalid_slp() {
+        use crate::slp_validator::tests::{tx, genesis, send};
+        let genesis_tx = tx(&[([0; 32], 0)], genesis(&[0x01], &[], 100), 1);
+        let token_hash = genesis_tx.hash();
+        let valid = tx(&[(token_hash, 1)], send(&[0x01], &token_hash, &[100]), 1);
+        let invalid = tx(&[(token_hash, 1)], send(&[0x01], &token_hash, &[101]), 1);
+        let pending = tx(&[([5; 32], 1)], send(&[0x01], &token_hash, &[1]), 1);
+        let historic_tx = |raw_tx: &cashcontracts::Tx| HistoricTx {
+            hash: raw_tx.hash(),
+            height: None,
+            timestamp: 0,
+            tx_type: TxType::SLP {
+                token_hash,
+                token_type: TokenType::Standard,
+                slp_type: SLPTxType::Send,
+            },
+            inputs: vec![],
+            outputs: vec![HistoricTxOutput {
+                value_satoshis: 546,
+                value_token: SLPAmount::new(1, 0),
+                output: OutputType::Unknown,
+            }],
+        };
+        let mut history = TxHistory {
+            txs: vec![historic_tx(&valid), historic_tx(&invalid), historic_tx(&pending)],
+            trade_offers: HashMap::new(),
+            pnd_txs: HashMap::new(),
+            pandas_slp: HashSet::new(),
+            mint_batons: HashMap::new(),
+            new_tokens: HashMap::new(),
+        };
+        let provider = vec![(token_hash, genesis_tx)].into_iter().collect::<HashMap<_, _>>();
+        let mut validator = SlpValidator::new(None, Some(&provider as &dyn SlpTxProvider));
+        validator.add_txs(&[valid.clone(), invalid.clone(), pending.clone()]);
+        assert_eq!(history._apply_slp_validity(&mut validator).unwrap(), vec![pending.hash()]);
+        let is_slp = history.txs.iter()
+            .map(|tx| match tx.tx_type { TxType::SLP {..} => true, TxType::Default => false })
+            .collect::<Vec<_>>();
+        assert_eq!(is_slp, vec![true, false, true]);
+        assert_eq!(history.txs[1].outputs[0].value_token, SLPAmount::new(0, 0));
+    }
+
+    #[test]
+    #[ignore]
+    fn parses_mint() {
+        use crate::slp_validator::tests::{tx, genesis, mint};
+        // requires a migrated database at DATABASE_URL; all changes are rolled back
+        let pool = crate::db::create_pool(&std::env::var("DATABASE_URL").expect("DATABASE_URL"), 1).unwrap();
+        let db = Db::from_pool(&pool).unwrap();
+        let config = SLPDEXConfig::default();
+        let genesis_tx = tx(&[([0; 32], 0)], genesis(&[0x01], &[0x02], 100), 2);
+        let token_hash = genesis_tx.hash();
+        let genesis_message = SlpMessage::parse(&genesis_tx.outputs()[0].script().to_vec()).unwrap().unwrap();
+        let token = Token::from_genesis(token_hash, &genesis_message, None, 0, 0).unwrap();
+        let mint_tx = tx(&[(token_hash, 2)], mint(&[0x01], &token_hash, &[0x03], 500), 3);
+        let last_mint_tx = tx(&[(mint_tx.hash(), 3)], mint(&[0x01], &token_hash, &[], 7), 1);
+        db.connection().test_transaction::<_, diesel::result::Error, _>(|| {
+            db.add_tokens(&[token])?;
+            let history = TxHistory::from_txs(&[mint_tx.clone(), last_mint_tx.clone()], 0, &config, &db);
+            for tx in history.txs.iter() {
+                match tx.tx_type {
+                    TxType::SLP {slp_type: SLPTxType::Mint, token_hash: tx_token_hash, ..} =>
+                        assert_eq!(tx_token_hash, token_hash),
+                    _ => panic!("not parsed as MINT: {}", tx_hash_to_hex(&tx.hash)),
+                }
+            }
+            let amounts = |tx: &HistoricTx| tx.outputs.iter()
+                .map(|output| output.value_token)
+                .collect::<Vec<_>>();
+            assert_eq!(amounts(&history.txs[0]), vec![SLPAmount::new(0, 0), SLPAmount::new(500, 0),
+                                                      SLPAmount::new(0, 0), SLPAmount::new(0, 0)]);
+            assert_eq!(amounts(&history.txs[1]), vec![SLPAmount::new(0, 0), SLPAmount::new(7, 0)]);
+            assert_eq!(history.mint_batons[&0], Some(3));
+            assert_eq!(history.mint_batons[&1], None);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn adds_genesis_token_with_tx() {
+        use crate::slp_validator::tests::{tx, genesis, send};
+        // requires a migrated database at DATABASE_URL; all changes are rolled back
+        let pool = crate::db::create_pool(&std::env::var("DATABASE_URL").expect("DATABASE_URL"), 1).unwrap();
+        let db = Db::from_pool(&pool).unwrap();
+        let config = SLPDEXConfig::default();
+        let genesis_tx = tx(&[([0; 32], 0)], genesis(&[0x01], &[], 100), 1);
+        let token_hash = genesis_tx.hash();
+        let send_tx = tx(&[(token_hash, 1)], send(&[0x01], &token_hash, &[100]), 1);
+        db.connection().test_transaction::<_, diesel::result::Error, _>(|| {
+            let history = TxHistory::from_txs(&[genesis_tx.clone(), send_tx.clone()], 0, &config, &db);
+            assert!(db.token(&token_hash)?.is_none());
+            assert_eq!(history.new_tokens.keys().collect::<Vec<_>>(), vec![&0]);
+            match history.txs[1].tx_type {
+                TxType::SLP {slp_type: SLPTxType::Send, ..} => {},
+                _ => panic!("SEND of a token created in the same batch not parsed"),
+            }
+            db.add_tx_history(&history)?;
+            assert_eq!(db.token(&token_hash)?.map(|token| token.block_created_height), Some(0));
+            db.confirm_block_txs(&[0xee; 32], &[token_hash, send_tx.hash()], 600_000, 0, 1)?;
+            assert_eq!(db.token(&token_hash)?.map(|token| token.block_created_height), Some(600_000));
+            Ok(())
+        });
+    }
+}
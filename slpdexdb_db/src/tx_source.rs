@@ -1,12 +1,13 @@
 use cashcontracts::{Address, tx_hash_to_hex};
 use json::{JsonValue, object, array};
-use slpdexdb_base::SLPDEXConfig;
+use slpdexdb_base::{SLPDEXConfig, Result};
 use crate::endpoint::Endpoint;
 
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TxFilter {
     Address(Address),
+    OutputAddress(Address),  // only txs paying to the address
     TokenId([u8; 32]),
     MinBlockHeight(i32),
     MinTxHash([u8; 32]),
@@ -24,6 +25,7 @@ pub enum SortKey {
 pub enum TxResultKind {
     Complete,
     SLPValidity,
+    Inputs,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -40,16 +42,16 @@ pub struct TxSource {
 pub mod tx_result {
     use serde::{Deserialize, Serialize};
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct Blk {
         pub t: u64,
         pub i: i32,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct Tx {
         pub h: String,
     }
-    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
     #[serde(untagged)]
     pub enum StackItem {
         Str(String),
@@ -67,13 +69,13 @@ pub mod tx_result {
             }
         }
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxInputEdge {
         pub a: Option<String>,
         pub h: String,
         pub i: i32,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxInput {
         pub e: TxInputEdge,
         #[serde(default)]
@@ -93,23 +95,23 @@ pub mod tx_result {
         #[serde(default)]
         pub b7: StackItem,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxOutputEdge {
         pub v: u64,
         pub a: Option<String>,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxOutput {
         pub e: TxOutputEdge,
         #[serde(default)]
         pub b0: StackItem,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxSLP {
         pub valid: bool,
         pub detail: TxSLPDetail
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxSLPDetail {
         pub decimals: i32,
         #[serde(rename = "tokenIdHex")]
@@ -120,12 +122,12 @@ pub mod tx_result {
         pub version_type: i32,
         pub outputs: Vec<TxSLPOutput>,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxSLPOutput {
         pub address: String,
         pub amount: String,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxEntry {
         pub blk: Option<Blk>,
         pub tx: Tx,
@@ -136,19 +138,32 @@ pub mod tx_result {
         pub slp: Option<TxSLP>,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxSLPValidity {
         pub tx: Tx,
         pub slp: TxSLP,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxResult {
         pub u: Option<Vec<TxEntry>>,
         pub c: Option<Vec<TxEntry>>,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct TxInputs {
+        pub tx: Tx,
+        #[serde(rename = "in")]
+        pub inputs: Vec<TxInput>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct TxInputsResult {
+        pub u: Option<Vec<TxInputs>>,
+        pub c: Option<Vec<TxInputs>>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TxSLPValidityResult {
         pub u: Option<Vec<TxSLPValidity>>,
         pub c: Option<Vec<TxSLPValidity>>,
@@ -165,12 +180,20 @@ impl TxFilter {
                     TxFilter::Address(addr) if is_exch =>
                         Some(base64::encode(addr.bytes())),
                     TxFilter::Address(addr) if !is_exch =>
-                        Some(addr.with_prefix("simpleledger".to_string()).cash_addr().to_string()),
+                        Some(config.network.slp_addr(addr)),
                     _ => None,
                 }
             })
             .map(JsonValue::String)
             .collect::<Vec<_>>();
+        let output_addresses = filters.iter()
+            .filter_map(|filter| match filter {
+                TxFilter::OutputAddress(addr) =>
+                    Some(config.network.slp_addr(addr)),
+                _ => None,
+            })
+            .map(JsonValue::String)
+            .collect::<Vec<_>>();
         filters.iter()
             .flat_map(|filter| {
                 match filter {
@@ -202,18 +225,24 @@ impl TxFilter {
                     vec![]
                 }
             )
+            .chain(
+                if output_addresses.len() > 0 {
+                    vec![("out.e.a", object!{"$in" => JsonValue::Array(output_addresses)})]
+                } else {
+                    vec![]
+                }
+            )
             .chain(vec![("slp.valid", JsonValue::Boolean(true))])
             .collect()
     }
 
-    pub fn bch_conditions(filters: &[TxFilter]) -> Vec<(&'static str, JsonValue)> {
+    pub fn bch_conditions(filters: &[TxFilter], config: &SLPDEXConfig) -> Vec<(&'static str, JsonValue)> {
         let base_address_list = filters.iter()
             .filter_map(|filter| {
                 match filter {
-                    TxFilter::Address(addr) => {
-                        let prefix = "bitcoincash";
-                        let addr = addr.with_prefix(prefix.to_string());
-                        Some(addr.cash_addr()[prefix.len() + 1..].to_string())
+                    TxFilter::Address(addr) | TxFilter::OutputAddress(addr) => {
+                        let prefix = config.network.cash_addr_prefix;
+                        Some(config.network.cash_addr(addr)[prefix.len() + 1..].to_string())
                     }
                     _ => None,
                 }
@@ -283,6 +312,10 @@ impl TxSource {
         }
     }
 
+    pub fn with_endpoint(endpoint: Endpoint) -> Self {
+        TxSource { endpoint }
+    }
+
     fn _query(&self,
               endpoint_url: &str,
               conditions: Vec<(&'static str, JsonValue)>,
@@ -306,8 +339,14 @@ impl TxSource {
                 "sort" => sort,
             },
         };
-        if result_kind == TxResultKind::SLPValidity {
-            query["r"] = object!{"f" => "[.[] | {tx: .tx, slp: .slp} ]"};
+        match result_kind {
+            TxResultKind::Complete => {},
+            TxResultKind::SLPValidity => {
+                query["r"] = object!{"f" => "[.[] | {tx: .tx, slp: .slp} ]"};
+            },
+            TxResultKind::Inputs => {
+                query["r"] = object!{"f" => "[.[] | {tx: .tx, in: [.in[] | {e: .e}]} ]"};
+            },
         }
         let query_json = json::stringify(query);
         println!("{}", query_json);
@@ -332,7 +371,7 @@ impl TxSource {
         let sort = TxFilter::sort_by(filters);
         let mut results = Vec::new();
         if !slp_only {
-            let mut bch_conditions = TxFilter::bch_conditions(filters);
+            let mut bch_conditions = TxFilter::bch_conditions(filters, config);
             bch_conditions.append(&mut base_conditions.clone());
             results.push(
                 self._query(
@@ -383,4 +422,24 @@ impl TxSource {
         }
         Ok(results)
     }
+
+    // txs spending any of the given outputs; bitdb indexes all txs, so one query suffices
+    pub fn request_spending_txs(&self, outputs: &[([u8; 32], i32)], confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxInputs>> {
+        let tx_hashes = outputs.iter()
+            .map(|(tx_hash, _)| JsonValue::String(tx_hash_to_hex(tx_hash)))
+            .collect::<Vec<_>>();
+        let result_json = self._query(
+            &self.endpoint.bitdb_endpoint_url,
+            vec![("in.e.h", object!{"$in" => JsonValue::Array(tx_hashes)})],
+            object!{},
+            TxResultKind::Inputs,
+            confirmedness,
+        )?;
+        let result = serde_json::from_str::<tx_result::TxInputsResult>(&result_json)?;
+        let mut results = Vec::new();
+        result.c.map(|mut r| results.append(&mut r));
+        result.u.map(|mut r| results.append(&mut r));
+        Ok(results)
+    }
 }
@@ -59,7 +59,18 @@ impl UpdateHistory {
                 );
                 filters.push(TxFilter::Address(Address::from_bytes(AddressType::P2PKH, address_hash)));
             },
-            _ => unimplemented!(),
+            AddressUTXOs => {
+                let mut address_hash = [0; 20];
+                address_hash.copy_from_slice(
+                    self.subject.hash.as_ref().expect("Subject hash must be present for AddressUTXOs")
+                );
+                filters.push(TxFilter::OutputAddress(Address::from_bytes(AddressType::P2PKH, address_hash)));
+            },
+            UpdateSubjectType::TokenStats => {
+                if let Some(token_hash) = &self.subject.hash {
+                    filters.push(TxFilter::TokenId(tx_hash_from_slice(token_hash)));
+                }
+            },
         };
         filters
     }
@@ -100,6 +111,7 @@ impl UpdateHistory {
             completed: tokens.is_empty(),
         }
     }
+
 }
 
 impl std::fmt::Display for UpdateHistory {
@@ -0,0 +1,173 @@
+use actix::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::convert::identity;
+use std::time::{Duration, Instant};
+use slpdexdb_base::Error;
+use slpdexdb_node::actors::OutgoingMsg;
+use slpdexdb_node::messages::{GetDataMessage, InvVector, ObjectType};
+use slpdexdb_node::NodeMessage;
+use crate::actors::{PeersActor, SendToAnyPeer, DbReadActor};
+use crate::msg::FetchUnprocessedBlocks;
+
+const BACKFILL_INTERVAL: Duration = Duration::from_secs(10);
+const BACKFILL_TIMEOUT: Duration = Duration::from_secs(60);
+const BACKFILL_BATCH: i64 = 16;
+
+// Requests main chain blocks that were never processed, e.g. because the endpoint was down.
+// Peers answer with regular block messages, which go through the same pipeline as live blocks.
+pub struct BackfillActor {
+    db_read: Addr<DbReadActor>,
+    peers: Addr<PeersActor>,
+    pending: PendingBlocks,
+}
+
+// the last batch requested from a peer, until all of it got processed or it timed out
+#[derive(Default)]
+struct PendingBlocks {
+    blocks: HashMap<[u8; 32], i32>,
+    requested_at: Option<Instant>,
+}
+
+impl PendingBlocks {
+    // blocks to request given the lowest unprocessed ones, none while the last batch is in flight
+    fn next_request(&mut self, unprocessed: &[([u8; 32], i32)], now: Instant) -> Vec<([u8; 32], i32)> {
+        let unprocessed_hashes = unprocessed.iter().map(|(block_hash, _)| *block_hash).collect::<HashSet<_>>();
+        // a full batch says nothing about the blocks above it
+        let max_known_height = match unprocessed.last() {
+            Some(&(_, height)) if unprocessed.len() as i64 >= BACKFILL_BATCH => height,
+            _ => std::i32::MAX,
+        };
+        self.blocks.retain(|block_hash, &mut height| {
+            unprocessed_hashes.contains(block_hash) || height > max_known_height
+        });
+        let in_flight = match self.requested_at {
+            Some(requested_at) => !self.blocks.is_empty() && now - requested_at < BACKFILL_TIMEOUT,
+            None => false,
+        };
+        if in_flight {
+            return vec![];
+        }
+        unprocessed.to_vec()
+    }
+
+    fn requested(&mut self, blocks: Vec<([u8; 32], i32)>, now: Instant) {
+        self.blocks = blocks.into_iter().collect();
+        self.requested_at = Some(now);
+    }
+}
+
+// sent to self with the result of FetchUnprocessedBlocks
+struct UnprocessedBlocks(Vec<([u8; 32], i32)>);
+
+impl Message for UnprocessedBlocks {
+    type Result = ();
+}
+
+// sent to self once a peer got the getdata
+struct BlocksRequested(Vec<([u8; 32], i32)>);
+
+impl Message for BlocksRequested {
+    type Result = ();
+}
+
+impl BackfillActor {
+    pub fn new(db_read: Addr<DbReadActor>, peers: Addr<PeersActor>) -> Self {
+        BackfillActor { db_read, peers, pending: PendingBlocks::default() }
+    }
+
+    fn _fetch_unprocessed_blocks(&mut self, ctx: &mut Context<Self>) {
+        let own_addr = ctx.address();
+        Arbiter::spawn(
+            self.db_read.send(FetchUnprocessedBlocks(BACKFILL_BATCH))
+                .from_err::<Error>()
+                .and_then(identity)
+                .map(move |blocks| own_addr.do_send(UnprocessedBlocks(blocks)))
+                .map_err(|err| eprintln!("Failed to query unprocessed blocks: {}", err))
+        );
+    }
+
+    fn _request_blocks(&mut self, blocks: Vec<([u8; 32], i32)>, ctx: &mut Context<Self>) {
+        let blocks = self.pending.next_request(&blocks, Instant::now());
+        if blocks.len() == 0 {
+            return;
+        }
+        println!("backfilling {} blocks from height {}", blocks.len(), blocks[0].1);
+        let get_data = GetDataMessage {
+            inv_vectors: blocks.iter()
+                .map(|&(hash, _)| InvVector { type_id: ObjectType::Block, hash })
+                .collect(),
+        };
+        let own_addr = ctx.address();
+        Arbiter::spawn(
+            self.peers.send(SendToAnyPeer(OutgoingMsg(get_data.packet())))
+                .map(move |sent| if sent {
+                    own_addr.do_send(BlocksRequested(blocks));
+                })
+                .map_err(|err| eprintln!("{}", err))
+        );
+    }
+}
+
+impl Actor for BackfillActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(BACKFILL_INTERVAL, |act, ctx| act._fetch_unprocessed_blocks(ctx));
+    }
+}
+
+impl Handler<UnprocessedBlocks> for BackfillActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnprocessedBlocks, ctx: &mut Self::Context) -> Self::Result {
+        self._request_blocks(msg.0, ctx);
+    }
+}
+
+impl Handler<BlocksRequested> for BackfillActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlocksRequested, _ctx: &mut Self::Context) -> Self::Result {
+        self.pending.requested(msg.0, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(heights: std::ops::Range<i32>) -> Vec<([u8; 32], i32)> {
+        heights.map(|height| ([height as u8; 32], height)).collect()
+    }
+
+    #[test]
+    fn waits_for_pending_blocks() {
+        let start = Instant::now();
+        let later = start + BACKFILL_INTERVAL;
+        let mut pending = PendingBlocks::default();
+        assert_eq!(pending.next_request(&blocks(1..3), start), blocks(1..3));
+        pending.requested(blocks(1..3), start);
+        assert!(pending.next_request(&blocks(1..4), later).is_empty());
+        assert!(pending.next_request(&blocks(2..4), later).is_empty());
+        // lower blocks filling a whole batch don't tell whether the pending ones got processed
+        let lower = blocks(100..100 + BACKFILL_BATCH as i32);
+        pending.requested(blocks(200..202), start);
+        assert!(pending.next_request(&lower, later).is_empty());
+        // the batch is done once it's missing from a complete result
+        assert_eq!(pending.next_request(&blocks(100..110), later), blocks(100..110));
+        assert!(pending.next_request(&[], later).is_empty());
+    }
+
+    #[test]
+    fn resends_after_timeout() {
+        let start = Instant::now();
+        let mut pending = PendingBlocks::default();
+        pending.requested(blocks(1..3), start);
+        let before_timeout = start + BACKFILL_TIMEOUT - Duration::from_secs(1);
+        assert!(pending.next_request(&blocks(1..3), before_timeout).is_empty());
+        let timed_out = start + BACKFILL_TIMEOUT;
+        assert_eq!(pending.next_request(&blocks(1..3), timed_out), blocks(1..3));
+        pending.requested(blocks(1..3), timed_out);
+        assert!(pending.next_request(&blocks(1..3), timed_out + BACKFILL_INTERVAL).is_empty());
+    }
+}
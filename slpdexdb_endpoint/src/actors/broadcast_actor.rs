@@ -1,61 +1,12 @@
 use actix::prelude::*;
 use slpdexdb_base::Error;
-use slpdexdb_db::{OutputType, Utxo, SpentUtxo, NewUtxo, TxDelta};
+use slpdexdb_db::{OutputType, Utxo, SpentUtxo, TxDelta};
 use slpdexdb_base::SLPAmount;
 use std::collections::{HashMap, HashSet};
 use std::convert::identity;
 use std::sync::Arc;
-use crate::msg::{NewTransactions, TxEvent, TxBroadcastEvent};
-
-pub struct UpdateDbUtxosActor;
-
-impl Actor for UpdateDbUtxosActor {
-    type Context = Context<Self>;
-}
-
-impl Handler<NewTransactions> for UpdateDbUtxosActor {
-    type Result = Result<(), Error>;
-
-    fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
-        let mut add_utxos = Vec::new();
-        let mut remove_utxos = Vec::new();
-        for (idx, tx) in msg.tx_history.txs.iter().enumerate() {
-            let trade_offer = msg.tx_history.trade_offers.get(&idx);
-            for input in tx.inputs.iter() {
-                match &input.output {
-                    OutputType::Address(address) if msg.relevant_addresses.contains(address) => {},
-                    _ if trade_offer.is_some() => {},
-                    _ => continue,
-                }
-                remove_utxos.push(SpentUtxo {
-                    tx_hash: input.output_tx.clone(),
-                    vout: input.output_idx,
-                })
-            }
-            for (output_idx, output) in tx.outputs.iter().enumerate() {
-                match &output.output {
-                    OutputType::Address(address) => {
-                        add_utxos.push(NewUtxo::Address {
-                            tx_hash: tx.hash.clone(),
-                            vout: output_idx as i32,
-                            address: address.clone(),
-                        });
-                    },
-                    _ if trade_offer.is_some() => {
-                        add_utxos.push(NewUtxo::TradeOffer {
-                            tx_hash: tx.hash.clone(),
-                            vout: output_idx as i32,
-                        });
-                    },
-                    _ => {},
-                }
-            }
-        }
-        msg.db.lock().unwrap().remove_utxos(&remove_utxos)?;
-        msg.db.lock().unwrap().add_utxos(&add_utxos)?;
-        Ok(())
-    }
-}
+use crate::msg::{NewTransactions, TxEvent, TxBroadcastEvent, UpdateOrderBooks};
+use crate::actors::OrderBookActor;
 
 pub struct BroadcastAddressUtxosActor {
     event_broadcast: Addr<BroadcastActor>,
@@ -121,11 +72,12 @@ impl Handler<NewTransactions> for BroadcastAddressUtxosActor {
 
 pub struct BroadcastTradeOfferUtxosActor {
     event_broadcast: Addr<BroadcastActor>,
+    order_books: Addr<OrderBookActor>,
 }
 
 impl BroadcastTradeOfferUtxosActor {
-    pub fn new(event_broadcast: Addr<BroadcastActor>) -> Self {
-        BroadcastTradeOfferUtxosActor { event_broadcast }
+    pub fn new(event_broadcast: Addr<BroadcastActor>, order_books: Addr<OrderBookActor>) -> Self {
+        BroadcastTradeOfferUtxosActor { event_broadcast, order_books }
     }
 }
 
@@ -159,6 +111,11 @@ impl Handler<NewTransactions> for BroadcastTradeOfferUtxosActor {
                     vout: trade_offer.input_idx,
                 });
         }
+        self.order_books.do_send(UpdateOrderBooks {
+            add_utxos: token_add_utxos.clone(),
+            remove_utxos: token_remove_utxos.clone(),
+            subscribers: msg.subscribers.clone(),
+        });
         Response::fut(
             self.event_broadcast
                 .send(TxBroadcastEvent::TradeOfferUtxoDelta {
@@ -190,17 +147,7 @@ impl Handler<NewTransactions> for BroadcastTxHistoryActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
-        let input_txs = msg.db.lock().unwrap().tx_outputs(
-            msg.tx_history.txs.iter().flat_map(|tx| {
-                tx.inputs.iter().filter_map(|input| {
-                    if msg.relevant_addresses.contains(input.output.address()?) {
-                        Some(input.output_tx.clone())
-                    } else {
-                        None
-                    }
-                })
-            })
-        )?;
+        let input_txs = &msg.input_outputs;
         let mut address_tx_deltas = HashMap::new();
         for tx in msg.tx_history.txs.iter() {
             let decimals = tx.outputs.iter()
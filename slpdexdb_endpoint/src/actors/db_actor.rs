@@ -1,38 +1,54 @@
 use actix::prelude::*;
-use diesel::prelude::*;
-use slpdexdb_base::{Error, GENESIS};
-use slpdexdb_db::Db;
-use slpdexdb_node::{HeaderTipQuery, HeaderTip, AddHeadersQuery};
+use slpdexdb_base::{Error, Network};
+use slpdexdb_db::{Db, DbPool, Utxo, TxHistoryPage, TradeOffer, UnconfirmedTx, TokenStats};
+use slpdexdb_node::{HeaderTipQuery, HeaderTip, AddHeadersQuery, BlockLocatorQuery, MAX_PEERS};
+use crate::msg::{ProcessReorg, FetchAddressUtxos, FetchAddressTxDeltas, FetchTradeOfferUtxos,
+                 FetchUnprocessedBlocks, FetchUnconfirmedTxs, FetchTokenStats, PersistPeers};
 
 
+// writes headers and peers; runs on a SyncArbiter with a single thread, headers must be
+// initialized with Db::init_headers before it is started
 pub struct DbActor {
-    db: Db,
-    //pool: Pool,
+    pool: DbPool,
+    network: Network,
+    reorg: Recipient<ProcessReorg>,
 }
 
 impl DbActor {
-    pub fn create() -> Result<Addr<Self>, Error> {
-        let connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL");
-        let connection = PgConnection::establish(&connection_str)?;
-        let db = Db::new(connection);
-        Ok(DbActor::start(DbActor { db }))
+    pub fn new(pool: DbPool, network: Network, reorg: Recipient<ProcessReorg>) -> Self {
+        DbActor { pool, network, reorg }
     }
 }
 
 impl Actor for DbActor {
-    type Context = Context<Self>;
+    type Context = SyncContext<Self>;
 }
 
 impl Handler<HeaderTipQuery> for DbActor {
     type Result = Result<HeaderTip, Error>;
 
     fn handle(&mut self, _msg: HeaderTipQuery, _ctx: &mut Self::Context) -> Self::Result {
-        let result = self.db.header_tip()?;
+        let result = Db::from_pool(&self.pool)?.header_tip()?;
         Ok(result
             .map(|(header, height)| {
                 HeaderTip { header, height }
             })
-            .unwrap_or_else(|| HeaderTip { header: GENESIS, height: 0 }))
+            .unwrap_or_else(|| {
+                let (header, height) = self.network.initial_tip();
+                HeaderTip { header, height }
+            }))
+    }
+}
+
+impl Handler<BlockLocatorQuery> for DbActor {
+    type Result = Result<Vec<[u8; 32]>, Error>;
+
+    fn handle(&mut self, _msg: BlockLocatorQuery, _ctx: &mut Self::Context) -> Self::Result {
+        let block_locator = Db::from_pool(&self.pool)?.block_locator()?;
+        if block_locator.len() == 0 {
+            return Ok(vec![self.network.initial_tip().0.hash()]);
+        }
+        Ok(block_locator)
     }
 }
 
@@ -40,7 +56,93 @@ impl Handler<AddHeadersQuery> for DbActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: AddHeadersQuery, _ctx: &mut Self::Context) -> Self::Result {
-        self.db.add_headers(&msg.0)?;
+        if let Some(reorg) = Db::from_pool(&self.pool)?.add_headers(&msg.0, &self.network)? {
+            eprintln!("Chain reorg at height {}, {} blocks disconnected",
+                      reorg.fork_height, reorg.disconnected_blocks.len());
+            self.reorg.do_send(ProcessReorg(reorg))
+                .map_err(|err| eprintln!("Failed to send reorg: {}", err))
+                .ok();
+        }
+        Ok(())
+    }
+}
+
+impl Handler<PersistPeers> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: PersistPeers, _ctx: &mut Self::Context) -> Self::Result {
+        let PersistPeers(peers) = msg;
+        let db = Db::from_pool(&self.pool)?;
+        db.upsert_peers(&peers)?;
+        db.prune_peers(MAX_PEERS as i64)?;
         Ok(())
     }
 }
+
+// read-only queries, run on a SyncArbiter so they don't queue behind resyncs
+pub struct DbReadActor {
+    pool: DbPool,
+}
+
+impl DbReadActor {
+    pub fn new(pool: DbPool) -> Self {
+        DbReadActor { pool }
+    }
+}
+
+impl Actor for DbReadActor {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<FetchAddressUtxos> for DbReadActor {
+    type Result = Result<Vec<Utxo>, Error>;
+
+    fn handle(&mut self, msg: FetchAddressUtxos, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchAddressUtxos(address) = msg;
+        Ok(Db::from_pool(&self.pool)?.utxos_address(&address)?)
+    }
+}
+
+impl Handler<FetchAddressTxDeltas> for DbReadActor {
+    type Result = Result<TxHistoryPage, Error>;
+
+    fn handle(&mut self, msg: FetchAddressTxDeltas, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchAddressTxDeltas { address, filter, cursor, limit } = msg;
+        Ok(Db::from_pool(&self.pool)?.address_tx_deltas(&address, &filter, cursor, limit)?)
+    }
+}
+
+impl Handler<FetchTradeOfferUtxos> for DbReadActor {
+    type Result = Result<Vec<TradeOffer>, Error>;
+
+    fn handle(&mut self, msg: FetchTradeOfferUtxos, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchTradeOfferUtxos(filter) = msg;
+        Ok(Db::from_pool(&self.pool)?.trade_offer_utxos(filter)?)
+    }
+}
+
+impl Handler<FetchUnprocessedBlocks> for DbReadActor {
+    type Result = Result<Vec<([u8; 32], i32)>, Error>;
+
+    fn handle(&mut self, msg: FetchUnprocessedBlocks, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchUnprocessedBlocks(limit) = msg;
+        Ok(Db::from_pool(&self.pool)?.unprocessed_blocks(limit)?)
+    }
+}
+
+impl Handler<FetchUnconfirmedTxs> for DbReadActor {
+    type Result = Result<Vec<UnconfirmedTx>, Error>;
+
+    fn handle(&mut self, _msg: FetchUnconfirmedTxs, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(Db::from_pool(&self.pool)?.unconfirmed_txs()?)
+    }
+}
+
+impl Handler<FetchTokenStats> for DbReadActor {
+    type Result = Result<Option<TokenStats>, Error>;
+
+    fn handle(&mut self, msg: FetchTokenStats, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchTokenStats(token_hash) = msg;
+        Ok(Db::from_pool(&self.pool)?.token_stats(&token_hash)?)
+    }
+}
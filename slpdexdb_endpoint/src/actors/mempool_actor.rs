@@ -0,0 +1,444 @@
+use actix::prelude::*;
+use slpdexdb_base::Error;
+use slpdexdb_db::{UnconfirmedTx, DoubleSpentUtxos};
+use std::collections::HashMap;
+use std::convert::identity;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use cashcontracts::{Tx, tx_hash_to_hex};
+use crate::msg::{AddMempoolTx, AddUnconfirmedTxs, MempoolBlock, MempoolEvent, SubscribeMempool, TxBroadcastEvent,
+                 FetchUnconfirmedTxs, RemoveDoubleSpentTxs, InvalidateOrderBooks};
+use crate::actors::{TxSubscribers, DbReadActor, ResyncActor, OrderBookActor};
+use crate::actors::broadcast_actor::BroadcastActor;
+
+type OutPoint = ([u8; 32], i32);
+
+const MAX_MEMPOOL_TXS: usize = 100_000;
+// same as the default -mempoolexpiry of bitcoind
+const MEMPOOL_EXPIRY_SECS: i64 = 14 * 24 * 60 * 60;
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+fn unix_time() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+struct MempoolEntry {
+    inputs: Vec<OutPoint>,
+    n_outputs: i32,
+    added_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MempoolAcceptance {
+    Accepted,
+    AlreadyKnown,
+    Conflict(Vec<[u8; 32]>),
+    Full,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MempoolEviction {
+    pub confirmed: Vec<[u8; 32]>,
+    pub double_spent: Vec<[u8; 32]>,
+}
+
+pub struct Mempool {
+    txs: HashMap<[u8; 32], MempoolEntry>,
+    spends: HashMap<OutPoint, [u8; 32]>,
+    max_txs: usize,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Mempool::with_max_txs(MAX_MEMPOOL_TXS)
+    }
+
+    pub fn with_max_txs(max_txs: usize) -> Self {
+        Mempool { txs: HashMap::new(), spends: HashMap::new(), max_txs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn contains(&self, tx_hash: &[u8; 32]) -> bool {
+        self.txs.contains_key(tx_hash)
+    }
+
+    pub fn spender(&self, outpoint: &OutPoint) -> Option<&[u8; 32]> {
+        self.spends.get(outpoint)
+    }
+
+    pub fn add(&mut self, tx_hash: [u8; 32], inputs: Vec<OutPoint>, n_outputs: i32) -> MempoolAcceptance {
+        self.add_at(tx_hash, inputs, n_outputs, unix_time())
+    }
+
+    pub fn add_at(&mut self,
+                  tx_hash: [u8; 32],
+                  inputs: Vec<OutPoint>,
+                  n_outputs: i32,
+                  added_at: i64) -> MempoolAcceptance {
+        if self.txs.contains_key(&tx_hash) {
+            return MempoolAcceptance::AlreadyKnown;
+        }
+        let mut conflicts = inputs.iter()
+            .filter_map(|outpoint| self.spends.get(outpoint))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            conflicts.dedup();
+            return MempoolAcceptance::Conflict(conflicts);
+        }
+        if self.txs.len() >= self.max_txs {
+            return MempoolAcceptance::Full;
+        }
+        for outpoint in inputs.iter() {
+            self.spends.insert(*outpoint, tx_hash);
+        }
+        self.txs.insert(tx_hash, MempoolEntry { inputs, n_outputs, added_at });
+        MempoolAcceptance::Accepted
+    }
+
+    pub fn add_tx(&mut self, tx: &Tx) -> MempoolAcceptance {
+        self.add(tx.hash(), Self::_tx_inputs(tx), tx.outputs().len() as i32)
+    }
+
+    pub fn remove_block(&mut self, txs: &[([u8; 32], Vec<OutPoint>)]) -> MempoolEviction {
+        let mut eviction = MempoolEviction::default();
+        for (tx_hash, _) in txs.iter() {
+            if self._remove(tx_hash).is_some() {
+                eviction.confirmed.push(*tx_hash);
+            }
+        }
+        for (tx_hash, inputs) in txs.iter() {
+            for outpoint in inputs.iter() {
+                let spender = match self.spends.get(outpoint) {
+                    Some(spender) if spender != tx_hash => *spender,
+                    _ => continue,
+                };
+                self._evict_with_descendants(spender, &mut eviction.double_spent);
+            }
+        }
+        eviction
+    }
+
+    pub fn remove_block_txs(&mut self, txs: &[Tx]) -> MempoolEviction {
+        self.remove_block(
+            &txs.iter().map(|tx| (tx.hash(), Self::_tx_inputs(tx))).collect::<Vec<_>>()
+        )
+    }
+
+    // evicts txs added before the given time, together with everything spending them
+    pub fn expire(&mut self, added_before: i64) -> Vec<[u8; 32]> {
+        let expired_txs = self.txs.iter()
+            .filter(|(_, entry)| entry.added_at < added_before)
+            .map(|(tx_hash, _)| *tx_hash)
+            .collect::<Vec<_>>();
+        let mut evicted = Vec::new();
+        for tx_hash in expired_txs {
+            self._evict_with_descendants(tx_hash, &mut evicted);
+        }
+        evicted
+    }
+
+    fn _tx_inputs(tx: &Tx) -> Vec<OutPoint> {
+        tx.inputs().iter()
+            .map(|input| (input.outpoint.tx_hash, input.outpoint.vout as i32))
+            .collect()
+    }
+
+    fn _remove(&mut self, tx_hash: &[u8; 32]) -> Option<MempoolEntry> {
+        let entry = self.txs.remove(tx_hash)?;
+        for outpoint in entry.inputs.iter() {
+            if self.spends.get(outpoint) == Some(tx_hash) {
+                self.spends.remove(outpoint);
+            }
+        }
+        Some(entry)
+    }
+
+    fn _evict_with_descendants(&mut self, tx_hash: [u8; 32], evicted: &mut Vec<[u8; 32]>) {
+        let entry = match self._remove(&tx_hash) {
+            Some(entry) => entry,
+            None => return,
+        };
+        evicted.push(tx_hash);
+        for vout in 0..entry.n_outputs {
+            if let Some(child) = self.spends.get(&(tx_hash, vout)).cloned() {
+                self._evict_with_descendants(child, evicted);
+            }
+        }
+    }
+}
+
+pub struct MempoolActor {
+    db_read: Addr<DbReadActor>,
+    mempool: Mempool,
+    subscribers: Vec<Recipient<MempoolEvent>>,
+}
+
+// sent to self with the unconfirmed txs of the db on startup
+struct UnconfirmedTxsLoaded(Vec<UnconfirmedTx>);
+
+impl Message for UnconfirmedTxsLoaded {
+    type Result = ();
+}
+
+impl MempoolActor {
+    pub fn new(db_read: Addr<DbReadActor>) -> Self {
+        MempoolActor {
+            db_read,
+            mempool: Mempool::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    fn _expire(&mut self) {
+        let expired = self.mempool.expire(unix_time() - MEMPOOL_EXPIRY_SECS);
+        if !expired.is_empty() {
+            println!("expiring {} tx(s) from mempool", expired.len());
+            self._notify(MempoolEvent::Expired { tx_hashes: Arc::new(expired) });
+        }
+    }
+
+    fn _notify(&self, event: MempoolEvent) {
+        for subscriber in self.subscribers.iter() {
+            subscriber.do_send(event.clone())
+                .map_err(|err| eprintln!("Failed to send mempool event: {}", err)).ok();
+        }
+    }
+}
+
+impl Actor for MempoolActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(EXPIRY_INTERVAL, |act, _ctx| act._expire());
+        let own_addr = ctx.address();
+        Arbiter::spawn(
+            self.db_read.send(FetchUnconfirmedTxs)
+                .from_err::<Error>()
+                .and_then(identity)
+                .map(move |unconfirmed_txs| own_addr.do_send(UnconfirmedTxsLoaded(unconfirmed_txs)))
+                .map_err(|err| eprintln!("Failed to load unconfirmed txs: {}", err))
+        );
+    }
+}
+
+impl Handler<UnconfirmedTxsLoaded> for MempoolActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnconfirmedTxsLoaded, _ctx: &mut Self::Context) -> Self::Result {
+        for tx in msg.0 {
+            let inputs = tx.inputs.iter().map(|input| (input.tx_hash, input.vout)).collect();
+            self.mempool.add_at(tx.tx_hash, inputs, tx.n_outputs, tx.timestamp);
+        }
+        println!("loaded {} unconfirmed txs into mempool", self.mempool.len());
+    }
+}
+
+impl Handler<SubscribeMempool> for MempoolActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeMempool, _ctx: &mut Self::Context) -> Self::Result {
+        self.subscribers.push(msg.0);
+    }
+}
+
+impl Handler<AddMempoolTx> for MempoolActor {
+    type Result = Result<MempoolAcceptance, Error>;
+
+    fn handle(&mut self, msg: AddMempoolTx, _ctx: &mut Self::Context) -> Self::Result {
+        let AddMempoolTx(tx) = msg;
+        let acceptance = self.mempool.add_tx(&tx);
+        if let MempoolAcceptance::Conflict(conflicting_txs) = &acceptance {
+            println!("tx {} conflicts with {} mempool tx(s)",
+                     tx_hash_to_hex(&tx.hash()), conflicting_txs.len());
+            self._notify(MempoolEvent::Conflict {
+                tx_hash: tx.hash(),
+                conflicting_txs: Arc::new(conflicting_txs.clone()),
+            });
+        }
+        Ok(acceptance)
+    }
+}
+
+// txs of disconnected blocks are unconfirmed again
+impl Handler<AddUnconfirmedTxs> for MempoolActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddUnconfirmedTxs, _ctx: &mut Self::Context) -> Self::Result {
+        for tx in msg.0 {
+            let inputs = tx.inputs.iter().map(|input| (input.tx_hash, input.vout)).collect();
+            match self.mempool.add_at(tx.tx_hash, inputs, tx.n_outputs, tx.timestamp) {
+                MempoolAcceptance::Conflict(conflicting_txs) => {
+                    self._notify(MempoolEvent::Conflict {
+                        tx_hash: tx.tx_hash,
+                        conflicting_txs: Arc::new(conflicting_txs),
+                    });
+                },
+                MempoolAcceptance::Full => {
+                    eprintln!("mempool full, dropping reorged tx {}", tx_hash_to_hex(&tx.tx_hash));
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Handler<MempoolBlock> for MempoolActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: MempoolBlock, _ctx: &mut Self::Context) -> Self::Result {
+        let eviction = self.mempool.remove_block_txs(&msg.txs);
+        if !eviction.confirmed.is_empty() {
+            self._notify(MempoolEvent::Confirmed {
+                block_hash: msg.block_hash,
+                tx_hashes: Arc::new(eviction.confirmed),
+            });
+        }
+        if !eviction.double_spent.is_empty() {
+            println!("evicting {} double spent tx(s) from mempool", eviction.double_spent.len());
+            self._notify(MempoolEvent::DoubleSpent {
+                block_hash: msg.block_hash,
+                tx_hashes: Arc::new(eviction.double_spent),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct RemoveDoubleSpentUtxosActor {
+    resync: Addr<ResyncActor>,
+    order_books: Addr<OrderBookActor>,
+    event_broadcast: Addr<BroadcastActor>,
+    subscribers: Arc<Mutex<TxSubscribers>>,
+}
+
+impl RemoveDoubleSpentUtxosActor {
+    pub fn new(resync: Addr<ResyncActor>,
+               order_books: Addr<OrderBookActor>,
+               event_broadcast: Addr<BroadcastActor>,
+               subscribers: Arc<Mutex<TxSubscribers>>) -> Self {
+        RemoveDoubleSpentUtxosActor { resync, order_books, event_broadcast, subscribers }
+    }
+}
+
+impl Actor for RemoveDoubleSpentUtxosActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<MempoolEvent> for RemoveDoubleSpentUtxosActor {
+    type Result = Response<(), Error>;
+
+    fn handle(&mut self, msg: MempoolEvent, _ctx: &mut Self::Context) -> Self::Result {
+        // only a conflicting spend being mined proves a tx invalid; expired txs may still be mined
+        let tx_hashes = match msg {
+            MempoolEvent::DoubleSpent { tx_hashes, .. } => tx_hashes,
+            _ => return Response::reply(Ok(())),
+        };
+        let event_broadcast = self.event_broadcast.clone();
+        let event_broadcast2 = self.event_broadcast.clone();
+        let subscribers = self.subscribers.clone();
+        let subscribers2 = self.subscribers.clone();
+        let order_books = self.order_books.clone();
+        // removed by the resync actor, which is the single writer of tx history
+        Response::fut(
+            self.resync
+                .send(RemoveDoubleSpentTxs(tx_hashes))
+                .from_err()
+                .and_then(identity)
+                .and_then(move |double_spent| {
+                    let DoubleSpentUtxos { address_utxos, trade_offer_utxos, trade_offer_tokens, .. } = double_spent;
+                    order_books.do_send(InvalidateOrderBooks(trade_offer_tokens.into_iter().collect()));
+                    event_broadcast2
+                        .send(TxBroadcastEvent::AddressUtxoDelta {
+                            add_utxos: HashMap::new(),
+                            remove_utxos: address_utxos,
+                            subscribers: subscribers2,
+                        })
+                        .from_err()
+                        .and_then(identity)
+                        .map(move |_| trade_offer_utxos)
+                })
+                .and_then(move |trade_offer_utxos| {
+                    event_broadcast
+                        .send(TxBroadcastEvent::TradeOfferUtxoDelta {
+                            add_utxos: HashMap::new(),
+                            remove_utxos: trade_offer_utxos,
+                            subscribers,
+                        })
+                        .from_err()
+                })
+                .and_then(identity)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_spend_rejected() {
+        let mut mempool = Mempool::new();
+        let funding = [1; 32];
+        assert_eq!(mempool.add([2; 32], vec![(funding, 0)], 1), MempoolAcceptance::Accepted);
+        assert_eq!(mempool.add([2; 32], vec![(funding, 0)], 1), MempoolAcceptance::AlreadyKnown);
+        assert_eq!(mempool.add([3; 32], vec![(funding, 1), (funding, 0)], 1),
+                   MempoolAcceptance::Conflict(vec![[2; 32]]));
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.spender(&(funding, 0)), Some(&[2; 32]));
+        assert_eq!(mempool.spender(&(funding, 1)), None);
+    }
+
+    #[test]
+    fn block_confirms_and_evicts() {
+        let mut mempool = Mempool::new();
+        let funding = [1; 32];
+        mempool.add([2; 32], vec![(funding, 0)], 2);
+        mempool.add([3; 32], vec![([2; 32], 0)], 1);
+        mempool.add([4; 32], vec![([3; 32], 0), ([2; 32], 1)], 1);
+        mempool.add([5; 32], vec![(funding, 1)], 1);
+        mempool.add([6; 32], vec![([5; 32], 0)], 1);
+        let eviction = mempool.remove_block(&[
+            ([5; 32], vec![(funding, 1)]),
+            ([7; 32], vec![(funding, 0)]),
+        ]);
+        assert_eq!(eviction.confirmed, vec![[5; 32]]);
+        assert_eq!(eviction.double_spent, vec![[2; 32], [3; 32], [4; 32]]);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&[6; 32]));
+        assert_eq!(mempool.spender(&(funding, 0)), None);
+        assert_eq!(mempool.spender(&([5; 32], 0)), Some(&[6; 32]));
+    }
+
+    #[test]
+    fn expires_with_descendants() {
+        let mut mempool = Mempool::new();
+        let funding = [1; 32];
+        mempool.add_at([2; 32], vec![(funding, 0)], 1, 100);
+        mempool.add_at([3; 32], vec![([2; 32], 0)], 1, 300);
+        mempool.add_at([4; 32], vec![(funding, 1)], 1, 300);
+        let mut expired = mempool.expire(200);
+        expired.sort();
+        assert_eq!(expired, vec![[2; 32], [3; 32]]);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&[4; 32]));
+        assert_eq!(mempool.spender(&(funding, 0)), None);
+        assert_eq!(mempool.expire(200), Vec::<[u8; 32]>::new());
+    }
+
+    #[test]
+    fn full_mempool_rejects() {
+        let mut mempool = Mempool::with_max_txs(1);
+        let funding = [1; 32];
+        assert_eq!(mempool.add([2; 32], vec![(funding, 0)], 1), MempoolAcceptance::Accepted);
+        assert_eq!(mempool.add([2; 32], vec![(funding, 0)], 1), MempoolAcceptance::AlreadyKnown);
+        assert_eq!(mempool.add([3; 32], vec![(funding, 1)], 1), MempoolAcceptance::Full);
+        assert_eq!(mempool.spender(&(funding, 1)), None);
+        mempool.remove_block(&[([2; 32], vec![(funding, 0)])]);
+        assert_eq!(mempool.add([3; 32], vec![(funding, 1)], 1), MempoolAcceptance::Accepted);
+    }
+}
@@ -3,6 +3,9 @@ mod resync_actor;
 mod tx_actor;
 mod peers_actor;
 mod ws_actor;
+mod mempool_actor;
+mod order_book_actor;
+mod backfill_actor;
 pub mod broadcast_actor;
 
 pub use db_actor::*;
@@ -10,3 +13,6 @@ pub use resync_actor::*;
 pub use tx_actor::*;
 pub use peers_actor::*;
 pub use ws_actor::*;
+pub use mempool_actor::*;
+pub use order_book_actor::*;
+pub use backfill_actor::*;
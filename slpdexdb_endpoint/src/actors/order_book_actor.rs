@@ -0,0 +1,117 @@
+use actix::prelude::*;
+use slpdexdb_base::Error;
+use slpdexdb_db::{OrderBook, OrderBookSnapshot, TradeOfferFilter};
+use std::collections::{HashMap, HashSet};
+use std::convert::identity;
+use std::sync::Arc;
+use crate::actors::DbReadActor;
+use crate::msg::{FetchOrderBook, FetchTradeOfferUtxos, UpdateOrderBooks, InvalidateOrderBooks, TxEvent};
+
+// books are loaded from the db on first request and kept up to date from utxo deltas of new txs;
+// on reorgs and double spends they're dropped and reloaded on the next request
+pub struct OrderBookActor {
+    db_read: Addr<DbReadActor>,
+    order_books: HashMap<[u8; 32], OrderBook>,
+    // bumped on every change, so books loaded concurrently with a change aren't cached
+    generations: HashMap<[u8; 32], u64>,
+}
+
+// sent to self once a book has been loaded from the db
+struct CacheOrderBook {
+    order_book: OrderBook,
+    generation: u64,
+}
+
+impl Message for CacheOrderBook {
+    type Result = ();
+}
+
+impl OrderBookActor {
+    pub fn new(db_read: Addr<DbReadActor>) -> Self {
+        OrderBookActor { db_read, order_books: HashMap::new(), generations: HashMap::new() }
+    }
+
+    fn _generation(&self, token_hash: &[u8; 32]) -> u64 {
+        self.generations.get(token_hash).cloned().unwrap_or(0)
+    }
+}
+
+impl Actor for OrderBookActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<FetchOrderBook> for OrderBookActor {
+    type Result = Response<OrderBookSnapshot, Error>;
+
+    fn handle(&mut self, msg: FetchOrderBook, ctx: &mut Self::Context) -> Self::Result {
+        let FetchOrderBook(token_hash) = msg;
+        if let Some(order_book) = self.order_books.get(&token_hash) {
+            return Response::reply(Ok(order_book.snapshot()));
+        }
+        let generation = self._generation(&token_hash);
+        let own_addr = ctx.address();
+        Response::fut(
+            self.db_read.send(FetchTradeOfferUtxos(TradeOfferFilter::TokenHash(token_hash)))
+                .from_err()
+                .and_then(identity)
+                .map(move |trade_offers| {
+                    let order_book = OrderBook::from_trade_offers(token_hash, trade_offers);
+                    let snapshot = order_book.snapshot();
+                    own_addr.do_send(CacheOrderBook { order_book, generation });
+                    snapshot
+                })
+        )
+    }
+}
+
+impl Handler<CacheOrderBook> for OrderBookActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CacheOrderBook, _ctx: &mut Self::Context) -> Self::Result {
+        let token_hash = *msg.order_book.token_hash();
+        if self._generation(&token_hash) == msg.generation {
+            self.order_books.entry(token_hash).or_insert(msg.order_book);
+        }
+    }
+}
+
+impl Handler<InvalidateOrderBooks> for OrderBookActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: InvalidateOrderBooks, _ctx: &mut Self::Context) -> Self::Result {
+        for token_hash in msg.0 {
+            *self.generations.entry(token_hash).or_insert(0) += 1;
+            self.order_books.remove(&token_hash);
+        }
+    }
+}
+
+impl Handler<UpdateOrderBooks> for OrderBookActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: UpdateOrderBooks, _ctx: &mut Self::Context) -> Self::Result {
+        let subscribers = msg.subscribers.lock().unwrap();
+        let tokens = msg.add_utxos
+            .keys().chain(msg.remove_utxos.keys()).collect::<HashSet<_>>();
+        for token_hash in tokens {
+            *self.generations.entry(*token_hash).or_insert(0) += 1;
+            let order_book = match self.order_books.get_mut(token_hash) {
+                Some(order_book) => order_book,
+                None => continue,
+            };
+            let diff = order_book.apply(
+                msg.add_utxos.get(token_hash).map(|utxos| &utxos[..]).unwrap_or(&[]),
+                msg.remove_utxos.get(token_hash).map(|utxos| &utxos[..]).unwrap_or(&[]),
+            );
+            let (diff, recipients) = match (diff, subscribers.subscribers_token.get(token_hash)) {
+                (Some(diff), Some(recipients)) => (Arc::new(diff), recipients),
+                _ => continue,
+            };
+            for recipient in recipients.iter() {
+                recipient.do_send(TxEvent::OrderBookDiff { diff: diff.clone() })
+                    .map_err(|err| eprintln!("Failed to send order book diff: {}", err)).ok();
+            }
+        }
+        Ok(())
+    }
+}
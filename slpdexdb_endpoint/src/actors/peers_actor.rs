@@ -1,36 +1,180 @@
 use actix::prelude::*;
 use tokio_tcp::TcpStream;
 use std::convert::identity;
-use std::sync::Arc;
-use slpdexdb_base::Error;
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use slpdexdb_base::{Error, Network};
+use slpdexdb_db::models;
 use slpdexdb_node::actors::{NodeActor, IncomingMsg, OutgoingMsg};
-use slpdexdb_node::DbActor;
-use slpdexdb_node::msg::Subscribe;
+use slpdexdb_node::{DbActor, PeerTable, PeerInfo, AddrRateLimit, MAX_PEERS};
+use slpdexdb_node::msg::{Subscribe, HandshakeSuccess, NodeDisconnected, PeerMisbehaved, Disconnect, GetNodeStats,
+                         NodeStats, PeerAddrs};
 use slpdexdb_node::messages::{TxMessage, BlockMessage};
-use slpdexdb_node::NodeMessage;
 
 
-use crate::actors::TxActor;
-use crate::msg::ConnectToPeer;
+use crate::actors::{self, TxActor};
+use crate::msg::{ConnectToPeer, PersistPeers};
 
+const FILL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct PeersConfig {
+    pub network: Network,
+    pub seed_peers: Vec<SocketAddr>,
+    pub dns_seeds: Vec<String>,
+    pub default_port: u16,
+    pub target_outbound: usize,
+    pub ban_threshold: i32,
+    pub ban_duration_secs: i64,
+}
 
 pub struct PeersActor {
     tx_actor: Addr<TxActor>,
     db_actor: Addr<DbActor>,
-    nodes: Vec<Addr<NodeActor>>,
+    db_writer: Addr<actors::DbActor>,
+    peers: Vec<models::Peer>,
+    config: PeersConfig,
+    table: PeerTable,
+    nodes: HashMap<SocketAddr, Addr<NodeActor>>,
+    connecting: HashSet<SocketAddr>,
+    addr_limits: HashMap<SocketAddr, AddrRateLimit>,
+    next_peer: usize,
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
 }
 
 impl PeersActor {
-    pub fn new(tx_actor: Addr<TxActor>, db_actor: Addr<DbActor>) -> Self {
+    // peers are the ones persisted on the last run, loaded before the actor system starts
+    pub fn new(tx_actor: Addr<TxActor>,
+               db_actor: Addr<DbActor>,
+               db_writer: Addr<actors::DbActor>,
+               peers: Vec<models::Peer>,
+               config: PeersConfig) -> Self {
         PeersActor {
             tx_actor,
             db_actor,
-            nodes: Vec::new(),
+            db_writer,
+            peers,
+            config,
+            table: PeerTable::new(),
+            nodes: HashMap::new(),
+            connecting: HashSet::new(),
+            addr_limits: HashMap::new(),
+            next_peer: 0,
         }
     }
+
+    fn _persist(&self, addrs: impl IntoIterator<Item=SocketAddr>) {
+        let peers = addrs.into_iter()
+            .filter_map(|addr| self.table.get(&addr))
+            .map(|peer| models::Peer {
+                ip: peer.addr.ip().to_string(),
+                port: peer.addr.port() as i32,
+                last_seen: peer.last_seen,
+                last_attempt: peer.last_attempt,
+                n_failures: peer.n_failures,
+                banned_until: peer.banned_until,
+            })
+            .collect::<Vec<_>>();
+        Arbiter::spawn(
+            self.db_writer.send(PersistPeers(peers))
+                .from_err::<Error>()
+                .and_then(identity)
+                .map_err(|err| eprintln!("Failed to persist peers: {}", err))
+        );
+    }
+
+    // keeps the table bounded, never dropping the peers we're connected to
+    fn _evict_peers(&mut self) {
+        let keep = self.nodes.keys().chain(self.connecting.iter()).cloned().collect::<HashSet<_>>();
+        let evicted = self.table.evict(MAX_PEERS, &keep);
+        if !evicted.is_empty() {
+            println!("evicted {} peers from the peer table", evicted.len());
+        }
+    }
+
+    fn _load_peers(&mut self) {
+        let peers = std::mem::replace(&mut self.peers, Vec::new());
+        self.table = PeerTable::from_peers(peers.into_iter().filter_map(|peer| {
+            Some(PeerInfo {
+                addr: SocketAddr::new(peer.ip.parse().ok()?, peer.port as u16),
+                last_seen: peer.last_seen,
+                last_attempt: peer.last_attempt,
+                n_failures: peer.n_failures,
+                misbehaviour_score: 0,
+                banned_until: peer.banned_until,
+            })
+        }));
+    }
+
+    fn _resolve_dns_seeds(&self, ctx: &mut Context<Self>) {
+        let dns_seeds = self.config.dns_seeds.clone();
+        let port = self.config.default_port;
+        let own_addr = ctx.address();
+        std::thread::spawn(move || {
+            let addrs = dns_seeds.iter()
+                .filter_map(|seed| {
+                    (seed.as_str(), port).to_socket_addrs()
+                        .map_err(|err| eprintln!("Failed to resolve {}: {}", seed, err))
+                        .ok()
+                })
+                .flatten()
+                .collect::<Vec<_>>();
+            own_addr.do_send(AddPeers(addrs));
+        });
+    }
+
+    fn _fill_connections(&mut self, ctx: &mut Context<Self>) {
+        let n_outbound = self.nodes.len() + self.connecting.len();
+        if n_outbound >= self.config.target_outbound {
+            return;
+        }
+        let exclude = self.nodes.keys().chain(self.connecting.iter()).cloned().collect();
+        let candidates = self.table.candidates(unix_now(), &exclude);
+        for peer_addr in candidates.into_iter().take(self.config.target_outbound - n_outbound) {
+            self._connect(peer_addr, ctx);
+        }
+    }
+
+    fn _connect(&mut self, peer_addr: SocketAddr, ctx: &mut Context<Self>) {
+        let now = unix_now();
+        self.table.add(peer_addr, now);
+        self.table.record_attempt(&peer_addr, now);
+        self._persist(vec![peer_addr]);
+        self.connecting.insert(peer_addr);
+        let own_addr = ctx.address();
+        let own_addr2 = ctx.address();
+        let db_addr = self.db_actor.clone();
+        let network = self.config.network.clone();
+        println!("connecting on {}", peer_addr);
+        Arbiter::spawn(
+            TcpStream::connect(&peer_addr)
+                .from_err::<Error>()
+                .and_then(move |stream| {
+                    println!("connected to {}", peer_addr);
+                    let node = NodeActor::create_from_stream_db(stream, db_addr, network);
+                    node.do_send(Subscribe::Tx(own_addr.clone().recipient()));
+                    node.do_send(Subscribe::Block(own_addr.clone().recipient()));
+                    node.do_send(Subscribe::Addr(own_addr.clone().recipient()));
+                    node.do_send(Subscribe::HandshakeSuccess(own_addr.clone().recipient()));
+                    node.do_send(Subscribe::Disconnected(own_addr.clone().recipient()));
+                    node.do_send(Subscribe::Misbehaved(own_addr.clone().recipient()));
+                    own_addr.send(PeerConnected { peer_addr, node }).from_err()
+                })
+                .or_else(move |err| {
+                    println!("connecting to {} failed: {}", peer_addr, err);
+                    own_addr2.send(PeerConnectFailed { peer_addr })
+                })
+                .map_err(|err| eprintln!("{}", err))
+        );
+    }
 }
 
 pub struct PeerConnected {
+    pub peer_addr: SocketAddr,
     pub node: Addr<NodeActor>,
 }
 
@@ -38,34 +182,65 @@ impl Message for PeerConnected {
     type Result = ();
 }
 
+pub struct PeerConnectFailed {
+    pub peer_addr: SocketAddr,
+}
+
+impl Message for PeerConnectFailed {
+    type Result = ();
+}
+
+pub struct AddPeers(pub Vec<SocketAddr>);
+
+impl Message for AddPeers {
+    type Result = ();
+}
+
+// sends to one connected node, rotating between calls; false if none is connected
+pub struct SendToAnyPeer(pub OutgoingMsg);
+
+impl Message for SendToAnyPeer {
+    type Result = bool;
+}
+
+// stats of all connected nodes, nodes that disconnect meanwhile are left out
+pub struct GetPeerStats;
+
+impl Message for GetPeerStats {
+    type Result = Result<Vec<NodeStats>, Error>;
+}
+
 impl Actor for PeersActor {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self._load_peers();
+        let now = unix_now();
+        for &seed_peer in self.config.seed_peers.iter() {
+            self.table.add(seed_peer, now);
+        }
+        self._persist(self.config.seed_peers.clone());
+        self._resolve_dns_seeds(ctx);
+        self._fill_connections(ctx);
+        ctx.run_interval(FILL_INTERVAL, |act, ctx| act._fill_connections(ctx));
+    }
 }
 
 impl Handler<ConnectToPeer> for PeersActor {
-    type Result = Response<(), Error>;
+    type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: ConnectToPeer, ctx: &mut Self::Context) -> Self::Result {
-        let own_addr = ctx.address();
-        let own_addr2 = ctx.address();
-        let db_addr = self.db_actor.clone();
-        println!("connecting on {}", msg.socket_addr);
-        Response::fut(
-            TcpStream::connect(&msg.socket_addr)
-                .from_err()
-                .and_then(move |stream| {
-                    println!("connected");
-                    let node = NodeActor::create_from_stream_db(stream, db_addr);
-                    let node2 = node.clone();
-                    node.send(Subscribe::Tx(own_addr.clone().recipient())).from_err()
-                        .and_then(move |_| node2.send(Subscribe::Block(own_addr2.clone().recipient())).from_err())
-                        .and_then(move |_| own_addr.send(PeerConnected { node }).from_err())
-                })
-                .map_err(|err| {
-                    println!("{}", err);
-                    err
-                })
-        )
+        let is_banned = self.table.get(&msg.socket_addr)
+            .map(|peer| peer.is_banned(unix_now()))
+            .unwrap_or(false);
+        if is_banned {
+            println!("not connecting to banned peer {}", msg.socket_addr);
+            return Ok(());
+        }
+        if !self.nodes.contains_key(&msg.socket_addr) && !self.connecting.contains(&msg.socket_addr) {
+            self._connect(msg.socket_addr, ctx);
+        }
+        Ok(())
     }
 }
 
@@ -73,7 +248,100 @@ impl Handler<PeerConnected> for PeersActor {
     type Result = ();
 
     fn handle(&mut self, msg: PeerConnected, _ctx: &mut Self::Context) -> Self::Result {
-        self.nodes.push(msg.node);
+        self.connecting.remove(&msg.peer_addr);
+        self.nodes.insert(msg.peer_addr, msg.node);
+    }
+}
+
+impl Handler<PeerConnectFailed> for PeersActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerConnectFailed, _ctx: &mut Self::Context) -> Self::Result {
+        self.connecting.remove(&msg.peer_addr);
+        self.table.record_failure(&msg.peer_addr);
+        self._persist(vec![msg.peer_addr]);
+    }
+}
+
+impl Handler<NodeDisconnected> for PeersActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeDisconnected, ctx: &mut Self::Context) -> Self::Result {
+        println!("disconnected from {}", msg.peer_addr);
+        self.nodes.remove(&msg.peer_addr);
+        self.addr_limits.remove(&msg.peer_addr);
+        self.table.record_failure(&msg.peer_addr);
+        self._persist(vec![msg.peer_addr]);
+        self._fill_connections(ctx);
+    }
+}
+
+impl Handler<PeerMisbehaved> for PeersActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerMisbehaved, _ctx: &mut Self::Context) -> Self::Result {
+        let now = unix_now();
+        let is_banned = self.table
+            .record_misbehaviour(&msg.peer_addr,
+                                 msg.score,
+                                 self.config.ban_threshold,
+                                 self.config.ban_duration_secs,
+                                 now)
+            .map(|peer| peer.is_banned(now))
+            .unwrap_or(false);
+        if is_banned {
+            println!("banning {}: {}", msg.peer_addr, msg.reason);
+            if let Some(node) = self.nodes.get(&msg.peer_addr) {
+                node.do_send(Disconnect);
+            }
+            self._persist(vec![msg.peer_addr]);
+        }
+    }
+}
+
+impl Handler<HandshakeSuccess> for PeersActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: HandshakeSuccess, _ctx: &mut Self::Context) -> Self::Result {
+        self.table.record_success(&msg.peer_addr, unix_now());
+        self._persist(vec![msg.peer_addr]);
+        Ok(())
+    }
+}
+
+impl Handler<AddPeers> for PeersActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddPeers, ctx: &mut Self::Context) -> Self::Result {
+        let now = unix_now();
+        for &peer_addr in msg.0.iter() {
+            self.table.add(peer_addr, now);
+        }
+        self._evict_peers();
+        self._persist(msg.0);
+        self._fill_connections(ctx);
+    }
+}
+
+impl Handler<PeerAddrs> for PeersActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: PeerAddrs, _ctx: &mut Self::Context) -> Self::Result {
+        let now = unix_now();
+        let addresses = &msg.addrs.addresses;
+        let n_accepted = self.addr_limits.entry(msg.peer_addr)
+            .or_insert_with(|| AddrRateLimit::new(now))
+            .take(addresses.len(), now);
+        if n_accepted < addresses.len() {
+            eprintln!("Ignoring {} addresses from {}, rate limited", addresses.len() - n_accepted, msg.peer_addr);
+        }
+        let addresses = &addresses[..n_accepted];
+        for net_addr in addresses.iter() {
+            self.table.add(net_addr.addr, (net_addr.timestamp as i64).min(now));
+        }
+        self._evict_peers();
+        self._persist(addresses.iter().map(|net_addr| net_addr.addr));
+        Ok(())
     }
 }
 
@@ -97,9 +365,39 @@ impl Handler<OutgoingMsg> for PeersActor {
     type Result = ();
 
     fn handle(&mut self, msg: OutgoingMsg, _ctx: &mut Self::Context) {
-        for node in self.nodes.iter() {
+        for node in self.nodes.values() {
             node.do_send(msg.clone());
         }
-        //Response::fut(self.nodes[0].send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<SendToAnyPeer> for PeersActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: SendToAnyPeer, _ctx: &mut Self::Context) -> Self::Result {
+        if self.nodes.len() == 0 {
+            return false;
+        }
+        self.next_peer = (self.next_peer + 1) % self.nodes.len();
+        let node = self.nodes.values().nth(self.next_peer).unwrap();
+        node.do_send(msg.0);
+        true
+    }
+}
+
+impl Handler<GetPeerStats> for PeersActor {
+    type Result = Response<Vec<NodeStats>, Error>;
+
+    fn handle(&mut self, _msg: GetPeerStats, _ctx: &mut Self::Context) -> Self::Result {
+        let stats = self.nodes.values()
+            .map(|node| {
+                node.send(GetNodeStats)
+                    .then(|stats| Ok::<_, Error>(stats.ok().and_then(Result::ok)))
+            })
+            .collect::<Vec<_>>();
+        Response::fut(
+            futures::future::join_all(stats)
+                .map(|stats| stats.into_iter().filter_map(identity).collect())
+        )
     }
 }
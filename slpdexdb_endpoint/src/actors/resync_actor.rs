@@ -1,16 +1,20 @@
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::collections::HashSet;
 use actix::prelude::*;
-use cashcontracts::{Address, AddressType, tx_hex_to_hash, TxOutpoint};
+use cashcontracts::{Address, AddressType, tx_hash_to_hex, TxOutpoint};
 use slpdexdb_base::{Error, ErrorKind, SLPDEXConfig, PandaError};
 use slpdexdb_node::actors::OutgoingMsg;
 use slpdexdb_node::messages::TxMessage;
 use slpdexdb_node::NodeMessage;
 use slpdexdb_db::{tx_hash_from_slice, tx_hash_from_le_slice};
-use slpdexdb_db::{Db, TxSource, TokenSource, UpdateSubject, UpdateSubjectType, UpdateHistory,
-                  TxHistory, TxFilter, Token, OutputType, Confirmedness, TxType, panda_tools};
-use crate::msg::{ResyncAddress, ProcessTransactions, NewTransactions, ProcessBlock, RegisterOutgoing};
+use slpdexdb_db::{Db, DbPool, ChainSource, UpdateSubject, UpdateSubjectType, UpdateHistory,
+                  TxHistory, TxFilter, Token, OutputType, Confirmedness, TxType, panda_tools,
+                  BlockConfirmations, TokenSupply, DoubleSpentUtxos};
+use slpdexdb_db::token_result::TokenEntry;
+use crate::msg::{ResyncAddress, ProcessTransactions, NewTransactions, ProcessBlock, RegisterOutgoing,
+                 TxEvent, ActivateAddress, DeactivateAddress, RemoveDoubleSpentTxs};
+use crate::actors::TxSubscribers;
 use cryptopandas_base::genomics::{create_seed, mix_genes};
 use cryptopandas_base::utils::{pack_genes};
 use std::collections::HashMap;
@@ -18,34 +22,94 @@ use std::collections::HashMap;
 use slpdexdb_db::panda;
 
 
-fn _resync(db: &Db, config: &SLPDEXConfig) -> Result<(), Error> {
-    _init_panda_token(db, config)?;
-    //_resync_tokens(db)?;
-    //_resync_trade_offers(db, config, true)?;
-    //_resync_trade_offers(db, config, false)?;
+const MAX_NOTIFIED_CONFIRMATIONS: i32 = 6;
+
+fn _notify_confirmations(block_confirmations: BlockConfirmations,
+                         subscribers: &Arc<Mutex<TxSubscribers>>) {
+    let subscribers = subscribers.lock().unwrap();
+    let confirmations = block_confirmations.confirmations.iter()
+        .map(|confirmation| (confirmation.tx_hash, confirmation))
+        .collect::<HashMap<_, _>>();
+    let address_events = block_confirmations.address_txs.iter()
+        .filter_map(|(address, tx_hashes)| {
+            Some((subscribers.subscribers_address.get(address)?, tx_hashes))
+        });
+    let token_events = block_confirmations.token_txs.iter()
+        .filter_map(|(token_hash, tx_hashes)| {
+            Some((subscribers.subscribers_token.get(token_hash)?, tx_hashes))
+        });
+    let mut recipient_txs = HashMap::new();
+    for (recipients, tx_hashes) in address_events.chain(token_events) {
+        for recipient in recipients.iter() {
+            let txs = recipient_txs.entry(recipient.clone()).or_insert_with(HashSet::new);
+            txs.extend(tx_hashes.iter().cloned());
+        }
+    }
+    for (recipient, tx_hashes) in recipient_txs {
+        let txs = tx_hashes.iter()
+            .filter_map(|tx_hash| confirmations.get(tx_hash).map(|&confirmation| confirmation.clone()))
+            .collect::<Vec<_>>();
+        recipient.do_send(TxEvent::Confirmations {
+            block_hash: block_confirmations.block_hash,
+            height: block_confirmations.height,
+            txs: Arc::new(txs),
+        }).map_err(|err| eprintln!("Failed to send confirmations: {}", err)).ok();
+    }
+}
+
+fn _resync(db: &Db, config: &SLPDEXConfig, source: &dyn ChainSource) -> Result<(), Error> {
+    _init_panda_token(db, config, source)?;
+    _resync_token_stats(db)?;
+    _resync_token_supplies(db, source)?;
+    //_resync_tokens(db, source)?;
+    //_resync_trade_offers(db, config, source, true)?;
+    //_resync_trade_offers(db, config, source, false)?;
     Ok(())
 }
 
-fn _init_panda_token(db: &Db, config: &SLPDEXConfig) -> Result<(), Error> {
+fn _init_panda_token(db: &Db, config: &SLPDEXConfig, source: &dyn ChainSource) -> Result<(), Error> {
+    // networks without a panda token leave it zeroed
+    if config.panda_token_hash == [0; 32] {
+        return Ok(());
+    }
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let token_source = TokenSource::new();
-    let tx_source = TxSource::new();
-    let token_hash = tx_hex_to_hash("af5fb817275c12a403df832cf61af135d0cd7a63f9c0fedb10ff3b2b50799533").unwrap();
-    let token_entries = token_source.request_tokens(&[TxFilter::TokenId(token_hash.clone())])?;
+    let token_hash = config.panda_token_hash;
+    let token_entries = source.request_tokens(&[TxFilter::TokenId(token_hash.clone())])?;
+    let supplies = _token_supplies(&token_entries);
     let tokens = token_entries.into_iter()
         .filter_map(|token_entry| {
             Token::from_entry(token_entry).map_err(|err| eprintln!("token error: {}", err)).ok()
         })
         .collect::<Vec<_>>();
-    let tx_entries = tx_source.request_txs(&[TxFilter::TxHash(token_hash)], config, Confirmedness::Confirmed)?;
+    let tx_entries = source.request_txs(&[TxFilter::TxHash(token_hash)], config, Confirmedness::Confirmed)?;
     let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
     db.add_tokens(&tokens)?;
+    db.add_token_supplies(&supplies)?;
     db.add_tx_history(&history)?;
     Ok(())
 }
 
-fn _resync_tokens(db: &Db) -> Result<(), Error> {
-    let token_source = TokenSource::new();
+fn _token_supplies(token_entries: &[TokenEntry]) -> Vec<TokenSupply> {
+    token_entries.iter()
+        .filter_map(|token_entry| {
+            TokenSupply::from_entry(token_entry).map_err(|err| eprintln!("token supply error: {}", err)).ok()
+        })
+        .collect()
+}
+
+// supplies of tokens from before supply tracking only get correct once fetched from the source
+fn _resync_token_supplies(db: &Db, source: &dyn ChainSource) -> Result<(), Error> {
+    if !source.knows_token_supplies() {
+        return Ok(());
+    }
+    for token_hash in db.unsynced_token_supplies()? {
+        let token_entries = source.request_tokens(&[TxFilter::TokenId(token_hash)])?;
+        db.add_token_supplies(&_token_supplies(&token_entries))?;
+    }
+    Ok(())
+}
+
+fn _resync_tokens(db: &Db, source: &dyn ChainSource) -> Result<(), Error> {
     loop {
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
         let subject = UpdateSubject {
@@ -56,7 +120,8 @@ fn _resync_tokens(db: &Db) -> Result<(), Error> {
         let last_update = db.last_update(subject.clone())?
             .unwrap_or(UpdateHistory::initial(subject));
         println!("last update: {:?}", last_update);
-        let token_entries = token_source.request_tokens(&last_update.next_filters())?;
+        let token_entries = source.request_tokens(&last_update.next_filters())?;
+        let supplies = _token_supplies(&token_entries);
         let tokens = token_entries.into_iter()
             .filter_map(|token_entry| {
                 Token::from_entry(token_entry).map_err(|err| eprintln!("token error: {}", err)).ok()
@@ -71,13 +136,34 @@ fn _resync_tokens(db: &Db) -> Result<(), Error> {
             println!("document_uri: {:?}", token.document_uri.as_ref().map(|x| hex::encode(x.as_bytes())));
             db.add_tokens(&[token.clone()])?;
         }
+        db.add_token_supplies(&supplies)?;
         db.add_update_history(&UpdateHistory::from_tokens(&tokens, current_height))?;
     }
     Ok(())
 }
 
-fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> Result<(), Error> {
-    let tx_source = TxSource::new();
+// stats are derived from our own tables, so this only catches up on what changed since the last run
+fn _resync_token_stats(db: &Db) -> Result<(), Error> {
+    let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
+    let subject = UpdateSubject {
+        subject_type: UpdateSubjectType::TokenStats,
+        hash: None,
+        is_confirmed: true,
+    };
+    let last_update = db.last_update(subject.clone())?
+        .unwrap_or_else(|| UpdateHistory::initial(subject.clone()));
+    db.refresh_token_stats(last_update.last_height)?;
+    db.add_update_history(&UpdateHistory {
+        last_height: current_height,
+        last_tx_hash: None,
+        subject,
+        completed: true,
+    })?;
+    Ok(())
+}
+
+fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, source: &dyn ChainSource,
+                        is_confirmed: bool) -> Result<(), Error> {
     loop {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
@@ -90,7 +176,7 @@ fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> R
         };
         let last_update = db.last_update(subject.clone())?
                 .unwrap_or_else(|| UpdateHistory::initial(subject.clone()));
-        let tx_entries = tx_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
+        let tx_entries = source.request_txs(&last_update.next_filters(), config, confirmedness)?;
         let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
         if history.txs.len() == 0 {
             break
@@ -101,13 +187,12 @@ fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> R
             &UpdateHistory::from_tx_history(&history, subject, current_height)
         )?;
     }
-    db.update_utxo_set_exch()?;
     Ok(())
 }
 
-fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirmed: bool) -> Result<(), Error> {
+fn _resync_address(db: &Db, config: &SLPDEXConfig, source: &dyn ChainSource, address: &Address,
+                   is_confirmed: bool) -> Result<(), Error> {
     loop {
-        let tx_source = TxSource::new();
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
         let confirmedness = if is_confirmed { Confirmedness::Confirmed }
@@ -120,7 +205,7 @@ fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirm
         let last_update = db.last_update(subject.clone())?
             .unwrap_or(UpdateHistory::initial(subject.clone()));
         println!("last update: {}", last_update);
-        let tx_entries = tx_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
+        let tx_entries = source.request_txs(&last_update.next_filters(), config, confirmedness)?;
         let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
         if history.txs.len() > 0 {
             db.add_tx_history(&history)?;
@@ -136,123 +221,108 @@ fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirm
             break
         }
     }
-    db.update_utxo_set(address)?;
     Ok(())
 }
 
-pub struct ResyncActor {
-    db: Db,
-    config: SLPDEXConfig,
-    secret: Vec<u8>,
-    outgoing_recipient: Option<Recipient<OutgoingMsg>>,
-}
-
-impl ResyncActor {
-    pub fn new(db: Db, config: SLPDEXConfig, secret: Vec<u8>) -> Self {
-        ResyncActor { db, config, secret, outgoing_recipient: None }
-    }
-}
-
-impl Actor for ResyncActor {
-    type Context = SyncContext<Self>;
-
-    fn started(&mut self, _ctx: &mut Self::Context) {
-        _resync(&self.db, &self.config)
-            .map_err(|err| eprintln!("resync failed: {}", err))
-            .unwrap_or(());
+fn _process_tx_history(db: &Db,
+                       source: &dyn ChainSource,
+                       txs: &[cashcontracts::Tx],
+                       mut history: TxHistory,
+                       now: i64,
+                       subscribers: &Arc<Mutex<TxSubscribers>>) -> Result<Option<NewTransactions>, Error> {
+    let pending = history.validate_slp(txs, db, source.slp_tx_provider())?;
+    for tx_hash in pending {
+        eprintln!("SLP validity of {} pending, missing ancestors", tx_hash_to_hex(&tx_hash));
     }
-}
-
-impl Handler<ResyncAddress> for ResyncActor {
-    type Result = Result<(), Error>;
-
-    fn handle(&mut self, msg: ResyncAddress, _ctx: &mut Self::Context) -> Self::Result {
-        let address = msg.0;
-        _resync_address(&self.db, &self.config, &address, true)?;
-        _resync_address(&self.db, &self.config, &address, false)?;
-        Ok(())
+    let addresses = history.txs.iter()
+        .flat_map(|tx| {
+            tx.outputs.iter()
+                .map(|output| output.output.clone())
+                .chain(tx.inputs.iter().map(|input| input.output.clone()))
+                .filter_map(|output| match output {
+                    OutputType::Address(address) => Some(address),
+                    _ => None,
+                })
+        })
+        .collect::<Vec<_>>();
+    let subscribers_addresses = &subscribers.lock().unwrap().subscribers_address;
+    let relevant_addresses = addresses.into_iter()
+        .filter(|address| subscribers_addresses.contains_key(address))
+        .collect::<HashSet<_>>();
+    if history.txs.iter().filter(|tx| match tx.tx_type {
+        TxType::SLP {..} => true,
+        TxType::Default => false,
+    }).count() == 0 &&
+        relevant_addresses.len() == 0 {
+        return Ok(None)
     }
-}
-
-impl Handler<ProcessTransactions> for ResyncActor {
-    type Result = Result<(), Error>;
-
-    fn handle(&mut self, msg: ProcessTransactions, _ctx: &mut Self::Context) -> Self::Result {
-        let tx_source = TxSource::new();
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
-        let db = msg.db.lock().unwrap();
-        let mut history = TxHistory::from_txs(&msg.txs, timestamp, &msg.config, &*db);
-        let addresses = history.txs.iter()
-            .flat_map(|tx| {
-                tx.outputs.iter()
-                    .map(|output| output.output.clone())
-                    .chain(tx.inputs.iter().map(|input| input.output.clone()))
-                    .filter_map(|output| match output {
-                        OutputType::Address(address) => Some(address),
-                        _ => None,
-                    })
-            })
-            .collect::<Vec<_>>();
-        let subscribers_addresses = &msg.subscribers.lock().unwrap().subscribers_address;
-        let relevant_addresses = addresses.into_iter()
-            .filter(|address| subscribers_addresses.contains_key(address))
-            .collect::<HashSet<_>>();
-        if history.txs.iter().filter(|tx| match tx.tx_type {
-                TxType::SLP {..} => true,
-                TxType::Default => false,
-            }).count() == 0 &&
-            relevant_addresses.len() == 0 {
-            return Ok(())
-        }
-        history.validate_slp(&tx_source, &*db, &msg.config)?;
-        if history.txs.iter().filter(|tx| match tx.tx_type {
-            TxType::SLP {..} => true,
-            TxType::Default => false,
-        }).count() == 0 &&
-            relevant_addresses.len() == 0 {
-            return Ok(())
-        }
-        db.add_tx_history(&history)?;
-        for (idx, tx) in history.txs.iter().enumerate() {
-            if history.pandas_slp.contains(&idx) {
-                if let Some(pos) = tx.outputs.iter().position(|output| output.value_token.base_amount() > 0) {
-                    if let TxType::SLP { token_hash, .. } = tx.tx_type {
-                        panda_tools::switch_owners(token_hash.clone(),
-                                                   tx.hash.clone(),
-                                                   pos as i32,
-                                                   db.connection())?;
-                    }
+    db.add_tx_history(&history)?;
+    for (idx, tx) in history.txs.iter().enumerate() {
+        if history.pandas_slp.contains(&idx) {
+            if let Some(pos) = tx.outputs.iter().position(|output| output.value_token.base_amount() > 0) {
+                if let TxType::SLP { token_hash, .. } = tx.tx_type {
+                    panda_tools::switch_owners(token_hash.clone(),
+                                               tx.hash.clone(),
+                                               pos as i32,
+                                               db.connection())?;
                 }
             }
-            println!("{}", tx);
         }
-        println!("txs valid.");
-        let new_transactions = NewTransactions {
-            now: timestamp,
-            subscribers: msg.subscribers.clone(),
-            tx_history: Arc::new(history),
-            db: msg.db.clone(),
-            relevant_addresses: Arc::new(relevant_addresses),
-        };
-        for broadcast in msg.broadcasts.iter() {
-            broadcast.do_send(new_transactions.clone()).unwrap();  // TODO: handle error
-        }
-        Ok(())
+        println!("{}", tx);
     }
+    println!("txs valid.");
+    let input_outputs = db.tx_outputs(
+        history.txs.iter().flat_map(|tx| {
+            tx.inputs.iter().filter_map(|input| {
+                if relevant_addresses.contains(input.output.address()?) {
+                    Some(input.output_tx.clone())
+                } else {
+                    None
+                }
+            })
+        })
+    )?;
+    Ok(Some(NewTransactions {
+        now,
+        subscribers: subscribers.clone(),
+        tx_history: Arc::new(history),
+        input_outputs: Arc::new(input_outputs),
+        relevant_addresses: Arc::new(relevant_addresses),
+    }))
 }
 
-impl Handler<ProcessBlock> for ResyncActor {
-    type Result = Result<(), Error>;
+fn _broadcast_new_transactions(new_transactions: NewTransactions, broadcasts: &[Recipient<NewTransactions>]) {
+    for broadcast in broadcasts.iter() {
+        broadcast.do_send(new_transactions.clone())
+            .map_err(|err| eprintln!("Failed to send new transactions: {}", err))
+            .ok();
+    }
+}
 
-    fn handle(&mut self, msg: ProcessBlock, _ctx: &mut Self::Context) -> Self::Result {
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+// the single writer for tx histories, so they're added in the order they arrive;
+// connections are checked out per message, so an idle actor doesn't hold one
+pub struct ResyncActor {
+    pool: DbPool,
+    config: SLPDEXConfig,
+    secret: Vec<u8>,
+    chain_source: Box<dyn ChainSource>,
+    outgoing_recipient: Option<Recipient<OutgoingMsg>>,
+}
 
-        let db = msg.db.lock().unwrap();
+impl ResyncActor {
+    pub fn new(pool: DbPool, config: SLPDEXConfig, secret: Vec<u8>, chain_source: Box<dyn ChainSource>) -> Self {
+        ResyncActor { pool, config, secret, chain_source, outgoing_recipient: None }
+    }
 
-        let outgoing = self.outgoing_recipient.as_ref().unwrap();
-        let tx_set = msg.tx_hashes.into_iter().collect::<HashSet<_>>();
+    // records the pandas born in the block and returns their txs to be broadcast
+    fn _birth_pandas(&self,
+                     db: &Db,
+                     txs: &[cashcontracts::Tx],
+                     block_hash: &[u8; 32],
+                     timestamp: i64) -> Result<Vec<cashcontracts::Tx>, Error> {
+        let tx_set = txs.iter().map(|tx| tx.hash()).collect::<HashSet<_>>();
         let pending_pnd = db.pending_pnd()?;
+        let mut panda_txs = Vec::new();
         let mut born_pnds = Vec::new();
         for (pnd, tx) in pending_pnd {
             let hash = tx_hash_from_slice(&tx.hash);
@@ -260,7 +330,6 @@ impl Handler<ProcessBlock> for ResyncActor {
                 born_pnds.push((pnd, tx, hash));
             }
         }
-        let block_hash = msg.header.hash();
         let pandas = panda_tools::get_pandas_by_ids(
             born_pnds.iter()
                 .flat_map(|(pnd, _, _)| vec![pnd.father, pnd.mother])
@@ -273,7 +342,8 @@ impl Handler<ProcessBlock> for ResyncActor {
             .collect::<HashMap<_, _>>();
 
         for (pnd, tx, tx_hash) in born_pnds {
-            let seed = create_seed(&block_hash, &tx_hash);
+            let pnd1_tx_id = tx.id;
+            let seed = create_seed(block_hash, &tx_hash);
             let father = &pandas[&pnd.father];
             let mother = &pandas[&pnd.mother];
             let mut father_genes = [0; 48];
@@ -312,26 +382,147 @@ impl Handler<ProcessBlock> for ResyncActor {
             let tx = panda.tx().map_err(|missing_funds| -> Error {
                 ErrorKind::PandaError(PandaError::InsufficientFunds(missing_funds)).into()
             })?;
-            let token = panda.token(timestamp as i64, self.config.panda_token_hash, &tx);
-
-            outgoing.do_send(OutgoingMsg(TxMessage { tx: tx.clone() }.packet())).unwrap();
+            let token = panda.token(timestamp, self.config.panda_token_hash, &tx);
 
             let hash = tx.hash();
 
             db.add_tokens(&[token])?;
-            let tx_history = TxHistory::from_txs(&[tx], timestamp as i64, &self.config, &db);
-            db.add_tx_history(&tx_history);
+            let tx_history = TxHistory::from_txs(&[tx.clone()], timestamp, &self.config, &db);
+            db.add_tx_history(&tx_history)?;
 
             let db_txs = db.txs(vec![hash.clone()].into_iter())?;
             let db_tx = db_txs.get(&hash).unwrap();
 
-            panda_tools::insert_panda_from_genes(
+            let panda_id = panda_tools::insert_panda_from_genes(
                 /*genesis_tx:*/ &db_tx.id,
                 /*owner_tx:*/ &db_tx.id,
                 /*owner_tx_idx:*/ &1,
                 /*genes:*/ &new_genes,
                 db.connection(),
             )?;
+            panda_tools::record_panda_birth(&pnd1_tx_id, &panda_id, block_hash, db.connection())?;
+            panda_txs.push(tx);
+        }
+        Ok(panda_txs)
+    }
+}
+
+impl Actor for ResyncActor {
+    type Context = SyncContext<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        Db::from_pool(&self.pool)
+            .and_then(|db| _resync(&db, &self.config, &*self.chain_source))
+            .map_err(|err| eprintln!("resync failed: {}", err))
+            .unwrap_or(());
+    }
+}
+
+impl Handler<ActivateAddress> for ResyncActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: ActivateAddress, ctx: &mut Self::Context) -> Self::Result {
+        let address = msg.0;
+        Db::from_pool(&self.pool)?.set_address_active(&address, true)?;
+        Handler::<ResyncAddress>::handle(self, ResyncAddress(address), ctx)
+    }
+}
+
+impl Handler<DeactivateAddress> for ResyncActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeactivateAddress, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(Db::from_pool(&self.pool)?.set_address_active(&msg.0, false)?)
+    }
+}
+
+impl Handler<RemoveDoubleSpentTxs> for ResyncActor {
+    type Result = Result<DoubleSpentUtxos, Error>;
+
+    fn handle(&mut self, msg: RemoveDoubleSpentTxs, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(Db::from_pool(&self.pool)?.remove_double_spent_txs(&msg.0)?)
+    }
+}
+
+impl Handler<ResyncAddress> for ResyncActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: ResyncAddress, _ctx: &mut Self::Context) -> Self::Result {
+        let address = msg.0;
+        let db = Db::from_pool(&self.pool)?;
+        _resync_address(&db, &self.config, &*self.chain_source, &address, true)?;
+        _resync_address(&db, &self.config, &*self.chain_source, &address, false)?;
+        Ok(())
+    }
+}
+
+impl Handler<ProcessTransactions> for ResyncActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: ProcessTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let db = &Db::from_pool(&self.pool)?;
+        let history = TxHistory::from_txs(&msg.txs, timestamp, &msg.config, db);
+        if let Some(new_transactions) = _process_tx_history(db, &*self.chain_source, &msg.txs, history, timestamp,
+                                                            &msg.subscribers)? {
+            _broadcast_new_transactions(new_transactions, &msg.broadcasts);
+        }
+        Ok(())
+    }
+}
+
+impl Handler<ProcessBlock> for ResyncActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: ProcessBlock, _ctx: &mut Self::Context) -> Self::Result {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let db = &Db::from_pool(&self.pool)?;
+
+        let block_hash = msg.header.hash();
+        // blocks can arrive twice, e.g. announced live while also being backfilled
+        if db.is_block_processed(&block_hash)? {
+            return Ok(());
+        }
+        // blocks without a known header are left for the backfill once headers caught up
+        let height = match db.header_height(&block_hash)? {
+            Some(height) => height,
+            None => {
+                eprintln!("Skipping block {} without a known header", tx_hash_to_hex(&block_hash));
+                return Ok(());
+            },
+        };
+        let outgoing = self.outgoing_recipient.clone()
+            .ok_or_else(|| -> Error { "no outgoing peers registered".into() })?;
+        // side effects are only sent once the block is committed
+        let (new_transactions, block_confirmations, panda_txs) = db.connection().transaction::<_, Error, _>(|| {
+            let history = TxHistory::from_block_txs(&msg.txs,
+                                                    Some(height),
+                                                    msg.header.timestamp as i64,
+                                                    &msg.config,
+                                                    db);
+            let new_transactions = _process_tx_history(db, &*self.chain_source, &msg.txs, history,
+                                                       timestamp as i64, &msg.subscribers)?;
+            let block_confirmations = db.confirm_block_txs(
+                &block_hash,
+                &msg.txs.iter().map(|tx| tx.hash()).collect::<Vec<_>>(),
+                height,
+                msg.header.timestamp as i64,
+                MAX_NOTIFIED_CONFIRMATIONS,
+            )?;
+            let panda_txs = self._birth_pandas(db, &msg.txs, &block_hash, timestamp as i64)?;
+            db.mark_block_processed(&block_hash, height, timestamp as i64)?;
+            Ok((new_transactions, block_confirmations, panda_txs))
+        })?;
+        if let Some(new_transactions) = new_transactions {
+            _broadcast_new_transactions(new_transactions, &msg.broadcasts);
+        }
+        _notify_confirmations(block_confirmations, &msg.subscribers);
+        for tx in panda_txs {
+            outgoing.do_send(OutgoingMsg(TxMessage { tx }.packet()))
+                .map_err(|err| eprintln!("Failed to send panda tx: {}", err))
+                .ok();
         }
         Ok(())
     }
@@ -1,15 +1,17 @@
 use actix::prelude::*;
 use std::collections::{HashSet, HashMap};
 use std::convert::identity;
+use futures::future::{self, Either};
 use slpdexdb_base::{Error, SLPDEXConfig};
-use slpdexdb_db::{Db, Utxo, TxDelta, TradeOffer};
+use slpdexdb_db::{Utxo, TxHistoryPage, TradeOffer, OrderBookSnapshot};
 use slpdexdb_node::actors::{IncomingMsg, OutgoingMsg};
 use slpdexdb_node::messages::{TxMessage, BlockMessage};
-use crate::msg::{ActivateAddress, DeactivateAddress, ResyncAddress, FetchAddressUtxos,
+use crate::msg::{ActivateAddress, DeactivateAddress, FetchAddressUtxos,
                  FetchAddressTxDeltas, FetchTradeOfferUtxos, SubscribeToEvent, UnsubscribeFromEvent,
-                 TxEvent, NewTransactions, ProcessTransactions, ProcessBlock};
-use crate::actors::ResyncActor;
-use crate::actors::broadcast_actor::{UpdateDbUtxosActor, BroadcastAddressUtxosActor,
+                 FetchOrderBook, TxEvent, NewTransactions, ProcessTransactions, ProcessBlock, ProcessReorg,
+                 AddMempoolTx, AddUnconfirmedTxs, MempoolBlock, SubscribeMempool, InvalidateOrderBooks};
+use crate::actors::{ResyncActor, DbReadActor, OrderBookActor, MempoolActor, MempoolAcceptance, RemoveDoubleSpentUtxosActor};
+use crate::actors::broadcast_actor::{BroadcastAddressUtxosActor,
                                      BroadcastTradeOfferUtxosActor, BroadcastTxHistoryActor,
                                      BroadcastActor};
 use slpdexdb_node::NodeMessage;
@@ -23,31 +25,39 @@ pub struct TxSubscribers {
 }
 
 pub struct TxActor {
-    db: Arc<Mutex<Db>>,
+    db_read: Addr<DbReadActor>,
+    order_books: Addr<OrderBookActor>,
     config: SLPDEXConfig,
     resync: Addr<ResyncActor>,
+    mempool: Addr<MempoolActor>,
     subscribers: Arc<Mutex<TxSubscribers>>,
     broadcasts: Vec<Recipient<NewTransactions>>,
 }
 
 impl TxActor {
-    pub fn start_with(db: Arc<Mutex<Db>>,
+    pub fn start_with(db_read: Addr<DbReadActor>,
                       config: SLPDEXConfig,
                       resync: Addr<ResyncActor>) -> Addr<Self> {
         let broadcast = BroadcastActor::start(BroadcastActor);
+        let order_books = OrderBookActor::start(OrderBookActor::new(db_read.clone()));
+        let subscribers = Arc::new(Mutex::new(TxSubscribers {
+            subscribers_address: HashMap::new(),
+            subscribers_token: HashMap::new(),
+        }));
         let broadcasts = vec![
-            UpdateDbUtxosActor::start(UpdateDbUtxosActor).recipient(),
             BroadcastAddressUtxosActor::start(BroadcastAddressUtxosActor::new(broadcast.clone())).recipient(),
-            BroadcastTradeOfferUtxosActor::start(BroadcastTradeOfferUtxosActor::new(broadcast.clone())).recipient(),
+            BroadcastTradeOfferUtxosActor::start(
+                BroadcastTradeOfferUtxosActor::new(broadcast.clone(), order_books.clone())
+            ).recipient(),
             BroadcastTxHistoryActor::start(BroadcastTxHistoryActor::new(broadcast.clone())).recipient(),
         ];
+        let mempool = MempoolActor::start(MempoolActor::new(db_read.clone()));
+        let remove_double_spent = RemoveDoubleSpentUtxosActor::start(
+            RemoveDoubleSpentUtxosActor::new(resync.clone(), order_books.clone(), broadcast.clone(), subscribers.clone())
+        );
+        mempool.do_send(SubscribeMempool(remove_double_spent.recipient()));
         Self::start(TxActor {
-            db, config, resync,
-            subscribers: Arc::new(Mutex::new(TxSubscribers {
-                subscribers_address: HashMap::new(),
-                subscribers_token: HashMap::new(),
-            })),
-            broadcasts,
+            db_read, order_books, config, resync, mempool, subscribers, broadcasts,
         })
     }
 }
@@ -68,17 +78,23 @@ impl Handler<IncomingMsg<TxMessage>> for TxActor {
 
     fn handle(&mut self, msg: IncomingMsg<TxMessage>, _ctx: &mut Self::Context) -> Self::Result {
         let tx = msg.0.tx.clone();
+        let resync = self.resync.clone();
+        let process_txs = ProcessTransactions {
+            subscribers: self.subscribers.clone(),
+            txs: vec![tx.clone()],
+            config: self.config.clone(),
+            broadcasts: self.broadcasts.clone(),
+        };
         Response::fut(
-            self.resync
-                .send(ProcessTransactions {
-                    db: self.db.clone(),
-                    subscribers: self.subscribers.clone(),
-                    txs: vec![tx],
-                    config: self.config.clone(),
-                    broadcasts: self.broadcasts.clone(),
-                })
+            self.mempool
+                .send(AddMempoolTx(tx))
                 .from_err()
                 .and_then(identity)
+                .and_then(move |acceptance| match acceptance {
+                    MempoolAcceptance::Accepted =>
+                        Either::A(resync.send(process_txs).from_err().and_then(identity)),
+                    _ => Either::B(future::ok(())),
+                })
         )
     }
 }
@@ -88,14 +104,14 @@ impl Handler<IncomingMsg<BlockMessage>> for TxActor {
     type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: IncomingMsg<BlockMessage>, _ctx: &mut Self::Context) -> Self::Result {
-        let hashes = msg.0.hashes.clone();
+        let txs = msg.0.txs.clone();
         let header = msg.0.header.clone();
+        self.mempool.do_send(MempoolBlock { block_hash: header.hash(), txs: txs.clone() });
         Response::fut(
             self.resync
                 .send(ProcessBlock {
-                    db: self.db.clone(),
                     subscribers: self.subscribers.clone(),
-                    tx_hashes: hashes,
+                    txs,
                     header,
                     config: self.config.clone(),
                     broadcasts: self.broadcasts.clone(),
@@ -110,40 +126,31 @@ impl Handler<ActivateAddress> for TxActor {
     type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: ActivateAddress, _ctx: &mut Self::Context) -> Self::Result {
-        let ActivateAddress(address) = msg;
-        let resync = self.resync.clone();
-        Response::fut(
-            futures::future::result(self.db.lock().unwrap().set_address_active(&address, true)).from_err()
-                .and_then(move |_| resync.send(ResyncAddress(address)).from_err())
-                .and_then(identity)
-        )
+        Response::fut(self.resync.send(msg).from_err().and_then(identity))
     }
 }
 
 impl Handler<DeactivateAddress> for TxActor {
-    type Result = Result<(), Error>;
+    type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: DeactivateAddress, _ctx: &mut Self::Context) -> Self::Result {
-        let DeactivateAddress(address) = msg;
-        Ok(self.db.lock().unwrap().set_address_active(&address, false)?)
+        Response::fut(self.resync.send(msg).from_err().and_then(identity))
     }
 }
 
 impl Handler<FetchAddressUtxos> for TxActor {
-    type Result = Result<Vec<Utxo>, Error>;
+    type Result = Response<Vec<Utxo>, Error>;
 
     fn handle(&mut self, msg: FetchAddressUtxos, _ctx: &mut Self::Context) -> Self::Result {
-        let FetchAddressUtxos(address) = msg;
-        Ok(self.db.lock().unwrap().utxos_address(&address)?)
+        Response::fut(self.db_read.send(msg).from_err().and_then(identity))
     }
 }
 
 impl Handler<FetchAddressTxDeltas> for TxActor {
-    type Result = Result<Vec<TxDelta>, Error>;
+    type Result = Response<TxHistoryPage, Error>;
 
     fn handle(&mut self, msg: FetchAddressTxDeltas, _ctx: &mut Self::Context) -> Self::Result {
-        let FetchAddressTxDeltas(address) = msg;
-        Ok(self.db.lock().unwrap().address_tx_deltas(&address)?)
+        Response::fut(self.db_read.send(msg).from_err().and_then(identity))
     }
 }
 
@@ -188,10 +195,46 @@ impl Handler<UnsubscribeFromEvent> for TxActor {
 }
 
 impl Handler<FetchTradeOfferUtxos> for TxActor {
-    type Result = Result<Vec<TradeOffer>, Error>;
+    type Result = Response<Vec<TradeOffer>, Error>;
 
     fn handle(&mut self, msg: FetchTradeOfferUtxos, _ctx: &mut Self::Context) -> Self::Result {
-        let FetchTradeOfferUtxos(filter) = msg;
-        Ok(self.db.lock().unwrap().trade_offer_utxos(filter)?)
+        Response::fut(self.db_read.send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<FetchOrderBook> for TxActor {
+    type Result = Response<OrderBookSnapshot, Error>;
+
+    fn handle(&mut self, msg: FetchOrderBook, _ctx: &mut Self::Context) -> Self::Result {
+        Response::fut(self.order_books.send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<ProcessReorg> for TxActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: ProcessReorg, _ctx: &mut Self::Context) -> Self::Result {
+        let ProcessReorg(reorg) = msg;
+        self.mempool.do_send(AddUnconfirmedTxs(reorg.unconfirmed_txs.clone()));
+        self.order_books.do_send(InvalidateOrderBooks(reorg.token_txs.keys().cloned().collect()));
+        let subscribers = self.subscribers.lock().unwrap();
+        let address_events = reorg.address_txs.iter()
+            .filter_map(|(address, tx_hashes)| {
+                Some((subscribers.subscribers_address.get(address)?, tx_hashes))
+            });
+        let token_events = reorg.token_txs.iter()
+            .filter_map(|(token_hash, tx_hashes)| {
+                Some((subscribers.subscribers_token.get(token_hash)?, tx_hashes))
+            });
+        for (recipients, tx_hashes) in address_events.chain(token_events) {
+            let tx_hashes = Arc::new(tx_hashes.clone());
+            for recipient in recipients.iter() {
+                recipient.do_send(TxEvent::Rollback {
+                    fork_height: reorg.fork_height,
+                    tx_hashes: tx_hashes.clone(),
+                }).map_err(|err| eprintln!("Failed to send rollback: {}", err)).ok();
+            }
+        }
+        Ok(())
     }
 }
@@ -2,12 +2,14 @@ use actix::prelude::*;
 use cashcontracts::{Address, tx_hash_to_hex, tx_hex_to_hash};
 use std::convert::identity;
 use actix_web_actors::ws;
-use slpdexdb_base::{Error, convert_numeric};
+use futures::future;
+use slpdexdb_base::{Error, ErrorKind, TokenError, Network, convert_numeric};
 use serde::Deserialize;
 use json::{object, JsonValue, stringify};
 use std::sync::Arc;
+use slpdexdb_db::{TxDelta, TxHistoryFilter, TxHistoryCursor, TxHistoryPage};
 use crate::actors::TxActor;
-use crate::msg::{ActivateAddress, FetchAddressUtxos, FetchAddressTxDeltas, SubscribeToEvent,
+use crate::msg::{ActivateAddress, FetchAddressUtxos, FetchAddressTxDeltas, FetchOrderBook, SubscribeToEvent,
                  UnsubscribeFromEvent, TxEvent};
 
 
@@ -17,21 +19,61 @@ pub enum WsIncomingMessage {
     ListenToTokens {
         #[serde(rename = "tokenIdsHex")]
         token_ids_hex: Vec<String>,
-    }
+    },
+    FetchTxHistory {
+        cursor: Option<WsTxHistoryCursor>,
+        #[serde(rename = "tokenIdHex")]
+        token_id_hex: Option<String>,
+        #[serde(rename = "bchOnly", default)]
+        bch_only: bool,
+        confirmed: Option<bool>,
+        limit: Option<i64>,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct WsTxHistoryCursor {
+    timestamp: i64,
+    tx: String,
 }
 
 impl Message for WsIncomingMessage {
     type Result = Result<(), Error>;
 }
 
+const TX_HISTORY_PAGE_SIZE: i64 = 100;
+const TX_HISTORY_MAX_PAGE_SIZE: i64 = 1000;
+
+struct SendTxHistoryPage(TxHistoryPage);
+
+impl Message for SendTxHistoryPage {
+    type Result = ();
+}
+
+fn tx_deltas_json(tx_deltas: &[TxDelta]) -> JsonValue {
+    JsonValue::Array(
+        tx_deltas.iter()
+            .map(|tx_delta| object!{
+                "tx" => tx_hash_to_hex(&tx_delta.tx_hash),
+                "deltaSatoshis" => tx_delta.delta_satoshis,
+                "deltaToken" => format!("{}", tx_delta.delta_token),
+                "deltaTokenBase" => tx_delta.delta_token.base_amount().to_string(),
+                "tokenIdHex" => tx_delta.token_hash.map(|token| tx_hash_to_hex(&token)),
+                "timestamp" => tx_delta.timestamp,
+            })
+            .collect()
+    )
+}
+
 pub struct WsActor {
     address: Address,
     tx: Addr<TxActor>,
+    network: Network,
 }
 
 impl WsActor {
-    pub fn new(address: Address, tx: Addr<TxActor>) -> Self {
-        WsActor { address, tx }
+    pub fn new(address: Address, tx: Addr<TxActor>, network: Network) -> Self {
+        WsActor { address, tx, network }
     }
 }
 
@@ -58,10 +100,16 @@ impl Actor for WsActor {
                                                 remove_utxos: Arc::new(vec![]) }
                 ).from_err())
                 .and_then(move |_| {
-                    tx2.send(FetchAddressTxDeltas(address2)).from_err().and_then(identity)
+                    tx2.send(FetchAddressTxDeltas {
+                        address: address2,
+                        filter: TxHistoryFilter::default(),
+                        cursor: None,
+                        limit: TX_HISTORY_PAGE_SIZE,
+                    }).from_err().and_then(identity)
                 })
-                .and_then(move |tx_deltas| own_address2.send(
-                    TxEvent::AddressNewTxDeltas { tx_deltas: Arc::new(tx_deltas) }
+                // the initial frame keeps the TxHistory format, older pages are fetched with FetchTxHistory
+                .and_then(move |page| own_address2.send(
+                    TxEvent::AddressNewTxDeltas { tx_deltas: Arc::new(page.tx_deltas) }
                 ).from_err())
                 .and_then(move |_| {
                     tx3.send(SubscribeToEvent::Address(address3, own_address3.recipient()))
@@ -149,7 +197,7 @@ impl Handler<TxEvent> for WsActor {
                                     "isInverted" => trade_offer.is_inverted,
                                     "sellAmountTokenBase" => trade_offer.sell_amount_token
                                         .base_amount().to_string(),
-                                    "receivingAddress" => trade_offer.receiving_address.cash_addr(),
+                                    "receivingAddress" => self.network.cash_addr(&trade_offer.receiving_address),
                                     "tokenIdHex" => tx_hash_to_hex(&token_hash),
                                 })
                                 .collect()
@@ -169,15 +217,83 @@ impl Handler<TxEvent> for WsActor {
                 ctx.text(stringify(
                     object!{
                         "type" => "TxHistory",
-                        "addTxHistory" => JsonValue::Array(
-                            tx_deltas.iter()
-                                .map(|tx_delta| object!{
-                                    "tx" => tx_hash_to_hex(&tx_delta.tx_hash),
-                                    "deltaSatoshis" => tx_delta.delta_satoshis,
-                                    "deltaToken" => format!("{}", tx_delta.delta_token),
-                                    "deltaTokenBase" => tx_delta.delta_token.base_amount().to_string(),
-                                    "tokenIdHex" => tx_delta.token_hash.map(|token| tx_hash_to_hex(&token)),
-                                    "timestamp" => tx_delta.timestamp,
+                        "addTxHistory" => tx_deltas_json(&tx_deltas),
+                    }
+                ))
+            },
+            TxEvent::Rollback { fork_height, tx_hashes } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "Rollback",
+                        "forkHeight" => fork_height,
+                        "txs" => JsonValue::Array(
+                            tx_hashes.iter()
+                                .map(|tx_hash| tx_hash_to_hex(tx_hash).into())
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::Confirmations { block_hash, height, txs } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "Confirmations",
+                        "blockHash" => tx_hash_to_hex(&block_hash),
+                        "height" => height,
+                        "txs" => JsonValue::Array(
+                            txs.iter()
+                                .map(|confirmation| object!{
+                                    "txHash" => tx_hash_to_hex(&confirmation.tx_hash),
+                                    "height" => confirmation.height,
+                                    "timestamp" => confirmation.timestamp,
+                                    "confirmations" => confirmation.confirmations,
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::OrderBook { snapshot } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "OrderBook",
+                        "tokenIdHex" => tx_hash_to_hex(&snapshot.token_hash),
+                        "sequence" => snapshot.sequence,
+                        "bestAsk" => snapshot.best_ask.clone()
+                            .map(|price| format!("{}", convert_numeric::PrettyRational(price))),
+                        "asks" => JsonValue::Array(
+                            snapshot.asks.iter()
+                                .map(|level| object!{
+                                    "pricePerToken" => format!("{}", convert_numeric::PrettyRational(
+                                        level.price_per_token.clone()
+                                    )),
+                                    "amountToken" => format!("{}", level.amount_token),
+                                    "amountTokenBase" => level.amount_token.base_amount().to_string(),
+                                    "cumulativeAmountToken" => format!("{}", level.cumulative_amount_token),
+                                    "cumulativeAmountTokenBase" => level.cumulative_amount_token
+                                        .base_amount().to_string(),
+                                    "numOffers" => level.num_offers,
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::OrderBookDiff { diff } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "OrderBookDiff",
+                        "tokenIdHex" => tx_hash_to_hex(&diff.token_hash),
+                        "sequence" => diff.sequence,
+                        "asks" => JsonValue::Array(
+                            diff.asks.iter()
+                                .map(|level| object!{
+                                    "pricePerToken" => format!("{}", convert_numeric::PrettyRational(
+                                        level.price_per_token.clone()
+                                    )),
+                                    "amountToken" => format!("{}", level.amount_token),
+                                    "amountTokenBase" => level.amount_token.base_amount().to_string(),
+                                    "numOffers" => level.num_offers,
                                 })
                                 .collect()
                         ),
@@ -198,13 +314,76 @@ impl Handler<WsIncomingMessage> for WsActor {
                 println!("subscribe to {:?}", token_ids_hex);
                 let token_hashes = token_ids_hex.iter()
                     .filter_map(|token_hash| tx_hex_to_hash(token_hash))
-                    .collect();
+                    .collect::<Vec<_>>();
+                let fetch_order_books = token_hashes.iter()
+                    .map(|token_hash| {
+                        self.tx.send(FetchOrderBook(token_hash.clone())).from_err().and_then(identity)
+                    })
+                    .collect::<Vec<_>>();
+                let own_address = ctx.address();
                 Response::fut(
                     self.tx
                         .send(SubscribeToEvent::Tokens(token_hashes, ctx.address().recipient()))
                         .from_err()
+                        .and_then(move |_| future::join_all(fetch_order_books))
+                        .map(move |snapshots| {
+                            for snapshot in snapshots {
+                                own_address.do_send(TxEvent::OrderBook { snapshot: Arc::new(snapshot) });
+                            }
+                        })
                 )
-            }
+            },
+            WsIncomingMessage::FetchTxHistory { cursor, token_id_hex, bch_only, confirmed, limit } => {
+                let cursor = match cursor {
+                    Some(cursor) => match tx_hex_to_hash(&cursor.tx) {
+                        Some(tx_hash) => Some(TxHistoryCursor { timestamp: cursor.timestamp, tx_hash }),
+                        None => return Response::reply(Err(
+                            ErrorKind::InvalidRequest(format!("invalid cursor tx {}", cursor.tx)).into()
+                        )),
+                    },
+                    None => None,
+                };
+                let token_hash = match token_id_hex {
+                    Some(token_id_hex) => match tx_hex_to_hash(&token_id_hex) {
+                        Some(token_hash) => Some(token_hash),
+                        None => return Response::reply(Err(
+                            ErrorKind::TokenError(TokenError::InvalidTokenHex(token_id_hex)).into()
+                        )),
+                    },
+                    None => None,
+                };
+                let own_address = ctx.address();
+                Response::fut(
+                    self.tx
+                        .send(FetchAddressTxDeltas {
+                            address: self.address.clone(),
+                            filter: TxHistoryFilter { token_hash, bch_only, confirmed },
+                            cursor,
+                            limit: limit.unwrap_or(TX_HISTORY_PAGE_SIZE).max(1).min(TX_HISTORY_MAX_PAGE_SIZE),
+                        })
+                        .from_err()
+                        .and_then(identity)
+                        .and_then(move |page| own_address.send(SendTxHistoryPage(page)).from_err())
+                )
+            },
         }
     }
 }
+
+impl Handler<SendTxHistoryPage> for WsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendTxHistoryPage, ctx: &mut Self::Context) -> Self::Result {
+        let SendTxHistoryPage(page) = msg;
+        ctx.text(stringify(
+            object!{
+                "type" => "TxHistoryPage",
+                "txHistory" => tx_deltas_json(&page.tx_deltas),
+                "nextCursor" => page.next_cursor.map(|cursor| object!{
+                    "timestamp" => cursor.timestamp,
+                    "tx" => tx_hash_to_hex(&cursor.tx_hash),
+                }),
+            }
+        ))
+    }
+}
@@ -1,75 +1,222 @@
 mod actors;
 mod msg;
 
-use std::str::FromStr;
-use std::net;
-use std::sync::{Arc, Mutex};
 use actix::prelude::*;
-use diesel::prelude::*;
 
 use actix_web::{middleware, web, App, HttpResponse, HttpRequest, HttpServer};
 use actix_web_actors::ws;
 
-use cashcontracts::{Address};
-use slpdexdb_base::SLPDEXConfig;
-use slpdexdb_db::Db;
-use crate::actors::{TxActor, ResyncActor, PeersActor, WsActor};
-use crate::msg::{ConnectToPeer, RegisterOutgoing};
+use cashcontracts::{Address, tx_hash_to_hex, tx_hex_to_hash};
+use futures::{Future, future::{self, Either}};
+use json::{object, JsonValue, stringify};
+use slpdexdb_base::{Error, Settings, DatabaseSettings, ChainSourceSettings, Network};
+use slpdexdb_db::{Db, DbPool, create_pool, Endpoint, ChainSource, SlpdbChainSource, FixtureChainSource, RpcChainSource};
+use crate::actors::{TxActor, ResyncActor, DbReadActor, PeersActor, PeersConfig, WsActor, BackfillActor, GetPeerStats};
+use crate::msg::{RegisterOutgoing, FetchTokenStats};
+use slpdexdb_node::GetHeaderSyncStatus;
 
-pub fn connect_db() -> Db {
-    let connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL");
-    let connection = PgConnection::establish(&connection_str).unwrap();
-    Db::new(connection)
+pub fn connect_db_pool(settings: &DatabaseSettings) -> Result<DbPool, Error> {
+    create_pool(&settings.url, settings.pool_size)
+}
+
+pub fn connect_chain_source(settings: &ChainSourceSettings) -> Result<Box<dyn ChainSource>, Error> {
+    Ok(match settings {
+        ChainSourceSettings::Slpdb { bitdb_url, slpdb_url } => {
+            let default_endpoint = Endpoint::new();
+            Box::new(SlpdbChainSource::new(Endpoint::with_urls(
+                bitdb_url.clone().unwrap_or(default_endpoint.bitdb_endpoint_url),
+                slpdb_url.clone().unwrap_or(default_endpoint.slpdb_endpoint_url),
+            )))
+        },
+        ChainSourceSettings::Fixture { path } => {
+            Box::new(FixtureChainSource::from_file(path)?)
+        },
+        ChainSourceSettings::Rpc { url, user, password } => Box::new(RpcChainSource::new(
+            url.clone(),
+            user.clone(),
+            password.clone(),
+        )),
+    })
 }
 
 fn index(r: HttpRequest,
          stream: web::Payload,
          path: web::Path<(String,)>,
-         tx: web::Data<Addr<TxActor>>) -> Result<HttpResponse, actix_web::Error> {
+         tx: web::Data<Addr<TxActor>>,
+         network: web::Data<Network>) -> Result<HttpResponse, actix_web::Error> {
     let address_str = &path.0;
     eprintln!("connect to address {}", address_str);
     let address = Address::from_cash_addr(address_str.clone()).unwrap();  // TODO: handle error
     //Ok(HttpResponse::Ok().body("Hello"))
-    ws::start(WsActor::new(address, tx.get_ref().clone()), &r, stream)
+    ws::start(WsActor::new(address, tx.get_ref().clone(), network.get_ref().clone()), &r, stream)
+}
+
+fn header_sync_status(db: web::Data<Addr<slpdexdb_node::DbActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    db.send(GetHeaderSyncStatus)
+        .map_err(actix_web::error::ErrorInternalServerError)
+        .map(|status| {
+            let body = match status {
+                Some(status) => object!{
+                    "tipHeight" => status.tip_height,
+                    "tipHash" => tx_hash_to_hex(&status.tip_hash),
+                    "peerHeight" => status.best_peer_height,
+                    "progress" => status.progress(),
+                    "synced" => status.is_synced(),
+                },
+                None => JsonValue::Null,
+            };
+            HttpResponse::Ok().content_type("application/json").body(stringify(body))
+        })
+}
+
+fn token_stats(path: web::Path<(String,)>, db_read: web::Data<Addr<DbReadActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    let token_hash = match tx_hex_to_hash(&path.0) {
+        Some(token_hash) => token_hash,
+        None => return Either::A(future::ok::<_, actix_web::Error>(HttpResponse::BadRequest().body("invalid token id"))),
+    };
+    Either::B(db_read.send(FetchTokenStats(token_hash))
+        .map_err(actix_web::error::ErrorInternalServerError)
+        .and_then(|stats| stats.map_err(actix_web::error::ErrorInternalServerError))
+        .map(|stats| {
+            let body = match stats {
+                Some(stats) => object!{
+                    "holderCount" => stats.holder_count,
+                    "txCount" => stats.tx_count,
+                    "utxoCount" => stats.utxo_count,
+                    "lastActiveHeight" => stats.last_active_height,
+                    "lastUpdatedHeight" => stats.last_updated_height,
+                },
+                None => JsonValue::Null,
+            };
+            HttpResponse::Ok().content_type("application/json").body(stringify(body))
+        }))
+}
+
+fn peer_status(peers: web::Data<Addr<PeersActor>>) -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    peers.send(GetPeerStats)
+        .map_err(actix_web::error::ErrorInternalServerError)
+        .and_then(|stats| stats.map_err(actix_web::error::ErrorInternalServerError))
+        .map(|stats| {
+            let body = JsonValue::Array(stats.into_iter()
+                .map(|stats| object!{
+                    "peerAddr" => stats.peer_addr.to_string(),
+                    "latencyMs" => stats.latency.map(|latency| latency.as_millis() as u64),
+                    "misbehaviourScore" => stats.misbehaviour_score,
+                })
+                .collect());
+            HttpResponse::Ok().content_type("application/json").body(stringify(body))
+        })
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
     env_logger::init();
-    let port = std::env::var("PORT").unwrap_or("7501".to_string());
+    let (settings, args) = Settings::load(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    });
+    // utxos and token holders are kept up to date incrementally; this only repairs inconsistent tables
+    if args.first().map(String::as_str) == Some("repair-utxos") {
+        let db = Db::from_pool(&connect_db_pool(&settings.database)?)?;
+        db.rebuild_utxo_sets()?;
+        db.rebuild_token_holders()?;
+        return Ok(());
+    }
+    // import-headers <file> [start height]: raw 80 byte headers, e.g. from an Electrum headers file
+    if args.first().map(String::as_str) == Some("import-headers") {
+        let path = args.get(1).ok_or("usage: import-headers <file> [start height]")?;
+        let start_height = args.get(2).map(|height| height.parse()).transpose()?.unwrap_or(0);
+        let db = Db::from_pool(&connect_db_pool(&settings.database)?)?;
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let n_imported = db.import_headers(&mut file, start_height, &settings.slpdex.network)?;
+        println!("Imported {} headers", n_imported);
+        return Ok(());
+    }
+    // export-checkpoint <height>: bootstrap checkpoint to embed in slpdexdb_base/checkpoints
+    if args.first().map(String::as_str) == Some("export-checkpoint") {
+        let height = args.get(1).ok_or("usage: export-checkpoint <height>")?.parse()?;
+        let db = Db::from_pool(&connect_db_pool(&settings.database)?)?;
+        print!("{}", db.bootstrap_checkpoint(height, &settings.slpdex.network)?);
+        return Ok(());
+    }
+    let secret = settings.endpoint.secret().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }).to_vec();
+    // fail before starting any actors, e.g. on a malformed fixture
+    connect_chain_source(&settings.endpoint.chain_source)?;
+    let network = settings.slpdex.network.clone();
+    let bind = settings.endpoint.bind;
+    let pool = connect_db_pool(&settings.database)?;
+    let peers = {
+        let db = Db::from_pool(&pool)?;
+        db.init_headers(&network)?;
+        db.peers()?
+    };
     actix::System::run(move || {
-        let secret = hex::decode(std::env::var("SECRET").unwrap()).unwrap();
+        let resync_pool = pool.clone();
+        let resync_config = settings.slpdex.clone();
+        let chain_source = settings.endpoint.chain_source.clone();
         let resync_addr = SyncArbiter::start(1, move || {
-            ResyncActor::new(connect_db(), SLPDEXConfig::default(), secret.clone())
+            let chain_source = connect_chain_source(&chain_source).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(2);
+            });
+            ResyncActor::new(resync_pool.clone(), resync_config.clone(), secret.clone(), chain_source)
         });
-        let db_addr = actors::DbActor::create().unwrap();
-        let db_addr = slpdexdb_node::DbActor::start(slpdexdb_node::DbActor {
-            add_header_query: db_addr.clone().recipient(),
-            header_tip_query: db_addr.recipient(),
+        let read_pool = pool.clone();
+        let db_read_addr = SyncArbiter::start(settings.endpoint.read_workers, move || DbReadActor::new(read_pool.clone()));
+        let tx_addr = TxActor::start_with(db_read_addr.clone(), settings.slpdex.clone(), resync_addr.clone());
+        let header_pool = pool.clone();
+        let header_network = network.clone();
+        let reorg_recipient = tx_addr.clone().recipient();
+        let db_writer_addr = SyncArbiter::start(1, move || {
+            actors::DbActor::new(header_pool.clone(), header_network.clone(), reorg_recipient.clone())
         });
-        let tx_addr = TxActor::start_with(Arc::new(Mutex::new(connect_db())), SLPDEXConfig::default(), resync_addr.clone());
-        let peers_addr = PeersActor::start(PeersActor::new(tx_addr.clone(), db_addr));
+        let db_addr = slpdexdb_node::DbActor::start(slpdexdb_node::DbActor::new(
+            db_writer_addr.clone().recipient(),
+            db_writer_addr.clone().recipient(),
+            db_writer_addr.clone().recipient(),
+        ));
+        let header_sync_addr = db_addr.clone();
+        let peers_config = PeersConfig {
+            network: network.clone(),
+            seed_peers: settings.endpoint.seed_peers.clone(),
+            dns_seeds: network.dns_seeds.iter().map(|seed| seed.to_string()).collect(),
+            default_port: network.default_port,
+            target_outbound: settings.endpoint.target_outbound,
+            ban_threshold: settings.endpoint.ban_threshold,
+            ban_duration_secs: settings.endpoint.ban_duration_secs,
+        };
+        let peers_addr = PeersActor::start(PeersActor::new(tx_addr.clone(), db_addr, db_writer_addr, peers, peers_config));
         resync_addr.do_send(RegisterOutgoing {recipient: peers_addr.clone().recipient()} );
-        //let socket_addr = net::SocketAddr::from_str("147.135.131.25:8333").unwrap();
-        //let socket_addr = net::SocketAddr::from_str("100.1.209.114:8333").unwrap();
-        let socket_addr = net::SocketAddr::from_str("137.74.30.99:8333").unwrap();
-
-        Arbiter::spawn(
-            peers_addr.send(ConnectToPeer { socket_addr })
-                .from_err()
-                .and_then(|x| x)
-                .map_err(|err| {eprintln!("{}", err);})
-        );
+        BackfillActor::new(db_read_addr.clone(), peers_addr.clone()).start();
+        let peer_status_addr = peers_addr.clone();
 
         HttpServer::new(move || {
             App::new()
                 .wrap(middleware::Logger::default())
                 .data(tx_addr.clone())
+                .data(network.clone())
+                .data(header_sync_addr.clone())
+                .data(peer_status_addr.clone())
+                .data(db_read_addr.clone())
                 .service(
                     web::resource("/ws/{address}").route(web::get().to(index))
                 )
+                .service(
+                    web::resource("/status/headers").route(web::get().to_async(header_sync_status))
+                )
+                .service(
+                    web::resource("/status/peers").route(web::get().to_async(peer_status))
+                )
+                .service(
+                    web::resource("/tokens/{token_id}/stats").route(web::get().to_async(token_stats))
+                )
         })
-            .bind(format!("127.0.0.1:{}", port)).unwrap()
+            .bind(bind).unwrap()
             .start();
     })?;
     Ok(())
@@ -3,11 +3,13 @@ use cashcontracts::Address;
 use slpdexdb_base::Error;
 use std::net;
 use slpdexdb_base::{SLPDEXConfig, BlockHeader};
-use slpdexdb_db::{Db, Utxo, SpentUtxo, TxDelta, TradeOfferFilter, TradeOffer, TxHistory};
+use slpdexdb_db::{Utxo, SpentUtxo, TxDelta, TxHistoryFilter, TxHistoryCursor, TxHistoryPage,
+                  TradeOfferFilter, TradeOffer, TxHistory, ChainReorg, TxConfirmation, OrderBookSnapshot,
+                  OrderBookDiff, UnconfirmedTx, DoubleSpentUtxos, TokenStats, models};
 use slpdexdb_node::actors::{OutgoingMsg};
 use std::collections::{HashSet, HashMap};
 use std::sync::{Arc, Mutex};
-use crate::actors::TxSubscribers;
+use crate::actors::{TxSubscribers, MempoolAcceptance};
 
 
 pub struct ConnectToPeer {
@@ -36,22 +38,63 @@ impl Message for ResyncAddress {
     type Result = Result<(), Error>;
 }
 
+pub struct FetchUnprocessedBlocks(pub i64);
+
+impl Message for FetchUnprocessedBlocks {
+    type Result = Result<Vec<([u8; 32], i32)>, Error>;
+}
+
+pub struct FetchUnconfirmedTxs;
+
+impl Message for FetchUnconfirmedTxs {
+    type Result = Result<Vec<UnconfirmedTx>, Error>;
+}
+
+pub struct PersistPeers(pub Vec<models::Peer>);
+
+impl Message for PersistPeers {
+    type Result = Result<(), Error>;
+}
+
+pub struct RemoveDoubleSpentTxs(pub Arc<Vec<[u8; 32]>>);
+
+impl Message for RemoveDoubleSpentTxs {
+    type Result = Result<DoubleSpentUtxos, Error>;
+}
+
 pub struct FetchTradeOfferUtxos(pub TradeOfferFilter);
 
 impl Message for FetchTradeOfferUtxos {
     type Result = Result<Vec<TradeOffer>, Error>;
 }
 
+pub struct FetchOrderBook(pub [u8; 32]);
+
+impl Message for FetchOrderBook {
+    type Result = Result<OrderBookSnapshot, Error>;
+}
+
 pub struct FetchAddressUtxos(pub Address);
 
 impl Message for FetchAddressUtxos {
     type Result = Result<Vec<Utxo>, Error>;
 }
 
-pub struct FetchAddressTxDeltas(pub Address);
+pub struct FetchTokenStats(pub [u8; 32]);
+
+impl Message for FetchTokenStats {
+    type Result = Result<Option<TokenStats>, Error>;
+}
+
+pub struct FetchAddressTxDeltas {
+    pub address: Address,
+    pub filter: TxHistoryFilter,
+    pub cursor: Option<TxHistoryCursor>,
+    pub limit: i64,
+}
 
 impl Message for FetchAddressTxDeltas {
-    type Result = Result<Vec<TxDelta>, Error>;
+    type Result = Result<TxHistoryPage, Error>;
 }
 
 pub enum SubscribeToEvent {
@@ -85,6 +128,21 @@ pub enum TxEvent {
     AddressNewTxDeltas {
         tx_deltas: Arc<Vec<TxDelta>>,
     },
+    Rollback {
+        fork_height: i32,
+        tx_hashes: Arc<Vec<[u8; 32]>>,
+    },
+    Confirmations {
+        block_hash: [u8; 32],
+        height: i32,
+        txs: Arc<Vec<TxConfirmation>>,
+    },
+    OrderBook {
+        snapshot: Arc<OrderBookSnapshot>,
+    },
+    OrderBookDiff {
+        diff: Arc<OrderBookDiff>,
+    },
 }
 
 impl Message for TxEvent {
@@ -114,11 +172,29 @@ impl Message for TxBroadcastEvent {
     type Result = Result<(), Error>;
 }
 
+pub struct UpdateOrderBooks {
+    pub add_utxos: HashMap<[u8; 32], Vec<TradeOffer>>,
+    pub remove_utxos: HashMap<[u8; 32], Vec<SpentUtxo>>,
+    pub subscribers: SyncTxSubscribers,
+}
+
+impl Message for UpdateOrderBooks {
+    type Result = Result<(), Error>;
+}
+
+// drops cached books whose offers may have changed in ways not covered by UpdateOrderBooks
+pub struct InvalidateOrderBooks(pub Vec<[u8; 32]>);
+
+impl Message for InvalidateOrderBooks {
+    type Result = ();
+}
+
 #[derive(Clone)]
 pub struct NewTransactions {
     pub now: i64,
-    pub db: Arc<Mutex<Db>>,
     pub tx_history: Arc<TxHistory>,
+    // outputs spent by the txs from relevant addresses
+    pub input_outputs: Arc<HashMap<([u8; 32], i32), models::TxOutput>>,
     pub relevant_addresses: Arc<HashSet<Address>>,
     pub subscribers: SyncTxSubscribers,
 }
@@ -129,7 +205,6 @@ impl Message for NewTransactions {
 
 pub struct ProcessTransactions {
     pub txs: Vec<cashcontracts::Tx>,
-    pub db: Arc<Mutex<Db>>,
     pub config: SLPDEXConfig,
     pub subscribers: Arc<Mutex<TxSubscribers>>,
     pub broadcasts: Vec<Recipient<NewTransactions>>,
@@ -141,8 +216,7 @@ impl Message for ProcessTransactions {
 
 pub struct ProcessBlock {
     pub header: BlockHeader,
-    pub tx_hashes: Vec<[u8; 32]>,
-    pub db: Arc<Mutex<Db>>,
+    pub txs: Vec<cashcontracts::Tx>,
     pub config: SLPDEXConfig,
     pub subscribers: Arc<Mutex<TxSubscribers>>,
     pub broadcasts: Vec<Recipient<NewTransactions>>,
@@ -152,6 +226,62 @@ impl Message for ProcessBlock {
     type Result = Result<(), Error>;
 }
 
+pub struct ProcessReorg(pub ChainReorg);
+
+impl Message for ProcessReorg {
+    type Result = Result<(), Error>;
+}
+
+pub struct AddMempoolTx(pub cashcontracts::Tx);
+
+impl Message for AddMempoolTx {
+    type Result = Result<MempoolAcceptance, Error>;
+}
+
+pub struct AddUnconfirmedTxs(pub Vec<UnconfirmedTx>);
+
+impl Message for AddUnconfirmedTxs {
+    type Result = ();
+}
+
+pub struct MempoolBlock {
+    pub block_hash: [u8; 32],
+    pub txs: Vec<cashcontracts::Tx>,
+}
+
+impl Message for MempoolBlock {
+    type Result = Result<(), Error>;
+}
+
+#[derive(Clone)]
+pub enum MempoolEvent {
+    Conflict {
+        tx_hash: [u8; 32],
+        conflicting_txs: Arc<Vec<[u8; 32]>>,
+    },
+    Confirmed {
+        block_hash: [u8; 32],
+        tx_hashes: Arc<Vec<[u8; 32]>>,
+    },
+    DoubleSpent {
+        block_hash: [u8; 32],
+        tx_hashes: Arc<Vec<[u8; 32]>>,
+    },
+    Expired {
+        tx_hashes: Arc<Vec<[u8; 32]>>,
+    },
+}
+
+impl Message for MempoolEvent {
+    type Result = Result<(), Error>;
+}
+
+pub struct SubscribeMempool(pub Recipient<MempoolEvent>);
+
+impl Message for SubscribeMempool {
+    type Result = ();
+}
+
 pub struct RegisterOutgoing {
     pub recipient: Recipient<OutgoingMsg>,
 }
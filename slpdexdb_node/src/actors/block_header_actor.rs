@@ -1,15 +1,36 @@
 use actix::prelude::*;
-use slpdexdb_base::Error;
-use crate::messages::{HeadersMessage, GetDataMessage, GetHeadersMessage};
+use std::convert::identity;
+use std::net::SocketAddr;
+use slpdexdb_base::{Error, ErrorKind, HeaderError, Network, check_header_batch};
+use crate::messages::{HeadersMessage, GetHeadersMessage, GetDataMessage, InvVector, ObjectType};
 use crate::message::NodeMessage;
 use crate::message_packet::MessagePacket;
-use crate::actors::{NodeActor, IncomingMsg, OutgoingMsg};
-use crate::db_query::{DbActor, HeaderTipQuery, AddHeadersQuery};
-use crate::msg::{Subscribe, HandshakeSuccess};
+use crate::actors::{NodeActor, IncomingMsg, OutgoingMsg, INVALID_DATA_SCORE};
+use crate::db_query::{DbActor, HeaderTip, BlockLocatorQuery, AddHeadersQuery, HeaderTipQuery, UpdateHeaderSync};
+use crate::msg::{Subscribe, HandshakeSuccess, Misbehaving};
+
+// peers send at most this many headers per message, a full batch means there are more
+const MAX_HEADERS_RESULTS: usize = 2000;
 
 pub struct BlockHeaderActor {
     pub db: Addr<DbActor>,
     pub node: Addr<NodeActor>,
+    pub network: Network,
+    peer_addr: Option<SocketAddr>,
+    peer_height: i32,
+    getheaders_pending: bool,
+}
+
+// sent to self once a headers batch has been stored
+struct HeadersAdded {
+    tip: HeaderTip,
+    block_hashes: Vec<[u8; 32]>,
+    full_batch: bool,
+    requested: bool,
+}
+
+impl Message for HeadersAdded {
+    type Result = Result<(), Error>;
 }
 
 impl Actor for BlockHeaderActor {
@@ -19,39 +40,71 @@ impl Actor for BlockHeaderActor {
         self.node.do_send(Subscribe::HandshakeSuccess(ctx.address().recipient()));
         self.node.do_send(Subscribe::Headers(ctx.address().recipient()));
     }
-}
 
-fn dump_err(err: Error) {
-    eprintln!("Error: {}", err);
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(peer_addr) = self.peer_addr {
+            self.db.do_send(UpdateHeaderSync::PeerDisconnected(peer_addr));
+        }
+    }
 }
 
 impl BlockHeaderActor {
-    fn _fetch_headers(db: Addr<DbActor>, node: Addr<NodeActor>)
-            -> impl futures::Future<Item=(), Error=Error> {
-        db.send(HeaderTipQuery).from_err()
-            .and_then(|x| x)
-            .and_then(move |tip| {
+    pub fn new(node: Addr<NodeActor>, db: Addr<DbActor>, network: Network) -> Self {
+        BlockHeaderActor { db, node, network, peer_addr: None, peer_height: 0, getheaders_pending: false }
+    }
+
+    // only headers that are invalid by themselves; missing history or a bad bootstrap are our problem
+    fn _report_invalid_header(node: &Addr<NodeActor>, err: &Error) {
+        match err.kind() {
+            ErrorKind::InvalidHeader(HeaderError::InvalidProofOfWork(_)) |
+            ErrorKind::InvalidHeader(HeaderError::UnexpectedBits(..)) |
+            ErrorKind::InvalidHeader(HeaderError::TimestampTooEarly(_)) |
+            ErrorKind::InvalidHeader(HeaderError::CheckpointMismatch(..)) => {
+                node.do_send(Misbehaving { score: INVALID_DATA_SCORE, reason: format!("{}", err) });
+            },
+            _ => {},
+        }
+    }
+
+    fn _fetch_headers(&mut self) -> impl futures::Future<Item=(), Error=Error> {
+        self.getheaders_pending = true;
+        let node = self.node.clone();
+        let genesis_hash = self.network.genesis.hash();
+        self.db.send(BlockLocatorQuery).from_err()
+            .and_then(identity)
+            .and_then(move |mut block_locator_hashes| {
+                if block_locator_hashes.len() == 0 {
+                    block_locator_hashes.push(genesis_hash);
+                }
                 node.send(OutgoingMsg(GetHeadersMessage {
                     version: 70015,
-                    block_locator_hashes: vec![tip.header.hash()],
+                    block_locator_hashes,
                     hash_stop: [0; 32],
                 }.packet())).from_err()
             })
     }
+
+    fn _update_peer_height(&mut self, height: i32) {
+        if height <= self.peer_height {
+            return;
+        }
+        self.peer_height = height;
+        if let Some(peer_addr) = self.peer_addr {
+            self.db.do_send(UpdateHeaderSync::PeerHeight(peer_addr, height));
+        }
+    }
 }
 
 impl Handler<HandshakeSuccess> for BlockHeaderActor {
     type Result = Response<(), Error>;
 
-    fn handle(&mut self, msg: HandshakeSuccess, ctx: &mut Self::Context) -> Self::Result {
-        let node = self.node.clone();
-        let db = self.db.clone();
-        Response::fut(
-            Self::_fetch_headers(db, node)
-            /*self.node
-                .send(OutgoingMsg(MessagePacket::from_payload(b"sendheaders", vec![]))).from_err()
-                .and_then(move |_| Self::_fetch_headers(db, node))*/
-        )
+    fn handle(&mut self, msg: HandshakeSuccess, _ctx: &mut Self::Context) -> Self::Result {
+        self.peer_addr = Some(msg.peer_addr);
+        self.db.do_send(UpdateHeaderSync::PeerHeight(msg.peer_addr, msg.start_height));
+        self.peer_height = msg.start_height;
+        // BIP130: announce new blocks with headers instead of inv
+        self.node.do_send(OutgoingMsg(MessagePacket::from_payload(b"sendheaders", vec![])));
+        Response::fut(self._fetch_headers())
     }
 }
 
@@ -59,15 +112,62 @@ impl Handler<IncomingMsg<HeadersMessage>> for BlockHeaderActor {
     type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: IncomingMsg<HeadersMessage>, ctx: &mut Self::Context) -> Self::Result {
-        let node = self.node.clone();
-        let db = self.db.clone();
-        if msg.0.headers.len() == 0 {
+        let requested = std::mem::replace(&mut self.getheaders_pending, false);
+        let headers = &msg.0.headers;
+        if headers.len() == 0 {
             return Response::reply(Ok(()));
         }
+        if let Err(err) = check_header_batch(headers, &self.network.difficulty) {
+            Self::_report_invalid_header(&self.node, &err);
+            return Response::reply(Err(err));
+        }
+        let block_hashes = headers.iter().map(|header| header.hash()).collect::<Vec<_>>();
+        let full_batch = headers.len() >= MAX_HEADERS_RESULTS;
+        let node = self.node.clone();
+        let db = self.db.clone();
+        let addr = ctx.address();
         Response::fut(
-            self.db.send(AddHeadersQuery(msg.0.headers.clone())).from_err()
-                .and_then(|r| r)
-                .and_then(move |_| Self::_fetch_headers(db, node))
+            self.db.send(AddHeadersQuery(headers.clone())).from_err()
+                .and_then(identity)
+                .map_err(move |err| {
+                    Self::_report_invalid_header(&node, &err);
+                    err
+                })
+                .and_then(move |_| db.send(HeaderTipQuery).from_err().and_then(identity))
+                .and_then(move |tip| {
+                    addr.send(HeadersAdded { tip, block_hashes, full_batch, requested }).from_err()
+                        .and_then(identity)
+                })
         )
     }
 }
+
+impl Handler<HeadersAdded> for BlockHeaderActor {
+    type Result = Response<(), Error>;
+
+    fn handle(&mut self, msg: HeadersAdded, _ctx: &mut Self::Context) -> Self::Result {
+        let HeadersAdded { tip, block_hashes, full_batch, requested } = msg;
+        let extends_tip = block_hashes.last() == Some(&tip.header.hash());
+        if extends_tip {
+            self._update_peer_height(tip.height);
+        }
+        // after sendheaders, new blocks are announced as headers instead of inv
+        if !requested && extends_tip {
+            self.node.do_send(OutgoingMsg(GetDataMessage {
+                inv_vectors: block_hashes.into_iter()
+                    .map(|hash| InvVector { type_id: ObjectType::Block, hash })
+                    .collect(),
+            }.packet()));
+        }
+        if requested && !full_batch {
+            eprintln!("Headers synced to height {}", tip.height);
+        }
+        self.db.do_send(UpdateHeaderSync::Tip(tip));
+        // unsolicited announcements that don't connect to our tip (e.g. we missed a block or are on
+        // a stale branch) are resolved with a locator request, but only once to avoid loops
+        if full_batch || (!requested && !extends_tip) {
+            return Response::fut(self._fetch_headers());
+        }
+        Response::reply(Ok(()))
+    }
+}
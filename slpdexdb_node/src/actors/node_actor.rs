@@ -6,17 +6,27 @@ use tokio_io::AsyncRead;
 use std::io;
 use std::sync::Arc;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use slpdexdb_base::Error;
+use slpdexdb_base::{Error, Network};
 
 use crate::codec::MessageCodec;
 use crate::message::NodeMessage;
-use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage};
+use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage,
+                      AddrMessage, PingMessage, PongMessage};
 use crate::message_packet::MessagePacket;
 use crate::actors::{VersionActor, InvActor, BlockHeaderActor};
-use crate::msg::{Subscribe, HandshakeSuccess};
+use crate::msg::{Subscribe, HandshakeSuccess, NodeDisconnected, Misbehaving, PeerMisbehaved, Disconnect,
+                 GetNodeStats, NodeStats, PeerAddrs};
 use crate::db_query::DbActor;
 
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+const PONG_TIMEOUT: Duration = Duration::from_secs(120);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub const MALFORMED_MESSAGE_SCORE: i32 = 20;
+pub const INVALID_DATA_SCORE: i32 = 100;
+
 pub struct IncomingMsg<M: NodeMessage>(pub Arc<M>);
 
 impl<M: NodeMessage> Message for IncomingMsg<M> {
@@ -32,6 +42,11 @@ impl Message for OutgoingMsg {
 
 pub struct NodeActor {
     framed: actix::io::FramedWrite<WriteHalf<TcpStream>, MessageCodec>,
+    peer_addr: SocketAddr,
+    last_received: Instant,
+    pending_ping: Option<(u64, Instant)>,
+    latency: Option<Duration>,
+    misbehaviour_score: i32,
 
     subscribers_version: Vec<Recipient<IncomingMsg<VersionMessage>>>,
     subscribers_verack: Vec<Recipient<IncomingMsg<VerackMessage>>>,
@@ -40,23 +55,31 @@ pub struct NodeActor {
     subscribers_tx: Vec<Recipient<IncomingMsg<TxMessage>>>,
     subscribers_handshake: Vec<Recipient<HandshakeSuccess>>,
     subscribers_block: Vec<Recipient<IncomingMsg<BlockMessage>>>,
+    subscribers_addr: Vec<Recipient<PeerAddrs>>,
+    subscribers_disconnected: Vec<Recipient<NodeDisconnected>>,
+    subscribers_misbehaved: Vec<Recipient<PeerMisbehaved>>,
 }
 
 impl NodeActor {
-    pub fn create_from_stream_db(stream: TcpStream, db_actor: Addr<DbActor>) -> Addr<Self> {
+    pub fn create_from_stream_db(stream: TcpStream, db_actor: Addr<DbActor>, network: Network) -> Addr<Self> {
         let local_addr = stream.local_addr().unwrap(); // TODO: handle error
         //println!("local addr {}", local_addr);
         //let local_addr = SocketAddr::new("185.220.70.238".parse().unwrap(), 8333);
         let peer_addr = stream.peer_addr().unwrap(); // TODO: handle error
         let addr = NodeActor::create(|ctx| {
             let (r, w) = stream.split();
-            ctx.add_stream(FramedRead::new(r, MessageCodec));
+            ctx.add_stream(FramedRead::new(r, MessageCodec::for_network(&network)));
             NodeActor {
                 framed: actix::io::FramedWrite::new(
                     w,
-                    MessageCodec,
+                    MessageCodec::for_network(&network),
                     ctx,
                 ),
+                peer_addr,
+                last_received: Instant::now(),
+                pending_ping: None,
+                latency: None,
+                misbehaviour_score: 0,
                 subscribers_handshake: Vec::new(),
                 subscribers_inv: Vec::new(),
                 subscribers_version: Vec::new(),
@@ -64,52 +87,144 @@ impl NodeActor {
                 subscribers_headers: Vec::new(),
                 subscribers_tx: Vec::new(),
                 subscribers_block: Vec::new(),
+                subscribers_addr: Vec::new(),
+                subscribers_disconnected: Vec::new(),
+                subscribers_misbehaved: Vec::new(),
             }
         });
         InvActor::start(InvActor { node: addr.clone() });
-        VersionActor::start(VersionActor { node: addr.clone(), local_addr, peer_addr });
-        BlockHeaderActor::start(BlockHeaderActor { node: addr.clone(), db: db_actor });
+        VersionActor::start(VersionActor { node: addr.clone(), local_addr, peer_addr, peer_start_height: 0 });
+        BlockHeaderActor::start(BlockHeaderActor::new(addr.clone(), db_actor, network));
         addr
     }
 
-    fn _broadcast<M: NodeMessage + Send + Sync>(msg: MessagePacket, subs: &[Recipient<IncomingMsg<M>>]) {
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    fn _broadcast<M: NodeMessage + Send + Sync>(msg: MessagePacket, subs: &[Recipient<IncomingMsg<M>>])
+            -> io::Result<()> {
         let mut cur = io::Cursor::new(msg.payload());
-        let msg = Arc::new(M::from_stream(&mut cur).unwrap());  // TODO: handle error
+        let msg = Arc::new(M::from_stream(&mut cur)?);
         for sub in subs.iter() {
-            sub.do_send(IncomingMsg(Arc::clone(&msg)));
+            sub.do_send(IncomingMsg(Arc::clone(&msg))).ok();
+        }
+        Ok(())
+    }
+
+    fn _broadcast_addrs(&self, msg: MessagePacket) -> io::Result<()> {
+        let addrs = Arc::new(AddrMessage::from_stream(&mut io::Cursor::new(msg.payload()))?);
+        for sub in self.subscribers_addr.iter() {
+            sub.do_send(PeerAddrs { peer_addr: self.peer_addr, addrs: Arc::clone(&addrs) }).ok();
+        }
+        Ok(())
+    }
+
+    fn _misbehave(&mut self, score: i32, reason: String) {
+        eprintln!("Peer {} misbehaved (+{}): {}", self.peer_addr, score, reason);
+        self.misbehaviour_score += score;
+        for sub in self.subscribers_misbehaved.iter() {
+            sub.do_send(PeerMisbehaved {
+                peer_addr: self.peer_addr,
+                score,
+                reason: reason.clone(),
+            }).ok();
+        }
+    }
+
+    fn _keepalive(&mut self, ctx: &mut Context<Self>) {
+        if self.last_received.elapsed() > IDLE_TIMEOUT {
+            eprintln!("Peer {} idle for too long, disconnecting", self.peer_addr);
+            ctx.stop();
+            return;
+        }
+        match self.pending_ping {
+            Some((_, sent)) if sent.elapsed() > PONG_TIMEOUT => {
+                eprintln!("Peer {} didn't answer ping, disconnecting", self.peer_addr);
+                ctx.stop();
+            },
+            Some(_) => {},
+            None => {
+                let nonce = rand::random();
+                self.pending_ping = Some((nonce, Instant::now()));
+                self.framed.write(PingMessage { nonce }.packet());
+            },
+        }
+    }
+
+    fn _handle_pong(&mut self, msg: MessagePacket) -> io::Result<()> {
+        let pong = PongMessage::from_stream(&mut io::Cursor::new(msg.payload()))?;
+        match self.pending_ping {
+            Some((nonce, sent)) if nonce == pong.nonce => {
+                self.latency = Some(sent.elapsed());
+                self.pending_ping = None;
+            },
+            _ => {},
         }
+        Ok(())
     }
 }
 
 impl Actor for NodeActor {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(PING_INTERVAL, |act, ctx| act._keepalive(ctx));
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        for sub in self.subscribers_disconnected.iter() {
+            sub.do_send(NodeDisconnected { peer_addr: self.peer_addr }).ok();
+        }
+    }
 }
 
 impl actix::io::WriteHandler<io::Error> for NodeActor {
     fn error(&mut self, err: io::Error, _ctx: &mut Self::Context) -> Running {
-        eprintln!("error: {}", err);
-        Running::Continue
+        eprintln!("error writing to {}: {}", self.peer_addr, err);
+        Running::Stop
     }
 }
 
 impl StreamHandler<MessagePacket, io::Error> for NodeActor {
     fn handle(&mut self, msg: MessagePacket, ctx: &mut Context<Self>) {
         //println!("msg: {}", msg);
-        match msg.header().command_name() {
+        self.last_received = Instant::now();
+        let command = msg.header().command_name().to_vec();
+        let result = match command.as_slice() {
             b"version" => Self::_broadcast(msg, &self.subscribers_version),
             b"verack" => Self::_broadcast(msg, &self.subscribers_verack),
             b"inv" => Self::_broadcast(msg, &self.subscribers_inv),
             b"headers" => Self::_broadcast(msg, &self.subscribers_headers),
-            b"verack" => Self::_broadcast(msg, &self.subscribers_verack),
             b"tx" => Self::_broadcast(msg, &self.subscribers_tx),
-            b"block" => {
-                println!("block msg: {}", msg);
-                Self::_broadcast(msg, &self.subscribers_block)
-            },
+            b"block" => Self::_broadcast(msg, &self.subscribers_block),
+            b"addr" => self._broadcast_addrs(msg),
+            b"ping" => PingMessage::from_stream(&mut io::Cursor::new(msg.payload()))
+                .map(|ping| self.framed.write(PongMessage { nonce: ping.nonce }.packet())),
+            b"pong" => self._handle_pong(msg),
+            b"sendheaders" | b"sendcmpct" | b"feefilter" | b"getheaders" | b"getaddr" |
+            b"notfound" | b"reject" | b"mempool" | b"getdata" => Ok(()),
             _ => {
+                eprintln!("Unknown command {} from {}", String::from_utf8_lossy(&command), self.peer_addr);
+                Ok(())
             },
+        };
+        if let Err(err) = result {
+            self._misbehave(
+                MALFORMED_MESSAGE_SCORE,
+                format!("Invalid {} message: {}", String::from_utf8_lossy(&command), err),
+            );
         }
     }
+
+    fn error(&mut self, err: io::Error, _ctx: &mut Self::Context) -> Running {
+        if err.kind() == io::ErrorKind::InvalidData {
+            self._misbehave(INVALID_DATA_SCORE, format!("{}", err));
+        } else {
+            eprintln!("error reading from {}: {}", self.peer_addr, err);
+        }
+        Running::Stop
+    }
 }
 
 impl Handler<Subscribe> for NodeActor {
@@ -124,6 +239,9 @@ impl Handler<Subscribe> for NodeActor {
             Subscribe::Headers(recipient) => self.subscribers_headers.push(recipient),
             Subscribe::Tx(recipient) => self.subscribers_tx.push(recipient),
             Subscribe::Block(recipient) => self.subscribers_block.push(recipient),
+            Subscribe::Addr(recipient) => self.subscribers_addr.push(recipient),
+            Subscribe::Disconnected(recipient) => self.subscribers_disconnected.push(recipient),
+            Subscribe::Misbehaved(recipient) => self.subscribers_misbehaved.push(recipient),
         }
     }
 }
@@ -146,3 +264,140 @@ impl Handler<OutgoingMsg> for NodeActor {
         self.framed.write(msg.0);
     }
 }
+
+impl Handler<Misbehaving> for NodeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Misbehaving, _: &mut Self::Context) -> Self::Result {
+        self._misbehave(msg.score, msg.reason);
+    }
+}
+
+impl Handler<Disconnect> for NodeActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Disconnect, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+    }
+}
+
+impl Handler<GetNodeStats> for NodeActor {
+    type Result = Result<NodeStats, Error>;
+
+    fn handle(&mut self, _msg: GetNodeStats, _: &mut Self::Context) -> Self::Result {
+        Ok(NodeStats {
+            peer_addr: self.peer_addr,
+            latency: self.latency,
+            misbehaviour_score: self.misbehaviour_score,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_query::{HeaderTipQuery, HeaderTip, AddHeadersQuery, BlockLocatorQuery};
+    use futures::Future;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    struct TestDb;
+
+    impl Actor for TestDb {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<HeaderTipQuery> for TestDb {
+        type Result = Result<HeaderTip, Error>;
+
+        fn handle(&mut self, _msg: HeaderTipQuery, _ctx: &mut Self::Context) -> Self::Result {
+            Ok(HeaderTip { header: Network::regtest().genesis, height: 0 })
+        }
+    }
+
+    impl Handler<AddHeadersQuery> for TestDb {
+        type Result = Result<(), Error>;
+
+        fn handle(&mut self, _msg: AddHeadersQuery, _ctx: &mut Self::Context) -> Self::Result {
+            Ok(())
+        }
+    }
+
+    impl Handler<BlockLocatorQuery> for TestDb {
+        type Result = Result<Vec<[u8; 32]>, Error>;
+
+        fn handle(&mut self, _msg: BlockLocatorQuery, _ctx: &mut Self::Context) -> Self::Result {
+            Ok(vec![Network::regtest().genesis.hash()])
+        }
+    }
+
+    struct Recorder {
+        events: mpsc::Sender<&'static str>,
+    }
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<HandshakeSuccess> for Recorder {
+        type Result = Result<(), Error>;
+
+        fn handle(&mut self, _msg: HandshakeSuccess, _ctx: &mut Self::Context) -> Self::Result {
+            self.events.send("handshake").unwrap();
+            Ok(())
+        }
+    }
+
+    impl Handler<NodeDisconnected> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, _msg: NodeDisconnected, _ctx: &mut Self::Context) -> Self::Result {
+            self.events.send("disconnected").unwrap();
+            System::current().stop();
+        }
+    }
+
+    fn serve_handshake(listener: TcpListener) {
+        let magic = Network::regtest().magic;
+        let (mut stream, peer_addr) = listener.accept().unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        let packet = MessagePacket::from_stream(&mut stream, &magic).unwrap();
+        assert_eq!(packet.header().command_name(), b"version");
+        VersionMessage::from_addrs(&peer_addr, &local_addr).packet().write_to_stream(&mut stream, &magic).unwrap();
+        VerackMessage.packet().write_to_stream(&mut stream, &magic).unwrap();
+        loop {
+            let packet = MessagePacket::from_stream(&mut stream, &magic).unwrap();
+            if packet.header().command_name() == b"getaddr" {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_and_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve_handshake(listener));
+        let (sender, receiver) = mpsc::channel();
+        System::run(move || {
+            let test_db = TestDb.start();
+            let db = DbActor::start(DbActor::new(
+                test_db.clone().recipient(),
+                test_db.clone().recipient(),
+                test_db.recipient(),
+            ));
+            let recorder = Recorder { events: sender }.start();
+            Arbiter::spawn(
+                TcpStream::connect(&listen_addr)
+                    .map(move |stream| {
+                        let node = NodeActor::create_from_stream_db(stream, db, Network::regtest());
+                        node.do_send(Subscribe::HandshakeSuccess(recorder.clone().recipient()));
+                        node.do_send(Subscribe::Disconnected(recorder.recipient()));
+                    })
+                    .map_err(|err| panic!("{}", err))
+            );
+        }).unwrap();
+        server.join().unwrap();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec!["handshake", "disconnected"]);
+    }
+}
@@ -4,7 +4,7 @@ use std::convert::identity;
 
 use slpdexdb_base::Error;
 
-use crate::messages::{VersionMessage, VerackMessage};
+use crate::messages::{VersionMessage, VerackMessage, GetAddrMessage};
 use crate::message::NodeMessage;
 use crate::actors::{NodeActor, IncomingMsg, OutgoingMsg};
 use crate::msg::{Subscribe, HandshakeSuccess};
@@ -13,6 +13,7 @@ pub struct VersionActor {
     pub node: Addr<NodeActor>,
     pub local_addr: SocketAddr,
     pub peer_addr: SocketAddr,
+    pub peer_start_height: i32,
 }
 
 impl Actor for VersionActor {
@@ -29,6 +30,7 @@ impl Handler<IncomingMsg<VersionMessage>> for VersionActor {
     type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: IncomingMsg<VersionMessage>, _: &mut Self::Context) -> Self::Result {
+        self.peer_start_height = msg.0.start_height;
         Response::fut(self.node.send(OutgoingMsg(VerackMessage.packet())).from_err())
     }
 }
@@ -37,6 +39,13 @@ impl Handler<IncomingMsg<VerackMessage>> for VersionActor {
     type Result = Response<(), Error>;
 
     fn handle(&mut self, msg: IncomingMsg<VerackMessage>, _: &mut Self::Context) -> Self::Result {
-        Response::fut(self.node.send(HandshakeSuccess).from_err().and_then(identity))
+        let node = self.node.clone();
+        let peer_addr = self.peer_addr;
+        let start_height = self.peer_start_height;
+        Response::fut(
+            self.node.send(HandshakeSuccess { peer_addr, start_height }).from_err()
+                .and_then(identity)
+                .and_then(move |_| node.send(OutgoingMsg(GetAddrMessage.packet())).from_err())
+        )
     }
 }
@@ -1,12 +1,29 @@
 use std::io;
 use tokio_io::codec::{Decoder, Encoder};
 use bytes::{BytesMut};
+use slpdexdb_base::Network;
 
 use crate::message_packet::MessagePacket;
 use crate::message_header::{MessageHeader, HEADER_SIZE};
+use crate::message_error::MessageError;
 
-pub struct MessageCodec;
+pub const DEFAULT_MAX_PAYLOAD_SIZE: u32 = 32 * 1024 * 1024 + 1024;
 
+#[derive(Clone, Copy, Debug)]
+pub struct MessageCodec {
+    max_payload_size: u32,
+    magic: [u8; 4],
+}
+
+impl MessageCodec {
+    pub fn new(max_payload_size: u32, magic: [u8; 4]) -> Self {
+        MessageCodec { max_payload_size, magic }
+    }
+
+    pub fn for_network(network: &Network) -> Self {
+        MessageCodec::new(DEFAULT_MAX_PAYLOAD_SIZE, network.magic)
+    }
+}
 
 impl Decoder for MessageCodec {
     type Item = MessagePacket;
@@ -18,7 +35,10 @@ impl Decoder for MessageCodec {
             return Ok(None)
         }
         let mut cur = io::Cursor::new(src.as_ref());
-        let header = MessageHeader::from_stream(&mut cur)?;
+        let header = MessageHeader::from_stream(&mut cur, &self.magic)?;
+        if header.payload_size() > self.max_payload_size {
+            return Err(MessageError::PayloadTooLarge(header.payload_size()).into());
+        }
         let msg_size = header.payload_size() as usize + HEADER_SIZE;
         if src_len < msg_size {
             return Ok(None)
@@ -34,7 +54,45 @@ impl Encoder for MessageCodec {
     type Error = io::Error;
 
     fn encode(&mut self, item: MessagePacket, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        item.write_to_bytes(dst);
+        item.write_to_bytes(dst, &self.magic);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_rejects_oversized_payload() {
+        let magic = Network::mainnet().magic;
+        let mut codec = MessageCodec::new(16, magic);
+        let mut bytes = BytesMut::new();
+        MessagePacket::from_payload(b"ping", vec![0; 8]).write_to_bytes(&mut bytes, &magic);
+        let packet = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(packet.header().command_name(), b"ping");
+        assert!(bytes.is_empty());
+        MessagePacket::from_payload(b"block", vec![0; 17]).write_to_bytes(&mut bytes, &magic);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_waits_for_full_payload() {
+        let network = Network::mainnet();
+        let mut codec = MessageCodec::for_network(&network);
+        let mut full = BytesMut::new();
+        MessagePacket::from_payload(b"ping", vec![1; 8]).write_to_bytes(&mut full, &network.magic);
+        let mut partial = BytesMut::from(&full[..HEADER_SIZE + 4]);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        assert!(codec.decode(&mut full).unwrap().is_some());
+    }
+
+    #[test]
+    fn decode_rejects_wrong_magic() {
+        let mut codec = MessageCodec::for_network(&Network::testnet());
+        let mut bytes = BytesMut::new();
+        MessagePacket::from_payload(b"ping", vec![0; 8]).write_to_bytes(&mut bytes, &Network::mainnet().magic);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+}
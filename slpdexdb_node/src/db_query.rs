@@ -1,8 +1,11 @@
 use actix::prelude::*;
 use slpdexdb_base::{BlockHeader, Error};
 use futures::Future;
+use std::collections::HashMap;
 use std::convert::identity;
+use std::net::SocketAddr;
 
+#[derive(Clone)]
 pub struct HeaderTip {
     pub header: BlockHeader,
     pub height: i32,
@@ -20,9 +23,69 @@ impl Message for AddHeadersQuery {
     type Result = Result<(), Error>;
 }
 
+// block hashes from the tip back to genesis, exponentially spaced
+pub struct BlockLocatorQuery;
+
+impl Message for BlockLocatorQuery {
+    type Result = Result<Vec<[u8; 32]>, Error>;
+}
+
+pub enum UpdateHeaderSync {
+    Tip(HeaderTip),
+    PeerHeight(SocketAddr, i32),
+    PeerDisconnected(SocketAddr),
+}
+
+impl Message for UpdateHeaderSync {
+    type Result = ();
+}
+
+pub struct GetHeaderSyncStatus;
+
+impl Message for GetHeaderSyncStatus {
+    type Result = Option<HeaderSyncStatus>;
+}
+
+#[derive(Clone, Debug)]
+pub struct HeaderSyncStatus {
+    pub tip_height: i32,
+    pub tip_hash: [u8; 32],
+    pub best_peer_height: i32,
+}
+
+impl HeaderSyncStatus {
+    pub fn progress(&self) -> f64 {
+        if self.best_peer_height <= 0 || self.tip_height >= self.best_peer_height {
+            return 1.0;
+        }
+        self.tip_height.max(0) as f64 / self.best_peer_height as f64
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.tip_height >= self.best_peer_height
+    }
+}
+
 pub struct DbActor {
-    pub header_tip_query: Recipient<HeaderTipQuery>,
-    pub add_header_query: Recipient<AddHeadersQuery>,
+    header_tip_query: Recipient<HeaderTipQuery>,
+    add_header_query: Recipient<AddHeadersQuery>,
+    block_locator_query: Recipient<BlockLocatorQuery>,
+    header_tip: Option<HeaderTip>,
+    peer_heights: HashMap<SocketAddr, i32>,
+}
+
+impl DbActor {
+    pub fn new(header_tip_query: Recipient<HeaderTipQuery>,
+               add_header_query: Recipient<AddHeadersQuery>,
+               block_locator_query: Recipient<BlockLocatorQuery>) -> Self {
+        DbActor {
+            header_tip_query,
+            add_header_query,
+            block_locator_query,
+            header_tip: None,
+            peer_heights: HashMap::new(),
+        }
+    }
 }
 
 impl Actor for DbActor {
@@ -44,3 +107,65 @@ impl Handler<AddHeadersQuery> for DbActor {
         Response::fut(self.add_header_query.send(msg).from_err().and_then(identity))
     }
 }
+
+impl Handler<BlockLocatorQuery> for DbActor {
+    type Result = Response<Vec<[u8; 32]>, Error>;
+
+    fn handle(&mut self, msg: BlockLocatorQuery, _ctx: &mut Self::Context) -> Self::Result {
+        Response::fut(self.block_locator_query.send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<UpdateHeaderSync> for DbActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateHeaderSync, _ctx: &mut Self::Context) -> Self::Result {
+        match msg {
+            UpdateHeaderSync::Tip(tip) => self.header_tip = Some(tip),
+            UpdateHeaderSync::PeerHeight(peer_addr, height) => {
+                let peer_height = self.peer_heights.entry(peer_addr).or_insert(height);
+                *peer_height = (*peer_height).max(height);
+            },
+            UpdateHeaderSync::PeerDisconnected(peer_addr) => {
+                self.peer_heights.remove(&peer_addr);
+            },
+        }
+    }
+}
+
+impl Handler<GetHeaderSyncStatus> for DbActor {
+    type Result = Option<HeaderSyncStatus>;
+
+    fn handle(&mut self, _msg: GetHeaderSyncStatus, _ctx: &mut Self::Context) -> Self::Result {
+        let tip = self.header_tip.as_ref()?;
+        Some(HeaderSyncStatus {
+            tip_height: tip.height,
+            tip_hash: tip.header.hash(),
+            best_peer_height: self.peer_heights.values().cloned().max().unwrap_or(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(tip_height: i32, best_peer_height: i32) -> HeaderSyncStatus {
+        HeaderSyncStatus { tip_height, tip_hash: [0; 32], best_peer_height }
+    }
+
+    #[test]
+    fn header_sync_progress() {
+        // no peer heights known yet
+        assert_eq!(status(100, 0).progress(), 1.0);
+        assert!(status(100, 0).is_synced());
+        assert_eq!(status(150, 600).progress(), 0.25);
+        assert!(!status(150, 600).is_synced());
+        assert_eq!(status(-1, 600).progress(), 0.0);
+        assert_eq!(status(600, 600).progress(), 1.0);
+        assert!(status(600, 600).is_synced());
+        // a peer behind us
+        assert_eq!(status(610, 600).progress(), 1.0);
+        assert!(status(610, 600).is_synced());
+    }
+}
@@ -3,13 +3,15 @@ mod message_packet;
 mod message_error;
 mod message_header;
 mod message;
-mod codec;
+pub mod codec;
 pub mod actors;
 mod db_query;
 pub mod msg;
+mod peer_table;
 
 pub use message_packet::*;
 pub use message_error::*;
 pub use message_error::*;
 pub use message::*;
 pub use db_query::*;
+pub use peer_table::*;
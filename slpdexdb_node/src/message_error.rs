@@ -7,6 +7,7 @@ use std::fmt::{Display, Formatter};
 pub enum MessageError {
     WrongMagic,
     InvalidChecksum,
+    PayloadTooLarge(u32),
     IoError(io::Error),
 }
 
@@ -23,6 +24,10 @@ impl From<MessageError> for io::Error {
             IoError(err) => err,
             WrongMagic => io::Error::new(io::ErrorKind::InvalidData, "Wrong magic"),
             InvalidChecksum => io::Error::new(io::ErrorKind::InvalidData, "Invalid checksum"),
+            PayloadTooLarge(size) => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Payload too large: {} bytes", size),
+            ),
         }
     }
 }
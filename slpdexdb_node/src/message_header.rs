@@ -12,7 +12,6 @@ pub struct MessageHeader {
     checksum: [u8; 4],
 }
 
-pub const MESSAGE_MAGIC: &[u8] = b"\xe3\xe1\xf3\xe8";
 pub const HEADER_SIZE: usize = 4 + 12 + 4 + 4;
 
 impl MessageHeader {
@@ -24,12 +23,13 @@ impl MessageHeader {
         }
     }
 
-    pub fn from_stream<R: io::Read>(read: &mut R) -> Result<Self, MessageError> {
+    pub fn from_stream<R: io::Read>(read: &mut R, magic: &[u8; 4]) -> Result<Self, MessageError> {
+        let expected_magic = magic;
         let mut magic = [0; 4];
         let mut command = [0; 12];
         let mut checksum = [0; 4];
         read.read_exact(&mut magic)?;
-        if &magic[..] != MESSAGE_MAGIC {
+        if &magic != expected_magic {
             return Err(MessageError::WrongMagic)
         }
         read.read_exact(&mut command)?;
@@ -42,16 +42,16 @@ impl MessageHeader {
         })
     }
 
-    pub fn write_to_stream<W: io::Write>(&self, write: &mut W) -> io::Result<()> {
-        write.write(MESSAGE_MAGIC)?;
+    pub fn write_to_stream<W: io::Write>(&self, write: &mut W, magic: &[u8; 4]) -> io::Result<()> {
+        write.write(magic)?;
         write.write(&self.command)?;
         write.write_u32::<LittleEndian>(self.payload_size)?;
         write.write(&self.checksum)?;
         Ok(())
     }
 
-    pub fn write_to_bytes(&self, bytes: &mut BytesMut) {
-        bytes.put(MESSAGE_MAGIC);
+    pub fn write_to_bytes(&self, bytes: &mut BytesMut, magic: &[u8; 4]) {
+        bytes.put(magic.as_ref());
         bytes.put(self.command.as_ref());
         bytes.put_u32_le(self.payload_size);
         bytes.put(self.checksum.as_ref());
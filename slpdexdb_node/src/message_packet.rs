@@ -20,8 +20,8 @@ impl MessagePacket {
         Ok(())
     }
 
-    pub fn from_stream<R: io::Read>(read: &mut R) -> Result<Self, MessageError> {
-        let header = MessageHeader::from_stream(read)?;
+    pub fn from_stream<R: io::Read>(read: &mut R, magic: &[u8; 4]) -> Result<Self, MessageError> {
+        let header = MessageHeader::from_stream(read, magic)?;
         let mut payload = vec![0; header.payload_size() as usize];
         read.read_exact(&mut payload[..])?;
         Self::_check_checksum(&payload, header.checksum())?;
@@ -56,15 +56,15 @@ impl MessagePacket {
         }
     }
 
-    pub fn write_to_stream<W: io::Write>(&self, write: &mut W) -> io::Result<()> {
-        self.header.write_to_stream(write)?;
+    pub fn write_to_stream<W: io::Write>(&self, write: &mut W, magic: &[u8; 4]) -> io::Result<()> {
+        self.header.write_to_stream(write, magic)?;
         write.write(&self.payload)?;
         Ok(())
     }
 
-    pub fn write_to_bytes(&self, bytes: &mut BytesMut) {
+    pub fn write_to_bytes(&self, bytes: &mut BytesMut, magic: &[u8; 4]) {
         bytes.reserve(HEADER_SIZE + self.payload.len());
-        self.header.write_to_bytes(bytes);
+        self.header.write_to_bytes(bytes, magic);
         bytes.put(&self.payload);
     }
 
@@ -0,0 +1,81 @@
+use crate::message_packet::MessagePacket;
+use crate::message::NodeMessage;
+use cashcontracts::serialize::{read_var_int, write_var_int};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+const MAX_ADDR_ENTRIES: u64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct NetAddr {
+    pub timestamp: u32,
+    pub services: u64,
+    pub addr: SocketAddr,
+}
+
+#[derive(Clone, Debug)]
+pub struct AddrMessage {
+    pub addresses: Vec<NetAddr>,
+}
+
+impl NodeMessage for AddrMessage {
+    fn command() -> &'static [u8] {
+        b"addr"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        let mut payload = Vec::new();
+        write_var_int(&mut payload, self.addresses.len() as u64).unwrap();
+        for net_addr in self.addresses.iter() {
+            let ip = match net_addr.addr.ip() {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            payload.write_u32::<LittleEndian>(net_addr.timestamp).unwrap();
+            payload.write_u64::<LittleEndian>(net_addr.services).unwrap();
+            payload.write(&ip.octets()).unwrap();
+            payload.write_u16::<BigEndian>(net_addr.addr.port()).unwrap();
+        }
+        MessagePacket::from_payload(Self::command(), payload)
+    }
+
+    fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
+        let n_addr = read_var_int(stream)?;
+        if n_addr > MAX_ADDR_ENTRIES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Too many addr entries"));
+        }
+        let mut addresses = Vec::new();
+        for _ in 0..n_addr {
+            let timestamp = stream.read_u32::<LittleEndian>()?;
+            let services = stream.read_u64::<LittleEndian>()?;
+            let mut ip_bytes = [0; 16];
+            stream.read_exact(&mut ip_bytes)?;
+            let port = stream.read_u16::<BigEndian>()?;
+            let ip = Ipv6Addr::from(ip_bytes);
+            let ip = match ip.to_ipv4() {
+                Some(ipv4) if ip_bytes[..12] == [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff] =>
+                    IpAddr::V4(ipv4),
+                _ => IpAddr::V6(ip),
+            };
+            addresses.push(NetAddr { timestamp, services, addr: SocketAddr::new(ip, port) });
+        }
+        Ok(AddrMessage { addresses })
+    }
+}
+
+pub struct GetAddrMessage;
+
+impl NodeMessage for GetAddrMessage {
+    fn command() -> &'static [u8] {
+        b"getaddr"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        MessagePacket::from_payload(Self::command(), vec![])
+    }
+
+    fn from_stream(_stream: &mut impl io::Read) -> io::Result<Self> {
+        Ok(GetAddrMessage)
+    }
+}
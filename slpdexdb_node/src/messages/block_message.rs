@@ -1,13 +1,23 @@
 use crate::message_packet::MessagePacket;
 use crate::message::NodeMessage;
-use slpdexdb_base::BlockHeader;
-use cashcontracts::{Tx, serialize};
+use slpdexdb_base::{BlockHeader, merkle_root};
+use cashcontracts::{Tx, serialize, tx_hash_to_hex};
 use std::io;
 
 #[derive(Clone, Debug)]
 pub struct BlockMessage {
     pub header: BlockHeader,
-    pub hashes: Vec<[u8; 32]>,
+    pub txs: Vec<Tx>,
+}
+
+impl BlockMessage {
+    pub fn tx_hashes(&self) -> Vec<[u8; 32]> {
+        self.txs.iter().map(|tx| tx.hash()).collect()
+    }
+
+    pub fn has_valid_merkle_root(&self) -> bool {
+        merkle_root(&self.tx_hashes()) == self.header.merkle_root
+    }
 }
 
 impl NodeMessage for BlockMessage {
@@ -16,28 +26,76 @@ impl NodeMessage for BlockMessage {
     }
 
     fn packet(&self) -> MessagePacket {
-        unimplemented!()
+        let mut payload = Vec::new();
+        self.header.write_to_stream(&mut payload).unwrap();
+        serialize::write_var_int(&mut payload, self.txs.len() as u64).unwrap();
+        for tx in self.txs.iter() {
+            tx.write_to_stream(&mut payload).unwrap();
+        }
+        MessagePacket::from_payload(Self::command(), payload)
     }
 
     fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
         let header = BlockHeader::from_stream(stream)?;
-        let mut hashes = Vec::new();
-        let n_hashes = serialize::read_var_int(stream)?;
-        for _ in 0..n_hashes {
-            let mut hash = [0; 32];
-            stream.read_exact(&mut hash);
-            hashes.push(hash);
+        let n_txs = serialize::read_var_int(stream)?;
+        let mut txs = Vec::new();
+        for _ in 0..n_txs {
+            txs.push(Tx::read_from_stream(stream)?);
+        }
+        let block = BlockMessage { header, txs };
+        if !block.has_valid_merkle_root() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Merkle root mismatch in block {}", tx_hash_to_hex(&block.header.hash())),
+            ));
         }
-        Ok(BlockMessage { header, hashes })
+        Ok(block)
     }
 }
 
-/*impl std::fmt::Display for TxMessage {
-    fn fmt<'a>(&self, f: &mut std::fmt::Formatter<'a>) -> Result<(), std::fmt::Error> {
-        writeln!(f, "num of invs: {}", self.inv_vectors.len())?;
-        for inv_vector in self.inv_vectors.iter() {
-            writeln!(f, "{:?}\t{}", inv_vector.type_id, tx_hash_to_hex(&inv_vector.hash))?;
-        }
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashcontracts::tx_hex_to_hash;
+
+    // mainnet block 00000000b0c5a240…, a coinbase and a tx spending three coinbase outputs
+    const BLOCK_HEX: &str = concat!(
+        "010000004ddccd549d28f385ab457e98d1b11ce80bfea2c5ab93015ade4973e400000000bf4473e53794beae34e64fcc",
+        "c471dace6ae544180816f89591894e0f417a914cd74d6e49ffff001d323b3a7b02010000000100000000000000000000",
+        "00000000000000000000000000000000000000000000ffffffff0804ffff001d026e04ffffffff0100f2052a01000000",
+        "43410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e",
+        "7cfa99c48b0e3f39c51347a19a5819651503a5ac00000000010000000321f75f3139a013f50f315b23b0c9a2b6eac31e",
+        "2bec98e5891c924664889942260000000049483045022100cb2c6b346a978ab8c61b18b5e9397755cbd17d6eb2fe0083",
+        "ef32e067fa6c785a02206ce44e613f31d9a6b0517e46f3db1576e9812cc98d159bfdaf759a5014081b5c01ffffffff79",
+        "cda0945903627c3da1f85fc95d0b8ee3e76ae0cfdc9a65d09744b1f8fc85430000000049483045022047957cdd957cfd",
+        "0becd642f6b84d82f49b6cb4c51a91f49246908af7c3cfdf4a022100e96b46621f1bffcf5ea5982f88cef651e9354f57",
+        "91602369bf5a82a6cd61a62501fffffffffe09f5fe3ffbf5ee97a54eb5e5069e9da6b4856ee86fc52938c2f979b0f38e",
+        "82000000004847304402204165be9a4cbab8049e1af9723b96199bfd3e85f44c6b4c0177e3962686b26073022028f638",
+        "da23fc003760861ad481ead4099312c60030d4cb57820ce4d33812a5ce01ffffffff01009d966b01000000434104ea1f",
+        "eff861b51fe3f5f8a3b12d0f4712db80e919548a80839fc47c6a21e66d957e9c5d8cd108c7a2d2324bad71f9904ac0ae",
+        "7336507d785b17a2c115e427a32fac00000000",
+    );
+
+    #[test]
+    fn parses_real_block() {
+        let payload = hex::decode(BLOCK_HEX).unwrap();
+        let block = BlockMessage::from_stream(&mut io::Cursor::new(&payload)).unwrap();
+        assert_eq!(
+            block.header.hash(),
+            tx_hex_to_hash("00000000b0c5a240b2a61d2e75692224efd4cbecdf6eaf4cc2cf477ca7c270e7").unwrap(),
+        );
+        assert_eq!(block.tx_hashes(), vec![
+            tx_hex_to_hash("77dfc2fe598419b00641c296181a96cf16943697f573480b023b77cce82ada21").unwrap(),
+            tx_hex_to_hash("a3b0e9e7cddbbe78270fa4182a7675ff00b92872d8df7d14265a2b1e379a9d33").unwrap(),
+        ]);
+        assert_eq!(block.packet().payload(), payload.as_slice());
     }
-}*/
+
+    #[test]
+    fn rejects_wrong_merkle_root() {
+        let mut payload = hex::decode(BLOCK_HEX).unwrap();
+        payload[36] ^= 1;  // first byte of the merkle root
+        let err = BlockMessage::from_stream(&mut io::Cursor::new(&payload)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -4,6 +4,7 @@ use slpdexdb_base::BlockHeader;
 use std::io::{self, Read};
 use cashcontracts::serialize::read_var_int;
 
+pub const MAX_HEADERS: u64 = 2000;
 
 pub struct HeadersMessage {
     pub headers: Vec<BlockHeader>,
@@ -20,6 +21,9 @@ impl NodeMessage for HeadersMessage {
 
     fn from_stream(stream: &mut impl Read) -> io::Result<HeadersMessage> {
         let n_headers = read_var_int(stream)?;
+        if n_headers > MAX_HEADERS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Too many headers"));
+        }
         let mut headers = Vec::with_capacity(n_headers as usize);
         for _ in 0..n_headers {
             headers.push(BlockHeader::from_stream(stream)?);
@@ -1,3 +1,4 @@
+mod addr_message;
 mod get_data_message;
 mod get_headers_message;
 mod headers_message;
@@ -5,7 +6,9 @@ mod inv_message;
 mod tx_message;
 mod version_message;
 mod block_message;
+mod ping_message;
 
+pub use addr_message::*;
 pub use block_message::*;
 pub use get_data_message::*;
 pub use get_headers_message::*;
@@ -13,3 +16,4 @@ pub use headers_message::*;
 pub use inv_message::*;
 pub use tx_message::*;
 pub use version_message::*;
+pub use ping_message::*;
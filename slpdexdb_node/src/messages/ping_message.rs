@@ -0,0 +1,46 @@
+use crate::message_packet::MessagePacket;
+use crate::message::NodeMessage;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io;
+
+#[derive(Clone, Debug)]
+pub struct PingMessage {
+    pub nonce: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PongMessage {
+    pub nonce: u64,
+}
+
+impl NodeMessage for PingMessage {
+    fn command() -> &'static [u8] {
+        b"ping"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        let mut payload = Vec::new();
+        payload.write_u64::<LittleEndian>(self.nonce).unwrap();
+        MessagePacket::from_payload(Self::command(), payload)
+    }
+
+    fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
+        Ok(PingMessage { nonce: stream.read_u64::<LittleEndian>()? })
+    }
+}
+
+impl NodeMessage for PongMessage {
+    fn command() -> &'static [u8] {
+        b"pong"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        let mut payload = Vec::new();
+        payload.write_u64::<LittleEndian>(self.nonce).unwrap();
+        MessagePacket::from_payload(Self::command(), payload)
+    }
+
+    fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
+        Ok(PongMessage { nonce: stream.read_u64::<LittleEndian>()? })
+    }
+}
@@ -81,26 +81,26 @@ impl NodeMessage for VersionMessage {
     }
 
     fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
-        let version = stream.read_i32::<LittleEndian>().unwrap();
-        let services = stream.read_u64::<LittleEndian>().unwrap();
-        let timestamp = stream.read_i64::<LittleEndian>().unwrap();
+        let version = stream.read_i32::<LittleEndian>()?;
+        let services = stream.read_u64::<LittleEndian>()?;
+        let timestamp = stream.read_i64::<LittleEndian>()?;
 
-        let recv_services = stream.read_u64::<LittleEndian>().unwrap();
+        let recv_services = stream.read_u64::<LittleEndian>()?;
         let mut recv_addr_bytes = [0; 16];
-        stream.read(&mut recv_addr_bytes).unwrap();
+        stream.read_exact(&mut recv_addr_bytes)?;
         let recv_addr = IpAddr::from(recv_addr_bytes);
-        let recv_port = stream.read_u16::<LittleEndian>().unwrap();
+        let recv_port = stream.read_u16::<LittleEndian>()?;
 
-        let send_services = stream.read_u64::<LittleEndian>().unwrap();
+        let send_services = stream.read_u64::<LittleEndian>()?;
         let mut send_addr_bytes = [0; 16];
-        stream.read(&mut send_addr_bytes).unwrap();
+        stream.read_exact(&mut send_addr_bytes)?;
         let send_addr = IpAddr::from(send_addr_bytes);
-        let send_port = stream.read_u16::<LittleEndian>().unwrap();
+        let send_port = stream.read_u16::<LittleEndian>()?;
 
-        let nonce = stream.read_u64::<LittleEndian>().unwrap();
-        let user_agent = read_var_str(stream).unwrap();
-        let start_height = stream.read_i32::<LittleEndian>().unwrap();
-        let relay = stream.read_u8().unwrap() > 0;
+        let nonce = stream.read_u64::<LittleEndian>()?;
+        let user_agent = read_var_str(stream)?;
+        let start_height = stream.read_i32::<LittleEndian>()?;
+        let relay = stream.read_u8().unwrap_or(1) > 0;  // relay is optional
         Ok(VersionMessage {
             version, services, timestamp, recv_services, recv_addr, recv_port, send_services,
             send_addr, send_port, nonce, user_agent, start_height, relay,
@@ -1,7 +1,11 @@
 use actix::prelude::*;
 use slpdexdb_base::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use crate::actors::{NodeActor, IncomingMsg};
-use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage};
+use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage,
+                      AddrMessage};
 
 pub enum Subscribe {
     HandshakeSuccess(Recipient<HandshakeSuccess>),
@@ -11,6 +15,9 @@ pub enum Subscribe {
     Headers(Recipient<IncomingMsg<HeadersMessage>>),
     Tx(Recipient<IncomingMsg<TxMessage>>),
     Block(Recipient<IncomingMsg<BlockMessage>>),
+    Addr(Recipient<PeerAddrs>),
+    Disconnected(Recipient<NodeDisconnected>),
+    Misbehaved(Recipient<PeerMisbehaved>),
 }
 
 impl Message for Subscribe {
@@ -18,8 +25,70 @@ impl Message for Subscribe {
 }
 
 #[derive(Clone)]
-pub struct HandshakeSuccess;
+pub struct HandshakeSuccess {
+    pub peer_addr: SocketAddr,
+    pub start_height: i32,
+}
 
 impl Message for HandshakeSuccess {
     type Result = Result<(), Error>;
 }
+
+// addr messages carry the peer they came from, so they can be rate limited per peer
+#[derive(Clone)]
+pub struct PeerAddrs {
+    pub peer_addr: SocketAddr,
+    pub addrs: Arc<AddrMessage>,
+}
+
+impl Message for PeerAddrs {
+    type Result = Result<(), Error>;
+}
+
+#[derive(Clone)]
+pub struct NodeDisconnected {
+    pub peer_addr: SocketAddr,
+}
+
+impl Message for NodeDisconnected {
+    type Result = ();
+}
+
+pub struct Misbehaving {
+    pub score: i32,
+    pub reason: String,
+}
+
+impl Message for Misbehaving {
+    type Result = ();
+}
+
+#[derive(Clone)]
+pub struct PeerMisbehaved {
+    pub peer_addr: SocketAddr,
+    pub score: i32,
+    pub reason: String,
+}
+
+impl Message for PeerMisbehaved {
+    type Result = ();
+}
+
+pub struct Disconnect;
+
+impl Message for Disconnect {
+    type Result = ();
+}
+
+pub struct GetNodeStats;
+
+impl Message for GetNodeStats {
+    type Result = Result<NodeStats, Error>;
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeStats {
+    pub peer_addr: SocketAddr,
+    pub latency: Option<Duration>,
+    pub misbehaviour_score: i32,
+}
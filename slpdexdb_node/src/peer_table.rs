@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+const BACKOFF_BASE_SECS: i64 = 10;
+const BACKOFF_MAX_SECS: i64 = 3600;
+pub const MAX_PEERS: usize = 10_000;
+// like bitcoind, accept on average one address every 10 seconds per peer, in bursts of up to 1000
+const ADDR_RATE_PER_SEC: f64 = 0.1;
+const ADDR_BURST: f64 = 1000.0;
+
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    pub addr: SocketAddr,
+    pub last_seen: i64,
+    pub last_attempt: Option<i64>,
+    pub n_failures: i32,
+    pub misbehaviour_score: i32,
+    pub banned_until: Option<i64>,
+}
+
+impl PeerInfo {
+    pub fn is_banned(&self, now: i64) -> bool {
+        self.banned_until.map(|banned_until| banned_until > now).unwrap_or(false)
+    }
+
+    pub fn next_attempt(&self) -> i64 {
+        match self.last_attempt {
+            Some(last_attempt) if self.n_failures > 0 => {
+                let exponent = (self.n_failures - 1).min(16) as u32;
+                last_attempt + (BACKOFF_BASE_SECS << exponent).min(BACKOFF_MAX_SECS)
+            },
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PeerTable {
+    peers: HashMap<SocketAddr, PeerInfo>,
+}
+
+impl PeerTable {
+    pub fn new() -> Self {
+        PeerTable { peers: HashMap::new() }
+    }
+
+    pub fn from_peers(peers: impl IntoIterator<Item=PeerInfo>) -> Self {
+        PeerTable { peers: peers.into_iter().map(|peer| (peer.addr, peer)).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&PeerInfo> {
+        self.peers.get(addr)
+    }
+
+    pub fn add(&mut self, addr: SocketAddr, last_seen: i64) -> &PeerInfo {
+        let peer = self.peers.entry(addr).or_insert_with(|| PeerInfo {
+            addr,
+            last_seen,
+            last_attempt: None,
+            n_failures: 0,
+            misbehaviour_score: 0,
+            banned_until: None,
+        });
+        peer.last_seen = peer.last_seen.max(last_seen);
+        peer
+    }
+
+    pub fn record_attempt(&mut self, addr: &SocketAddr, now: i64) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(addr)?;
+        peer.last_attempt = Some(now);
+        Some(peer)
+    }
+
+    pub fn record_success(&mut self, addr: &SocketAddr, now: i64) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(addr)?;
+        peer.last_seen = now;
+        peer.n_failures = 0;
+        Some(peer)
+    }
+
+    pub fn record_failure(&mut self, addr: &SocketAddr) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(addr)?;
+        peer.n_failures += 1;
+        Some(peer)
+    }
+
+    pub fn record_misbehaviour(&mut self,
+                               addr: &SocketAddr,
+                               score: i32,
+                               ban_threshold: i32,
+                               ban_duration: i64,
+                               now: i64) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(addr)?;
+        peer.misbehaviour_score += score;
+        if peer.misbehaviour_score >= ban_threshold {
+            peer.misbehaviour_score = 0;
+            peer.banned_until = Some(now + ban_duration);
+        }
+        Some(peer)
+    }
+
+    pub fn candidates(&self, now: i64, exclude: &HashSet<SocketAddr>) -> Vec<SocketAddr> {
+        let mut candidates = self.peers.values()
+            .filter(|peer| !exclude.contains(&peer.addr))
+            .filter(|peer| !peer.is_banned(now))
+            .filter(|peer| peer.next_attempt() <= now)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|peer| (peer.n_failures, -peer.last_seen));
+        candidates.into_iter().map(|peer| peer.addr).collect()
+    }
+
+    /// Drops the least promising peers until at most `max_peers` are left, except those in `keep`.
+    pub fn evict(&mut self, max_peers: usize, keep: &HashSet<SocketAddr>) -> Vec<SocketAddr> {
+        if self.peers.len() <= max_peers {
+            return Vec::new();
+        }
+        let mut candidates = self.peers.values()
+            .filter(|peer| !keep.contains(&peer.addr))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|peer| (-peer.n_failures, peer.last_seen));
+        let evicted = candidates.into_iter()
+            .take(self.peers.len() - max_peers)
+            .map(|peer| peer.addr)
+            .collect::<Vec<_>>();
+        for addr in evicted.iter() {
+            self.peers.remove(addr);
+        }
+        evicted
+    }
+}
+
+/// Token bucket limiting how many gossiped addresses are accepted from a peer.
+#[derive(Clone, Debug)]
+pub struct AddrRateLimit {
+    tokens: f64,
+    last_update: i64,
+}
+
+impl AddrRateLimit {
+    pub fn new(now: i64) -> Self {
+        AddrRateLimit { tokens: ADDR_BURST, last_update: now }
+    }
+
+    /// Returns how many of `n_addrs` addresses may be processed at `now`.
+    pub fn take(&mut self, n_addrs: usize, now: i64) -> usize {
+        let elapsed = (now - self.last_update).max(0) as f64;
+        self.tokens = (self.tokens + elapsed * ADDR_RATE_PER_SEC).min(ADDR_BURST);
+        self.last_update = self.last_update.max(now);
+        let n_taken = (n_addrs as f64).min(self.tokens.floor()) as usize;
+        self.tokens -= n_taken as f64;
+        n_taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let addr = "127.0.0.1:8333".parse().unwrap();
+        let mut table = PeerTable::new();
+        table.add(addr, 100);
+        assert_eq!(table.candidates(100, &HashSet::new()), vec![addr]);
+        table.record_attempt(&addr, 100);
+        assert_eq!(table.record_failure(&addr).unwrap().next_attempt(), 110);
+        table.record_attempt(&addr, 110);
+        assert_eq!(table.record_failure(&addr).unwrap().next_attempt(), 130);
+        assert!(table.candidates(129, &HashSet::new()).is_empty());
+        assert_eq!(table.candidates(130, &HashSet::new()), vec![addr]);
+        for _ in 0..20 {
+            table.record_failure(&addr);
+        }
+        assert_eq!(table.get(&addr).unwrap().next_attempt(), 110 + 3600);
+        table.record_success(&addr, 200);
+        assert_eq!(table.get(&addr).unwrap().next_attempt(), 0);
+    }
+
+    #[test]
+    fn ban_after_threshold() {
+        let addr = "127.0.0.1:8333".parse().unwrap();
+        let mut table = PeerTable::new();
+        table.add(addr, 100);
+        assert!(!table.record_misbehaviour(&addr, 60, 100, 3600, 100).unwrap().is_banned(100));
+        let peer = table.record_misbehaviour(&addr, 60, 100, 3600, 200).unwrap();
+        assert_eq!(peer.banned_until, Some(3800));
+        assert!(table.candidates(3799, &HashSet::new()).is_empty());
+        assert_eq!(table.candidates(3800, &HashSet::new()), vec![addr]);
+    }
+
+    #[test]
+    fn candidates_prefer_healthy_peers() {
+        let good = "127.0.0.1:8333".parse().unwrap();
+        let bad = "127.0.0.2:8333".parse().unwrap();
+        let connected = "127.0.0.3:8333".parse().unwrap();
+        let mut table = PeerTable::new();
+        table.add(bad, 50);
+        table.add(good, 10);
+        table.add(connected, 60);
+        table.record_failure(&bad);
+        let exclude = vec![connected].into_iter().collect();
+        assert_eq!(table.candidates(1000, &exclude), vec![good, bad]);
+    }
+
+    #[test]
+    fn evicts_worst_peers() {
+        let good = "127.0.0.1:8333".parse().unwrap();
+        let old = "127.0.0.2:8333".parse().unwrap();
+        let bad = "127.0.0.3:8333".parse().unwrap();
+        let connected = "127.0.0.4:8333".parse().unwrap();
+        let mut table = PeerTable::new();
+        table.add(good, 100);
+        table.add(old, 10);
+        table.add(bad, 100);
+        table.add(connected, 0);
+        table.record_failure(&bad);
+        assert!(table.evict(4, &HashSet::new()).is_empty());
+        let keep = vec![connected].into_iter().collect();
+        assert_eq!(table.evict(2, &keep), vec![bad, old]);
+        assert_eq!(table.len(), 2);
+        assert!(table.get(&good).is_some());
+        assert!(table.get(&connected).is_some());
+    }
+
+    #[test]
+    fn rate_limits_addrs() {
+        let mut limit = AddrRateLimit::new(100);
+        assert_eq!(limit.take(600, 100), 600);
+        assert_eq!(limit.take(600, 100), 400);
+        assert_eq!(limit.take(10, 100), 0);
+        assert_eq!(limit.take(10, 150), 5);
+        assert_eq!(limit.take(1000, 1_000_000), 1000);
+    }
+}